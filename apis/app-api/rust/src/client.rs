@@ -0,0 +1,307 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use kubos_system::{Config as ServiceConfig, Credentials, ServiceRegistry, TaggedRequest};
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+use std::cell::Cell;
+use std::io;
+use std::net::UdpSocket;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a [`ServiceClient`] waits for each response by default
+///
+/// [`ServiceClient`]: struct.ServiceClient.html
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+/// How long a [`ServiceClient`] waits before its first retry by default
+///
+/// [`ServiceClient`]: struct.ServiceClient.html
+pub const DEFAULT_BACKOFF: Duration = Duration::from_millis(100);
+
+// Largest possible UDP payload
+const MAX_RESPONSE_SIZE: usize = 65507;
+
+/// The ways a service query can fail
+#[derive(Debug, Fail)]
+pub enum QueryError {
+    /// The service isn't listed in the service registry
+    #[fail(display = "Unknown service: {}", name)]
+    UnknownService {
+        /// The name of the service
+        name: String,
+    },
+    /// The request couldn't be sent, or the response couldn't be received
+    #[fail(display = "{}", err)]
+    Io {
+        /// The underlying error
+        #[cause]
+        err: io::Error,
+    },
+    /// No response was received from the service
+    #[fail(display = "No response from service after {} attempt(s)", attempts)]
+    NoResponse {
+        /// Number of times the request was sent
+        attempts: u32,
+    },
+    /// The service's response wasn't in the expected format
+    #[fail(display = "Malformed response: {}", err)]
+    Malformed {
+        /// Description of the problem
+        err: String,
+    },
+    /// The service reported errors while executing the query
+    #[fail(display = "{}", err)]
+    Service {
+        /// The errors reported by the service
+        err: String,
+    },
+    /// The query's result couldn't be converted into the requested type
+    #[fail(display = "Failed to parse result: {}", err)]
+    Deserialize {
+        /// The underlying error
+        err: String,
+    },
+}
+
+impl From<io::Error> for QueryError {
+    fn from(err: io::Error) -> Self {
+        QueryError::Io { err }
+    }
+}
+
+/// A reusable client for querying a KubOS service over UDP.
+///
+/// Requests are resent if no response arrives within the timeout, waiting `backoff` before the
+/// first retry and doubling the wait before each further retry. Mutations should only be retried
+/// if running them twice is harmless.
+///
+/// By default, each request is tagged with an ID which the service echoes back, so replies to
+/// earlier requests are ignored. Services which don't support request IDs (such as those using
+/// the Python service library) need `request_ids(false)`.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate kubos_app;
+/// # extern crate serde_json;
+/// use kubos_app::*;
+/// use std::time::Duration;
+///
+/// # fn func() -> Result<(), QueryError> {
+/// let client = ServiceClient::new(ServiceConfig::new("radio-service"))?
+///     .timeout(Some(Duration::from_millis(500)))
+///     .retries(3);
+///
+/// let power: serde_json::Value = client.query_as("{ power }")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ServiceClient {
+    socket: UdpSocket,
+    timeout: Option<Duration>,
+    retries: u32,
+    backoff: Duration,
+    request_ids: bool,
+    credentials: Option<Credentials>,
+    next_id: Cell<u64>,
+}
+
+impl ServiceClient {
+    /// Creates a client for the service at the address in the given configuration
+    pub fn new(config: ServiceConfig) -> Result<Self, QueryError> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(config.hosturl())?;
+
+        Ok(ServiceClient {
+            socket,
+            timeout: Some(DEFAULT_TIMEOUT),
+            retries: 0,
+            backoff: DEFAULT_BACKOFF,
+            request_ids: true,
+            credentials: None,
+            next_id: Cell::new(1),
+        })
+    }
+
+    /// Creates a client for a service listed in the given registry
+    pub fn from_registry(registry: &ServiceRegistry, name: &str) -> Result<Self, QueryError> {
+        match registry.config(name) {
+            Some(config) => ServiceClient::new(config),
+            None => Err(QueryError::UnknownService {
+                name: name.to_owned(),
+            }),
+        }
+    }
+
+    /// How long to wait for each response. `None` waits forever, so the request is never retried
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How many times to resend a request which gets no response
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// How long to wait before the first retry
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Whether to tag requests with IDs
+    pub fn request_ids(mut self, request_ids: bool) -> Self {
+        self.request_ids = request_ids;
+        self
+    }
+
+    /// Signs every request with the given credentials
+    pub fn credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    /// Executes a GraphQL query, returning the contents of the response's `msg` field
+    pub fn query(&self, query: &str) -> Result<Value, QueryError> {
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+
+            let err = match self.attempt(query) {
+                Ok(Some(response)) => return parse_response(response),
+                Ok(None) => QueryError::NoResponse { attempts: attempt },
+                Err(err) => err,
+            };
+
+            if attempt > self.retries || self.timeout.is_none() {
+                return Err(err);
+            }
+
+            thread::sleep(backoff);
+            backoff *= 2;
+        }
+    }
+
+    /// Executes a GraphQL query, converting the contents of the response's `msg` field into `T`
+    pub fn query_as<T: DeserializeOwned>(&self, query: &str) -> Result<T, QueryError> {
+        serde_json::from_value(self.query(query)?).map_err(|err| QueryError::Deserialize {
+            err: err.to_string(),
+        })
+    }
+
+    // Sends the request once, returning `None` if no response arrives in time
+    fn attempt(&self, query: &str) -> Result<Option<Value>, QueryError> {
+        // Signed requests are re-signed on each attempt, since the service rejects repeated
+        // counters
+        let mut request = match self.credentials {
+            Some(ref credentials) => credentials.sign(query).to_string(),
+            None => query.to_owned(),
+        };
+
+        let id = if self.request_ids {
+            let id = self.next_id.get();
+            self.next_id.set(id + 1);
+            request = serde_json::to_string(&TaggedRequest { id, request }).unwrap();
+            Some(id)
+        } else {
+            None
+        };
+
+        self.socket.send(request.as_bytes())?;
+
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let mut buf = vec![0; MAX_RESPONSE_SIZE];
+
+        loop {
+            let remaining = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Ok(None);
+                    }
+                    Some(deadline - now)
+                }
+                None => None,
+            };
+            self.socket.set_read_timeout(remaining)?;
+
+            let amt = match self.socket.recv(&mut buf) {
+                Ok(amt) => amt,
+                Err(ref err)
+                    if err.kind() == io::ErrorKind::WouldBlock
+                        || err.kind() == io::ErrorKind::TimedOut =>
+                {
+                    return Ok(None)
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            let response: Value =
+                serde_json::from_slice(&buf[0..amt]).map_err(|err| QueryError::Malformed {
+                    err: err.to_string(),
+                })?;
+
+            // Ignore late replies to earlier requests
+            match id {
+                Some(id) if response.get("id").and_then(Value::as_u64) != Some(id) => continue,
+                _ => return Ok(Some(response)),
+            }
+        }
+    }
+}
+
+// The message of the first error in a service's error report
+fn error_message(errs: &Value) -> String {
+    errs.get("message")
+        .or_else(|| errs.get(0).and_then(|err| err.get("message")))
+        .and_then(|message| message.as_str())
+        .map(|message| message.to_owned())
+        .unwrap_or_else(|| errs.to_string())
+}
+
+// Extracts the result of a query from a service's response, or the errors it reported
+fn parse_response(response: Value) -> Result<Value, QueryError> {
+    // Requests which the service couldn't parse are answered with a list of errors
+    if response.is_array() {
+        return Err(QueryError::Service {
+            err: error_message(&response),
+        });
+    }
+
+    match response.get("errs") {
+        Some(Value::String(errs)) if !errs.is_empty() => {
+            return Err(QueryError::Service { err: errs.clone() })
+        }
+        Some(Value::String(_)) | Some(Value::Null) | None => {}
+        Some(errs) => {
+            return Err(QueryError::Service {
+                err: error_message(errs),
+            })
+        }
+    }
+
+    match response.get("msg") {
+        Some(result) => Ok(result.clone()),
+        None => Err(QueryError::Malformed {
+            err: format!("No result returned in 'msg' key: {}", response),
+        }),
+    }
+}
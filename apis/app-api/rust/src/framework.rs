@@ -20,8 +20,21 @@
 #![deny(warnings)]
 
 use getopts::Options;
+use libc;
 use std::env;
 use std::fmt;
+use std::mem;
+use std::process;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// How often a running application checks whether it has been asked to shut down
+pub const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
 
 /// The different ways an application can be started
 #[derive(Clone, Debug, PartialEq)]
@@ -30,6 +43,9 @@ pub enum RunLevel {
     OnBoot,
     /// Logic intended to be run if the application is started manually
     OnCommand,
+    /// An application-specific run level, declared in the `run_levels` list of the application's
+    /// manifest
+    Custom(String),
 }
 
 impl fmt::Display for RunLevel {
@@ -37,6 +53,17 @@ impl fmt::Display for RunLevel {
         match self {
             RunLevel::OnBoot => write!(f, "OnBoot"),
             RunLevel::OnCommand => write!(f, "OnCommand"),
+            RunLevel::Custom(level) => write!(f, "{}", level),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for RunLevel {
+    fn from(level: &'a str) -> Self {
+        match level {
+            "OnBoot" => RunLevel::OnBoot,
+            "OnCommand" => RunLevel::OnCommand,
+            level => RunLevel::Custom(level.to_owned()),
         }
     }
 }
@@ -48,6 +75,50 @@ pub trait AppHandler {
 
     /// Called when the application is started on-demand through the `start_app` GraphQL mutation
     fn on_command(&self, args: Vec<String>);
+
+    /// Called when the application is started with one of the custom run levels declared in its
+    /// manifest
+    fn on_run_level(&self, run_level: &str, _args: Vec<String>) {
+        eprintln!(
+            "Error: Unknown run level was requested - {}. Available run levels: OnBoot, OnCommand",
+            run_level
+        );
+    }
+
+    /// Called when the application has been asked to shut down with `SIGTERM` (for example, by
+    /// the `stopApp` GraphQL mutation) or `SIGINT`.
+    ///
+    /// This is called once the run level handler has returned, so long-running handlers should
+    /// check [`shutdown_requested`] and return early. The application exits with a status of 0
+    /// once it returns.
+    ///
+    /// [`shutdown_requested`]: fn.shutdown_requested.html
+    fn on_shutdown(&self) {}
+}
+
+/// Whether the application has been asked to shut down. Long-running handlers should check this
+/// and return, so that [`on_shutdown`] can be called.
+///
+/// [`on_shutdown`]: trait.AppHandler.html#method.on_shutdown
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN.load(Ordering::SeqCst)
+}
+
+extern "C" fn request_shutdown(_signum: libc::c_int) {
+    SHUTDOWN.store(true, Ordering::SeqCst);
+}
+
+// Routes SIGTERM and SIGINT to the given handler, or `SIG_DFL`
+fn handle_shutdown_signals(handler: libc::sighandler_t) {
+    for signum in &[libc::SIGTERM, libc::SIGINT] {
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = handler;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(*signum, &action, ptr::null_mut());
+        }
+    }
 }
 
 /// A helper macro which detects the requested run level and calls the appropriate handler function
@@ -89,6 +160,12 @@ macro_rules! app_main {
 
 /// The entry point for all KubOS applications. The preferred way to use this application
 /// is through the `app_main!` macro
+///
+/// Runs the handler for the requested run level. If the application was asked to shut down in the
+/// meantime, the handler's `on_shutdown` function is then called and the application exits.
+///
+/// Only the first `SIGTERM` or `SIGINT` is caught. A second one terminates the application
+/// straight away, in case the run level handler never checks `shutdown_requested`.
 pub fn app_start(_pid: u32, handler: &AppHandler) {
     let args: Vec<String> = env::args().collect();
     let program = args[0].clone();
@@ -113,21 +190,37 @@ pub fn app_start(_pid: u32, handler: &AppHandler) {
         return;
     }
 
-    let _uuid = env::var_os("KUBOS_APP_UUID");
     let run_level = matches.opt_str("r").unwrap_or("OnCommand".to_owned());
 
-    match run_level.as_ref() {
-        "OnBoot" => {
-            handler.on_boot(args);
-        }
-        "OnCommand" => {
-            handler.on_command(args);
-        }
-        level => {
-            eprintln!(
-                "Error: Unknown run level was requested - {}. Available run levels: OnBoot, OnCommand", level
-            );
-            return;
-        }
+    let request_shutdown: extern "C" fn(libc::c_int) = request_shutdown;
+    handle_shutdown_signals(request_shutdown as libc::sighandler_t);
+
+    let finished = Arc::new(AtomicBool::new(false));
+    let watcher = {
+        let finished = finished.clone();
+        thread::spawn(move || {
+            while !finished.load(Ordering::SeqCst) {
+                if shutdown_requested() {
+                    handle_shutdown_signals(libc::SIG_DFL);
+                    break;
+                }
+                thread::sleep(SHUTDOWN_POLL_INTERVAL);
+            }
+        })
+    };
+
+    match RunLevel::from(run_level.as_ref()) {
+        RunLevel::OnBoot => handler.on_boot(args),
+        RunLevel::OnCommand => handler.on_command(args),
+        RunLevel::Custom(level) => handler.on_run_level(&level, args),
+    }
+
+    finished.store(true, Ordering::SeqCst);
+    handle_shutdown_signals(libc::SIG_DFL);
+    let _ = watcher.join();
+
+    if shutdown_requested() {
+        handler.on_shutdown();
+        process::exit(0);
     }
 }
@@ -0,0 +1,143 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use failure;
+use kubos_system::Config as ServiceConfig;
+use query::query;
+use std::env;
+use std::path::{Path, PathBuf};
+use store::Store;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How long to wait for the applications service to acknowledge a heartbeat
+pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Information about the running application, as provided by the applications service.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate kubos_app;
+/// use kubos_app::*;
+/// use std::time::Duration;
+///
+/// # fn func() {
+/// let info = AppInfo::from_env();
+/// println!("Running {} version {:?}", info.name, info.version);
+///
+/// // Let the applications service know we're still alive every 10 seconds
+/// info.start_heartbeat(Duration::from_secs(10));
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct AppInfo {
+    /// The UUID the application was registered with, if it was started by the applications
+    /// service
+    pub uuid: Option<String>,
+    /// The name of the application
+    pub name: String,
+    /// The version of the application, if it was started by the applications service
+    pub version: Option<String>,
+    /// The directory the application's persistent state is kept in, if it was started by the
+    /// applications service
+    pub state_dir: Option<PathBuf>,
+    /// The configuration of the applications service, which heartbeats are sent to
+    pub app_service: ServiceConfig,
+}
+
+impl AppInfo {
+    /// Reads the application's information from the environment set up by the applications
+    /// service. If the application wasn't started by the applications service, its name is taken
+    /// from the name of its executable.
+    pub fn from_env() -> Self {
+        let name = env::var("KUBOS_APP_NAME").ok().unwrap_or_else(|| {
+            env::args()
+                .next()
+                .as_ref()
+                .and_then(|program| Path::new(program).file_name())
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+
+        AppInfo {
+            uuid: env::var("KUBOS_APP_UUID").ok(),
+            name,
+            version: env::var("KUBOS_APP_VERSION").ok(),
+            state_dir: env::var_os("KUBOS_APP_STATE_DIR").map(PathBuf::from),
+            app_service: ServiceConfig::new("app-service"),
+        }
+    }
+
+    /// The application's section of the system configuration file, which is named after the
+    /// application
+    pub fn config(&self) -> ServiceConfig {
+        ServiceConfig::new(&self.name)
+    }
+
+    /// The application's persistent key-value store, which keeps its contents between runs and
+    /// across upgrades. Returns `None` if the application wasn't started by the applications
+    /// service.
+    pub fn store(&self) -> Option<Store> {
+        self.state_dir.as_ref().map(Store::new)
+    }
+
+    /// Tells the applications service that the application is still alive. The time of the most
+    /// recent heartbeat is reported by the `lastHeartbeat` field of the `apps` query.
+    pub fn heartbeat(&self) -> Result<(), failure::Error> {
+        let uuid = match self.uuid {
+            Some(ref uuid) => uuid,
+            None => bail!("Application was not started by the applications service"),
+        };
+
+        let request = format!(
+            r#"mutation {{
+                heartbeat(uuid: "{}") {{
+                    success,
+                    errors
+                }}
+            }}"#,
+            uuid
+        );
+
+        let response = query(
+            self.app_service.clone(),
+            &request,
+            Some(HEARTBEAT_TIMEOUT),
+        )?;
+
+        let result = &response["heartbeat"];
+        match result["success"].as_bool() {
+            Some(true) => Ok(()),
+            _ => bail!(
+                "Heartbeat failed: {}",
+                result["errors"].as_str().unwrap_or("Unknown error")
+            ),
+        }
+    }
+
+    /// Starts a thread which sends a heartbeat every `interval` until the application exits.
+    /// Failures are logged to stderr.
+    pub fn start_heartbeat(&self, interval: Duration) -> JoinHandle<()> {
+        let info = self.clone();
+        thread::spawn(move || loop {
+            if let Err(err) = info.heartbeat() {
+                eprintln!("{}", err);
+            }
+            thread::sleep(interval);
+        })
+    }
+}
@@ -82,19 +82,32 @@ extern crate juniper;
 #[cfg(test)]
 extern crate kubos_service;
 extern crate kubos_system;
+extern crate libc;
+extern crate serde;
 #[cfg(not(test))]
 extern crate serde_json;
 #[cfg(test)]
 #[macro_use]
 extern crate serde_json;
 #[cfg(test)]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(test)]
 extern crate tempfile;
 
+mod client;
 mod framework;
+mod info;
 mod query;
+mod store;
 #[cfg(test)]
 mod tests;
 
+pub use client::{QueryError, ServiceClient, DEFAULT_BACKOFF, DEFAULT_TIMEOUT};
 pub use framework::*;
-pub use query::query;
+pub use info::AppInfo;
+pub use store::{Store, StoreError, STORE_FILE};
+pub use query::{query, query_registered, query_service, query_signed};
 pub use kubos_system::Config as ServiceConfig;
+pub use kubos_system::Credentials;
+pub use kubos_system::{Protocol, ServiceEndpoint, ServiceRegistry};
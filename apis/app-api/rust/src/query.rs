@@ -14,10 +14,10 @@
  * limitations under the License.
  */
 
+use client::ServiceClient;
 use failure;
-use kubos_system::Config as ServiceConfig;
+use kubos_system::{Config as ServiceConfig, Credentials, ServiceRegistry};
 use serde_json;
-use std::net::UdpSocket;
 use std::time::Duration;
 
 /// The result type used by `query`
@@ -83,51 +83,128 @@ pub fn query(
     query: &str,
     timeout: Option<Duration>,
 ) -> AppResult<serde_json::Value> {
-    let socket = UdpSocket::bind("0.0.0.0:0")?;
-    socket.connect(config.hosturl())?;
-    socket.send(query.as_bytes())?;
-
-    // Allow the caller to set a read timeout on the socket
-    socket.set_read_timeout(timeout).unwrap();
-
-    let mut buf = [0; 4096];
-    let (amt, _) = socket.recv_from(&mut buf)?;
+    send_request(config, query, timeout)
+}
 
-    let v: serde_json::Value = serde_json::from_slice(&buf[0..(amt)])?;
+/// Execute a GraphQL query against a running KubOS Service which requires authenticated requests.
+///
+/// The query is signed with the given credentials before it is sent. Otherwise, this function
+/// behaves exactly like [`query`].
+///
+/// [`query`]: fn.query.html
+///
+/// # Arguments
+///
+/// * `config` - The configuration information for the service which should be queried
+/// * `query` - The raw GraphQL query as a string
+/// * `timeout` - The timeout provided to the UDP socket. Note: This function will block when `None`
+///               is provided here
+/// * `credentials` - The key used to sign the request
+///
+/// # Examples
+///
+/// ```
+/// # extern crate failure;
+/// # extern crate kubos_app;
+/// # use failure;
+/// use kubos_app::*;
+/// use std::time::Duration;
+///
+/// # fn func() -> Result<(), failure::Error> {
+/// let credentials = Credentials::from_config(&ServiceConfig::new("mission-app"))
+///     .expect("No credentials configured");
+///
+/// let request = r#"mutation {
+/// 		deploy {
+/// 			success
+/// 		}
+/// 	}"#;
+///
+/// let result = query_signed(
+///     ServiceConfig::new("antenna-service"),
+///     request,
+///     Some(Duration::from_secs(1)),
+///     &credentials,
+/// )?;
+/// # Ok(())
+/// # }
+/// ```
+///
+pub fn query_signed(
+    config: ServiceConfig,
+    query: &str,
+    timeout: Option<Duration>,
+    credentials: &Credentials,
+) -> AppResult<serde_json::Value> {
+    let request = credentials.sign(query).to_string();
+    send_request(config, &request, timeout)
+}
 
-    if let Some(errs) = v.get("errs") {
-        if errs.is_string() {
-            let errs_str = errs.as_str().unwrap();
-            if errs_str.len() > 0 {
-                return Err(format_err!("{}", errs_str.to_string()));
-            }
-        } else if !errs.is_null() {
-            match errs.get("message") {
-                Some(message) => {
-                    return Err(format_err!("{}", message.as_str().unwrap().to_string()));
-                }
-                None => {
-                    return Err(format_err!("{}", serde_json::to_string(errs).unwrap()));
-                }
-            }
-        }
-    }
+/// Execute a GraphQL query against a running KubOS Service, looking up its address by name.
+///
+/// The service is found in the system config file (or the file passed with the `-c` option)
+/// using a [`ServiceRegistry`]. Unlike [`query`], an error is returned if the service isn't
+/// listed, rather than falling back to the default address.
+///
+/// [`ServiceRegistry`]: struct.ServiceRegistry.html
+/// [`query`]: fn.query.html
+///
+/// # Arguments
+///
+/// * `name` - The name of the service which should be queried
+/// * `query` - The raw GraphQL query as a string
+/// * `timeout` - The timeout provided to the UDP socket. Note: This function will block when `None`
+///               is provided here
+///
+/// # Examples
+///
+/// ```
+/// # extern crate failure;
+/// # extern crate kubos_app;
+/// # use failure;
+/// use kubos_app::*;
+/// use std::time::Duration;
+///
+/// # fn func() -> Result<(), failure::Error> {
+/// let result = query_service("antenna-service", "{ power }", Some(Duration::from_secs(1)))?;
+/// # Ok(())
+/// # }
+/// ```
+///
+pub fn query_service(
+    name: &str,
+    query: &str,
+    timeout: Option<Duration>,
+) -> AppResult<serde_json::Value> {
+    query_registered(&ServiceRegistry::new()?, name, query, timeout)
+}
 
-    match v.get(0) {
-        Some(err) if err.get("message").is_some() => {
-            return Err(format_err!(
-                "{}",
-                err["message"].as_str().unwrap().to_string(),
-            ));
-        }
-        _ => {}
-    }
+/// Execute a GraphQL query against a service listed in the given registry.
+///
+/// Behaves like [`query_service`], for callers which have already loaded a [`ServiceRegistry`]
+/// (for example, from a non-default config file).
+///
+/// [`query_service`]: fn.query_service.html
+/// [`ServiceRegistry`]: struct.ServiceRegistry.html
+pub fn query_registered(
+    registry: &ServiceRegistry,
+    name: &str,
+    query: &str,
+    timeout: Option<Duration>,
+) -> AppResult<serde_json::Value> {
+    let config = registry
+        .config(name)
+        .ok_or_else(|| format_err!("Unknown service: {}", name))?;
+    send_request(config, query, timeout)
+}
 
-    match v.get("msg") {
-        Some(result) => Ok(result.clone()),
-        None => Err(format_err!(
-            "No result returned in 'msg' key: {}",
-            serde_json::to_string(&v).unwrap()
-        )),
-    }
+fn send_request(
+    config: ServiceConfig,
+    request: &str,
+    timeout: Option<Duration>,
+) -> AppResult<serde_json::Value> {
+    let client = ServiceClient::new(config)?
+        .timeout(timeout)
+        .request_ids(false);
+    Ok(client.query(request)?)
 }
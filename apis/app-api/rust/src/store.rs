@@ -0,0 +1,229 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use libc;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{self, Value};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The name of the file, in an application's state directory, which its store is saved to
+pub const STORE_FILE: &str = "store.json";
+
+/// How long to wait for another process to finish changing a store before giving up
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How often to retry taking a store's lock while another process holds it
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The ways accessing an application's store can fail
+#[derive(Debug, Fail)]
+pub enum StoreError {
+    /// The store couldn't be read or written
+    #[fail(display = "Failed to {}: {}", action, err)]
+    Io {
+        /// What was being done
+        action: String,
+        /// The underlying error
+        #[cause]
+        err: io::Error,
+    },
+    /// The store, or a value in it, couldn't be parsed
+    #[fail(display = "Failed to parse {}: {}", entity, err)]
+    Parse {
+        /// What was being parsed
+        entity: String,
+        /// The underlying error
+        err: String,
+    },
+}
+
+/// A small persistent key-value store, for applications to keep state between runs.
+///
+/// Values are stored as JSON in a single file. Every change rewrites the file to a temporary
+/// file which is then renamed into place, so the store is never left half-written. Changes are
+/// made while holding a lock on the store's directory, so that an application and the
+/// applications service (which lets operators inspect and edit the store) can both safely change
+/// it.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate kubos_app;
+/// use kubos_app::*;
+///
+/// # fn func() -> Result<(), StoreError> {
+/// let store = AppInfo::from_env()
+///     .store()
+///     .expect("Not started by the applications service");
+///
+/// let count: u32 = store.get("count")?.unwrap_or(0);
+/// store.set("count", &(count + 1))?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Store {
+    dir: PathBuf,
+}
+
+impl Store {
+    /// Opens the store in the given directory. The directory is created when the store is first
+    /// changed, if needed.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Store {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// The path of the file the store is saved to
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(STORE_FILE)
+    }
+
+    /// Reads every key and value in the store
+    pub fn load(&self) -> Result<BTreeMap<String, Value>, StoreError> {
+        let path = self.path();
+
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(err) => {
+                return Err(StoreError::Io {
+                    action: format!("read {:?}", path),
+                    err,
+                })
+            }
+        };
+
+        serde_json::from_slice(&data).map_err(|err| StoreError::Parse {
+            entity: format!("{:?}", path),
+            err: err.to_string(),
+        })
+    }
+
+    /// Reads a value, or `None` if the key isn't set
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, StoreError> {
+        match self.load()?.remove(key) {
+            Some(value) => serde_json::from_value(value)
+                .map(Some)
+                .map_err(|err| StoreError::Parse {
+                    entity: format!("value of {}", key),
+                    err: err.to_string(),
+                }),
+            None => Ok(None),
+        }
+    }
+
+    /// Sets a value
+    pub fn set<T: Serialize>(&self, key: &str, value: &T) -> Result<(), StoreError> {
+        let value = serde_json::to_value(value).map_err(|err| StoreError::Parse {
+            entity: format!("value of {}", key),
+            err: err.to_string(),
+        })?;
+
+        self.update(|values| {
+            values.insert(key.to_owned(), value);
+        })
+    }
+
+    /// Removes a value. Returns whether the key was set
+    pub fn remove(&self, key: &str) -> Result<bool, StoreError> {
+        let mut removed = false;
+        self.update(|values| removed = values.remove(key).is_some())?;
+        Ok(removed)
+    }
+
+    // Changes the store's contents while holding its lock
+    fn update<F>(&self, change: F) -> Result<(), StoreError>
+    where
+        F: FnOnce(&mut BTreeMap<String, Value>),
+    {
+        fs::create_dir_all(&self.dir).map_err(|err| StoreError::Io {
+            action: format!("create {:?}", self.dir),
+            err,
+        })?;
+
+        // The lock is released when the directory is closed
+        let lock = File::open(&self.dir)
+            .and_then(lock_dir)
+            .map_err(|err| StoreError::Io {
+                action: format!("lock {:?}", self.dir),
+                err,
+            })?;
+
+        let mut values = self.load()?;
+        change(&mut values);
+
+        let path = self.path();
+        let temp_path = self.dir.join(format!(".{}.tmp", STORE_FILE));
+
+        let data = serde_json::to_vec_pretty(&values).map_err(|err| StoreError::Parse {
+            entity: format!("{:?}", path),
+            err: err.to_string(),
+        })?;
+
+        // The store may be changed by a privileged process (such as the applications service) in a
+        // directory the app can write to, so the temporary file must be a new file rather than
+        // whatever an existing path points to. Any leftover file or symlink is removed first
+        // (which doesn't touch a symlink's target). If that fails, creating the new file does too
+        let _ = fs::remove_file(&temp_path);
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(&temp_path)
+            .and_then(|mut file| {
+                file.write_all(&data)?;
+                file.sync_all()
+            }).map_err(|err| StoreError::Io {
+                action: format!("write {:?}", temp_path),
+                err,
+            })?;
+
+        fs::rename(&temp_path, &path).map_err(|err| StoreError::Io {
+            action: format!("rename {:?} to {:?}", temp_path, path),
+            err,
+        })?;
+
+        drop(lock);
+        Ok(())
+    }
+}
+
+// Takes an exclusive lock on a store's directory, waiting up to `LOCK_TIMEOUT` for any other
+// holder to release it
+fn lock_dir(dir: File) -> io::Result<File> {
+    let deadline = Instant::now() + LOCK_TIMEOUT;
+    loop {
+        if unsafe { libc::flock(dir.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+            return Ok(dir);
+        }
+
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EWOULDBLOCK) || Instant::now() >= deadline {
+            return Err(err);
+        }
+        thread::sleep(LOCK_RETRY_INTERVAL);
+    }
+}
@@ -0,0 +1,212 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::mock_service::*;
+use kubos_service::Service;
+use kubos_system::{Config as ServiceConfig, Credentials};
+use query::{query, query_signed};
+
+use std::net::UdpSocket;
+use std::time::Duration;
+use tempfile::TempDir;
+
+const AUTH_CONFIG: &str = r#"
+    [mock-service.auth]
+    required = true
+
+    [mock-service.auth.keys.ground]
+    secret = "0123456789abcdef"
+    roles = ["operator"]
+
+    [mock-service.auth.keys.payload]
+    secret = "fedcba9876543210"
+    roles = ["payload"]
+
+    [mock-service.auth.mutations]
+    ping = ["operator"]
+    "#;
+
+fn ground() -> Credentials {
+    Credentials::new("ground", &[0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef])
+}
+
+fn payload() -> Credentials {
+    Credentials::new("payload", &[0xfe, 0xdc, 0xba, 0x98, 0x76, 0x54, 0x32, 0x10])
+}
+
+#[test]
+fn auth_signed_query() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "0.0.0.0", 8761, AUTH_CONFIG);
+
+    let result = query_signed(
+        ServiceConfig::new_from_path("mock-service", config_file.to_string_lossy().to_string()),
+        "{ ping }",
+        Some(Duration::from_secs(1)),
+        &payload(),
+    ).unwrap();
+
+    assert_eq!(result, json!({"ping": "query"}));
+}
+
+#[test]
+fn auth_signed_mutation() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "0.0.0.0", 8760, AUTH_CONFIG);
+
+    let result = query_signed(
+        ServiceConfig::new_from_path("mock-service", config_file.to_string_lossy().to_string()),
+        "mutation { ping }",
+        Some(Duration::from_secs(1)),
+        &ground(),
+    ).unwrap();
+
+    assert_eq!(result, json!({"ping": "mutation"}));
+}
+
+#[test]
+fn auth_unsigned_rejected() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "0.0.0.0", 8759, AUTH_CONFIG);
+
+    let result = query(
+        ServiceConfig::new_from_path("mock-service", config_file.to_string_lossy().to_string()),
+        "{ ping }",
+        Some(Duration::from_secs(1)),
+    ).unwrap_err();
+
+    assert_eq!(format!("{}", result), "Request is not authenticated");
+}
+
+#[test]
+fn auth_bad_secret() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "0.0.0.0", 8758, AUTH_CONFIG);
+
+    let result = query_signed(
+        ServiceConfig::new_from_path("mock-service", config_file.to_string_lossy().to_string()),
+        "{ ping }",
+        Some(Duration::from_secs(1)),
+        &Credentials::new("ground", b"not the secret"),
+    ).unwrap_err();
+
+    assert_eq!(format!("{}", result), "Invalid MAC for key ground");
+}
+
+#[test]
+fn auth_forbidden_mutation() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "0.0.0.0", 8757, AUTH_CONFIG);
+
+    let result = query_signed(
+        ServiceConfig::new_from_path("mock-service", config_file.to_string_lossy().to_string()),
+        "mutation { renamed: ping }",
+        Some(Duration::from_secs(1)),
+        &payload(),
+    ).unwrap_err();
+
+    assert_eq!(
+        format!("{}", result),
+        "Not authorized to execute mutation ping"
+    );
+}
+
+#[test]
+fn auth_replay_rejected() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "0.0.0.0", 8756, AUTH_CONFIG);
+
+    let request = ground().sign("mutation { ping }").to_string();
+
+    let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+    socket.connect("0.0.0.0:8756").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+
+    let mut buf = [0; 4096];
+
+    socket.send(request.as_bytes()).unwrap();
+    let amt = socket.recv(&mut buf).unwrap();
+    let first: ::serde_json::Value = ::serde_json::from_slice(&buf[0..amt]).unwrap();
+    assert_eq!(first["errs"], json!(""));
+
+    socket.send(request.as_bytes()).unwrap();
+    let amt = socket.recv(&mut buf).unwrap();
+    let second: ::serde_json::Value = ::serde_json::from_slice(&buf[0..amt]).unwrap();
+    assert!(
+        second["errs"]
+            .as_str()
+            .unwrap()
+            .starts_with("Replayed request for key ground")
+    );
+}
+
+#[test]
+fn auth_subscription_signed() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(
+        config_file,
+        "127.0.0.1",
+        8749,
+        r#"
+        [mock-service.auth]
+        subscribe = ["operator"]
+
+        [mock-service.auth.keys.ground]
+        secret = "0123456789abcdef"
+        roles = ["operator"]
+
+        [mock-service.auth.keys.payload]
+        secret = "fedcba9876543210"
+        roles = ["payload"]
+        "#
+    );
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.connect("127.0.0.1:8749").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+
+    let subscribe = r#"{"subscribe": {"query": "{ ping }", "lease": 1}}"#;
+    let mut buf = [0; 4096];
+    let mut send = |request: &str| {
+        socket.send(request.as_bytes()).unwrap();
+        let amt = socket.recv(&mut buf).unwrap();
+        let response: ::serde_json::Value = ::serde_json::from_slice(&buf[0..amt]).unwrap();
+        response
+    };
+
+    // Plain queries don't need to be signed, but subscriptions do
+    assert_eq!(send("{ ping }")["errs"], json!(""));
+    assert_eq!(send(subscribe)["errs"], json!("Request is not authenticated"));
+    assert_eq!(
+        send(&payload().sign(subscribe).to_string())["errs"],
+        json!("Not authorized to subscribe")
+    );
+
+    let response = send(&ground().sign(subscribe).to_string());
+    assert_eq!(response["errs"], json!(""));
+    assert_eq!(response["msg"]["subscribe"]["lease"], json!(1));
+}
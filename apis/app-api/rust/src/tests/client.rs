@@ -0,0 +1,177 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::mock_service::*;
+use client::{QueryError, ServiceClient};
+use kubos_service::Service;
+use kubos_system::Config as ServiceConfig;
+
+use serde_json::{self, Value};
+use std::net::UdpSocket;
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Ping {
+    ping: String,
+}
+
+fn config(port: u16) -> ServiceConfig {
+    ServiceConfig::new_from_str(
+        "fake-service",
+        &format!("[fake-service.addr]\nip = \"127.0.0.1\"\nport = {}", port),
+    )
+}
+
+// Receives a request sent to a fake service, returning its ID and the client's address
+fn receive(socket: &UdpSocket) -> (u64, ::std::net::SocketAddr) {
+    let mut buf = [0; 4096];
+    let (amt, peer) = socket.recv_from(&mut buf).unwrap();
+    let request: Value = serde_json::from_slice(&buf[0..amt]).unwrap();
+    assert_eq!(request["request"], json!("{ ping }"));
+    (request["id"].as_u64().unwrap(), peer)
+}
+
+#[test]
+fn client_query_as() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "127.0.0.1", 8768);
+
+    let client = ServiceClient::new(config(8768)).unwrap();
+    let result: Ping = client.query_as("{ ping }").unwrap();
+    assert_eq!(
+        result,
+        Ping {
+            ping: "query".to_owned()
+        }
+    );
+
+    // The client can be reused
+    let result: Ping = client.query_as("mutation { ping }").unwrap();
+    assert_eq!(result.ping, "mutation");
+}
+
+#[test]
+fn client_query_as_wrong_type() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "127.0.0.1", 8769);
+
+    let client = ServiceClient::new(config(8769)).unwrap();
+    match client.query_as::<Vec<u32>>("{ ping }") {
+        Err(QueryError::Deserialize { .. }) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn client_service_error() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "127.0.0.1", 8770);
+
+    let client = ServiceClient::new(config(8770)).unwrap();
+    match client.query("{ ping(fail: true) }") {
+        Err(QueryError::Service { err }) => assert!(err.contains("Query failed")),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn client_invalid_query() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "127.0.0.1", 8771);
+
+    let client = ServiceClient::new(config(8771)).unwrap();
+    match client.query("{ ping") {
+        Err(QueryError::Service { .. }) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn client_no_response() {
+    let service = UdpSocket::bind("127.0.0.1:8772").unwrap();
+
+    let client = ServiceClient::new(config(8772))
+        .unwrap()
+        .timeout(Some(Duration::from_millis(50)))
+        .retries(2)
+        .backoff(Duration::from_millis(10));
+
+    match client.query("{ ping }") {
+        Err(QueryError::NoResponse { attempts: 3 }) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    // Each attempt has a new ID
+    let ids: Vec<u64> = (0..3).map(|_| receive(&service).0).collect();
+    assert_eq!(ids, vec![1, 2, 3]);
+}
+
+#[test]
+fn client_ignores_stale_reply() {
+    let service = UdpSocket::bind("127.0.0.1:8773").unwrap();
+
+    let handle = thread::spawn(move || {
+        // Answer the first attempt only after the client has given up on it
+        let (first, _) = receive(&service);
+        let (second, peer) = receive(&service);
+        for &(id, result) in &[(first, "stale"), (second, "fresh")] {
+            let response = json!({"msg": {"ping": result}, "errs": "", "id": id});
+            service
+                .send_to(response.to_string().as_bytes(), &peer)
+                .unwrap();
+        }
+    });
+
+    let client = ServiceClient::new(config(8773))
+        .unwrap()
+        .timeout(Some(Duration::from_millis(200)))
+        .retries(1)
+        .backoff(Duration::from_millis(10));
+
+    let result: Ping = client.query_as("{ ping }").unwrap();
+    assert_eq!(result.ping, "fresh");
+    handle.join().unwrap();
+}
+
+#[test]
+fn client_tagged_request() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "127.0.0.1", 8774);
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.connect("127.0.0.1:8774").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+
+    let mut buf = [0; 4096];
+    for &(request, ok) in &[("{ ping }", true), ("{ ping", false)] {
+        let tagged = json!({"id": 42, "request": request});
+        socket.send(tagged.to_string().as_bytes()).unwrap();
+
+        let amt = socket.recv(&mut buf).unwrap();
+        let response: Value = serde_json::from_slice(&buf[0..amt]).unwrap();
+        assert_eq!(response["id"], json!(42));
+        assert_eq!(response["errs"] == json!(""), ok);
+    }
+}
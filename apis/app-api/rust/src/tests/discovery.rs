@@ -0,0 +1,73 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::mock_service::*;
+use kubos_service::Service;
+use kubos_system::{Config as ServiceConfig, ServiceRegistry};
+use query::query_registered;
+
+use std::time::Duration;
+use tempfile::TempDir;
+
+#[test]
+fn query_registered_good() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "127.0.0.1", 8753);
+
+    let registry =
+        ServiceRegistry::new_from_path(&config_file.to_string_lossy().to_string()).unwrap();
+
+    let result = query_registered(
+        &registry,
+        "mock-service",
+        "{ ping }",
+        Some(Duration::from_secs(1)),
+    ).unwrap();
+
+    assert_eq!(result, json!({"ping": "query"}));
+}
+
+#[test]
+fn query_registered_unknown() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "127.0.0.1", 8755);
+
+    let registry =
+        ServiceRegistry::new_from_path(&config_file.to_string_lossy().to_string()).unwrap();
+
+    let result = query_registered(
+        &registry,
+        "fake-service",
+        "{ ping }",
+        Some(Duration::from_secs(1)),
+    ).unwrap_err();
+
+    assert_eq!(format!("{}", result), "Unknown service: fake-service");
+}
+
+#[test]
+fn ping_registered() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "127.0.0.1", 8754);
+
+    let registry =
+        ServiceRegistry::new_from_path(&config_file.to_string_lossy().to_string()).unwrap();
+
+    assert!(registry.ping("mock-service", Duration::from_secs(1)).is_ok());
+}
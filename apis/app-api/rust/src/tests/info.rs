@@ -0,0 +1,95 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::mock_service::*;
+use framework::RunLevel;
+use info::AppInfo;
+use kubos_service::Service;
+use kubos_system::Config as ServiceConfig;
+use std::fs;
+
+use tempfile::TempDir;
+
+fn app_info(config_file: &::std::path::Path, uuid: Option<&str>) -> AppInfo {
+    AppInfo {
+        uuid: uuid.map(|uuid| uuid.to_owned()),
+        name: "mock-app".to_owned(),
+        version: Some("1.0".to_owned()),
+        state_dir: None,
+        app_service: ServiceConfig::new_from_path(
+            "mock-service",
+            config_file.to_string_lossy().to_string(),
+        ),
+    }
+}
+
+#[test]
+fn run_level_names() {
+    for name in &["OnBoot", "OnCommand", "OnDeploy"] {
+        assert_eq!(RunLevel::from(*name).to_string(), *name);
+    }
+    assert_eq!(
+        RunLevel::from("OnDeploy"),
+        RunLevel::Custom("OnDeploy".to_owned())
+    );
+}
+
+#[test]
+fn heartbeat_good() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "0.0.0.0", 8766);
+
+    app_info(&config_file, Some("a-b-c-d-e")).heartbeat().unwrap();
+}
+
+#[test]
+fn heartbeat_rejected() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "0.0.0.0", 8767);
+
+    let err = app_info(&config_file, Some("f-g-h")).heartbeat().unwrap_err();
+    assert_eq!(err.to_string(), "Heartbeat failed: f-g-h is not running");
+}
+
+#[test]
+fn heartbeat_not_started_by_service() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+
+    let err = app_info(&config_file, None).heartbeat().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Application was not started by the applications service"
+    );
+}
+
+#[test]
+fn app_info_store() {
+    let registry_dir = TempDir::new().unwrap();
+    let config = registry_dir.path().join("config.toml");
+
+    assert!(app_info(&config, Some("a-b-c")).store().is_none());
+
+    let mut info = app_info(&config, Some("a-b-c"));
+    info.state_dir = Some(registry_dir.path().join("a-b-c/state"));
+    info.store().unwrap().set("count", &3).unwrap();
+    assert_eq!(
+        fs::read_to_string(registry_dir.path().join("a-b-c/state/store.json")).unwrap(),
+        "{\n  \"count\": 3\n}"
+    );
+}
@@ -33,6 +33,12 @@ graphql_object!(QueryRoot: Context as "Query" |&self| {
     }
 });
 
+#[derive(GraphQLObject)]
+pub struct HeartbeatResponse {
+    pub errors: String,
+    pub success: bool,
+}
+
 pub struct MutationRoot;
 
 /// Base GraphQL mutation model
@@ -41,4 +47,12 @@ graphql_object!(MutationRoot: Context as "Mutation" |&self| {
         {
             Ok(String::from("mutation"))
         }
+
+    field heartbeat(uuid: String) -> FieldResult<HeartbeatResponse>
+        {
+            Ok(match uuid.as_ref() {
+                "a-b-c-d-e" => HeartbeatResponse { errors: "".to_owned(), success: true },
+                _ => HeartbeatResponse { errors: format!("{} is not running", uuid), success: false },
+            })
+        }
 });
@@ -18,13 +18,17 @@ mod mock_service;
 
 macro_rules! mock_service {
     ($config:ident, $addr:expr, $port:expr) => {{
+        mock_service!($config, $addr, $port, "")
+    }};
+    ($config:ident, $addr:expr, $port:expr, $extra:expr) => {{
         let config = format!(
             r#"
             [mock-service.addr]
             ip = "{}"
             port = {}
+            {}
             "#,
-            $addr, $port
+            $addr, $port, $extra
         );
 
         ::std::fs::write($config.clone(), config).unwrap();
@@ -44,4 +48,10 @@ macro_rules! mock_service {
     }};
 }
 
+mod auth;
+mod client;
+mod discovery;
+mod info;
 mod query;
+mod store;
+mod subscription;
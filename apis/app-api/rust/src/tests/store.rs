@@ -0,0 +1,140 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use store::*;
+use std::fs;
+use std::sync::Arc;
+use std::thread;
+
+use tempfile::TempDir;
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct Progress {
+    image: String,
+    count: u32,
+}
+
+#[test]
+fn store_get_missing() {
+    let dir = TempDir::new().unwrap();
+    let store = Store::new(dir.path().join("state"));
+
+    assert_eq!(store.get::<u32>("count").unwrap(), None);
+    assert!(store.load().unwrap().is_empty());
+}
+
+#[test]
+fn store_set_get() {
+    let dir = TempDir::new().unwrap();
+    let store = Store::new(dir.path().join("state"));
+    let progress = Progress {
+        image: "img-0042.png".to_owned(),
+        count: 42,
+    };
+
+    store.set("progress", &progress).unwrap();
+    store.set("mode", &"safe").unwrap();
+
+    // A new handle sees the saved values
+    let store = Store::new(dir.path().join("state"));
+    assert_eq!(store.get::<Progress>("progress").unwrap(), Some(progress));
+    assert_eq!(store.get::<String>("mode").unwrap(), Some("safe".to_owned()));
+    assert_eq!(
+        store.load().unwrap().keys().collect::<Vec<_>>(),
+        vec!["mode", "progress"]
+    );
+    assert!(!dir.path().join("state/.store.json.tmp").exists());
+}
+
+#[test]
+fn store_remove() {
+    let dir = TempDir::new().unwrap();
+    let store = Store::new(dir.path());
+
+    store.set("count", &1).unwrap();
+    assert_eq!(store.remove("count").unwrap(), true);
+    assert_eq!(store.remove("count").unwrap(), false);
+    assert_eq!(store.get::<u32>("count").unwrap(), None);
+}
+
+#[test]
+fn store_wrong_type() {
+    let dir = TempDir::new().unwrap();
+    let store = Store::new(dir.path());
+
+    store.set("count", &"many").unwrap();
+    let err = store.get::<u32>("count").unwrap_err().to_string();
+    assert!(
+        err.starts_with("Failed to parse value of count: invalid type"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn store_corrupt() {
+    let dir = TempDir::new().unwrap();
+    let store = Store::new(dir.path());
+
+    fs::write(dir.path().join(STORE_FILE), "{").unwrap();
+    assert!(store.get::<u32>("count").is_err());
+    assert!(store.set("count", &1).is_err());
+
+    // The corrupt file is left for inspection rather than being overwritten
+    assert_eq!(fs::read_to_string(store.path()).unwrap(), "{");
+}
+
+#[test]
+fn store_temp_file_symlink() {
+    let dir = TempDir::new().unwrap();
+    let target = dir.path().join("target");
+    fs::write(&target, "untouched").unwrap();
+
+    let store = Store::new(dir.path().join("state"));
+    fs::create_dir_all(dir.path().join("state")).unwrap();
+    ::std::os::unix::fs::symlink(
+        &target,
+        dir.path().join(format!("state/.{}.tmp", STORE_FILE)),
+    ).unwrap();
+
+    store.set("count", &1).unwrap();
+
+    assert_eq!(fs::read_to_string(&target).unwrap(), "untouched");
+    assert_eq!(store.get::<u32>("count").unwrap(), Some(1));
+}
+
+#[test]
+fn store_concurrent_updates() {
+    let dir = TempDir::new().unwrap();
+    let store = Arc::new(Store::new(dir.path()));
+
+    let threads: Vec<_> = (0..8)
+        .map(|num| {
+            let store = store.clone();
+            thread::spawn(move || {
+                for count in 0..10 {
+                    store.set(&format!("thread-{}", num), &count).unwrap();
+                }
+            })
+        }).collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let values = store.load().unwrap();
+    assert_eq!(values.len(), 8);
+    assert!(values.values().all(|value| value.as_u64() == Some(9)));
+}
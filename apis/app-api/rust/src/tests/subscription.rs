@@ -0,0 +1,121 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::mock_service::*;
+use kubos_service::Service;
+use kubos_system::Config as ServiceConfig;
+
+use serde_json::{self, Value};
+use std::net::UdpSocket;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn request(socket: &UdpSocket, request: &str) -> Value {
+    socket.send(request.as_bytes()).unwrap();
+    receive(socket)
+}
+
+fn receive(socket: &UdpSocket) -> Value {
+    let mut buf = [0; 4096];
+    let amt = socket.recv(&mut buf).unwrap();
+    serde_json::from_slice(&buf[0..amt]).unwrap()
+}
+
+#[test]
+fn subscription_push() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "127.0.0.1", 8752);
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.connect("127.0.0.1:8752").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let response = request(
+        &socket,
+        r#"{"subscribe": {"query": "{ ping }", "lease": 1, "interval": 0.2}}"#,
+    );
+    assert_eq!(response["errs"], json!(""));
+    let id = response["msg"]["subscribe"]["id"].clone();
+
+    // Results keep arriving until the lease expires
+    let mut results = 0;
+    loop {
+        let message = receive(&socket);
+        assert_eq!(message["subscription"], id);
+        if message["errs"] == json!("Subscription expired") {
+            break;
+        }
+        assert_eq!(message["msg"], json!({"ping": "query"}));
+        results += 1;
+    }
+
+    assert!(results >= 3);
+}
+
+#[test]
+fn subscription_cancel() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "127.0.0.1", 8751);
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.connect("127.0.0.1:8751").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+
+    let response = request(
+        &socket,
+        r#"{"subscribe": {"query": "{ ping }", "lease": 60, "on_change": true}}"#,
+    );
+    let id = response["msg"]["subscribe"]["id"].as_u64().unwrap();
+
+    // The first result is always sent. Since the result never changes, nothing else is
+    let first = receive(&socket);
+    assert_eq!(first["msg"], json!({"ping": "query"}));
+
+    let response = request(&socket, &format!(r#"{{"unsubscribe": {{"id": {}}}}}"#, id));
+    assert_eq!(response["msg"], json!({"unsubscribe": {"id": id}}));
+
+    let mut buf = [0; 4096];
+    assert!(socket.recv(&mut buf).is_err());
+}
+
+#[test]
+fn subscription_mutation_rejected() {
+    let config_dir = TempDir::new().unwrap();
+    let config_file = config_dir.path().join("config.toml");
+    mock_service!(config_file, "127.0.0.1", 8750);
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.connect("127.0.0.1:8750").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+
+    let response = request(
+        &socket,
+        r#"{"subscribe": {"query": "mutation { ping }", "lease": 10}}"#,
+    );
+    assert_eq!(response["msg"], Value::Null);
+    assert_eq!(
+        response["errs"],
+        json!("Subscriptions may not contain mutations")
+    );
+}
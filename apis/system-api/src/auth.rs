@@ -0,0 +1,209 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Message authentication for service requests
+//!
+//! An authenticated request wraps the raw GraphQL query in a small JSON envelope:
+//!
+//! ```json
+//! {"query": "mutation { ... }", "key": "ground", "counter": 42, "timestamp": 1539000000, "mac": "..."}
+//! ```
+//!
+//! The `mac` field is a hex-encoded, keyed BLAKE2b-256 digest of the key name, counter,
+//! timestamp and query, generated using the secret shared between the client and the service.
+//! Services reject envelopes whose counter does not increase or whose timestamp falls outside of
+//! their configured window, which prevents captured requests from being replayed.
+
+use blake2_rfc::blake2b::{Blake2b, Blake2bResult};
+use config::Config;
+use failure::Error;
+use serde_json;
+use std::cell::Cell;
+use std::cmp;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Length, in bytes, of the MAC attached to an authenticated request
+pub const MAC_LEN: usize = 32;
+
+/// A GraphQL request which has been signed with a shared secret
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SignedRequest {
+    /// The raw GraphQL query
+    pub query: String,
+    /// The name of the key used to sign the request
+    pub key: String,
+    /// Monotonically increasing request counter, tracked per key
+    pub counter: u64,
+    /// Seconds since the Unix epoch at which the request was generated
+    pub timestamp: u64,
+    /// Hex-encoded MAC of the request contents
+    pub mac: String,
+}
+
+impl SignedRequest {
+    /// Attempts to interpret a raw service request as a signed request envelope.
+    ///
+    /// Returns `None` if the data is not a signed request (for example, a plain GraphQL query)
+    pub fn from_slice(data: &[u8]) -> Option<SignedRequest> {
+        serde_json::from_slice(data).ok()
+    }
+
+    /// Checks the request's MAC against the one generated with the given secret
+    pub fn verify(&self, secret: &[u8]) -> bool {
+        match decode_hex(&self.mac) {
+            Ok(mac) => {
+                compute_mac(secret, &self.key, self.counter, self.timestamp, &self.query) == mac[..]
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for SignedRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Serializing a struct of strings and integers can't fail
+        write!(f, "{}", serde_json::to_string(self).unwrap())
+    }
+}
+
+/// Generates the MAC for a request
+///
+/// # Arguments
+///
+/// * `secret` - The secret shared between the client and the service
+/// * `key` - The name the service knows the secret by
+/// * `counter` - The request counter
+/// * `timestamp` - The request generation time, in seconds since the Unix epoch
+/// * `query` - The raw GraphQL query
+pub fn compute_mac(
+    secret: &[u8],
+    key: &str,
+    counter: u64,
+    timestamp: u64,
+    query: &str,
+) -> Blake2bResult {
+    let mut hasher = Blake2b::with_key(MAC_LEN, secret);
+    hasher.update(key.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(counter.to_string().as_bytes());
+    hasher.update(b"\n");
+    hasher.update(timestamp.to_string().as_bytes());
+    hasher.update(b"\n");
+    hasher.update(query.as_bytes());
+    hasher.finalize()
+}
+
+/// Client-side credentials used to sign requests sent to a service
+///
+/// ### Examples
+///
+/// ```rust
+/// use kubos_system::Credentials;
+///
+/// let creds = Credentials::new("ground", b"secret");
+/// let request = creds.sign("mutation { noop { success } }");
+/// assert!(request.verify(b"secret"));
+/// ```
+#[derive(Debug)]
+pub struct Credentials {
+    key: String,
+    secret: Vec<u8>,
+    counter: Cell<u64>,
+}
+
+impl Credentials {
+    /// Creates a new set of credentials
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The name the service knows the secret by
+    /// * `secret` - The shared secret
+    pub fn new(key: &str, secret: &[u8]) -> Self {
+        Credentials {
+            key: key.to_owned(),
+            secret: secret.to_vec(),
+            counter: Cell::new(0),
+        }
+    }
+
+    /// Loads credentials from the `[<name>.auth]` section of a config file.
+    ///
+    /// ```toml
+    /// [my-app.auth]
+    /// key = "payload-ops"
+    /// secret = "00112233445566778899aabbccddeeff"
+    /// ```
+    ///
+    /// Returns `None` if the section is missing or the secret is not valid hex
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let auth = config.get("auth")?;
+        let key = auth.get("key")?.as_str()?;
+        let secret = decode_hex(auth.get("secret")?.as_str()?).ok()?;
+
+        Some(Credentials::new(key, &secret))
+    }
+
+    /// The name of the key these credentials sign with
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Signs a GraphQL query, producing a request envelope which can be sent to a service
+    ///
+    /// The request counter is seeded from the current time (in milliseconds), so that requests
+    /// made by a restarted client are still accepted by a long-running service.
+    pub fn sign(&self, query: &str) -> SignedRequest {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let millis = now.as_secs() * 1000 + u64::from(now.subsec_millis());
+
+        let counter = cmp::max(self.counter.get() + 1, millis);
+        self.counter.set(counter);
+
+        let timestamp = now.as_secs();
+        let mac = compute_mac(&self.secret, &self.key, counter, timestamp, query);
+
+        SignedRequest {
+            query: query.to_owned(),
+            key: self.key.clone(),
+            counter,
+            timestamp,
+            mac: encode_hex(mac.as_bytes()),
+        }
+    }
+}
+
+/// Encodes binary data as a lowercase hex string
+pub fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decodes a hex string into binary data
+pub fn decode_hex(data: &str) -> Result<Vec<u8>, Error> {
+    if data.len() % 2 != 0 {
+        bail!("Hex string has an odd number of digits");
+    }
+
+    (0..data.len())
+        .step_by(2)
+        .map(|i| {
+            data.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| format_err!("Invalid hex digits at offset {}", i))
+        }).collect()
+}
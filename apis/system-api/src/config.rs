@@ -14,10 +14,12 @@
 // limitations under the License.
 //
 use getopts::Options;
+use serde::de::DeserializeOwned;
 use std::env;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io;
 use std::io::prelude::*;
+use std::time::SystemTime;
 use toml;
 use toml::Value;
 
@@ -27,6 +29,82 @@ pub static DEFAULT_PATH: &str = "/home/system/etc/config.toml";
 pub static DEFAULT_IP: &str = "127.0.0.1";
 /// The default port for service bindings
 pub const DEFAULT_PORT: u16 = 8080;
+/// Prefix of the environment variables which override config file values
+pub static ENV_PREFIX: &str = "KUBOS_";
+
+/// Errors which occur when loading or interpreting configuration data
+#[derive(Clone, Debug, Fail, PartialEq)]
+pub enum ConfigError {
+    /// The config file could not be read
+    #[fail(display = "Unable to read config file {}: {}", path, err)]
+    ReadError {
+        /// Path to the config file
+        path: String,
+        /// Underlying error
+        err: String,
+    },
+    /// The config data is not valid TOML, or doesn't match the expected structure
+    #[fail(display = "Unable to parse config for {}: {}", name, err)]
+    ParseError {
+        /// Category name
+        name: String,
+        /// Underlying error
+        err: String,
+    },
+    /// The config data was parsed, but contains values which aren't allowed
+    #[fail(display = "Invalid config for {}: {}", name, err)]
+    ValidationError {
+        /// Category name
+        name: String,
+        /// Description of the invalid value
+        err: String,
+    },
+}
+
+/// A typed view of a category's configuration, loaded with [`Config::section`].
+///
+/// ### Examples
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate serde_derive;
+/// extern crate kubos_system;
+///
+/// use kubos_system::{Config, ConfigSection};
+///
+/// #[derive(Deserialize)]
+/// #[serde(default)]
+/// struct Settings {
+///     hold_count: u16,
+/// }
+///
+/// impl Default for Settings {
+///     fn default() -> Self {
+///         Settings { hold_count: 5 }
+///     }
+/// }
+///
+/// impl ConfigSection for Settings {
+///     fn validate(&self) -> Result<(), String> {
+///         match self.hold_count {
+///             0 => Err("hold_count must be at least 1".to_owned()),
+///             _ => Ok(()),
+///         }
+///     }
+/// }
+///
+/// # fn main() {
+/// let config = Config::new_from_str("example-service", "[example-service]\nhold_count = 8");
+/// let settings: Settings = config.section().unwrap();
+/// assert_eq!(settings.hold_count, 8);
+/// # }
+/// ```
+pub trait ConfigSection: DeserializeOwned {
+    /// Checks that the loaded values are usable, returning a description of the problem if not
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
 
 #[derive(Clone, Debug, Deserialize)]
 /// A simple address consisting of an IP address and port number
@@ -76,8 +154,16 @@ impl Address {
 ///
 /// When `addr`, `addr.ip`, or `addr.port` are not provided in the config file, the default IP
 /// `"127.0.0.1"` and default port `8080` are used instead.
+///
+/// Any value can be overridden with an environment variable named
+/// `KUBOS_<CATEGORY>__<KEY>`, where the category and key are uppercased and dashes are replaced
+/// with underscores. The category and each nested key are separated with a double underscore, so
+/// `KUBOS_MY_SERVICE__ADDR__PORT=8282` changes the port `my-service` listens on. Values are
+/// interpreted as TOML (numbers, booleans, arrays) when possible, and as plain strings otherwise.
 #[derive(Clone, Debug)]
 pub struct Config {
+    name: String,
+    path: Option<String>,
     addr: Address,
     raw: Value,
 }
@@ -85,6 +171,8 @@ pub struct Config {
 impl Default for Config {
     fn default() -> Self {
         Config {
+            name: String::new(),
+            path: None,
             addr: Address::default(),
             raw: Value::String("".to_string()),
         }
@@ -108,7 +196,10 @@ impl Config {
     /// `name` - Category name used as a key in the config file
     /// `path` - Path to configuration file
     pub fn new_from_path(name: &str, path: String) -> Self {
-        parse_config_file(name, path).unwrap_or(Config::default())
+        let contents = get_file_data(path.clone()).unwrap_or_default();
+        let mut config = parse_config_str(name, &contents).unwrap_or_else(|_| Config::named(name));
+        config.path = Some(path);
+        config
     }
 
     /// Creates and parses configuration data from the passed in configuration
@@ -117,7 +208,74 @@ impl Config {
     /// `name` - Category name used as a key in the config
     /// `config` - Config data as a string
     pub fn new_from_str(name: &str, config: &str) -> Self {
-        parse_config_str(name, config).unwrap_or(Config::default())
+        parse_config_str(name, config).unwrap_or_else(|_| Config::named(name))
+    }
+
+    fn named(name: &str) -> Self {
+        Config {
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the category name this configuration was loaded for
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the path of the file this configuration was loaded from, if any
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_ref().map(|path| path.as_str())
+    }
+
+    /// Returns the last modification time of the file this configuration was loaded from
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.path
+            .as_ref()
+            .and_then(|path| fs::metadata(path).ok())
+            .and_then(|meta| meta.modified().ok())
+    }
+
+    /// Re-reads the configuration from the file it was originally loaded from.
+    ///
+    /// Unlike [`Config::new_from_path`], a missing or malformed file is reported as an error,
+    /// so that a bad edit doesn't silently replace a running service's configuration with the
+    /// defaults. Configurations which weren't loaded from a file are returned unchanged.
+    pub fn reload(&self) -> Result<Config, ConfigError> {
+        match self.path {
+            Some(ref path) => parse_config_file(&self.name, path),
+            None => Ok(self.clone()),
+        }
+    }
+
+    /// Deserializes the category's configuration into a typed structure and validates it.
+    ///
+    /// Categories which are missing from the config file are treated as an empty table, so
+    /// structures using `#[serde(default)]` will receive their default values.
+    pub fn section<T: ConfigSection>(&self) -> Result<T, ConfigError> {
+        let raw = match self.raw {
+            Value::Table(_) => self.raw.clone(),
+            _ => Value::Table(Default::default()),
+        };
+
+        let section: T = raw.try_into().map_err(|err: toml::de::Error| ConfigError::ParseError {
+            name: self.name.clone(),
+            err: err.to_string(),
+        })?;
+
+        section
+            .validate()
+            .map_err(|err| ConfigError::ValidationError {
+                name: self.name.clone(),
+                err,
+            })?;
+
+        Ok(section)
+    }
+
+    /// Returns the configured address
+    pub fn addr(&self) -> &Address {
+        &self.addr
     }
 
     /// Returns the configured hosturl string in the following
@@ -156,7 +314,7 @@ impl Config {
     }
 }
 
-fn get_config_path() -> String {
+pub(crate) fn get_config_path() -> String {
     let args: Vec<String> = env::args().collect();
 
     let mut opts = Options::new();
@@ -181,21 +339,105 @@ fn get_file_data(path: String) -> Result<String, io::Error> {
     Ok(contents)
 }
 
-fn parse_config_file(name: &str, path: String) -> Result<Config, toml::de::Error> {
-    let contents = get_file_data(path).unwrap_or("".to_string());
-    parse_config_str(name, &contents)
+fn parse_config_file(name: &str, path: &str) -> Result<Config, ConfigError> {
+    let contents = get_file_data(path.to_owned()).map_err(|err| ConfigError::ReadError {
+        path: path.to_owned(),
+        err: err.to_string(),
+    })?;
+    let mut config = parse_config_str(name, &contents)?;
+    config.path = Some(path.to_owned());
+    Ok(config)
 }
 
-fn parse_config_str(name: &str, contents: &str) -> Result<Config, toml::de::Error> {
-    let data: Value = toml::from_str(&contents)?;
-    let mut config = Config::default();
+fn parse_config_str(name: &str, contents: &str) -> Result<Config, ConfigError> {
+    let parse_error = |err: toml::de::Error| ConfigError::ParseError {
+        name: name.to_owned(),
+        err: err.to_string(),
+    };
+
+    let data: Value = toml::from_str(&contents).map_err(parse_error)?;
+    let mut config = Config::named(name);
 
-    if let Some(data) = data.get(name) {
+    let mut raw = data.get(name).cloned();
+    apply_env_overrides(name, &mut raw);
+
+    if let Some(data) = raw {
         if let Some(address) = data.get("addr") {
-            config.addr = address.clone().try_into()?;
+            config.addr = address.clone().try_into().map_err(parse_error)?;
         }
-        config.raw = data.clone();
+        config.raw = data;
     }
 
     Ok(config)
 }
+
+// Converts a config name or key into its environment variable form ("hold-count" -> "HOLD_COUNT")
+fn env_key(key: &str) -> String {
+    key.to_uppercase().replace('-', "_")
+}
+
+fn apply_env_overrides(name: &str, raw: &mut Option<Value>) {
+    // The separator stops `app` from matching the variables of `app-service`
+    let prefix = format!("{}{}__", ENV_PREFIX, env_key(name));
+
+    // Variables which aren't valid UTF-8 can't be ours, and mustn't stop the service starting
+    let vars = env::vars_os().filter_map(|(var, value)| {
+        match (var.into_string(), value.into_string()) {
+            (Ok(var), Ok(value)) => Some((var, value)),
+            _ => None,
+        }
+    });
+
+    for (var, value) in vars {
+        if !var.starts_with(&prefix) || var.len() == prefix.len() {
+            continue;
+        }
+
+        let path: Vec<&str> = var[prefix.len()..].split("__").collect();
+        if path.iter().any(|key| key.is_empty()) {
+            continue;
+        }
+
+        // Anything which isn't a valid TOML value (most commonly an unquoted string) is used as-is
+        let value = toml::from_str::<Value>(&format!("value = {}", value))
+            .ok()
+            .and_then(|table| table.get("value").cloned())
+            .unwrap_or(Value::String(value));
+
+        override_value(
+            raw.get_or_insert_with(|| Value::Table(Default::default())),
+            &path,
+            value,
+        );
+    }
+}
+
+fn override_value(target: &mut Value, path: &[&str], value: Value) {
+    if !target.is_table() {
+        *target = Value::Table(Default::default());
+    }
+
+    let table = match target.as_table_mut() {
+        Some(table) => table,
+        None => return,
+    };
+
+    // Prefer updating an existing key, so the original spelling of the key is preserved
+    let key = table
+        .keys()
+        .find(|key| env_key(key) == path[0])
+        .cloned()
+        .unwrap_or_else(|| path[0].to_lowercase());
+
+    if path.len() == 1 {
+        table.insert(key, value);
+    } else {
+        override_value(
+            table
+                .entry(key)
+                .or_insert_with(|| Value::Table(Default::default())),
+            &path[1..],
+            value,
+        );
+    }
+}
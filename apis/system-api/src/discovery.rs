@@ -0,0 +1,295 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Discovery of the services described by the system config file
+//!
+//! Any category in the config file with an `addr` section is treated as a service. The protocol
+//! the service speaks is taken from its `protocol` key when present:
+//!
+//! ```toml
+//! [payload-service]
+//! protocol = "graphql"
+//!
+//! [payload-service.addr]
+//! ip = "127.0.0.1"
+//! port = 8200
+//! ```
+//!
+//! Otherwise, the file transfer and shell services are recognized by name and all other
+//! services are assumed to use GraphQL. Services which set `direct_port` (such as the
+//! telemetry service) are also listed a second time, as a telemetry direct endpoint.
+//!
+//! A service with an unknown `protocol` is left out, rather than failing the whole registry,
+//! and the problem is reported by [`ServiceRegistry::errors`].
+//!
+//! [`ServiceRegistry::errors`]: struct.ServiceRegistry.html#method.errors
+
+use config::{get_config_path, Config, ConfigError};
+use failure::Error;
+use serde_json;
+use std::fmt;
+use std::fs;
+use std::net::UdpSocket;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use toml::{self, Value};
+
+/// The communication protocol a service endpoint speaks
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Protocol {
+    /// GraphQL requests over UDP (the standard Kubos service interface)
+    GraphQL,
+    /// The CBOR-based file transfer protocol
+    File,
+    /// The CBOR-based shell protocol
+    Shell,
+    /// JSON telemetry entries sent directly to the telemetry database
+    TelemetryDirect,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Protocol::GraphQL => "graphql",
+            Protocol::File => "file",
+            Protocol::Shell => "shell",
+            Protocol::TelemetryDirect => "telemetry-direct",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Error> {
+        match name {
+            "graphql" => Ok(Protocol::GraphQL),
+            "file" => Ok(Protocol::File),
+            "shell" => Ok(Protocol::Shell),
+            "telemetry-direct" => Ok(Protocol::TelemetryDirect),
+            other => bail!("Unknown protocol: {}", other),
+        }
+    }
+}
+
+/// A single address a service can be reached at
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServiceEndpoint {
+    /// The service's name (its category in the config file)
+    pub name: String,
+    /// The protocol spoken at this address
+    pub protocol: Protocol,
+    /// The service's address, in `ip:port` form
+    pub addr: String,
+}
+
+/// The set of services described by a system config file
+///
+/// ### Examples
+///
+/// ```rust,no_run
+/// use kubos_system::{Protocol, ServiceRegistry};
+/// use std::time::Duration;
+///
+/// let registry = ServiceRegistry::new().unwrap();
+/// for service in registry.services() {
+///     println!("{} ({}) at {}", service.name, service.protocol, service.addr);
+/// }
+///
+/// let alive = registry.ping("app-service", Duration::from_millis(500)).is_ok();
+/// ```
+#[derive(Clone, Debug)]
+pub struct ServiceRegistry {
+    path: Option<String>,
+    contents: String,
+    services: Vec<ServiceEndpoint>,
+    errors: Vec<ConfigError>,
+}
+
+impl ServiceRegistry {
+    /// Loads the services from the system config file, or from the path passed as the
+    /// '-c' or '--config' option to this executable
+    pub fn new() -> Result<Self, ConfigError> {
+        Self::new_from_path(&get_config_path())
+    }
+
+    /// Loads the services from the given config file
+    ///
+    /// # Arguments
+    /// `path` - Path to the config file
+    pub fn new_from_path(path: &str) -> Result<Self, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(|err| ConfigError::ReadError {
+            path: path.to_owned(),
+            err: err.to_string(),
+        })?;
+
+        let mut registry = Self::new_from_str(&contents)?;
+        registry.path = Some(path.to_owned());
+        Ok(registry)
+    }
+
+    /// Loads the services from config data held in a string
+    ///
+    /// # Arguments
+    /// `contents` - Config data in TOML format
+    pub fn new_from_str(contents: &str) -> Result<Self, ConfigError> {
+        let data: Value = toml::from_str(contents).map_err(|err| ConfigError::ParseError {
+            name: "services".to_owned(),
+            err: err.to_string(),
+        })?;
+
+        let mut services = vec![];
+        let mut errors = vec![];
+        if let Some(table) = data.as_table() {
+            for (name, section) in table.iter() {
+                if section.get("addr").is_none() {
+                    continue;
+                }
+
+                // Resolve the address through Config, so that environment overrides apply
+                let config = Config::new_from_str(name, contents);
+
+                let protocol = match config.get("protocol").as_ref().and_then(|val| val.as_str()) {
+                    Some(protocol) => match protocol.parse::<Protocol>() {
+                        Ok(protocol) => protocol,
+                        Err(err) => {
+                            // Skip just this service, so that the others can still be found
+                            errors.push(ConfigError::ValidationError {
+                                name: name.to_owned(),
+                                err: err.to_string(),
+                            });
+                            continue;
+                        }
+                    },
+                    None => default_protocol(name),
+                };
+
+                services.push(ServiceEndpoint {
+                    name: name.to_owned(),
+                    protocol,
+                    addr: config.hosturl(),
+                });
+
+                if let Some(port) = config.get("direct_port").and_then(|port| port.as_integer()) {
+                    services.push(ServiceEndpoint {
+                        name: name.to_owned(),
+                        protocol: Protocol::TelemetryDirect,
+                        addr: format!("{}:{}", config.addr().ip(), port),
+                    });
+                }
+            }
+        }
+
+        Ok(ServiceRegistry {
+            path: None,
+            contents: contents.to_owned(),
+            services,
+            errors,
+        })
+    }
+
+    /// Returns every service endpoint found in the config file
+    pub fn services(&self) -> &[ServiceEndpoint] {
+        &self.services
+    }
+
+    /// Returns the problems with the services which were left out of the registry, such as an
+    /// unknown protocol
+    pub fn errors(&self) -> &[ConfigError] {
+        &self.errors
+    }
+
+    /// Looks up a service's main endpoint by name
+    ///
+    /// # Arguments
+    /// `name` - Name of the service
+    pub fn find(&self, name: &str) -> Option<&ServiceEndpoint> {
+        self.services
+            .iter()
+            .find(|service| service.name == name && service.protocol != Protocol::TelemetryDirect)
+    }
+
+    /// Returns all endpoints which speak the given protocol
+    ///
+    /// # Arguments
+    /// `protocol` - The protocol to search for
+    pub fn with_protocol(&self, protocol: Protocol) -> Vec<&ServiceEndpoint> {
+        self.services
+            .iter()
+            .filter(|service| service.protocol == protocol)
+            .collect()
+    }
+
+    /// Returns the full configuration for a service, or `None` if no such service is registered
+    ///
+    /// # Arguments
+    /// `name` - Name of the service
+    pub fn config(&self, name: &str) -> Option<Config> {
+        self.find(name)?;
+
+        Some(match self.path {
+            Some(ref path) => Config::new_from_path(name, path.clone()),
+            None => Config::new_from_str(name, &self.contents),
+        })
+    }
+
+    /// Checks whether a GraphQL service is responding, by sending it a `ping` query.
+    ///
+    /// Any response (including an error, such as a rejected unsigned request) counts as the
+    /// service being alive. Returns the round-trip time of the request.
+    ///
+    /// # Arguments
+    /// `name` - Name of the service
+    /// `timeout` - How long to wait for a response
+    pub fn ping(&self, name: &str, timeout: Duration) -> Result<Duration, Error> {
+        let service = self
+            .find(name)
+            .ok_or_else(|| format_err!("Unknown service: {}", name))?;
+
+        if service.protocol != Protocol::GraphQL {
+            bail!(
+                "Unable to ping {}: {} services don't support liveness checks",
+                name,
+                service.protocol
+            );
+        }
+
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.set_read_timeout(Some(timeout))?;
+        socket.connect(&service.addr)?;
+
+        let start = Instant::now();
+        socket.send(b"{ ping }")?;
+
+        let mut buf = [0; 4096];
+        let size = socket.recv(&mut buf)?;
+        let elapsed = start.elapsed();
+
+        serde_json::from_slice::<serde_json::Value>(&buf[0..size])
+            .map_err(|err| format_err!("Invalid response from {}: {}", name, err))?;
+
+        Ok(elapsed)
+    }
+}
+
+fn default_protocol(name: &str) -> Protocol {
+    match name {
+        "file-transfer-service" => Protocol::File,
+        "shell-service" => Protocol::Shell,
+        _ => Protocol::GraphQL,
+    }
+}
@@ -18,25 +18,42 @@
 
 //! KubOS System level APIs
 
+extern crate blake2_rfc;
 #[macro_use]
 extern crate failure;
 
 extern crate getopts;
+extern crate libc;
+extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+extern crate serde_json;
 extern crate toml;
 
+mod auth;
 mod config;
+mod discovery;
+mod request;
 mod uboot;
+mod watch;
 
+pub use auth::*;
 pub use config::*;
+pub use discovery::*;
+pub use request::TaggedRequest;
 pub use uboot::UBootVars;
+pub use watch::ConfigWatcher;
+
+use failure::Error;
+use std::process::Command;
 
 /// The name of the KubOS app service that can be used to derive service configuration
 pub const SERVICE_APP: &'static str = "app-service";
 /// The name of the KubOS telemetry db service that can be used to dervice service configuration
 pub const SERVICE_TELEMETRY: &'static str = "telemetry-service";
 
+const REBOOT_PATH: &'static str = "/sbin/reboot";
+
 /// Information about the version(s) of KubOS installed in the system
 pub struct KubosVersions {
     /// The current or "active" version of KubOS
@@ -62,3 +79,19 @@ pub fn initial_deploy() -> Option<bool> {
     let vars = UBootVars::new();
     vars.get_bool(uboot::VAR_KUBOS_INITIAL_DEPLOY)
 }
+
+/// Reboot the system
+///
+/// Asks init to stop every process and restart the OBC, using `/sbin/reboot`. Returns once the
+/// reboot has been requested, or an error if it couldn't be.
+pub fn reboot() -> Result<(), Error> {
+    let status = Command::new(REBOOT_PATH)
+        .status()
+        .map_err(|err| format_err!("Failed to execute {}: {}", REBOOT_PATH, err))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format_err!("{} failed: {}", REBOOT_PATH, status))
+    }
+}
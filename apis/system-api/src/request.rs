@@ -0,0 +1,48 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+//! Request IDs for service requests
+//!
+//! A client which may resend a request, or which reuses its socket, can tag each request with an
+//! ID by wrapping the raw request (a GraphQL query or a signed request envelope) in a small JSON
+//! envelope:
+//!
+//! ```json
+//! {"id": 42, "request": "{ ping }"}
+//! ```
+//!
+//! The service adds the same `id` field to its response, which lets the client discard replies
+//! to earlier requests which arrive late.
+
+use serde_json;
+
+/// A service request tagged with a client-chosen ID
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TaggedRequest {
+    /// The ID which the service echoes in its response
+    pub id: u64,
+    /// The raw request
+    pub request: String,
+}
+
+impl TaggedRequest {
+    /// Attempts to interpret a raw service request as a tagged request envelope.
+    ///
+    /// Returns `None` if the data is not a tagged request (for example, a plain GraphQL query)
+    pub fn from_slice(data: &[u8]) -> Option<TaggedRequest> {
+        serde_json::from_slice(data).ok()
+    }
+}
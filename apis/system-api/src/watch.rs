@@ -0,0 +1,98 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use config::Config;
+use libc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Once;
+use std::time::SystemTime;
+
+// Number of SIGHUPs received by this process. Each watcher remembers the last count it saw, so
+// that a single signal is seen by every watcher in the process
+static HANGUPS: AtomicUsize = AtomicUsize::new(0);
+static INSTALL_HANDLER: Once = Once::new();
+
+extern "C" fn on_hangup(_signal: libc::c_int) {
+    HANGUPS.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Detects when a process's configuration should be reloaded.
+///
+/// A reload is requested by sending the process `SIGHUP`. If the category sets
+/// `watch_config = true`, modifying the config file also triggers a reload:
+///
+/// ```toml
+/// [example-service]
+/// watch_config = true
+/// ```
+///
+/// The watcher doesn't run in the background; callers should poll [`ConfigWatcher::changed`]
+/// periodically (for example, whenever a socket read times out) and then call
+/// [`Config::reload`].
+pub struct ConfigWatcher {
+    watch_file: bool,
+    modified: Option<SystemTime>,
+    hangups: usize,
+}
+
+impl ConfigWatcher {
+    /// Creates a watcher for the given configuration, installing the process's `SIGHUP` handler
+    /// if it hasn't been already
+    pub fn new(config: &Config) -> Self {
+        INSTALL_HANDLER.call_once(|| unsafe {
+            libc::signal(libc::SIGHUP, on_hangup as *const () as libc::sighandler_t);
+        });
+
+        ConfigWatcher {
+            watch_file: config
+                .get("watch_config")
+                .and_then(|watch| watch.as_bool())
+                .unwrap_or(false),
+            modified: config.modified(),
+            hangups: HANGUPS.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Returns true if a reload has been requested since the last call
+    ///
+    /// # Arguments
+    ///
+    /// `config` - The configuration currently in use
+    pub fn changed(&mut self, config: &Config) -> bool {
+        let hangups = HANGUPS.load(Ordering::SeqCst);
+        let mut changed = hangups != self.hangups;
+        self.hangups = hangups;
+
+        if self.watch_file {
+            let modified = config.modified();
+            if modified.is_some() && modified != self.modified {
+                changed = true;
+            }
+            self.modified = modified;
+        }
+
+        changed
+    }
+
+    /// Updates the watcher's settings after the configuration has been reloaded
+    pub fn update(&mut self, config: &Config) {
+        self.watch_file = config
+            .get("watch_config")
+            .and_then(|watch| watch.as_bool())
+            .unwrap_or(false);
+        self.modified = config.modified();
+    }
+}
@@ -0,0 +1,96 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+#![deny(warnings)]
+extern crate kubos_system;
+
+use kubos_system::{Config, Credentials, SignedRequest};
+
+#[test]
+fn sign_verify() {
+    let creds = Credentials::new("ground", b"secret");
+    let request = creds.sign("{ ping }");
+
+    assert_eq!(request.key, "ground");
+    assert_eq!(request.query, "{ ping }");
+    assert!(request.verify(b"secret"));
+    assert!(!request.verify(b"other secret"));
+}
+
+#[test]
+fn sign_tampered() {
+    let creds = Credentials::new("ground", b"secret");
+
+    let mut request = creds.sign("{ ping }");
+    request.query = "mutation { reset }".to_owned();
+    assert!(!request.verify(b"secret"));
+
+    let mut request = creds.sign("{ ping }");
+    request.counter += 1;
+    assert!(!request.verify(b"secret"));
+}
+
+#[test]
+fn sign_counter_increases() {
+    let creds = Credentials::new("ground", b"secret");
+    let first = creds.sign("{ ping }");
+    let second = creds.sign("{ ping }");
+
+    assert!(second.counter > first.counter);
+}
+
+#[test]
+fn signed_request_round_trip() {
+    let creds = Credentials::new("ground", b"secret");
+    let request = creds.sign("{ ping }");
+
+    let parsed = SignedRequest::from_slice(request.to_string().as_bytes());
+    assert_eq!(parsed, Some(request));
+}
+
+#[test]
+fn signed_request_plain_query() {
+    assert_eq!(SignedRequest::from_slice(b"{ ping }"), None);
+}
+
+#[test]
+fn credentials_from_config() {
+    let config = Config::new_from_str(
+        "my-app",
+        r#"
+    [my-app.auth]
+    key = "payload"
+    secret = "0a0b0c"
+    "#,
+    );
+
+    let creds = Credentials::from_config(&config).unwrap();
+    assert_eq!(creds.key(), "payload");
+    assert!(creds.sign("{ ping }").verify(&[0x0a, 0x0b, 0x0c]));
+}
+
+#[test]
+fn credentials_from_config_bad_secret() {
+    let config = Config::new_from_str(
+        "my-app",
+        r#"
+    [my-app.auth]
+    key = "payload"
+    secret = "not hex"
+    "#,
+    );
+
+    assert!(Credentials::from_config(&config).is_none());
+}
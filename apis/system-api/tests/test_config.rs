@@ -15,9 +15,14 @@
  */
 #![deny(warnings)]
 extern crate kubos_system;
+extern crate libc;
+#[macro_use]
+extern crate serde_derive;
 extern crate tempfile;
 extern crate toml;
 
+use kubos_system::{ConfigError, ConfigSection};
+use std::env;
 use std::io::Write;
 use tempfile::NamedTempFile;
 use toml::Value;
@@ -54,6 +59,7 @@ fn new_from_str() {
     "#,
     );
 
+    assert_eq!(config.name(), "category-1");
     assert_eq!(config.get("a"), Some(Value::Integer(1)));
     assert_eq!(config.get("b"), Some(Value::Integer(2)));
     assert_eq!(
@@ -168,3 +174,214 @@ fn only_category_config() {
     assert_eq!(config.get("c"), None);
     assert_eq!(config.get("d"), None);
 }
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default)]
+struct Settings {
+    hold_count: u16,
+    timeout: f64,
+    bus: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            hold_count: 5,
+            timeout: 2.0,
+            bus: None,
+        }
+    }
+}
+
+impl ConfigSection for Settings {
+    fn validate(&self) -> Result<(), String> {
+        if self.hold_count == 0 {
+            return Err("hold_count must be at least 1".to_owned());
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn section_good() {
+    let config = kubos_system::Config::new_from_str(
+        "category-1",
+        r#"
+    [category-1]
+    hold_count = 8
+    bus = "/dev/i2c-1"
+    "#,
+    );
+
+    let settings: Settings = config.section().unwrap();
+    assert_eq!(
+        settings,
+        Settings {
+            hold_count: 8,
+            timeout: 2.0,
+            bus: Some("/dev/i2c-1".to_owned()),
+        }
+    );
+}
+
+#[test]
+fn section_missing_category() {
+    let config = kubos_system::Config::new_from_str("category-1", "");
+
+    let settings: Settings = config.section().unwrap();
+    assert_eq!(settings, Settings::default());
+}
+
+#[test]
+fn section_bad_type() {
+    let config = kubos_system::Config::new_from_str(
+        "category-1",
+        r#"
+    [category-1]
+    hold_count = "many"
+    "#,
+    );
+
+    match config.section::<Settings>() {
+        Err(ConfigError::ParseError { name, .. }) => assert_eq!(name, "category-1"),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn section_invalid() {
+    let config = kubos_system::Config::new_from_str(
+        "category-1",
+        r#"
+    [category-1]
+    hold_count = 0
+    "#,
+    );
+
+    match config.section::<Settings>() {
+        Err(ConfigError::ValidationError { err, .. }) => {
+            assert_eq!(err, "hold_count must be at least 1")
+        }
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn env_override() {
+    // Each test uses its own category, since the environment is shared between test threads
+    env::set_var("KUBOS_ENV_CATEGORY__HOLD_COUNT", "9");
+    env::set_var("KUBOS_ENV_CATEGORY__BUS", "/dev/ttyS3");
+    env::set_var("KUBOS_ENV_CATEGORY__ADDR__PORT", "9999");
+
+    let config = kubos_system::Config::new_from_str(
+        "env-category",
+        r#"
+    [env-category]
+    hold_count = 3
+
+    [env-category.addr]
+    ip = "10.0.1.1"
+    port = 1234
+    "#,
+    );
+
+    assert_eq!(config.get("hold_count"), Some(Value::Integer(9)));
+    assert_eq!(
+        config.get("bus"),
+        Some(Value::String("/dev/ttyS3".to_owned()))
+    );
+    assert_eq!(config.hosturl(), "10.0.1.1:9999");
+}
+
+#[test]
+fn env_override_missing_category() {
+    env::set_var("KUBOS_ENV_MISSING__ADDR__PORT", "4321");
+
+    let config = kubos_system::Config::new_from_str("env-missing", "");
+
+    assert_eq!(
+        config.hosturl(),
+        format!("{}:4321", kubos_system::DEFAULT_IP)
+    );
+}
+
+#[test]
+fn env_override_longer_name() {
+    // These belong to `env-prefix-service`, not `env-prefix`
+    env::set_var("KUBOS_ENV_PREFIX_SERVICE__ADDR__PORT", "5555");
+    env::set_var("KUBOS_ENV_PREFIX_SERVICE_BUS", "/dev/ttyS1");
+
+    let config = kubos_system::Config::new_from_str("env-prefix", "");
+
+    assert_eq!(config.get("service_bus"), None);
+    assert_eq!(
+        config.hosturl(),
+        format!("{}:{}", kubos_system::DEFAULT_IP, kubos_system::DEFAULT_PORT)
+    );
+}
+
+#[test]
+fn env_override_not_unicode() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    env::set_var("KUBOS_ENV_UNICODE__BAD", OsStr::from_bytes(b"\xff\xfe"));
+    env::set_var("KUBOS_ENV_UNICODE__GOOD", "1");
+
+    let config = kubos_system::Config::new_from_str("env-unicode", "");
+
+    assert_eq!(config.get("bad"), None);
+    assert_eq!(config.get("good"), Some(Value::Integer(1)));
+}
+
+#[test]
+fn reload_file() {
+    let mut file = NamedTempFile::new().unwrap();
+    writeln!(file, "[category-1]\nhold_count = 1").unwrap();
+
+    let path = file.path().to_string_lossy().to_string();
+    let config = kubos_system::Config::new_from_path("category-1", path.clone());
+    assert_eq!(config.path(), Some(path.as_str()));
+    assert_eq!(config.get("hold_count"), Some(Value::Integer(1)));
+
+    writeln!(file, "bus = \"/dev/i2c-2\"").unwrap();
+
+    let config = config.reload().unwrap();
+    assert_eq!(config.get("hold_count"), Some(Value::Integer(1)));
+    assert_eq!(
+        config.get("bus"),
+        Some(Value::String("/dev/i2c-2".to_owned()))
+    );
+}
+
+#[test]
+fn reload_bad_file() {
+    let mut file = NamedTempFile::new().unwrap();
+    writeln!(file, "[category-1]\nhold_count = 1").unwrap();
+
+    let config = kubos_system::Config::new_from_path(
+        "category-1",
+        file.path().to_string_lossy().to_string(),
+    );
+
+    writeln!(file, "[[[not toml").unwrap();
+
+    match config.reload() {
+        Err(ConfigError::ParseError { .. }) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn watcher_hangup() {
+    let config = kubos_system::Config::new_from_str("category-1", "");
+    let mut watcher = kubos_system::ConfigWatcher::new(&config);
+    assert!(!watcher.changed(&config));
+
+    unsafe {
+        libc::raise(libc::SIGHUP);
+    }
+
+    assert!(watcher.changed(&config));
+    assert!(!watcher.changed(&config));
+}
@@ -0,0 +1,202 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+#![deny(warnings)]
+extern crate kubos_system;
+extern crate tempfile;
+
+use kubos_system::{ConfigError, Protocol, ServiceEndpoint, ServiceRegistry};
+use std::io::Write;
+use std::net::UdpSocket;
+use std::thread;
+use std::time::Duration;
+use tempfile::NamedTempFile;
+
+static CONFIG: &str = r#"
+    [my-app]
+    mode = "nominal"
+
+    [app-service.addr]
+    ip = "127.0.0.1"
+    port = 8000
+
+    [file-transfer-service.addr]
+    ip = "127.0.0.1"
+    port = 8008
+
+    [shell-service.addr]
+    ip = "127.0.0.1"
+    port = 8010
+
+    [telemetry-service]
+    direct_port = 8005
+
+    [telemetry-service.addr]
+    ip = "10.0.0.2"
+    port = 8006
+
+    [payload-service]
+    protocol = "file"
+
+    [payload-service.addr]
+    ip = "127.0.0.1"
+    port = 8200
+    "#;
+
+fn endpoint(name: &str, protocol: Protocol, addr: &str) -> ServiceEndpoint {
+    ServiceEndpoint {
+        name: name.to_owned(),
+        protocol,
+        addr: addr.to_owned(),
+    }
+}
+
+#[test]
+fn discover_services() {
+    let registry = ServiceRegistry::new_from_str(CONFIG).unwrap();
+
+    assert_eq!(
+        registry.services(),
+        &[
+            endpoint("app-service", Protocol::GraphQL, "127.0.0.1:8000"),
+            endpoint("file-transfer-service", Protocol::File, "127.0.0.1:8008"),
+            endpoint("payload-service", Protocol::File, "127.0.0.1:8200"),
+            endpoint("shell-service", Protocol::Shell, "127.0.0.1:8010"),
+            endpoint("telemetry-service", Protocol::GraphQL, "10.0.0.2:8006"),
+            endpoint("telemetry-service", Protocol::TelemetryDirect, "10.0.0.2:8005"),
+        ]
+    );
+}
+
+#[test]
+fn discover_find() {
+    let registry = ServiceRegistry::new_from_str(CONFIG).unwrap();
+
+    assert_eq!(
+        registry.find("telemetry-service"),
+        Some(&endpoint("telemetry-service", Protocol::GraphQL, "10.0.0.2:8006"))
+    );
+    assert_eq!(registry.find("my-app"), None);
+    assert_eq!(
+        registry.with_protocol(Protocol::TelemetryDirect),
+        vec![&endpoint("telemetry-service", Protocol::TelemetryDirect, "10.0.0.2:8005")]
+    );
+}
+
+#[test]
+fn discover_config() {
+    let registry = ServiceRegistry::new_from_str(CONFIG).unwrap();
+
+    let config = registry.config("telemetry-service").unwrap();
+    assert_eq!(config.hosturl(), "10.0.0.2:8006");
+    assert!(registry.config("my-app").is_none());
+}
+
+#[test]
+fn discover_bad_protocol() {
+    let registry = ServiceRegistry::new_from_str(
+        r#"
+    [payload-service]
+    protocol = "carrier-pigeon"
+
+    [payload-service.addr]
+    port = 8200
+
+    [app-service.addr]
+    ip = "127.0.0.1"
+    port = 8000
+    "#,
+    ).unwrap();
+
+    // Only the bad service is left out
+    assert_eq!(
+        registry.services(),
+        &[endpoint("app-service", Protocol::GraphQL, "127.0.0.1:8000")]
+    );
+    assert!(registry.find("payload-service").is_none());
+    assert_eq!(
+        registry.errors(),
+        &[ConfigError::ValidationError {
+            name: "payload-service".to_owned(),
+            err: "Unknown protocol: carrier-pigeon".to_owned(),
+        }]
+    );
+}
+
+#[test]
+fn discover_from_file() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(file, "{}", CONFIG).unwrap();
+
+    let registry =
+        ServiceRegistry::new_from_path(&file.path().to_string_lossy().to_string()).unwrap();
+    assert_eq!(registry.services().len(), 6);
+
+    let config = registry.config("app-service").unwrap();
+    assert_eq!(config.path(), Some(file.path().to_string_lossy().as_ref()));
+}
+
+#[test]
+fn discover_missing_file() {
+    assert!(ServiceRegistry::new_from_path("/not/a/real/config.toml").is_err());
+}
+
+#[test]
+fn ping_alive() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let mut buf = [0; 4096];
+        let (_, peer) = socket.recv_from(&mut buf).unwrap();
+        socket
+            .send_to(br#"{"msg": {"ping": "pong"}, "errs": ""}"#, peer)
+            .unwrap();
+    });
+
+    let registry = ServiceRegistry::new_from_str(&format!(
+        "[mock-service.addr]\nip = \"{}\"\nport = {}",
+        addr.ip(),
+        addr.port()
+    )).unwrap();
+
+    assert!(registry.ping("mock-service", Duration::from_secs(1)).is_ok());
+}
+
+#[test]
+fn ping_no_response() {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+
+    let registry = ServiceRegistry::new_from_str(&format!(
+        "[mock-service.addr]\nip = \"{}\"\nport = {}",
+        addr.ip(),
+        addr.port()
+    )).unwrap();
+
+    assert!(
+        registry
+            .ping("mock-service", Duration::from_millis(100))
+            .is_err()
+    );
+}
+
+#[test]
+fn ping_unsupported() {
+    let registry = ServiceRegistry::new_from_str(CONFIG).unwrap();
+
+    assert!(registry.ping("shell-service", Duration::from_millis(100)).is_err());
+    assert!(registry.ping("unknown-service", Duration::from_millis(100)).is_err());
+}
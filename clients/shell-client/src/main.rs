@@ -0,0 +1,409 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+extern crate channel_protocol;
+extern crate clap;
+#[macro_use]
+extern crate failure;
+extern crate libc;
+extern crate shell_protocol;
+
+use channel_protocol::{ChannelProtocol, ProtocolError};
+use clap::{App, AppSettings, Arg};
+use shell_protocol::messages::{self, Message, SpawnOptions, MAX_DATA_SIZE, MESSAGE_SIZE};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::process;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often to check for a change in the local terminal's size
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// How long to wait for the shell service to respond to a request
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Puts the local terminal in raw mode, so that every key press (including control
+// characters like Ctrl-C) is passed through to the remote process. The terminal's
+// original settings are restored when this is dropped.
+struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    // Returns `None` if stdin isn't a terminal
+    fn enable() -> Option<RawMode> {
+        unsafe {
+            let mut original: libc::termios = mem::zeroed();
+            if libc::isatty(libc::STDIN_FILENO) == 0
+                || libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0
+            {
+                return None;
+            }
+
+            let mut raw = original;
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return None;
+            }
+
+            Some(RawMode { original })
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+// The size of the local terminal, as (columns, rows)
+fn window_size() -> Option<(u16, u16)> {
+    let mut size: libc::winsize = unsafe { mem::zeroed() };
+    match unsafe { libc::ioctl(libc::STDIN_FILENO, libc::TIOCGWINSZ, &mut size) } {
+        0 => Some((size.ws_col, size.ws_row)),
+        _ => None,
+    }
+}
+
+// How to start a session
+enum Request<'a> {
+    Spawn {
+        command: &'a str,
+        options: SpawnOptions,
+    },
+    Attach {
+        channel_id: u32,
+    },
+}
+
+// Sends everything read from stdin to the remote process, closing its stdin at end-of-file.
+// The main thread is busy receiving, so this uses its own socket.
+fn forward_stdin(host_ip: &str, remote_addr: &str, channel_id: u32) -> Result<(), failure::Error> {
+    let protocol = ChannelProtocol::new(host_ip, remote_addr, MESSAGE_SIZE as u32);
+    let mut stdin = io::stdin();
+    let mut buffer = vec![0; MAX_DATA_SIZE];
+
+    loop {
+        let count = stdin.read(&mut buffer)?;
+        if count == 0 {
+            protocol.send(messages::stdin::to_cbor(channel_id, None)?)?;
+            return Ok(());
+        }
+
+        protocol.send(messages::stdin::to_cbor(channel_id, Some(&buffer[..count]))?)?;
+    }
+}
+
+// Waits for the next message on a channel
+fn recv(protocol: &ChannelProtocol, channel_id: u32) -> Result<Message, failure::Error> {
+    let start = Instant::now();
+    while start.elapsed() < RESPONSE_TIMEOUT {
+        match protocol.recv_message(Some(RESPONSE_TIMEOUT)) {
+            Ok(ref message) if message.channel_id != channel_id => continue,
+            Ok(message) => return Ok(messages::parse_message(message)?),
+            Err(ProtocolError::ReceiveTimeout) => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    bail!("No response from the shell service")
+}
+
+// Prints the processes running in the shell service
+fn list(host_ip: &str, remote_addr: &str) -> Result<(), failure::Error> {
+    let channel_id = channel_protocol::generate_channel();
+    let protocol = ChannelProtocol::new(host_ip, remote_addr, MESSAGE_SIZE as u32);
+
+    protocol.send(messages::list::to_cbor(channel_id, None)?)?;
+
+    loop {
+        if let Message::List {
+            process_list: Some(process_list),
+            ..
+        } = recv(&protocol, channel_id)?
+        {
+            let mut process_list: Vec<_> = process_list.into_iter().collect();
+            process_list.sort_by_key(|&(_, ref info)| info.runtime);
+
+            println!("{:>8} {:>8} {:>10}  {}", "CHANNEL", "PID", "RUNTIME", "COMMAND");
+            for (channel_id, info) in process_list {
+                println!(
+                    "{:>8} {:>8} {:>9}s  {}",
+                    channel_id, info.pid, info.runtime, info.path
+                );
+            }
+            return Ok(());
+        }
+    }
+}
+
+// Sends a signal to a process running in the shell service
+fn kill(
+    host_ip: &str,
+    remote_addr: &str,
+    channel_id: u32,
+    signal: Option<u32>,
+) -> Result<(), failure::Error> {
+    let protocol = ChannelProtocol::new(host_ip, remote_addr, MESSAGE_SIZE as u32);
+    protocol.send(messages::kill::to_cbor(channel_id, signal)?)?;
+    Ok(())
+}
+
+// Runs a command with the shell service, or attaches to one which is already running,
+// returning its exit code and signal
+fn run(
+    host_ip: &str,
+    remote_addr: &str,
+    request: Request,
+    pty: bool,
+) -> Result<(u32, u32), failure::Error> {
+    let protocol = ChannelProtocol::new(host_ip, remote_addr, MESSAGE_SIZE as u32);
+
+    let channel_id = match request {
+        Request::Spawn { command, options } => {
+            let channel_id = channel_protocol::generate_channel();
+            protocol.send(messages::spawn::to_cbor(channel_id, command, &options)?)?;
+            channel_id
+        }
+        Request::Attach { channel_id } => {
+            protocol.send(messages::attach::to_cbor(channel_id)?)?;
+            channel_id
+        }
+    };
+
+    let start = Instant::now();
+    let mut raw_mode = None;
+    let mut size = None;
+    let mut started = false;
+    let mut stdout = io::stdout();
+    let mut stderr = io::stderr();
+
+    loop {
+        if !started && start.elapsed() > RESPONSE_TIMEOUT {
+            bail!("No response from the shell service at {}", remote_addr);
+        }
+
+        if started && pty {
+            let current = window_size();
+            if current != size {
+                if let Some((columns, rows)) = current {
+                    protocol.send(messages::resize::to_cbor(channel_id, columns, rows)?)?;
+                }
+                size = current;
+            }
+        }
+
+        let message = match protocol.recv_message(Some(POLL_INTERVAL)) {
+            Ok(message) => message,
+            Err(ProtocolError::ReceiveTimeout) => continue,
+            Err(err) => return Err(err.into()),
+        };
+        if message.channel_id != channel_id {
+            continue;
+        }
+
+        match messages::parse_message(message)? {
+            Message::Pid { .. } => {
+                if pty {
+                    raw_mode = RawMode::enable();
+                }
+
+                let host_ip = host_ip.to_owned();
+                let remote_addr = remote_addr.to_owned();
+                thread::spawn(move || {
+                    if let Err(err) = forward_stdin(&host_ip, &remote_addr, channel_id) {
+                        eprint!("Failed to send input: {}\r\n", err);
+                    }
+                });
+                started = true;
+            }
+            Message::Stdout {
+                data: Some(data), ..
+            } => {
+                stdout.write_all(&data)?;
+                stdout.flush()?;
+            }
+            Message::Stderr {
+                data: Some(data), ..
+            } => {
+                stderr.write_all(&data)?;
+                stderr.flush()?;
+            }
+            Message::Error { message, .. } => {
+                drop(raw_mode);
+                bail!("{}", message);
+            }
+            Message::Exit { code, signal, .. } => {
+                drop(raw_mode);
+                return Ok((code, signal));
+            }
+            _ => {}
+        }
+    }
+}
+
+// Checks that an argument is a number which fits in `T`
+fn is_number<T: FromStr>(value: String) -> Result<(), String> {
+    value
+        .parse::<T>()
+        .map(|_| ())
+        .map_err(|_| format!("{} is not a valid number", value))
+}
+
+fn main() {
+    let args = App::new("Shell client")
+        .setting(AppSettings::TrailingVarArg)
+        .arg(
+            Arg::with_name("host_ip")
+                .short("h")
+                .takes_value(true)
+                .default_value("0.0.0.0"),
+        ).arg(
+            Arg::with_name("remote_ip")
+                .short("-r")
+                .takes_value(true)
+                .default_value("0.0.0.0"),
+        ).arg(
+            Arg::with_name("remote_port")
+                .short("-p")
+                .takes_value(true)
+                .default_value("6000"),
+        ).arg(Arg::with_name("no_pty").short("-n"))
+        .arg(Arg::with_name("cwd").short("-d").takes_value(true))
+        .arg(
+            Arg::with_name("env")
+                .short("-e")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        ).arg(
+            Arg::with_name("uid")
+                .short("-u")
+                .takes_value(true)
+                .validator(is_number::<u32>),
+        ).arg(
+            Arg::with_name("timeout")
+                .short("-t")
+                .takes_value(true)
+                .validator(is_number::<u64>),
+        )
+        .arg(
+            Arg::with_name("list")
+                .short("-l")
+                .conflicts_with_all(&["attach", "kill"]),
+        ).arg(
+            Arg::with_name("attach")
+                .short("-a")
+                .takes_value(true)
+                .validator(is_number::<u32>)
+                .conflicts_with("kill"),
+        ).arg(
+            Arg::with_name("kill")
+                .short("-k")
+                .takes_value(true)
+                .validator(is_number::<u32>),
+        ).arg(
+            Arg::with_name("signal")
+                .short("-s")
+                .takes_value(true)
+                .validator(is_number::<u32>)
+                .requires("kill"),
+        )
+        .arg(
+            Arg::with_name("command")
+                .index(1)
+                .default_value("/bin/sh"),
+        ).arg(Arg::with_name("args").index(2).multiple(true))
+        .get_matches();
+
+    let host_ip = args.value_of("host_ip").unwrap();
+    let remote_addr = format!(
+        "{}:{}",
+        args.value_of("remote_ip").unwrap(),
+        args.value_of("remote_port").unwrap()
+    );
+    let command = args.value_of("command").unwrap();
+    // Numeric arguments have already been checked by their validators
+    let command_args: Vec<String> = args
+        .values_of("args")
+        .map(|values| values.map(|value| value.to_owned()).collect())
+        .unwrap_or_default();
+    // A pseudo terminal is only useful if there is a local terminal to drive it
+    let pty = !args.is_present("no_pty") && unsafe { libc::isatty(libc::STDIN_FILENO) } != 0;
+
+    if args.is_present("list") {
+        if let Err(err) = list(host_ip, &remote_addr) {
+            eprintln!("Failed to list processes: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(channel_id) = args.value_of("kill") {
+        let signal = args.value_of("signal").map(|signal| signal.parse().unwrap());
+        if let Err(err) = kill(host_ip, &remote_addr, channel_id.parse().unwrap(), signal) {
+            eprintln!("Failed to send signal: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let request = match args.value_of("attach") {
+        Some(channel_id) => Request::Attach {
+            channel_id: channel_id.parse().unwrap(),
+        },
+        None => {
+            let env: HashMap<String, String> = args
+                .values_of("env")
+                .map(|values| {
+                    values
+                        .map(|value| {
+                            let mut parts = value.splitn(2, '=');
+                            let name = parts.next().unwrap().to_owned();
+                            (name, parts.next().unwrap_or("").to_owned())
+                        }).collect()
+                }).unwrap_or_default();
+
+            Request::Spawn {
+                command,
+                options: SpawnOptions {
+                    args: Some(command_args),
+                    pty,
+                    cwd: args.value_of("cwd").map(|cwd| cwd.to_owned()),
+                    env: if env.is_empty() { None } else { Some(env) },
+                    uid: args.value_of("uid").map(|uid| uid.parse().unwrap()),
+                    timeout: args.value_of("timeout").map(|timeout| timeout.parse().unwrap()),
+                },
+            }
+        }
+    };
+
+    match run(host_ip, &remote_addr, request, pty) {
+        Ok((code, 0)) => process::exit(code as i32),
+        Ok((_, signal)) => {
+            eprintln!("Remote process killed by signal {}", signal);
+            process::exit(128 + signal as i32);
+        }
+        Err(err) => {
+            eprintln!("Shell session failed: {}", err);
+            process::exit(1);
+        }
+    }
+}
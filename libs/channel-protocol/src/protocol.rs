@@ -96,6 +96,16 @@ impl Protocol {
         Ok(())
     }
 
+    /// Change the destination of future messages
+    ///
+    /// # Arguments
+    ///
+    /// * remote_addr - The remote IP and port to communicate with
+    ///
+    pub fn set_remote_addr(&self, remote_addr: SocketAddr) {
+        self.remote_addr.set(remote_addr);
+    }
+
     /// Receive a raw cbor message message
     ///
     /// # Arguments
@@ -46,6 +46,14 @@ pub enum ProtocolError {
         /// Underlying error encountered
         err: String,
     },
+    /// A process was requested with an option which can't be used
+    #[fail(display = "Invalid spawn option {}: {}", option, err)]
+    InvalidSpawnOption {
+        /// The name of the option
+        option: String,
+        /// What is wrong with it
+        err: String,
+    },
     /// A general error was raised by the process
     #[fail(display = "Process error when {}: {}", action, err)]
     ProcesssError { action: String, err: io::Error },
@@ -58,6 +66,12 @@ pub enum ProtocolError {
         /// Underlying error encountered
         err: String,
     },
+    /// A session ran out of time, and its process was terminated
+    #[fail(display = "Session timed out: {}", reason)]
+    SessionTimeout {
+        /// Why the session timed out
+        reason: String,
+    },
     /// An error was encountered when spawning a process
     #[fail(display = "Error spawning command {}: {}", cmd, err)]
     SpawnError {
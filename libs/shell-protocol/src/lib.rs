@@ -18,6 +18,7 @@ extern crate cbor_protocol;
 extern crate channel_protocol;
 #[macro_use]
 extern crate failure;
+extern crate libc;
 #[macro_use]
 extern crate log;
 extern crate rand;
@@ -31,3 +32,4 @@ mod protocol;
 
 pub use error::ProtocolError;
 pub use protocol::Protocol as ShellProtocol;
+pub use protocol::{Session, SessionList};
@@ -0,0 +1,88 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use super::*;
+use channel_protocol::ChannelMessage;
+use error::ProtocolError;
+use serde_cbor::ser;
+
+pub fn from_cbor(message: &ChannelMessage) -> Result<Message, ProtocolError> {
+    let signal = match message.payload.get(0) {
+        Some(Value::U64(signal)) => Some(*signal as u32),
+        _ => None,
+    };
+
+    Ok(Message::Kill {
+        channel_id: message.channel_id,
+        signal: signal,
+    })
+}
+
+pub fn to_cbor(channel_id: u32, signal: Option<u32>) -> Result<Vec<u8>, ProtocolError> {
+    info!("-> {{ {}, kill, {:?} }}", channel_id, signal);
+
+    let result = match signal {
+        Some(signal) => ser::to_vec_packed(&(channel_id, "kill", signal)),
+        None => ser::to_vec_packed(&(channel_id, "kill")),
+    };
+
+    Ok(result.map_err(|err| ProtocolError::MessageCreationError {
+        message: "kill".to_owned(),
+        err,
+    })?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use channel_protocol;
+    use serde_cbor::de;
+
+    #[test]
+    fn create_parse_message() {
+        let channel_id = 13;
+        let signal = Some(9);
+
+        let raw = to_cbor(channel_id, signal).unwrap();
+        let parsed = channel_protocol::parse_message(de::from_slice(&raw).unwrap()).unwrap();
+        let msg = parse_message(parsed);
+
+        assert_eq!(
+            msg.unwrap(),
+            Message::Kill {
+                channel_id: channel_id,
+                signal: signal,
+            }
+        );
+    }
+
+    #[test]
+    fn create_parse_message_default() {
+        let channel_id = 13;
+
+        let raw = to_cbor(channel_id, None).unwrap();
+        let parsed = channel_protocol::parse_message(de::from_slice(&raw).unwrap()).unwrap();
+        let msg = parse_message(parsed);
+
+        assert_eq!(
+            msg.unwrap(),
+            Message::Kill {
+                channel_id: channel_id,
+                signal: None,
+            }
+        );
+    }
+}
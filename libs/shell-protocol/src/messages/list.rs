@@ -0,0 +1,150 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use super::*;
+use channel_protocol::ChannelMessage;
+use error::ProtocolError;
+use serde_cbor::{ser, ObjectKey};
+use std::collections::{BTreeMap, HashMap};
+
+fn parse_info(raw: &BTreeMap<ObjectKey, Value>) -> Option<ProcessInfo> {
+    let field = |name: &str| raw.get(&ObjectKey::String(name.to_owned()));
+
+    Some(ProcessInfo {
+        path: field("path")?.as_string()?.to_owned(),
+        pid: field("pid")?.as_u64()? as u32,
+        runtime: field("runtime").and_then(|runtime| runtime.as_u64()).unwrap_or(0),
+    })
+}
+
+pub fn from_cbor(message: &ChannelMessage) -> Result<Message, ProtocolError> {
+    let process_list = match message.payload.get(0) {
+        Some(Value::Object(raw_list)) => {
+            let mut process_list = HashMap::new();
+            for (key, value) in raw_list {
+                let channel_id = match key {
+                    ObjectKey::Integer(channel_id) => *channel_id as u32,
+                    _ => continue,
+                };
+                if let Some(info) = value.as_object().and_then(parse_info) {
+                    process_list.insert(channel_id, info);
+                }
+            }
+            Some(process_list)
+        }
+        _ => None,
+    };
+
+    Ok(Message::List {
+        channel_id: message.channel_id,
+        process_list: process_list,
+    })
+}
+
+pub fn to_cbor(
+    channel_id: u32,
+    process_list: Option<&HashMap<u32, ProcessInfo>>,
+) -> Result<Vec<u8>, ProtocolError> {
+    info!("-> {{ {}, list, {:?} }}", channel_id, process_list);
+
+    let result = match process_list {
+        Some(process_list) => {
+            let raw_list: BTreeMap<ObjectKey, Value> = process_list
+                .iter()
+                .map(|(channel_id, info)| {
+                    let mut raw = BTreeMap::new();
+                    raw.insert(
+                        ObjectKey::String("path".to_owned()),
+                        Value::String(info.path.to_owned()),
+                    );
+                    raw.insert(
+                        ObjectKey::String("pid".to_owned()),
+                        Value::U64(info.pid as u64),
+                    );
+                    raw.insert(
+                        ObjectKey::String("runtime".to_owned()),
+                        Value::U64(info.runtime),
+                    );
+                    (ObjectKey::Integer(*channel_id as i64), Value::Object(raw))
+                }).collect();
+            ser::to_vec_packed(&(channel_id, "list", Value::Object(raw_list)))
+        }
+        None => ser::to_vec_packed(&(channel_id, "list")),
+    };
+
+    Ok(result.map_err(|err| ProtocolError::MessageCreationError {
+        message: "list".to_owned(),
+        err,
+    })?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use channel_protocol;
+    use serde_cbor::de;
+
+    #[test]
+    fn create_parse_request() {
+        let channel_id = 13;
+
+        let raw = to_cbor(channel_id, None).unwrap();
+        let parsed = channel_protocol::parse_message(de::from_slice(&raw).unwrap()).unwrap();
+        let msg = parse_message(parsed);
+
+        assert_eq!(
+            msg.unwrap(),
+            Message::List {
+                channel_id: channel_id,
+                process_list: None,
+            }
+        );
+    }
+
+    #[test]
+    fn create_parse_list() {
+        let channel_id = 13;
+        let mut process_list = HashMap::new();
+        process_list.insert(
+            12,
+            ProcessInfo {
+                path: "/bin/sh".to_owned(),
+                pid: 45,
+                runtime: 300,
+            },
+        );
+        process_list.insert(
+            14,
+            ProcessInfo {
+                path: "top".to_owned(),
+                pid: 50,
+                runtime: 2,
+            },
+        );
+
+        let raw = to_cbor(channel_id, Some(&process_list)).unwrap();
+        let parsed = channel_protocol::parse_message(de::from_slice(&raw).unwrap()).unwrap();
+        let msg = parse_message(parsed);
+
+        assert_eq!(
+            msg.unwrap(),
+            Message::List {
+                channel_id: channel_id,
+                process_list: Some(process_list),
+            }
+        );
+    }
+}
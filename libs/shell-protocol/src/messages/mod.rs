@@ -17,14 +17,73 @@
 use channel_protocol::ChannelMessage;
 use error::ProtocolError;
 use serde_cbor::Value;
+use std::collections::HashMap;
+
+/// The size of the buffer shell protocol messages are received into
+pub const MESSAGE_SIZE: usize = 4096;
+/// The most stdio data sent in a single message, leaving room for the rest of the message
+pub const MAX_DATA_SIZE: usize = MESSAGE_SIZE - 64;
+
+/// Information about a process running in the shell service
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ProcessInfo {
+    /// The command the process was spawned with
+    pub path: String,
+    /// The ID of the process
+    pub pid: u32,
+    /// How long the process has been running, in seconds
+    pub runtime: u64,
+}
+
+/// How a process should be spawned
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SpawnOptions {
+    /// Arguments to pass to the process
+    pub args: Option<Vec<String>>,
+    /// Whether the process should be run in a new pseudo terminal
+    pub pty: bool,
+    /// The working directory of the process
+    pub cwd: Option<String>,
+    /// Environment variables to set for the process, in addition to the shell service's own
+    pub env: Option<HashMap<String, String>>,
+    /// The user ID to run the process as
+    pub uid: Option<u32>,
+    /// How long, in seconds, the process may run before it is terminated
+    pub timeout: Option<u64>,
+}
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum Message {
+    /// This message is sent to the shell service to direct a process' output to the sender,
+    /// taking over the session from its previous client.
+    Attach {
+        channel_id: u32,
+        /// The address of the new client. This is filled in by the shell service.
+        address: Option<String>,
+    },
+    /// This message is sent from the shell service when it can't carry out a request,
+    /// or a session ends early.
+    Error {
+        channel_id: u32,
+        message: String,
+    },
     Exit {
         channel_id: u32,
         code: u32,
         signal: u32,
     },
+    /// This message is sent to the shell service to send a signal to a process.
+    /// If no signal is given, `SIGTERM` is sent.
+    Kill {
+        channel_id: u32,
+        signal: Option<u32>,
+    },
+    /// This message is sent to the shell service to request a list of its processes.
+    /// The shell service replies with the same message, containing the list.
+    List {
+        channel_id: u32,
+        process_list: Option<HashMap<u32, ProcessInfo>>,
+    },
     Pid {
         channel_id: u32,
         pid: u32,
@@ -33,41 +92,70 @@ pub enum Message {
     Spawn {
         channel_id: u32,
         command: String,
-        args: Option<Vec<String>>,
+        options: SpawnOptions,
         // TODO: Add these options:
-        // - pty - boolean specifying need for a pty
-        // - env - list of environment variables
-        // - cwd - current working directory of process
-        // - uid - uid of process
         // - gid - gid of processs
         // - detached - boolean specifying if child process should be detached
     },
+    /// This message is sent to the shell service to set the window size of a process' pseudo terminal.
+    Resize {
+        channel_id: u32,
+        columns: u16,
+        rows: u16,
+    },
     /// This message is sent from the shell service when a process has produced data via stdout.
     Stdout {
         channel_id: u32,
-        data: Option<String>,
+        data: Option<Vec<u8>>,
     },
     Stderr {
         channel_id: u32,
-        data: Option<String>,
+        data: Option<Vec<u8>>,
     },
     Stdin {
         channel_id: u32,
-        data: Option<String>,
+        data: Option<Vec<u8>>,
     },
 }
 
+pub mod attach;
+pub mod error;
 pub mod exit;
+pub mod kill;
+pub mod list;
 pub mod pid;
+pub mod resize;
 pub mod spawn;
 pub mod stderr;
 pub mod stdin;
 pub mod stdout;
 
+// Stdio data is sent as a byte string, but older clients and services send text strings
+fn parse_data(message: &ChannelMessage) -> Option<Vec<u8>> {
+    match message.payload.get(0) {
+        Some(Value::Bytes(data)) => Some(data.to_owned()),
+        Some(Value::String(data)) => Some(data.as_bytes().to_vec()),
+        _ => None,
+    }
+}
+
+// Stdio data, encoded as a CBOR byte string
+fn data_value(data: Option<&[u8]>) -> Value {
+    match data {
+        Some(data) => Value::Bytes(data.to_vec()),
+        None => Value::Null,
+    }
+}
+
 pub fn parse_message(message: ChannelMessage) -> Result<Message, ProtocolError> {
     match message.name.as_ref() {
+        "attach" => Ok(attach::from_cbor(&message)?),
+        "error" => Ok(error::from_cbor(&message)?),
         "exit" => Ok(exit::from_cbor(&message)?),
+        "kill" => Ok(kill::from_cbor(&message)?),
+        "list" => Ok(list::from_cbor(&message)?),
         "pid" => Ok(pid::from_cbor(&message)?),
+        "resize" => Ok(resize::from_cbor(&message)?),
         "spawn" => Ok(spawn::from_cbor(&message)?),
         "stderr" => Ok(stderr::from_cbor(&message)?),
         "stdin" => Ok(stdin::from_cbor(&message)?),
@@ -0,0 +1,86 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use super::*;
+use channel_protocol::ChannelMessage;
+use error::ProtocolError;
+use serde_cbor::ser;
+
+pub fn from_cbor(message: &ChannelMessage) -> Result<Message, ProtocolError> {
+    let columns = *(match message.payload.get(0) {
+        Some(Value::U64(data)) => data,
+        _ => {
+            return Err(ProtocolError::MessageParseError {
+                err: "No columns found".to_owned(),
+            })
+        }
+    }) as u16;
+
+    let rows = *(match message.payload.get(1) {
+        Some(Value::U64(data)) => data,
+        _ => {
+            return Err(ProtocolError::MessageParseError {
+                err: "No rows found".to_owned(),
+            })
+        }
+    }) as u16;
+
+    Ok(Message::Resize {
+        channel_id: message.channel_id,
+        columns: columns,
+        rows: rows,
+    })
+}
+
+pub fn to_cbor(channel_id: u32, columns: u16, rows: u16) -> Result<Vec<u8>, ProtocolError> {
+    info!("-> {{ {}, resize, {}, {} }}", channel_id, columns, rows);
+
+    Ok(
+        ser::to_vec_packed(&(channel_id, "resize", columns, rows)).map_err(|err| {
+            ProtocolError::MessageCreationError {
+                message: "resize".to_owned(),
+                err,
+            }
+        })?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use channel_protocol;
+    use serde_cbor::de;
+
+    #[test]
+    fn create_parse_message() {
+        let channel_id = 13;
+        let columns = 80;
+        let rows = 24;
+
+        let raw = to_cbor(channel_id, columns, rows).unwrap();
+        let parsed = channel_protocol::parse_message(de::from_slice(&raw).unwrap()).unwrap();
+        let msg = parse_message(parsed);
+
+        assert_eq!(
+            msg.unwrap(),
+            Message::Resize {
+                channel_id: channel_id,
+                columns: columns,
+                rows: rows,
+            }
+        );
+    }
+}
@@ -20,9 +20,31 @@ use error::ProtocolError;
 use serde_cbor::{ser, ObjectKey};
 use std::collections::BTreeMap;
 
+// An option which was given a value of the wrong type
+fn invalid(option: &str, expected: &str) -> ProtocolError {
+    ProtocolError::InvalidSpawnOption {
+        option: option.to_owned(),
+        err: format!("must be {}", expected),
+    }
+}
+
+// Parses the process' environment variables from a map of names to values
+fn parse_env(
+    raw_env: &BTreeMap<ObjectKey, Value>,
+) -> Result<HashMap<String, String>, ProtocolError> {
+    raw_env
+        .iter()
+        .map(|(name, value)| match (name, value) {
+            (ObjectKey::String(name), Value::String(value)) => {
+                Ok((name.to_owned(), value.to_owned()))
+            }
+            _ => Err(invalid("env", "a map of strings")),
+        }).collect()
+}
+
 /// CBOR -> Message::Spawn
 pub fn from_cbor(message: &ChannelMessage) -> Result<Message, ProtocolError> {
-    let mut args: Option<Vec<String>> = None;
+    let mut options = SpawnOptions::default();
 
     let command = match message.payload.get(0) {
         Some(Value::String(command)) => command,
@@ -34,27 +56,55 @@ pub fn from_cbor(message: &ChannelMessage) -> Result<Message, ProtocolError> {
     };
 
     // Parse out options
-    match message.payload.get(1) {
-        Some(Value::Object(raw_options)) => {
-            // Parse out command arguments
-            args = match raw_options.get(&ObjectKey::String("args".to_owned())) {
-                Some(Value::Array(args)) => Some(
-                    args.to_vec()
-                        .iter()
-                        .filter_map(|s| s.as_string())
-                        .map(|s| s.to_owned())
-                        .collect(),
-                ),
-                _ => None,
-            };
+    if let Some(Value::Object(raw_options)) = message.payload.get(1) {
+        let option = |name: &str| raw_options.get(&ObjectKey::String(name.to_owned()));
+
+        // Parse out command arguments
+        options.args = match option("args") {
+            Some(Value::Array(args)) => Some(
+                args.to_vec()
+                    .iter()
+                    .filter_map(|s| s.as_string())
+                    .map(|s| s.to_owned())
+                    .collect(),
+            ),
+            _ => None,
+        };
+
+        // Parse out pseudo terminal request
+        if let Some(Value::Bool(value)) = option("pty") {
+            options.pty = *value;
         }
-        _ => {}
-    };
+
+        options.cwd = match option("cwd") {
+            Some(Value::String(cwd)) => Some(cwd.to_owned()),
+            Some(_) => return Err(invalid("cwd", "a string")),
+            None => None,
+        };
+
+        options.env = match option("env") {
+            Some(Value::Object(env)) => Some(parse_env(env)?),
+            Some(_) => return Err(invalid("env", "a map of strings")),
+            None => None,
+        };
+
+        options.uid = match option("uid") {
+            Some(Value::U64(uid)) if *uid <= u64::from(u32::max_value()) => Some(*uid as u32),
+            Some(_) => return Err(invalid("uid", "a user ID")),
+            None => None,
+        };
+
+        options.timeout = match option("timeout") {
+            Some(Value::U64(timeout)) if *timeout > 0 => Some(*timeout),
+            Some(_) => return Err(invalid("timeout", "a positive number of seconds")),
+            None => None,
+        };
+    }
 
     Ok(Message::Spawn {
         channel_id: message.channel_id,
         command: command.to_owned(),
-        args: args,
+        options,
     })
 }
 
@@ -62,17 +112,44 @@ pub fn from_cbor(message: &ChannelMessage) -> Result<Message, ProtocolError> {
 pub fn to_cbor(
     channel_id: u32,
     command: &str,
-    args: Option<&[String]>,
+    spawn_options: &SpawnOptions,
 ) -> Result<Vec<u8>, ProtocolError> {
     info!("-> {{ {}, spawn, {} }}", channel_id, command);
     let mut options = BTreeMap::new();
-    if let Some(args) = args {
+    let mut insert = |name: &str, value| {
+        options.insert(ObjectKey::String(name.to_owned()), value);
+    };
+
+    if let Some(ref args) = spawn_options.args {
         let args_vec = args
             .to_vec()
             .iter()
             .map(|s| Value::String(s.to_owned()))
             .collect();
-        options.insert(ObjectKey::String("args".to_owned()), Value::Array(args_vec));
+        insert("args", Value::Array(args_vec));
+    }
+    if spawn_options.pty {
+        insert("pty", Value::Bool(true));
+    }
+    if let Some(ref cwd) = spawn_options.cwd {
+        insert("cwd", Value::String(cwd.to_owned()));
+    }
+    if let Some(ref env) = spawn_options.env {
+        let env_map = env
+            .iter()
+            .map(|(name, value)| {
+                (
+                    ObjectKey::String(name.to_owned()),
+                    Value::String(value.to_owned()),
+                )
+            }).collect();
+        insert("env", Value::Object(env_map));
+    }
+    if let Some(uid) = spawn_options.uid {
+        insert("uid", Value::U64(u64::from(uid)));
+    }
+    if let Some(timeout) = spawn_options.timeout {
+        insert("timeout", Value::U64(timeout));
     }
 
     Ok(
@@ -96,7 +173,7 @@ mod tests {
         let channel_id = 10;
         let command = "/bin/pwd";
 
-        let raw = to_cbor(channel_id, command, None).unwrap();
+        let raw = to_cbor(channel_id, command, &SpawnOptions::default()).unwrap();
         let parsed = channel_protocol::parse_message(de::from_slice(&raw).unwrap()).unwrap();
         let msg = parse_message(parsed);
 
@@ -105,7 +182,7 @@ mod tests {
             Message::Spawn {
                 channel_id: channel_id,
                 command: command.to_owned(),
-                args: None
+                options: SpawnOptions::default(),
             }
         );
     }
@@ -116,7 +193,12 @@ mod tests {
         let command = "/bin/sleep";
         let args: Vec<String> = vec!["100".to_owned()];
 
-        let raw = to_cbor(channel_id, command, Some(&args)).unwrap();
+        let options = SpawnOptions {
+            args: Some(args),
+            ..Default::default()
+        };
+
+        let raw = to_cbor(channel_id, command, &options).unwrap();
         let parsed = channel_protocol::parse_message(de::from_slice(&raw).unwrap()).unwrap();
         let msg = parse_message(parsed);
 
@@ -125,7 +207,7 @@ mod tests {
             Message::Spawn {
                 channel_id: channel_id,
                 command: command.to_owned(),
-                args: Some(args)
+                options: options,
             }
         );
     }
@@ -136,7 +218,12 @@ mod tests {
         let command = "/usr/bin/echo";
         let args: Vec<String> = vec!["hello".to_owned(), "world".to_owned()];
 
-        let raw = to_cbor(channel_id, command, Some(&args)).unwrap();
+        let options = SpawnOptions {
+            args: Some(args),
+            ..Default::default()
+        };
+
+        let raw = to_cbor(channel_id, command, &options).unwrap();
         let parsed = channel_protocol::parse_message(de::from_slice(&raw).unwrap()).unwrap();
         let msg = parse_message(parsed);
 
@@ -145,8 +232,92 @@ mod tests {
             Message::Spawn {
                 channel_id: channel_id,
                 command: command.to_owned(),
-                args: Some(args)
+                options: options,
             }
         );
     }
+
+    #[test]
+    fn create_parse_spawn_pty() {
+        let channel_id = 10;
+        let command = "/bin/sh";
+        let args: Vec<String> = vec!["-l".to_owned()];
+
+        let options = SpawnOptions {
+            args: Some(args),
+            pty: true,
+            ..Default::default()
+        };
+
+        let raw = to_cbor(channel_id, command, &options).unwrap();
+        let parsed = channel_protocol::parse_message(de::from_slice(&raw).unwrap()).unwrap();
+        let msg = parse_message(parsed);
+
+        assert_eq!(
+            msg.unwrap(),
+            Message::Spawn {
+                channel_id: channel_id,
+                command: command.to_owned(),
+                options: options,
+            }
+        );
+    }
+
+    #[test]
+    fn create_parse_spawn_all_options() {
+        let channel_id = 10;
+        let command = "make";
+        let mut env = HashMap::new();
+        env.insert("PATH".to_owned(), "/home/kubos/bin:/usr/bin:/bin".to_owned());
+        env.insert("CC".to_owned(), "gcc".to_owned());
+        let options = SpawnOptions {
+            args: Some(vec!["all".to_owned()]),
+            pty: false,
+            cwd: Some("/home/kubos/project".to_owned()),
+            env: Some(env),
+            uid: Some(1000),
+            timeout: Some(60),
+        };
+
+        let raw = to_cbor(channel_id, command, &options).unwrap();
+        let parsed = channel_protocol::parse_message(de::from_slice(&raw).unwrap()).unwrap();
+        let msg = parse_message(parsed);
+
+        assert_eq!(
+            msg.unwrap(),
+            Message::Spawn {
+                channel_id: channel_id,
+                command: command.to_owned(),
+                options: options,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_spawn_bad_option() {
+        let mut options = BTreeMap::new();
+        options.insert(ObjectKey::String("cwd".to_owned()), Value::U64(1));
+
+        let raw = ser::to_vec_packed(&(10, "spawn", "/bin/ls", options)).unwrap();
+        let parsed = channel_protocol::parse_message(de::from_slice(&raw).unwrap()).unwrap();
+
+        assert_eq!(
+            format!("{}", parse_message(parsed).unwrap_err()),
+            "Invalid spawn option cwd: must be a string"
+        );
+    }
+
+    #[test]
+    fn parse_spawn_zero_timeout() {
+        let mut options = BTreeMap::new();
+        options.insert(ObjectKey::String("timeout".to_owned()), Value::U64(0));
+
+        let raw = ser::to_vec_packed(&(10, "spawn", "/bin/ls", options)).unwrap();
+        let parsed = channel_protocol::parse_message(de::from_slice(&raw).unwrap()).unwrap();
+
+        assert_eq!(
+            format!("{}", parse_message(parsed).unwrap_err()),
+            "Invalid spawn option timeout: must be a positive number of seconds"
+        );
+    }
 }
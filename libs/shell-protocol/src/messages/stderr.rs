@@ -21,25 +21,22 @@ use serde_cbor::ser;
 
 /// CBOR -> Message::Stderr
 pub fn from_cbor(message: &ChannelMessage) -> Result<Message, ProtocolError> {
-    if let Some(Value::String(data)) = message.payload.get(0) {
-        Ok(Message::Stderr {
-            channel_id: message.channel_id,
-            data: Some(data.to_owned()),
-        })
-    } else {
-        Ok(Message::Stderr {
-            channel_id: message.channel_id,
-            data: None,
-        })
-    }
+    Ok(Message::Stderr {
+        channel_id: message.channel_id,
+        data: parse_data(message),
+    })
 }
 
 /// Stderr -> CBOR
-pub fn to_cbor(channel_id: u32, data: Option<&str>) -> Result<Vec<u8>, ProtocolError> {
-    info!("-> {{ {}, stderr, '{:?}' }}", channel_id, data);
+pub fn to_cbor(channel_id: u32, data: Option<&[u8]>) -> Result<Vec<u8>, ProtocolError> {
+    info!(
+        "-> {{ {}, stderr, '{:?}' }}",
+        channel_id,
+        data.map(String::from_utf8_lossy)
+    );
 
     Ok(
-        ser::to_vec_packed(&(channel_id, "stderr", data)).map_err(|err| {
+        ser::to_vec_packed(&(channel_id, "stderr", data_value(data))).map_err(|err| {
             ProtocolError::MessageCreationError {
                 message: "stderr".to_owned(),
                 err,
@@ -57,7 +54,7 @@ mod tests {
     #[test]
     fn create_parse_message() {
         let channel_id = 13;
-        let data = "hello world";
+        let data: &[u8] = b"hello world";
 
         let raw = to_cbor(channel_id, Some(data)).unwrap();
         let parsed = channel_protocol::parse_message(de::from_slice(&raw).unwrap()).unwrap();
@@ -67,7 +64,7 @@ mod tests {
             msg.unwrap(),
             Message::Stderr {
                 channel_id: channel_id,
-                data: Some(data.to_owned()),
+                data: Some(data.to_vec()),
             }
         );
     }
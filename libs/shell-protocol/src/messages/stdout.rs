@@ -21,25 +21,22 @@ use serde_cbor::ser;
 
 /// CBOR -> Message::Stdout
 pub fn from_cbor(message: &ChannelMessage) -> Result<Message, ProtocolError> {
-    if let Some(Value::String(data)) = message.payload.get(0) {
-        Ok(Message::Stdout {
-            channel_id: message.channel_id,
-            data: Some(data.to_owned()),
-        })
-    } else {
-        Ok(Message::Stdout {
-            channel_id: message.channel_id,
-            data: None,
-        })
-    }
+    Ok(Message::Stdout {
+        channel_id: message.channel_id,
+        data: parse_data(message),
+    })
 }
 
 /// Stdout -> CBOR
-pub fn to_cbor(channel_id: u32, data: Option<&str>) -> Result<Vec<u8>, ProtocolError> {
-    info!("-> {{ {}, stdout, '{:?}' }}", channel_id, data);
+pub fn to_cbor(channel_id: u32, data: Option<&[u8]>) -> Result<Vec<u8>, ProtocolError> {
+    info!(
+        "-> {{ {}, stdout, '{:?}' }}",
+        channel_id,
+        data.map(String::from_utf8_lossy)
+    );
 
     Ok(
-        ser::to_vec_packed(&(channel_id, "stdout", data)).map_err(|err| {
+        ser::to_vec_packed(&(channel_id, "stdout", data_value(data))).map_err(|err| {
             ProtocolError::MessageCreationError {
                 message: "stdout".to_owned(),
                 err,
@@ -57,7 +54,7 @@ mod tests {
     #[test]
     fn create_parse_message() {
         let channel_id = 13;
-        let data = "hello world";
+        let data: &[u8] = b"hello world";
 
         let raw = to_cbor(channel_id, Some(data)).unwrap();
         let parsed = channel_protocol::parse_message(de::from_slice(&raw).unwrap()).unwrap();
@@ -67,7 +64,7 @@ mod tests {
             msg.unwrap(),
             Message::Stdout {
                 channel_id: channel_id,
-                data: Some(data.to_owned()),
+                data: Some(data.to_vec()),
             }
         );
     }
@@ -88,4 +85,49 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn create_parse_message_binary() {
+        let channel_id = 13;
+        let data = [0xff, 0x00, 0xd8, 0x0a, 0x80];
+
+        let raw = to_cbor(channel_id, Some(&data[..])).unwrap();
+        let parsed = channel_protocol::parse_message(de::from_slice(&raw).unwrap()).unwrap();
+        let msg = parse_message(parsed);
+
+        assert_eq!(
+            msg.unwrap(),
+            Message::Stdout {
+                channel_id: channel_id,
+                data: Some(data.to_vec()),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_message_text() {
+        let channel_id = 13;
+
+        // Older versions of the protocol sent text strings
+        let raw = ser::to_vec_packed(&(channel_id, "stdout", "hello world")).unwrap();
+        let parsed = channel_protocol::parse_message(de::from_slice(&raw).unwrap()).unwrap();
+        let msg = parse_message(parsed);
+
+        assert_eq!(
+            msg.unwrap(),
+            Message::Stdout {
+                channel_id: channel_id,
+                data: Some(b"hello world".to_vec()),
+            }
+        );
+    }
+
+    #[test]
+    fn max_data_size() {
+        let data = vec![0xff; MAX_DATA_SIZE];
+
+        let raw = to_cbor(999_999, Some(&data)).unwrap();
+
+        assert!(raw.len() <= MESSAGE_SIZE);
+    }
 }
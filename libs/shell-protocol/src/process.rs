@@ -15,83 +15,278 @@
 //
 
 use error::ProtocolError;
-use std::io;
-use std::io::{BufRead, BufReader, BufWriter, Write};
+use libc;
+use messages::{SpawnOptions, MAX_DATA_SIZE};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::mem;
 use std::os::unix::prelude::*;
-use std::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command, Stdio};
-use std::time::Duration;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::ptr;
+use std::thread;
+use std::time::{Duration, Instant};
 use timeout_readwrite::{TimeoutReader, TimeoutWriter};
 
-// Helper function for reading a line from a BufReader
-fn do_read<R: BufRead>(mut reader: R) -> Result<Option<String>, ProtocolError> {
-    let mut data = String::new();
-    match reader.read_line(&mut data) {
-        Ok(0) => Ok(None),
-        Ok(_) => Ok(Some(data)),
-        Err(err) => match err.kind() {
-            io::ErrorKind::TimedOut => return Err(ProtocolError::ReadTimeout),
-            _ => {
-                return Err(ProtocolError::ProcesssError {
-                    action: "reading".to_owned(),
-                    err,
-                });
+/// How long a process has to exit after being asked to terminate, before it is killed
+pub const TERMINATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Converts a pipe to the child process into a file
+fn into_file<T: IntoRawFd>(pipe: T) -> File {
+    unsafe { File::from_raw_fd(pipe.into_raw_fd()) }
+}
+
+// An option which can't be used to spawn a process
+fn invalid(option: &str, err: String) -> ProtocolError {
+    ProtocolError::InvalidSpawnOption {
+        option: option.to_owned(),
+        err,
+    }
+}
+
+// Looks up the primary group of a user
+fn primary_group(uid: u32) -> Option<u32> {
+    let mut passwd: libc::passwd = unsafe { mem::zeroed() };
+    let mut buffer = vec![0; 4096];
+    let mut result = ptr::null_mut();
+
+    let rc = unsafe {
+        libc::getpwuid_r(
+            uid,
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+    match rc == 0 && !result.is_null() {
+        true => Some(passwd.pw_gid),
+        false => None,
+    }
+}
+
+// Builds the command for a process, checking that its options can be used
+fn build_command(command: &str, options: &SpawnOptions) -> Result<Command, ProtocolError> {
+    let mut cmd = Command::new(command);
+    cmd.args(options.args.clone().unwrap_or_default());
+
+    if let Some(ref cwd) = options.cwd {
+        if !Path::new(cwd).is_dir() {
+            return Err(invalid("cwd", format!("{} is not a directory", cwd)));
+        }
+        cmd.current_dir(cwd);
+    }
+
+    if let Some(ref env) = options.env {
+        for (name, value) in env {
+            if name.is_empty() || name.contains('=') || name.contains('\0') {
+                return Err(invalid("env", format!("'{}' is not a valid name", name)));
+            }
+            if value.contains('\0') {
+                return Err(invalid("env", format!("the value of {} contains a NUL", name)));
             }
-        },
+        }
+        cmd.envs(env);
+    }
+
+    if let Some(uid) = options.uid {
+        let euid = unsafe { libc::geteuid() };
+        if uid != euid {
+            if euid != 0 {
+                return Err(invalid(
+                    "uid",
+                    "processes can only be run as another user by root".to_owned(),
+                ));
+            }
+            let gid = primary_group(uid)
+                .ok_or_else(|| invalid("uid", format!("no user has the ID {}", uid)))?;
+            cmd.uid(uid).gid(gid);
+        }
+    }
+
+    Ok(cmd)
+}
+
+// Opens a new pseudo terminal, returning its master and slave ends
+fn open_pty() -> io::Result<(File, File)> {
+    let mut master = 0;
+    let mut slave = 0;
+
+    if unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            ptr::null_mut(),
+            ptr::null(),
+            ptr::null(),
+        )
+    } != 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+
+    let (master, slave) = unsafe { (File::from_raw_fd(master), File::from_raw_fd(slave)) };
+
+    // Neither end should leak into the child process, which gets its own copies of the slave
+    for fd in &[master.as_raw_fd(), slave.as_raw_fd()] {
+        if unsafe { libc::fcntl(*fd, libc::F_SETFD, libc::FD_CLOEXEC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok((master, slave))
+}
+
+/// Reads the raw output of a process as it becomes available
+pub struct OutputReader {
+    reader: TimeoutReader<File>,
+}
+
+impl OutputReader {
+    fn new(file: File) -> Self {
+        OutputReader {
+            reader: TimeoutReader::new(file, Duration::from_millis(5)),
+        }
+    }
+
+    /// Read whatever output is available, up to `MAX_DATA_SIZE` bytes,
+    /// so that it fits in a single message
+    ///
+    /// A return value of `None` indicates the stream has been closed.
+    pub fn read(&mut self) -> Result<Option<Vec<u8>>, ProtocolError> {
+        let mut buffer = vec![0; MAX_DATA_SIZE];
+        let count = match self.reader.read(&mut buffer) {
+            Ok(count) => count,
+            Err(err) => match err.kind() {
+                io::ErrorKind::TimedOut => return Err(ProtocolError::ReadTimeout),
+                // Reading from a pseudo terminal fails once the process has closed it
+                _ if err.raw_os_error() == Some(libc::EIO) => 0,
+                _ => {
+                    return Err(ProtocolError::ProcesssError {
+                        action: "reading".to_owned(),
+                        err,
+                    })
+                }
+            },
+        };
+
+        if count == 0 {
+            return Ok(None);
+        }
+
+        buffer.truncate(count);
+        Ok(Some(buffer))
     }
 }
 
 pub struct ProcessHandler {
     process: Child,
-    pub stdout_reader: Option<BufReader<TimeoutReader<ChildStdout>>>,
-    pub stderr_reader: Option<BufReader<TimeoutReader<ChildStderr>>>,
-    pub stdin_writer: Option<BufWriter<TimeoutWriter<ChildStdin>>>,
+    // The master end of the process' pseudo terminal, if it has one
+    pty: Option<File>,
+    pub stdout_reader: Option<OutputReader>,
+    pub stderr_reader: Option<OutputReader>,
+    pub stdin_writer: Option<TimeoutWriter<File>>,
 }
 
 impl ProcessHandler {
     /// Spawn a process and setup stdout/stderr streams
-    pub fn spawn(
-        command: String,
-        args: Option<Vec<String>>,
-    ) -> Result<ProcessHandler, ProtocolError> {
-        let mut process = match Command::new(command.to_owned())
-            .stdin(Stdio::piped())
+    ///
+    /// If `options.pty` is true, the process is given a new pseudo terminal as its
+    /// controlling terminal and stdin/stdout/stderr. Its combined output is
+    /// then read from `stdout_reader`.
+    ///
+    /// The process' timeout isn't enforced here; see `terminate`.
+    pub fn spawn(command: String, options: &SpawnOptions) -> Result<ProcessHandler, ProtocolError> {
+        let cmd = build_command(&command, options)?;
+        if options.pty {
+            Self::spawn_pty(command, cmd)
+        } else {
+            Self::spawn_piped(command, cmd)
+        }
+    }
+
+    fn spawn_piped(command: String, mut cmd: Command) -> Result<ProcessHandler, ProtocolError> {
+        cmd.stdin(Stdio::piped())
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .args(args.unwrap_or(vec![]))
-            .spawn()
-        {
+            .stderr(Stdio::piped());
+
+        // Start a new process group, so the process' children can be terminated along with it
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setpgid(0, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        let mut process = match cmd.spawn() {
             Ok(process) => process,
             Err(err) => return Err(ProtocolError::SpawnError { cmd: command, err }),
         };
 
-        let stdout_reader = match process.stdout.take() {
-            Some(stdout) => Some(BufReader::new(TimeoutReader::new(
-                stdout,
-                Duration::from_millis(5),
-            ))),
-            None => None,
-        };
+        let stdout_reader = process
+            .stdout
+            .take()
+            .map(|stdout| OutputReader::new(into_file(stdout)));
+        let stderr_reader = process
+            .stderr
+            .take()
+            .map(|stderr| OutputReader::new(into_file(stderr)));
+        let stdin_writer = process
+            .stdin
+            .take()
+            .map(|stdin| TimeoutWriter::new(into_file(stdin), Duration::from_millis(5)));
 
-        let stderr_reader = match process.stderr.take() {
-            Some(stderr) => Some(BufReader::new(TimeoutReader::new(
-                stderr,
-                Duration::from_millis(5),
-            ))),
-            None => None,
-        };
+        Ok(ProcessHandler {
+            process,
+            pty: None,
+            stdout_reader,
+            stderr_reader,
+            stdin_writer,
+        })
+    }
 
-        let stdin_writer = match process.stdin.take() {
-            Some(stdin) => Some(BufWriter::new(TimeoutWriter::new(
-                stdin,
-                Duration::from_millis(5),
-            ))),
-            None => None,
+    fn spawn_pty(command: String, mut cmd: Command) -> Result<ProcessHandler, ProtocolError> {
+        let spawn_err = |err| ProtocolError::SpawnError {
+            cmd: command.clone(),
+            err,
         };
 
+        let (master, slave) = open_pty().map_err(&spawn_err)?;
+
+        cmd.stdin(Stdio::from(slave.try_clone().map_err(&spawn_err)?))
+            .stdout(Stdio::from(slave.try_clone().map_err(&spawn_err)?))
+            .stderr(Stdio::from(slave));
+
+        // Start a new session, so the pseudo terminal can become the process' controlling
+        // terminal. Only async-signal-safe calls may be made between fork and exec
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        let process = cmd.spawn().map_err(&spawn_err)?;
+        // Close our copies of the slave end, so reads fail once the process exits
+        drop(cmd);
+
+        let stdout_reader = Some(OutputReader::new(
+            master.try_clone().map_err(&spawn_err)?,
+        ));
+        let stdin_writer = Some(TimeoutWriter::new(
+            master.try_clone().map_err(&spawn_err)?,
+            Duration::from_millis(5),
+        ));
+
         Ok(ProcessHandler {
             process,
+            pty: Some(master),
             stdout_reader,
-            stderr_reader,
+            stderr_reader: None,
             stdin_writer,
         })
     }
@@ -101,9 +296,9 @@ impl ProcessHandler {
     /// A return value of `None` indicates the stream is
     /// no longer available and likewise the process
     /// is likely no longer alive.
-    pub fn read_stdout(&mut self) -> Result<Option<String>, ProtocolError> {
+    pub fn read_stdout(&mut self) -> Result<Option<Vec<u8>>, ProtocolError> {
         match self.stdout_reader {
-            Some(ref mut stdout_reader) => Ok(do_read(stdout_reader)?),
+            Some(ref mut stdout_reader) => stdout_reader.read(),
             None => Ok(None),
         }
     }
@@ -113,9 +308,9 @@ impl ProcessHandler {
     /// A return value of `None` indicates the stream is
     /// no longer available and likewise the process
     /// is likely no longer alive.
-    pub fn read_stderr(&mut self) -> Result<Option<String>, ProtocolError> {
+    pub fn read_stderr(&mut self) -> Result<Option<Vec<u8>>, ProtocolError> {
         match self.stderr_reader {
-            Some(ref mut stderr_reader) => Ok(do_read(stderr_reader)?),
+            Some(ref mut stderr_reader) => stderr_reader.read(),
             None => Ok(None),
         }
     }
@@ -130,7 +325,6 @@ impl ProcessHandler {
                         action: "write to stdin".to_owned(),
                         err,
                     })?;
-                stdin_writer.flush();
                 Ok(())
             }
             None => Ok(()),
@@ -138,17 +332,85 @@ impl ProcessHandler {
     }
 
     /// Close process' stdin pipe
+    ///
+    /// A pseudo terminal can't be closed without also closing the process' output,
+    /// so the terminal's end-of-file character is sent instead.
     pub fn close_stdin(&mut self) -> Result<(), ProtocolError> {
-        match self.stdin_writer {
-            Some(ref mut stdin_writer) => {
-                drop(stdin_writer);
-            }
-            None => {}
+        if self.pty.is_some() {
+            self.write_stdin(&[4])?;
         }
         self.stdin_writer = None;
         Ok(())
     }
 
+    /// Set the window size of the process' pseudo terminal, if it has one
+    pub fn resize(&mut self, columns: u16, rows: u16) -> Result<(), ProtocolError> {
+        if let Some(ref pty) = self.pty {
+            let size = libc::winsize {
+                ws_row: rows,
+                ws_col: columns,
+                ws_xpixel: 0,
+                ws_ypixel: 0,
+            };
+            if unsafe { libc::ioctl(pty.as_raw_fd(), libc::TIOCSWINSZ, &size) } != 0 {
+                return Err(ProtocolError::ProcesssError {
+                    action: "resize terminal".to_owned(),
+                    err: io::Error::last_os_error(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a signal to the process
+    pub fn kill(&mut self, signal: u32) -> Result<(), ProtocolError> {
+        if unsafe { libc::kill(self.process.id() as libc::pid_t, signal as libc::c_int) } != 0 {
+            return Err(ProtocolError::ProcesssError {
+                action: format!("send signal {}", signal),
+                err: io::Error::last_os_error(),
+            });
+        }
+        Ok(())
+    }
+
+    // Sends a signal to the process and any children it has started. Every process is
+    // spawned as the leader of its own process group
+    fn kill_group(&self, signal: libc::c_int) {
+        // Some of the processes may already have exited
+        unsafe {
+            libc::kill(-(self.process.id() as libc::pid_t), signal);
+        }
+    }
+
+    /// Stop the process and its children, if it is still running
+    ///
+    /// The process is sent `SIGTERM`, and then `SIGKILL` if it hasn't exited after `timeout`.
+    /// Any children which outlive it are killed.
+    pub fn terminate(&mut self, timeout: Duration) -> Result<(), ProtocolError> {
+        if self.status()?.is_some() {
+            return Ok(());
+        }
+
+        self.kill_group(libc::SIGTERM);
+
+        let start = Instant::now();
+        while start.elapsed() < timeout && self.status()?.is_none() {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        if self.status()?.is_none() {
+            warn!("Process {} didn't exit, killing it", self.process.id());
+        }
+        self.kill_group(libc::SIGKILL);
+        self.process
+            .wait()
+            .map_err(|err| ProtocolError::ProcesssError {
+                action: "wait for exit".to_owned(),
+                err,
+            })?;
+        Ok(())
+    }
+
     /// Retrieve ID of process
     pub fn id(&self) -> Result<u32, ProtocolError> {
         Ok(self.process.id())
@@ -170,3 +432,284 @@ impl ProcessHandler {
         }
     }
 }
+
+// A process is never left running without a session to control it
+impl Drop for ProcessHandler {
+    fn drop(&mut self) {
+        if let Err(err) = self.terminate(TERMINATE_TIMEOUT) {
+            warn!("Failed to terminate process {}: {}", self.process.id(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::thread;
+
+    fn spawn(
+        command: String,
+        args: Option<Vec<String>>,
+        pty: bool,
+    ) -> Result<ProcessHandler, ProtocolError> {
+        let options = SpawnOptions {
+            args,
+            pty,
+            ..Default::default()
+        };
+        ProcessHandler::spawn(command, &options)
+    }
+
+    // Collects a process' output until the stream closes
+    fn read_all(reader: &mut OutputReader) -> Vec<u8> {
+        let mut output = vec![];
+        loop {
+            match reader.read() {
+                Ok(Some(data)) => {
+                    assert!(data.len() <= MAX_DATA_SIZE);
+                    output.extend_from_slice(&data);
+                }
+                Ok(None) => return output,
+                Err(ProtocolError::ReadTimeout) => thread::sleep(Duration::from_millis(5)),
+                Err(err) => panic!("Read failed: {}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn read_without_newline() {
+        let mut process = spawn(
+            "/bin/sh".to_owned(),
+            Some(vec!["-c".to_owned(), "printf 'Continue? '; sleep 1".to_owned()]),
+            false,
+        ).unwrap();
+
+        let mut output = vec![];
+        while output.is_empty() {
+            match process.read_stdout() {
+                Ok(Some(data)) => output.extend_from_slice(&data),
+                Err(ProtocolError::ReadTimeout) => thread::sleep(Duration::from_millis(5)),
+                Ok(None) => panic!("Output closed early"),
+                Err(err) => panic!("Read failed: {}", err),
+            }
+        }
+
+        assert_eq!(output, b"Continue? ");
+    }
+
+    #[test]
+    fn read_binary() {
+        let mut process = spawn(
+            "/bin/sh".to_owned(),
+            Some(vec!["-c".to_owned(), "printf '\\377\\000\\330\\n'".to_owned()]),
+            false,
+        ).unwrap();
+
+        assert_eq!(
+            read_all(process.stdout_reader.as_mut().unwrap()),
+            vec![0xff, 0x00, 0xd8, b'\n']
+        );
+    }
+
+    #[test]
+    fn read_large_output() {
+        let mut process = spawn(
+            "/bin/dd".to_owned(),
+            Some(vec![
+                "if=/dev/zero".to_owned(),
+                "bs=1000".to_owned(),
+                "count=10".to_owned(),
+            ]),
+            false,
+        ).unwrap();
+
+        assert_eq!(
+            read_all(process.stdout_reader.as_mut().unwrap()),
+            vec![0; 10000]
+        );
+    }
+
+    #[test]
+    fn binary_stdin() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut process = spawn("/bin/cat".to_owned(), None, false).unwrap();
+
+        process.write_stdin(&data).unwrap();
+        process.close_stdin().unwrap();
+
+        assert_eq!(read_all(process.stdout_reader.as_mut().unwrap()), data);
+    }
+
+    #[test]
+    fn spawn_pty() {
+        let mut process = spawn(
+            "/bin/sh".to_owned(),
+            Some(vec![
+                "-c".to_owned(),
+                "test -t 0 && test -t 2 && read line && stty size".to_owned(),
+            ]),
+            true,
+        ).unwrap();
+        assert!(process.stderr_reader.is_none());
+
+        process.resize(100, 40).unwrap();
+        process.write_stdin(b"\n").unwrap();
+
+        assert_eq!(
+            read_all(process.stdout_reader.as_mut().unwrap()),
+            b"\r\n40 100\r\n"
+        );
+    }
+
+    #[test]
+    fn pty_stdin() {
+        let mut process = spawn("/bin/cat".to_owned(), None, true).unwrap();
+
+        process.write_stdin(b"hello\n").unwrap();
+        process.close_stdin().unwrap();
+
+        // The terminal echoes the input, then cat repeats it
+        assert_eq!(
+            read_all(process.stdout_reader.as_mut().unwrap()),
+            b"hello\r\nhello\r\n"
+        );
+    }
+
+    #[test]
+    fn terminate() {
+        let mut process =
+            spawn("/bin/sleep".to_owned(), Some(vec!["30".to_owned()]), false).unwrap();
+
+        process.terminate(Duration::from_secs(5)).unwrap();
+
+        assert_eq!(process.status().unwrap(), Some((0, libc::SIGTERM as u32)));
+    }
+
+    #[test]
+    fn terminate_ignored() {
+        let mut process = spawn(
+            "/bin/sh".to_owned(),
+            Some(vec![
+                "-c".to_owned(),
+                "trap '' TERM; echo ready; while true; do sleep 1; done".to_owned(),
+            ]),
+            false,
+        ).unwrap();
+        // Wait for the trap to be set up
+        while process.read_stdout().ok() != Some(Some(b"ready\n".to_vec())) {
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        let start = Instant::now();
+        process.terminate(Duration::from_millis(200)).unwrap();
+
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert_eq!(process.status().unwrap(), Some((0, libc::SIGKILL as u32)));
+    }
+
+    #[test]
+    fn terminate_children() {
+        let mut process = spawn(
+            "/bin/sh".to_owned(),
+            Some(vec!["-c".to_owned(), "sleep 30 & echo $!; wait".to_owned()]),
+            false,
+        ).unwrap();
+        let mut output = None;
+        while output.is_none() {
+            output = process.read_stdout().unwrap();
+        }
+        let child = String::from_utf8(output.unwrap()).unwrap();
+
+        process.terminate(Duration::from_secs(5)).unwrap();
+
+        // The orphaned child is either gone, or a zombie waiting to be reaped by init
+        let stat = ::std::fs::read_to_string(format!("/proc/{}/stat", child.trim()));
+        assert!(stat.map(|stat| stat.contains(") Z ")).unwrap_or(true));
+    }
+
+    #[test]
+    fn terminate_on_drop() {
+        let process =
+            spawn("/bin/sleep".to_owned(), Some(vec!["30".to_owned()]), false).unwrap();
+        let pid = process.id().unwrap() as libc::pid_t;
+
+        drop(process);
+
+        // The process has been reaped, so no longer exists
+        assert_eq!(unsafe { libc::kill(pid, 0) }, -1);
+    }
+
+    #[test]
+    fn spawn_cwd_env() {
+        let mut env = HashMap::new();
+        env.insert("GREETING".to_owned(), "hello".to_owned());
+        let options = SpawnOptions {
+            args: Some(vec!["-c".to_owned(), "echo $GREETING; pwd".to_owned()]),
+            cwd: Some("/tmp".to_owned()),
+            env: Some(env),
+            ..Default::default()
+        };
+
+        let mut process = ProcessHandler::spawn("/bin/sh".to_owned(), &options).unwrap();
+
+        assert_eq!(
+            read_all(process.stdout_reader.as_mut().unwrap()),
+            b"hello\n/tmp\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn spawn_bad_cwd() {
+        let options = SpawnOptions {
+            cwd: Some("/not/a/dir".to_owned()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            format!(
+                "{}",
+                ProcessHandler::spawn("/bin/pwd".to_owned(), &options)
+                    .err()
+                    .unwrap()
+            ),
+            "Invalid spawn option cwd: /not/a/dir is not a directory"
+        );
+    }
+
+    #[test]
+    fn spawn_bad_env() {
+        let mut env = HashMap::new();
+        env.insert("A=B".to_owned(), "C".to_owned());
+        let options = SpawnOptions {
+            env: Some(env),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            format!(
+                "{}",
+                ProcessHandler::spawn("/usr/bin/env".to_owned(), &options)
+                    .err()
+                    .unwrap()
+            ),
+            "Invalid spawn option env: 'A=B' is not a valid name"
+        );
+    }
+
+    #[test]
+    fn spawn_same_uid() {
+        let options = SpawnOptions {
+            args: Some(vec!["-u".to_owned()]),
+            uid: Some(unsafe { libc::geteuid() }),
+            ..Default::default()
+        };
+
+        let mut process = ProcessHandler::spawn("/usr/bin/id".to_owned(), &options).unwrap();
+
+        assert_eq!(
+            read_all(process.stdout_reader.as_mut().unwrap()),
+            format!("{}\n", unsafe { libc::geteuid() }).into_bytes()
+        );
+    }
+}
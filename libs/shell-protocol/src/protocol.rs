@@ -16,14 +16,37 @@
 
 use channel_protocol::{ChannelMessage, ChannelProtocol};
 use error::ProtocolError;
+use libc;
 use messages;
-use process::ProcessHandler;
-use std::time::Duration;
+use process::{ProcessHandler, TERMINATE_TIMEOUT};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A process started by the shell service
+#[derive(Clone, Debug)]
+pub struct Session {
+    /// The command the process was spawned with
+    pub path: String,
+    /// The ID of the process
+    pub pid: u32,
+    /// When the process was spawned
+    pub started: Instant,
+}
+
+/// The processes running in the shell service, by channel ID
+pub type SessionList = Arc<Mutex<HashMap<u32, Session>>>;
 
 pub struct Protocol {
     pub channel_protocol: ChannelProtocol,
     pub process: Box<Option<ProcessHandler>>,
     channel_id: u32,
+    sessions: SessionList,
+    idle_timeout: Option<Duration>,
+    session_timeout: Option<Duration>,
+    // When the process will be terminated, if it was spawned with a timeout
+    deadline: Option<Instant>,
 }
 
 /// Shell Protocol structure used in the shell service
@@ -31,9 +54,53 @@ impl Protocol {
     pub fn new(host_ip: &str, remote_addr: &str, channel_id: u32) -> Self {
         // Set up the full connection info
         Protocol {
-            channel_protocol: ChannelProtocol::new(host_ip, remote_addr, 4096),
+            channel_protocol: ChannelProtocol::new(
+                host_ip,
+                remote_addr,
+                messages::MESSAGE_SIZE as u32,
+            ),
             process: Box::new(None),
             channel_id: channel_id,
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            idle_timeout: None,
+            session_timeout: None,
+            deadline: None,
+        }
+    }
+
+    /// Share a list of sessions with other instances of the protocol.
+    ///
+    /// Spawned processes are added to the list, and `list` requests are answered from it.
+    pub fn with_sessions(mut self, sessions: SessionList) -> Self {
+        self.sessions = sessions;
+        self
+    }
+
+    /// End the session, terminating its process, once neither the client nor the process
+    /// has sent anything for this long. By default, sessions never become idle.
+    pub fn idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// End the session, terminating its process, once it has been running for this long.
+    /// By default, sessions may run indefinitely.
+    pub fn session_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.session_timeout = timeout;
+        self
+    }
+
+    // The reason the session should end, if it has run out of time
+    fn expired(&self, started: Instant, last_activity: Instant) -> Option<String> {
+        match (self.idle_timeout, self.session_timeout) {
+            (Some(timeout), _) if last_activity.elapsed() >= timeout => Some(format!(
+                "no activity for {} seconds",
+                timeout.as_secs()
+            )),
+            (_, Some(timeout)) if started.elapsed() >= timeout => {
+                Some(format!("running for {} seconds", timeout.as_secs()))
+            }
+            _ => None,
         }
     }
 
@@ -46,20 +113,28 @@ impl Protocol {
     ///
     /// # Errors
     ///
-    /// If this function encounters any errors, it will return an error message string
+    /// If this function encounters any errors, it will return an error message string.
+    /// If the session times out, its process is terminated and `ProtocolError::SessionTimeout`
+    /// is returned.
     ///
     pub fn message_engine<F>(&mut self, pump: F, timeout: Duration) -> Result<(), ProtocolError>
     where
         F: Fn(Duration) -> Result<ChannelMessage, ProtocolError>,
     {
+        let started = Instant::now();
+        let mut last_activity = Instant::now();
+
         loop {
             if let Some(process) = self.process.as_mut() {
                 // Check if process has stdout output
                 if process.stdout_reader.is_some() {
                     match process.read_stdout() {
                         Ok(Some(data)) => {
-                            self.channel_protocol
-                                .send(messages::stdout::to_cbor(self.channel_id, Some(&data))?)?;
+                            last_activity = Instant::now();
+                            self.channel_protocol.send(messages::stdout::to_cbor(
+                                self.channel_id,
+                                Some(&data),
+                            )?)?;
                         }
                         Err(ProtocolError::ReadTimeout) => {}
                         _ => {
@@ -74,8 +149,11 @@ impl Protocol {
                 if process.stderr_reader.is_some() {
                     match process.read_stderr() {
                         Ok(Some(data)) => {
-                            self.channel_protocol
-                                .send(messages::stderr::to_cbor(self.channel_id, Some(&data))?)?;
+                            last_activity = Instant::now();
+                            self.channel_protocol.send(messages::stderr::to_cbor(
+                                self.channel_id,
+                                Some(&data),
+                            )?)?;
                         }
                         Err(ProtocolError::ReadTimeout) => {}
                         _ => {
@@ -101,59 +179,192 @@ impl Protocol {
                         return Ok(());
                     }
                 }
+
+                // Stop the process once it has run for as long as it was allowed to.
+                // Its exit is then reported like any other
+                if self.deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                    info!("Process on channel {} timed out", self.channel_id);
+                    self.deadline = None;
+                    process.terminate(TERMINATE_TIMEOUT)?;
+                }
             }
+
+            // End the session if the client has gone away or it has run for too long
+            if let Some(reason) = self.expired(started, last_activity) {
+                self.channel_protocol.send(messages::error::to_cbor(
+                    self.channel_id,
+                    &format!("Session timed out: {}", reason),
+                )?)?;
+                if let Some(process) = self.process.as_mut() {
+                    process.terminate(TERMINATE_TIMEOUT)?;
+                    if let Some((code, signal)) = process.status()? {
+                        self.channel_protocol.send(messages::exit::to_cbor(
+                            self.channel_id,
+                            code,
+                            signal,
+                        )?)?;
+                    }
+                }
+                return Err(ProtocolError::SessionTimeout { reason });
+            }
+
             // Check for new messages from the client
             let message = match pump(timeout) {
                 Ok(message) => message,
-                Err(ProtocolError::ReceiveTimeout) => {
-                    // TODO when do we end this?
-                    continue;
-                }
+                Err(ProtocolError::ReceiveTimeout) => continue,
                 Err(e) => return Err(e),
             };
 
+            last_activity = Instant::now();
             self.process_message(message)?;
         }
     }
 
+    // Lets the client know why its request failed
+    fn report<T>(&self, result: Result<T, ProtocolError>) -> Result<T, ProtocolError> {
+        if let Err(ref err) = result {
+            self.channel_protocol
+                .send(messages::error::to_cbor(self.channel_id, &err.to_string())?)?;
+        }
+        result
+    }
+
     pub fn process_message(&mut self, message: ChannelMessage) -> Result<(), ProtocolError> {
-        let parsed_message = messages::parse_message(message)?;
+        let parsed_message = self.report(messages::parse_message(message))?;
 
         match parsed_message {
             messages::Message::Spawn {
                 channel_id,
                 command,
-                args,
+                options,
             } => {
-                info!(
-                    "<- {{ {}, spawn, {}, {{ args = {:?} }} }}",
-                    channel_id, command, args
-                );
+                info!("<- {{ {}, spawn, {}, {:?} }}", channel_id, command, options);
+
+                let deadline = match options.timeout {
+                    Some(timeout) => Some(self.report(
+                        Instant::now()
+                            .checked_add(Duration::from_secs(timeout))
+                            .ok_or_else(|| ProtocolError::InvalidSpawnOption {
+                                option: "timeout".to_owned(),
+                                err: format!("{} seconds is too long", timeout),
+                            }),
+                    )?),
+                    None => None,
+                };
 
-                self.process = Box::new(Some(ProcessHandler::spawn(command, args)?));
+                let process = self.report(ProcessHandler::spawn(command.clone(), &options))?;
+                self.process = Box::new(Some(process));
+                self.deadline = deadline;
                 if let Some(process) = self.process.as_ref() {
+                    let pid = process.id()?;
+                    self.sessions.lock().unwrap().insert(
+                        self.channel_id,
+                        Session {
+                            path: command,
+                            pid,
+                            started: Instant::now(),
+                        },
+                    );
+                    self.channel_protocol
+                        .send(messages::pid::to_cbor(self.channel_id, pid)?)?;
+                }
+            }
+            messages::Message::Kill { channel_id, signal } => {
+                info!("<- {{ {}, kill, {:?} }}", channel_id, signal);
+                if let Some(process) = self.process.as_mut() {
+                    // A bad signal shouldn't end the session
+                    if let Err(err) = process.kill(signal.unwrap_or(libc::SIGTERM as u32)) {
+                        warn!("Failed to signal process: {}", err);
+                    }
+                }
+            }
+            messages::Message::List {
+                channel_id,
+                process_list,
+            } => {
+                info!("<- {{ {}, list, {:?} }}", channel_id, process_list);
+                // Only requests (without a list) need a reply
+                if process_list.is_none() {
+                    let process_list = self
+                        .sessions
+                        .lock()
+                        .unwrap()
+                        .iter()
+                        .map(|(channel_id, session)| {
+                            (
+                                *channel_id,
+                                messages::ProcessInfo {
+                                    path: session.path.clone(),
+                                    pid: session.pid,
+                                    runtime: session.started.elapsed().as_secs(),
+                                },
+                            )
+                        }).collect();
+                    self.channel_protocol.send(messages::list::to_cbor(
+                        self.channel_id,
+                        Some(&process_list),
+                    )?)?;
+                }
+            }
+            messages::Message::Attach {
+                channel_id,
+                address,
+            } => {
+                info!("<- {{ {}, attach, {:?} }}", channel_id, address);
+                let address = address.and_then(|address| address.parse::<SocketAddr>().ok());
+                if let (Some(process), Some(address)) = (self.process.as_ref(), address) {
+                    self.channel_protocol.set_remote_addr(address);
+                    // Let the new client know it is now connected to the process
                     self.channel_protocol
                         .send(messages::pid::to_cbor(self.channel_id, process.id()?)?)?;
                 }
             }
             messages::Message::Stdin { channel_id, data } => {
-                info!("<- {{ {}, stdin, {:?} }}", channel_id, data);
+                info!(
+                    "<- {{ {}, stdin, {:?} }}",
+                    channel_id,
+                    data.as_ref().map(|data| String::from_utf8_lossy(data))
+                );
                 if let Some(process) = self.process.as_mut() {
                     match data {
-                        Some(data) => process.write_stdin(&data.as_bytes())?,
+                        Some(data) => process.write_stdin(&data)?,
                         None => process.close_stdin()?,
                     }
                 }
             }
+            messages::Message::Resize {
+                channel_id,
+                columns,
+                rows,
+            } => {
+                info!("<- {{ {}, resize, {}, {} }}", channel_id, columns, rows);
+                if let Some(process) = self.process.as_mut() {
+                    process.resize(columns, rows)?;
+                }
+            }
             messages::Message::Stdout { channel_id, data } => {
-                info!("<- {{ {}, stdout, {:?} }}", channel_id, data);
+                info!(
+                    "<- {{ {}, stdout, {:?} }}",
+                    channel_id,
+                    data.as_ref().map(|data| String::from_utf8_lossy(data))
+                );
             }
             messages::Message::Stderr { channel_id, data } => {
-                info!("<- {{ {}, stderr, {:?} }}", channel_id, data);
+                info!(
+                    "<- {{ {}, stderr, {:?} }}",
+                    channel_id,
+                    data.as_ref().map(|data| String::from_utf8_lossy(data))
+                );
             }
             messages::Message::Pid { channel_id, pid } => {
                 info!("<- {{ {}, pid, {} }}", channel_id, pid);
             }
+            messages::Message::Error {
+                channel_id,
+                message,
+            } => {
+                info!("<- {{ {}, error, {} }}", channel_id, message);
+            }
             messages::Message::Exit {
                 channel_id,
                 code,
@@ -166,3 +377,12 @@ impl Protocol {
         Ok(())
     }
 }
+
+impl Drop for Protocol {
+    fn drop(&mut self) {
+        // Only the instance which spawned a process owns its session
+        if self.process.is_some() {
+            self.sessions.lock().unwrap().remove(&self.channel_id);
+        }
+    }
+}
@@ -15,11 +15,111 @@
  */
 
 use error::*;
+use std::collections::BTreeMap;
 use std::fs;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use toml;
 
+/// A signature over the contents of an application package
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PackageSignature {
+    /// The name of the trusted key the package was signed with
+    pub key: String,
+    /// The hex-encoded Ed25519 signature
+    pub value: String,
+}
+
+/// When the app service should restart an application after it exits
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartMode {
+    /// Leave the application stopped
+    Never,
+    /// Restart the application if it exits with a non-zero status or is killed by a signal
+    OnFailure,
+    /// Always restart the application
+    Always,
+}
+
+/// An application's restart policy, declared in the `[restart]` section of its manifest:
+///
+/// ```toml
+/// [restart]
+/// policy = "on-failure"
+/// max_retries = 5
+/// backoff = 1
+/// max_backoff = 60
+/// ```
+///
+/// The delay before each restart starts at `backoff` seconds and doubles with each consecutive
+/// restart, up to `max_backoff` seconds. An application which stays up for longer than
+/// `max_backoff` seconds is considered healthy again, which resets the delay and retry count.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct RestartPolicy {
+    /// When to restart the application
+    pub policy: RestartMode,
+    /// Number of consecutive restarts to attempt before giving up (0 means no limit)
+    pub max_retries: u32,
+    /// Initial delay, in seconds, before restarting the application
+    pub backoff: u64,
+    /// Longest delay, in seconds, before restarting the application
+    pub max_backoff: u64,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy {
+            policy: RestartMode::Never,
+            max_retries: 0,
+            backoff: 1,
+            max_backoff: 60,
+        }
+    }
+}
+
+/// Limits on the resources an application may use, declared in the `[limits]` section of its
+/// manifest:
+///
+/// ```toml
+/// [limits]
+/// memory = 8388608
+/// address_space = 33554432
+/// cpu_time = 60
+/// nice = 10
+/// open_files = 64
+/// ```
+///
+/// Any limit which isn't given is left as inherited from the app service.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct ResourceLimits {
+    /// Largest resident set size, in bytes. An application which exceeds this is killed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<u64>,
+    /// Largest virtual address space, in bytes (`RLIMIT_AS`). Allocations beyond this fail
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address_space: Option<u64>,
+    /// CPU time, in seconds, after which the application is sent `SIGXCPU` (`RLIMIT_CPU`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_time: Option<u64>,
+    /// Scheduling priority, from -20 (highest) to 19 (lowest)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nice: Option<i32>,
+    /// Most files the application may have open at once (`RLIMIT_NOFILE`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open_files: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// Whether no limits are set
+    pub fn is_empty(&self) -> bool {
+        *self == ResourceLimits::default()
+    }
+}
+
 /// The high level metadata of an application
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct AppMetadata {
@@ -29,6 +129,38 @@ pub struct AppMetadata {
     pub version: String,
     /// The author of the application
     pub author: String,
+    /// The application's entry point, relative to the root of its package. Only needed if the
+    /// package contains more than the application binary and its manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub executable: Option<String>,
+    /// Custom run levels the application may be started with, in addition to `OnBoot` and
+    /// `OnCommand`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub run_levels: Vec<String>,
+    /// The user to run the application as. By default, it runs as the same user as the app
+    /// service
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// The directory to run the application in, either absolute or relative to the root of its
+    /// package. By default, it runs in the app service's working directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+    /// What to do when the application exits
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    /// Limits on the resources the application may use
+    #[serde(default, skip_serializing_if = "ResourceLimits::is_empty")]
+    pub limits: ResourceLimits,
+    /// Additional environment variables to run the application with
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// BLAKE2b checksums of files in the application's package, keyed by their path relative
+    /// to the package root. These are verified before the application is registered
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checksums: BTreeMap<String, String>,
+    /// Signature over the package's contents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<PackageSignature>,
 }
 /// Kubos App struct
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -42,6 +174,52 @@ pub struct App {
     /// The associated metadata of the application
     pub metadata: AppMetadata,
 }
+/// Why a version of an application was made the active version
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ActivationReason {
+    /// The version was registered
+    Register,
+    /// The version was selected with `setVersion`
+    SetVersion,
+    /// The version which replaced it failed, so it was automatically re-activated
+    Rollback,
+}
+
+/// A record of a version of an application being made the active version
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Activation {
+    /// When the version was activated, in seconds since the Unix epoch
+    pub time: u64,
+    /// Why the version was activated
+    pub reason: ActivationReason,
+    /// The version which was active beforehand, if any
+    pub previous: Option<String>,
+}
+
+impl Activation {
+    /// Creates a record of a version being activated now
+    pub fn now(reason: ActivationReason, previous: Option<String>) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or(0);
+
+        Activation {
+            time,
+            reason,
+            previous,
+        }
+    }
+
+    /// How long ago the version was activated
+    pub fn age(&self) -> Duration {
+        (UNIX_EPOCH + Duration::from_secs(self.time))
+            .elapsed()
+            .unwrap_or_default()
+    }
+}
+
 /// AppRegistryEntry
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct AppRegistryEntry {
@@ -49,6 +227,9 @@ pub struct AppRegistryEntry {
     pub active_version: bool,
     /// The app itself
     pub app: App,
+    /// Every time this version was made the active version, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<Activation>,
 }
 
 impl AppRegistryEntry {
@@ -75,10 +256,29 @@ impl AppRegistryEntry {
         }
     }
 
+    // The directory this version of the app was installed into
+    pub fn dir(&self) -> PathBuf {
+        let depth = match self.app.metadata.executable {
+            Some(ref executable) => Path::new(executable)
+                .components()
+                .filter(|component| match component {
+                    Component::Normal(_) => true,
+                    _ => false,
+                })
+                .count(),
+            None => 1,
+        };
+
+        let mut dir = PathBuf::from(self.app.path.clone());
+        for _ in 0..depth {
+            dir.pop();
+        }
+        dir
+    }
+
     // Create or update a registered apps entry information
     pub fn save(&self) -> Result<(), AppError> {
-        let mut app_toml = PathBuf::from(self.app.path.clone());
-        app_toml.set_file_name("app.toml");
+        let app_toml = self.dir().join("app.toml");
 
         let mut file = fs::File::create(app_toml)?;
         let toml_str = match toml::to_string(&self) {
@@ -40,6 +40,54 @@ pub enum AppError {
         /// Underlying error encountered
         err: String,
     },
+    /// An error was encountered while stopping an application
+    #[fail(display = "Failed to stop app: {}", err)]
+    StopError {
+        /// Underlying error encountered
+        err: String,
+    },
+    /// A file in an application package didn't match its checksum
+    #[fail(display = "Failed to verify app: {}", err)]
+    ChecksumError {
+        /// Underlying error encountered
+        err: String,
+    },
+    /// An application package's signature couldn't be verified
+    #[fail(display = "Failed to verify app signature: {}", err)]
+    SignatureError {
+        /// Underlying error encountered
+        err: String,
+    },
+    /// An application's heartbeat couldn't be recorded
+    #[fail(display = "Failed to record heartbeat: {}", err)]
+    HeartbeatError {
+        /// Underlying error encountered
+        err: String,
+    },
+    /// An error was encountered while changing the active version of an application
+    #[fail(display = "Failed to set version: {}", err)]
+    VersionError {
+        /// Underlying error encountered
+        err: String,
+    },
+    /// An error was encountered while scheduling an application
+    #[fail(display = "Failed to schedule app: {}", err)]
+    ScheduleError {
+        /// Underlying error encountered
+        err: String,
+    },
+    /// An application's persistent state couldn't be read or changed
+    #[fail(display = "Failed to access app state: {}", err)]
+    StateError {
+        /// Underlying error encountered
+        err: String,
+    },
+    /// An application's logs couldn't be read
+    #[fail(display = "Failed to read app logs: {}", err)]
+    LogError {
+        /// Underlying error encountered
+        err: String,
+    },
     /// An error was encountered while parsing data
     #[fail(display = "Failed to parse {}: {}", entity, err)]
     ParseError {
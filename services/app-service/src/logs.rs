@@ -0,0 +1,175 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use error::*;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// The directory, under each app's registry directory, that its output is written to
+pub const LOG_DIR: &str = "logs";
+/// The default number of runs to keep the output of, per app
+pub const DEFAULT_LOG_MAX_RUNS: usize = 10;
+/// The default size, in bytes, a run's log may reach before it is rotated
+pub const DEFAULT_LOG_MAX_SIZE: u64 = 1024 * 1024;
+/// The most output returned by a single tail request, so that responses fit in a UDP packet
+pub const MAX_TAIL_SIZE: u64 = 16 * 1024;
+
+/// How much application output is kept
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogSettings {
+    /// Number of runs to keep the output of, per app (0 means no limit)
+    pub max_runs: usize,
+    /// Size, in bytes, a run's log may reach before it is rotated (0 means no limit)
+    pub max_size: u64,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        LogSettings {
+            max_runs: DEFAULT_LOG_MAX_RUNS,
+            max_size: DEFAULT_LOG_MAX_SIZE,
+        }
+    }
+}
+
+/// The captured stdout and stderr of a single run of an application.
+///
+/// Each run is written to `<run>_<version>.log` in the app's log directory. Once it grows past
+/// the configured size, its contents are moved to `<run>_<version>.log.1`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunLog {
+    /// Run number, counting up from 1 for each app
+    pub run: u32,
+    /// The version of the app which was run
+    pub version: String,
+    /// Path of the log file
+    pub path: PathBuf,
+}
+
+impl RunLog {
+    fn from_path(path: PathBuf) -> Option<RunLog> {
+        let (run, version) = {
+            let name = path.file_name()?.to_str()?;
+            let name = if name.ends_with(".log") {
+                &name[0..name.len() - 4]
+            } else {
+                return None;
+            };
+            let split = name.find('_')?;
+            (
+                name[0..split].parse().ok()?,
+                name[split + 1..].to_owned(),
+            )
+        };
+
+        Some(RunLog { run, version, path })
+    }
+
+    /// Path of the previous chunk of the run's output, once the log has been rotated
+    pub fn rotated(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".1");
+        PathBuf::from(path)
+    }
+
+    /// Current size of the log, in bytes
+    pub fn size(&self) -> u64 {
+        fs::metadata(&self.path).map(|meta| meta.len()).unwrap_or(0)
+    }
+
+    /// Returns up to the last `lines` lines of the run's output (limited to [`MAX_TAIL_SIZE`]
+    /// bytes)
+    ///
+    /// [`MAX_TAIL_SIZE`]: constant.MAX_TAIL_SIZE.html
+    pub fn tail(&self, lines: usize) -> Result<String, AppError> {
+        let mut file = File::open(&self.path)?;
+        let size = file.metadata()?.len();
+        let start = size.saturating_sub(MAX_TAIL_SIZE);
+
+        file.seek(SeekFrom::Start(start))?;
+        let mut data = vec![];
+        file.read_to_end(&mut data)?;
+        let text = String::from_utf8_lossy(&data);
+
+        let mut all: Vec<&str> = text.lines().collect();
+        // Don't return a partial line from the middle of the file
+        if start > 0 && !all.is_empty() {
+            all.remove(0);
+        }
+
+        let skip = all.len().saturating_sub(lines);
+        Ok(all[skip..].join("\n"))
+    }
+}
+
+/// Lists the run logs in a log directory, oldest first
+pub fn runs(dir: &Path) -> Vec<RunLog> {
+    let mut runs: Vec<RunLog> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| RunLog::from_path(entry.path()))
+            .collect(),
+        Err(_) => vec![],
+    };
+
+    runs.sort_by_key(|log| log.run);
+    runs
+}
+
+/// Creates the log for a new run of an app, removing the oldest runs' logs if there are too many
+pub fn create(dir: &Path, version: &str, settings: &LogSettings) -> Result<(RunLog, File), AppError> {
+    fs::create_dir_all(dir)?;
+
+    let mut existing = runs(dir);
+    let run = existing.last().map(|log| log.run + 1).unwrap_or(1);
+
+    if settings.max_runs > 0 {
+        while existing.len() >= settings.max_runs {
+            let oldest = existing.remove(0);
+            let _ = fs::remove_file(oldest.rotated());
+            let _ = fs::remove_file(&oldest.path);
+        }
+    }
+
+    let log = RunLog {
+        run,
+        version: version.to_owned(),
+        path: dir.join(format!("{}_{}.log", run, version)),
+    };
+
+    // Appending means the app's writes always land at the end of the file, even after the log
+    // has been truncated by a rotation
+    let file = OpenOptions::new()
+        .append(true)
+        .create_new(true)
+        .open(&log.path)?;
+
+    Ok((log, file))
+}
+
+/// Rotates a run's log if it has grown past `max_size` bytes.
+///
+/// The app keeps writing to the same file, so the log is copied to its rotated path and then
+/// truncated. Anything written between the two steps is lost.
+pub fn rotate(log: &RunLog, max_size: u64) -> io::Result<()> {
+    if max_size == 0 || log.size() <= max_size {
+        return Ok(());
+    }
+
+    fs::copy(&log.path, log.rotated())?;
+    OpenOptions::new().write(true).open(&log.path)?.set_len(0)
+}
@@ -15,18 +15,23 @@
  */
 #![deny(warnings)]
 
+extern crate blake2_rfc;
+extern crate chrono;
+extern crate ed25519_dalek;
 #[macro_use]
 extern crate failure;
+extern crate flate2;
 extern crate getopts;
 #[macro_use]
 extern crate juniper;
 extern crate kubos_app;
 extern crate kubos_service;
+extern crate libc;
 #[macro_use]
 extern crate serde_derive;
-#[cfg(test)]
-#[macro_use]
+#[cfg_attr(test, macro_use)]
 extern crate serde_json;
+extern crate tar;
 #[cfg(test)]
 extern crate tempfile;
 extern crate toml;
@@ -34,17 +39,24 @@ extern crate uuid;
 
 mod app_entry;
 mod error;
+mod logs;
 mod objects;
+mod package;
 mod registry;
+mod sandbox;
+mod scheduler;
 mod schema;
+mod supervisor;
 #[cfg(test)]
 mod tests;
 
 use failure::Error;
 use getopts::Options;
 use kubos_service::{Config, Service};
+use package::SigningPolicy;
 use registry::AppRegistry;
 use std::env;
+use std::time::Duration;
 
 fn main() -> Result<(), Error> {
     let args: Vec<String> = env::args().collect();
@@ -64,13 +76,30 @@ fn main() -> Result<(), Error> {
         None => Config::new("app-service"),
     };
 
-    let registry = {
+    let mut registry = {
         match config.get("registry-dir") {
             Some(dir) => AppRegistry::new_from_dir(dir.as_str().unwrap())?,
             None => AppRegistry::new()?,
         }
     };
 
+    if let Some(window) = config
+        .get("rollback-window")
+        .and_then(|val| val.as_integer())
+    {
+        registry.rollback_window = Duration::from_secs(window.max(0) as u64);
+    }
+
+    registry.signing = SigningPolicy::from_config(&config)?;
+
+    if let Some(runs) = config.get("log-max-runs").and_then(|val| val.as_integer()) {
+        registry.logs.max_runs = runs.max(0) as usize;
+    }
+
+    if let Some(size) = config.get("log-max-size").and_then(|val| val.as_integer()) {
+        registry.logs.max_size = size.max(0) as u64;
+    }
+
     match matches.opt_present("b") {
         true => registry
             .run_onboot()
@@ -78,7 +107,12 @@ fn main() -> Result<(), Error> {
         false => {}
     }
 
-    Service::new(config, registry, schema::QueryRoot, schema::MutationRoot).start();
+    Service::new(config, registry, schema::QueryRoot, schema::MutationRoot)
+        .on_tick(|registry| {
+            registry.check_rollbacks();
+            registry.run_schedule();
+        })
+        .start();
 
     Ok(())
 }
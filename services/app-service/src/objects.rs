@@ -16,6 +16,9 @@
 
 use app_entry;
 use juniper::FieldResult;
+use logs::RunLog;
+use scheduler;
+use supervisor::AppStatus;
 
 /// Common response fields structure for requests
 /// which don't return any specific data
@@ -49,6 +52,94 @@ pub struct StartResponse {
     pub pid: Option<i32>,
 }
 
+/// Response fields for the `setVersion` mutation
+#[derive(GraphQLObject)]
+pub struct SetVersionResponse {
+    /// Any errors encountered by the request
+    pub errors: String,
+    /// Request completion success or failure
+    pub success: bool,
+    /// The registry entry of the newly activated version
+    pub entry: Option<KAppRegistryEntry>,
+}
+
+/// A record of a version of an app being made the active version
+#[derive(GraphQLObject)]
+pub struct Activation {
+    /// When the version was activated (seconds since the Unix epoch)
+    pub time: f64,
+    /// Why the version was activated ("register", "set-version" or "rollback")
+    pub reason: String,
+    /// The version which was active beforehand
+    pub previous: Option<String>,
+}
+
+impl From<app_entry::Activation> for Activation {
+    fn from(activation: app_entry::Activation) -> Self {
+        let reason = match activation.reason {
+            app_entry::ActivationReason::Register => "register",
+            app_entry::ActivationReason::SetVersion => "set-version",
+            app_entry::ActivationReason::Rollback => "rollback",
+        };
+
+        Activation {
+            time: activation.time as f64,
+            reason: reason.to_owned(),
+            previous: activation.previous,
+        }
+    }
+}
+
+/// Response fields for the `scheduleApp` mutation
+#[derive(GraphQLObject)]
+pub struct ScheduleResponse {
+    /// Any errors encountered by the request
+    pub errors: String,
+    /// Request completion success or failure
+    pub success: bool,
+    /// The new scheduled task
+    pub task: Option<ScheduledTask>,
+}
+
+/// An application start which is scheduled for later. Times are RFC 3339 UTC timestamps.
+#[derive(GraphQLObject)]
+pub struct ScheduledTask {
+    /// Unique name of the task
+    pub name: String,
+    /// The UUID of the application to start
+    pub uuid: String,
+    /// Additional arguments the application is started with
+    pub args: Vec<String>,
+    /// When a one-shot task runs, or when a periodic task first ran
+    pub time: Option<String>,
+    /// Number of seconds between runs of a periodic task
+    pub interval: Option<i32>,
+    /// Cron expression for a periodic task
+    pub cron: Option<String>,
+    /// When the task will next run
+    pub next_run: Option<String>,
+    /// When the task last ran
+    pub last_run: Option<String>,
+    /// The outcome of the task's last run
+    pub last_result: Option<String>,
+}
+
+impl From<scheduler::ScheduledTask> for ScheduledTask {
+    fn from(task: scheduler::ScheduledTask) -> Self {
+        ScheduledTask {
+            name: task.name,
+            uuid: task.uuid,
+            args: task.args,
+            time: task.time.map(scheduler::format_time),
+            interval: task.interval.map(|interval| interval as i32),
+            cron: task.cron,
+            next_run: task.next_run.map(scheduler::format_time),
+            last_run: task.last_run.map(scheduler::format_time),
+            last_result: task.last_result,
+        }
+    }
+}
+
 pub struct KApp(pub app_entry::App);
 
 graphql_object!(KApp: () as "App" |&self| {
@@ -91,7 +182,30 @@ graphql_object!(KApp: () as "App" |&self| {
     }
 });
 
-pub struct KAppRegistryEntry(pub app_entry::AppRegistryEntry);
+/// Response fields for the `stopApp` and `killApp` mutations
+#[derive(GraphQLObject)]
+pub struct StopResponse {
+    /// Any errors encountered by the request
+    pub errors: String,
+    /// Request completion success or failure
+    pub success: bool,
+    /// PIDs of the signalled processes
+    pub pids: Vec<i32>,
+}
+
+pub struct KAppRegistryEntry(pub app_entry::AppRegistryEntry, pub Option<AppStatus>);
+
+impl KAppRegistryEntry {
+    /// Pairs a registry entry with the run state of that version of the app
+    pub fn new(mut entry: app_entry::AppRegistryEntry, status: Option<AppStatus>) -> Self {
+        let status = status.filter(|status| status.version == entry.app.metadata.version);
+        entry.app.pid = status
+            .as_ref()
+            .and_then(|status| status.pids.last().cloned())
+            .unwrap_or(0);
+        KAppRegistryEntry(entry, status)
+    }
+}
 
 graphql_object!(KAppRegistryEntry: () as "AppRegistryEntry" |&self| {
     field app() -> FieldResult<KApp>
@@ -105,4 +219,103 @@ graphql_object!(KAppRegistryEntry: () as "AppRegistryEntry" |&self| {
     {
         Ok(self.0.active_version)
     }
+
+    field history() -> FieldResult<Vec<Activation>>
+        as "Every time this version was made the active version, oldest first"
+    {
+        Ok(self.0.history.iter().cloned().map(Activation::from).collect())
+    }
+
+    field running() -> FieldResult<bool>
+        as "Whether any instance of the app is running"
+    {
+        Ok(self.1.as_ref().map(|status| status.running()).unwrap_or(false))
+    }
+
+    field last_exit_code() -> FieldResult<Option<i32>>
+        as "Exit code of the most recent instance to exit"
+    {
+        Ok(self.1.as_ref().and_then(|status| status.last_exit_code))
+    }
+
+    field last_exit_signal() -> FieldResult<Option<i32>>
+        as "Signal which killed the most recent instance to exit"
+    {
+        Ok(self.1.as_ref().and_then(|status| status.last_exit_signal))
+    }
+
+    field last_exit_time() -> FieldResult<Option<f64>>
+        as "When the most recent instance exited (seconds since the Unix epoch)"
+    {
+        Ok(self.1.as_ref().and_then(|status| status.last_exit_timestamp()))
+    }
+
+    field limit_exceeded() -> FieldResult<Option<String>>
+        as "Resource limit the most recent instance to exit was stopped for exceeding"
+    {
+        Ok(self.1.as_ref().and_then(|status| status.limit_exceeded).map(|limit| limit.to_string()))
+    }
+
+    field last_heartbeat() -> FieldResult<Option<f64>>
+        as "When the running instance last reported that it was alive (seconds since the Unix epoch)"
+    {
+        Ok(self.1.as_ref().and_then(|status| status.last_heartbeat_timestamp()))
+    }
+
+    field restarts() -> FieldResult<i32>
+        as "Number of consecutive automatic restarts"
+    {
+        Ok(self.1.as_ref().map(|status| status.restarts as i32).unwrap_or(0))
+    }
+
+    field run() -> FieldResult<Option<i32>>
+        as "Run number of the most recently started instance, which identifies its log"
+    {
+        Ok(self.1.as_ref().and_then(|status| status.run).map(|run| run as i32))
+    }
+});
+
+/// A value in an application's persistent key-value store
+#[derive(GraphQLObject)]
+pub struct AppStateValue {
+    /// Key the value is stored under
+    pub key: String,
+    /// Value, as JSON
+    pub value: String,
+}
+
+pub struct KRunLog(pub RunLog);
+
+graphql_object!(KRunLog: () as "AppLog" |&self| {
+    description: "Captured output of a single run of an application"
+
+    field run() -> FieldResult<i32>
+        as "Run number"
+    {
+        Ok(self.0.run as i32)
+    }
+
+    field version() -> FieldResult<&String>
+        as "Version of the app which was run"
+    {
+        Ok(&self.0.version)
+    }
+
+    field path() -> FieldResult<String>
+        as "Absolute path of the log file, for download with the file transfer service"
+    {
+        Ok(self.0.path.to_string_lossy().into_owned())
+    }
+
+    field size() -> FieldResult<i32>
+        as "Current size of the log file, in bytes"
+    {
+        Ok(self.0.size() as i32)
+    }
+
+    field tail(lines = 20: i32) -> FieldResult<String>
+        as "The last lines of output"
+    {
+        Ok(self.0.tail(lines.max(0) as usize)?)
+    }
 });
@@ -0,0 +1,369 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use app_entry::AppMetadata;
+use blake2_rfc::blake2b::Blake2b;
+use ed25519_dalek::{PublicKey, Signature};
+use error::*;
+use flate2::read::GzDecoder;
+use kubos_service::Config;
+use serde_json;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::os::unix;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use tar::Archive;
+
+/// Size, in bytes, of the BLAKE2b digests used for package checksums
+pub const CHECKSUM_SIZE: usize = 64;
+
+// Whether a path looks like a gzipped tarball
+fn is_archive(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    name.ends_with(".tar.gz") || name.ends_with(".tgz")
+}
+
+/// Copies or unpacks an application package into a staging directory.
+///
+/// The package may either be a directory or a `.tar.gz` archive. Archives which hold a single
+/// top-level directory are treated as if that directory were the package.
+///
+/// Returns the path of the package's root directory (the one containing `manifest.toml`)
+pub fn stage(source: &Path, staging: &Path) -> Result<PathBuf, AppError> {
+    if source.is_dir() {
+        copy_tree(source, staging)?;
+        return Ok(staging.to_path_buf());
+    }
+
+    if !is_archive(source) {
+        return Err(AppError::RegisterError {
+            err: format!(
+                "{} is not a directory or a .tar.gz package",
+                source.display()
+            ),
+        });
+    }
+
+    fs::create_dir_all(staging)?;
+    let file = fs::File::open(source)?;
+    Archive::new(GzDecoder::new(file))
+        .unpack(staging)
+        .map_err(|err| AppError::RegisterError {
+            err: format!("Failed to unpack {}: {}", source.display(), err),
+        })?;
+
+    let contents: Vec<fs::DirEntry> = fs::read_dir(staging)?
+        .filter_map(|entry| entry.ok())
+        .collect();
+    if contents.len() == 1 && contents[0].path().is_dir() {
+        return Ok(contents[0].path());
+    }
+
+    Ok(staging.to_path_buf())
+}
+
+// Recursively copies a directory, preserving file permissions and symlinks
+fn copy_tree(source: &Path, dest: &Path) -> Result<(), AppError> {
+    fs::create_dir_all(dest)?;
+
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let target = dest.join(entry.file_name());
+
+        if file_type.is_dir() {
+            copy_tree(&entry.path(), &target)?;
+        } else if file_type.is_symlink() {
+            unix::fs::symlink(fs::read_link(entry.path())?, &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Whether a path can only descend into the directory it's joined to
+fn is_contained(path: &Path) -> bool {
+    path.components().all(|component| match component {
+        Component::Normal(_) | Component::CurDir => true,
+        _ => false,
+    })
+}
+
+// Makes sure a path from the manifest stays within the package
+fn package_path(root: &Path, path: &str) -> Result<PathBuf, AppError> {
+    let relative = Path::new(path);
+    if path.is_empty() || !is_contained(relative) {
+        return Err(AppError::RegisterError {
+            err: format!("{} is not a relative path within the package", path),
+        });
+    }
+
+    Ok(root.join(relative))
+}
+
+/// Makes sure a value used to build a registry path, such as an app's UUID or version, names
+/// exactly one directory within its parent
+pub fn check_dir_name(kind: &str, name: &str) -> Result<(), AppError> {
+    let path = Path::new(name);
+    let mut components = path.components();
+    let valid = match (components.next(), components.next()) {
+        (Some(Component::Normal(part)), None) => {
+            is_contained(path) && part.as_bytes() == name.as_bytes()
+        }
+        _ => false,
+    };
+
+    if !valid {
+        return Err(AppError::RegisterError {
+            err: format!("Invalid {}: {:?}", kind, name),
+        });
+    }
+
+    Ok(())
+}
+
+/// Works out the application's entry point, relative to the package root.
+///
+/// Packages which don't declare an `executable` in their manifest must contain exactly one
+/// file alongside `manifest.toml`, which is used as the entry point.
+pub fn find_executable(root: &Path, metadata: &AppMetadata) -> Result<String, AppError> {
+    match metadata.executable {
+        Some(ref executable) => {
+            if !package_path(root, executable)?.is_file() {
+                return Err(AppError::RegisterError {
+                    err: format!("Executable {} not found in package", executable),
+                });
+            }
+            Ok(executable.clone())
+        }
+        None => {
+            let files: Vec<fs::DirEntry> = fs::read_dir(root)?
+                .filter_map(|file| file.ok())
+                .filter(|file| file.file_name() != "manifest.toml")
+                .collect();
+
+            if files.len() != 1 || !files[0].path().is_file() {
+                return Err(AppError::RegisterError {
+                    err: "Exactly two files should be present in the app directory".to_owned(),
+                });
+            }
+
+            Ok(files[0].file_name().to_string_lossy().into_owned())
+        }
+    }
+}
+
+/// Checks every file listed in the manifest's `[checksums]` table against its BLAKE2b digest
+pub fn verify_checksums(root: &Path, checksums: &BTreeMap<String, String>) -> Result<(), AppError> {
+    for (path, expected) in checksums {
+        let file = package_path(root, path)?;
+        if !file.is_file() {
+            return Err(AppError::ChecksumError {
+                err: format!("{} not found in package", path),
+            });
+        }
+
+        if checksum(&file)? != expected.to_lowercase() {
+            return Err(AppError::ChecksumError {
+                err: format!("{} does not match its checksum", path),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Calculates the hex-encoded BLAKE2b digest of a file
+pub fn checksum(path: &Path) -> Result<String, AppError> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Blake2b::new(CHECKSUM_SIZE);
+    let mut buf = [0; 4096];
+
+    loop {
+        let len = file.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        hasher.update(&buf[0..len]);
+    }
+
+    Ok(encode_hex(hasher.finalize().as_bytes()))
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).ok())
+        .collect()
+}
+
+// Lists every file in a package, relative to its root, other than its manifest and registry entry
+fn package_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), AppError> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if entry.file_type()?.is_dir() {
+            package_files(root, &path, files)?;
+        } else if dir != root || (entry.file_name() != "manifest.toml"
+            && entry.file_name() != "app.toml")
+        {
+            // Paths under the root directory always have it as a prefix
+            files.push(path.strip_prefix(root).unwrap().to_path_buf());
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds the data which a package's signature covers.
+///
+/// The payload starts with the whole manifest except its `signature`, serialized as compact
+/// JSON with fields in declaration order and map keys sorted, on a single line. This means every
+/// setting (restart policy, checksums, run levels, user, environment, working directory and
+/// limits) is covered, regardless of how the manifest file itself is formatted.
+///
+/// This is followed by a line for every other file in the package except `manifest.toml`,
+/// sorted by path, in the same format as `b2sum`: the file's hex-encoded BLAKE2b digest, two
+/// spaces, and its path relative to the package root. Symlinks are hashed by the path they
+/// point to.
+pub fn signing_payload(root: &Path, metadata: &AppMetadata) -> Result<Vec<u8>, AppError> {
+    let mut manifest = metadata.clone();
+    manifest.signature = None;
+    let mut payload = serde_json::to_vec(&manifest).map_err(|err| AppError::SignatureError {
+        err: format!("Failed to serialize manifest: {}", err),
+    })?;
+    payload.push(b'\n');
+
+    let mut files = vec![];
+    package_files(root, root, &mut files)?;
+    files.sort();
+
+    for file in files {
+        let path = root.join(&file);
+        let digest = if fs::symlink_metadata(&path)?.file_type().is_symlink() {
+            let mut hasher = Blake2b::new(CHECKSUM_SIZE);
+            hasher.update(fs::read_link(&path)?.as_os_str().as_bytes());
+            encode_hex(hasher.finalize().as_bytes())
+        } else {
+            checksum(&path)?
+        };
+
+        payload.extend(format!("{}  {}\n", digest, file.to_string_lossy()).into_bytes());
+    }
+
+    Ok(payload)
+}
+
+/// Which package signatures the app registry accepts, configured under `[app-service]`:
+///
+/// ```toml
+/// [app-service]
+/// require-signatures = true
+///
+/// [app-service.trusted-keys]
+/// ground = "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c"
+/// ```
+///
+/// Each trusted key is a hex-encoded Ed25519 public key.
+#[derive(Clone, Debug, Default)]
+pub struct SigningPolicy {
+    /// Public keys trusted to sign packages, by name
+    pub trusted_keys: BTreeMap<String, PublicKey>,
+    /// Whether unsigned packages are refused
+    pub require_signatures: bool,
+}
+
+impl SigningPolicy {
+    /// Reads the signing policy from the app service's config
+    pub fn from_config(config: &Config) -> Result<SigningPolicy, AppError> {
+        let mut policy = SigningPolicy::default();
+
+        if let Some(require) = config
+            .get("require-signatures")
+            .and_then(|val| val.as_bool())
+        {
+            policy.require_signatures = require;
+        }
+
+        if let Some(keys) = config.get("trusted-keys") {
+            let keys = keys.as_table().ok_or_else(|| AppError::ParseError {
+                entity: "trusted-keys".to_owned(),
+                err: "Expected a table of key names and public keys".to_owned(),
+            })?;
+
+            for (name, key) in keys {
+                let key = key
+                    .as_str()
+                    .and_then(decode_hex)
+                    .and_then(|key| PublicKey::from_bytes(&key).ok())
+                    .ok_or_else(|| AppError::ParseError {
+                        entity: format!("trusted key {}", name),
+                        err: "Expected a hex-encoded Ed25519 public key".to_owned(),
+                    })?;
+                policy.trusted_keys.insert(name.clone(), key);
+            }
+        }
+
+        Ok(policy)
+    }
+
+    /// Checks the signature of the package rooted at `root` against the trusted keys.
+    ///
+    /// Unsigned packages are accepted unless signatures are required. A package which is signed
+    /// must have a valid signature from a trusted key, whether or not signatures are required.
+    pub fn verify(&self, root: &Path, metadata: &AppMetadata) -> Result<(), AppError> {
+        let signature = match metadata.signature {
+            Some(ref signature) => signature,
+            None if self.require_signatures => {
+                return Err(AppError::SignatureError {
+                    err: "Package is not signed".to_owned(),
+                })
+            }
+            None => return Ok(()),
+        };
+
+        let key = self
+            .trusted_keys
+            .get(&signature.key)
+            .ok_or_else(|| AppError::SignatureError {
+                err: format!("{} is not a trusted key", signature.key),
+            })?;
+
+        let value = decode_hex(&signature.value)
+            .and_then(|value| Signature::from_bytes(&value).ok())
+            .ok_or_else(|| AppError::SignatureError {
+                err: "Malformed signature".to_owned(),
+            })?;
+
+        key.verify_strict(&signing_payload(root, metadata)?, &value)
+            .map_err(|_| AppError::SignatureError {
+                err: "Signature does not match package contents".to_owned(),
+            })
+    }
+}
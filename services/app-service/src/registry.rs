@@ -16,18 +16,30 @@
 
 use app_entry::*;
 use error::*;
-use kubos_app::RunLevel;
+use kubos_app::{RunLevel, Store};
+use logs::{self, LogSettings, RunLog, LOG_DIR};
+use package::{self, SigningPolicy};
+use sandbox::Sandbox;
+use scheduler::{self, ScheduledTask, Scheduler, SCHEDULE_FILE};
+use serde_json::{self, Value};
 use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::Read;
 use std::os::unix;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::time::{Duration, Instant};
+use supervisor::{AppStatus, LaunchSpec, Supervisor};
 use toml;
 use uuid::Uuid;
 
 /// The default application registry directory in KubOS
 pub const K_APPS_DIR: &'static str = "/home/system/kubos/apps";
+// Prefix of the temporary directories packages are unpacked into while being registered
+const STAGING_PREFIX: &'static str = ".staging-";
+/// The directory, within an app's registry directory, its persistent state is kept in
+pub const STATE_DIR: &'static str = "state";
+/// The default number of seconds after an upgrade during which a failing app is rolled back
+pub const DEFAULT_ROLLBACK_WINDOW: u64 = 60;
 
 /// AppRegistry
 #[derive(Deserialize, Serialize, Debug)]
@@ -36,6 +48,22 @@ pub struct AppRegistry {
     pub entries: RefCell<Vec<AppRegistryEntry>>,
     /// The managed root directory of the AppRegistry
     pub apps_dir: String,
+    /// Owns the processes of all running applications
+    #[serde(skip)]
+    pub supervisor: Supervisor,
+    /// How long after a new version is activated that a failure rolls the app back to the
+    /// previously active version. A zero duration disables automatic rollback.
+    #[serde(skip)]
+    pub rollback_window: Duration,
+    /// Which package signatures are accepted when registering or activating an app
+    #[serde(skip)]
+    pub signing: SigningPolicy,
+    /// How much of each app's output is kept
+    #[serde(skip)]
+    pub logs: LogSettings,
+    /// Applications which are scheduled to be started later
+    #[serde(skip)]
+    pub scheduler: RefCell<Scheduler>,
 }
 
 impl AppRegistry {
@@ -55,6 +83,11 @@ impl AppRegistry {
         let registry = AppRegistry {
             entries: RefCell::new(Vec::new()),
             apps_dir: String::from(apps_dir),
+            supervisor: Supervisor::new(),
+            rollback_window: Duration::from_secs(DEFAULT_ROLLBACK_WINDOW),
+            signing: SigningPolicy::default(),
+            logs: LogSettings::default(),
+            scheduler: RefCell::new(Scheduler::load(&Path::new(apps_dir).join(SCHEDULE_FILE))),
         };
 
         let active_dir = PathBuf::from(format!("{}/active", apps_dir));
@@ -89,7 +122,11 @@ impl AppRegistry {
         for entry in fs::read_dir(&self.apps_dir)? {
             if let Ok(entry) = entry {
                 if let Ok(file_type) = entry.file_type() {
-                    if file_type.is_dir() && entry.file_name().to_str() != Some("active") {
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    if name.starts_with(STAGING_PREFIX) {
+                        // Left over from an interrupted registration
+                        let _ = fs::remove_dir_all(entry.path());
+                    } else if file_type.is_dir() && name != "active" {
                         reg_entries.extend(self.discover_versions(entry.path())?);
                     }
                 }
@@ -108,6 +145,9 @@ impl AppRegistry {
             }
 
             let version = version.unwrap();
+            if version.file_name() == LOG_DIR || version.file_name() == STATE_DIR {
+                continue;
+            }
 
             match version
                 .file_type()
@@ -165,12 +205,17 @@ impl AppRegistry {
         Ok(())
     }
 
-    /// Register an application binary with the AppRegistry, extracting metadata and installing it
-    /// into the proper folder structure under the AppRegistry directory.
+    /// Register an application package with the AppRegistry, extracting metadata and installing
+    /// it into the proper folder structure under the AppRegistry directory.
+    ///
+    /// A package is either a directory or a `.tar.gz` archive containing a `manifest.toml` file
+    /// alongside the application's files. The whole package is installed, and any checksums
+    /// listed in the manifest are verified before the application is activated.
     ///
     /// # Arguments
     ///
-    /// * `path` - The path to an application binary
+    /// * `path` - The path to an application package
+    /// * `uuid` - The UUID of an existing application to upgrade
     ///
     /// # Examples
     ///
@@ -187,53 +232,42 @@ impl AppRegistry {
             });
         }
 
-        if !app_path.is_dir() {
-            return Err(AppError::RegisterError {
-                err: format!("{} is not a directory", path),
-            });
-        }
+        // Unpack the package inside the registry, so it can be moved into place once it's
+        // been verified
+        let staging = PathBuf::from(format!(
+            "{}/{}{}",
+            self.apps_dir,
+            STAGING_PREFIX,
+            Uuid::new_v4().hyphenated()
+        ));
 
-        let files: Vec<fs::DirEntry> = fs::read_dir(app_path)?
-            .filter_map(|file| file.ok())
-            .collect();
+        let result = self.install(app_path, &staging, uuid);
+        let _ = fs::remove_dir_all(&staging);
+        result
+    }
 
-        if files.len() != 2 {
+    fn install(
+        &self,
+        app_path: &Path,
+        staging: &Path,
+        uuid: Option<String>,
+    ) -> Result<AppRegistryEntry, AppError> {
+        let root = package::stage(app_path, staging)?;
+
+        let manifest = root.join("manifest.toml");
+        if !manifest.is_file() {
             return Err(AppError::RegisterError {
-                err: "Exactly two files should be present in the app directory".to_owned(),
+                err: "Failed to find manifest file".to_owned(),
             });
         }
 
-        let mut manifest_file: Option<fs::DirEntry> = None;
-        let mut app_file: Option<fs::DirEntry> = None;
-
-        for file in files {
-            match file.file_name().to_str() {
-                Some("manifest.toml") => manifest_file = Some(file),
-                Some(_) => app_file = Some(file),
-                _ => {}
-            }
+        if root.join("app.toml").exists() {
+            return Err(AppError::RegisterError {
+                err: "app.toml is reserved for the app registry".to_owned(),
+            });
         }
 
-        let manifest = match manifest_file {
-            Some(file) => file,
-            None => {
-                return Err(AppError::RegisterError {
-                    err: "Failed to find manifest file".to_owned(),
-                })
-            }
-        };
-        let app = match app_file {
-            Some(file) => file,
-            None => {
-                return Err(AppError::RegisterError {
-                    err: "Failed to find app file".to_owned(),
-                })
-            }
-        };
-
-        let mut data = String::new();
-        fs::File::open(manifest.path()).and_then(|mut fp| fp.read_to_string(&mut data))?;
-
+        let data = fs::read_to_string(manifest)?;
         let metadata: AppMetadata = match toml::from_str(&data) {
             Ok(val) => val,
             Err(error) => {
@@ -244,23 +278,33 @@ impl AppRegistry {
             }
         };
 
+        // Both are used to build the app's directory, which is removed if it already exists, so
+        // must be checked before anything is changed
+        package::check_dir_name("version", &metadata.version)?;
+        // Run logs and persistent state are kept alongside the app's versions
+        if metadata.version == LOG_DIR || metadata.version == STATE_DIR {
+            return Err(AppError::RegisterError {
+                err: format!("Invalid version: {:?} is reserved", metadata.version),
+            });
+        }
+        if let Some(ref uuid) = uuid {
+            package::check_dir_name("UUID", uuid)?;
+        }
+
+        let executable = package::find_executable(&root, &metadata)?;
+        package::verify_checksums(&root, &metadata.checksums)?;
+        self.signing.verify(&root, &metadata)?;
+
         let mut entries = self.entries.borrow_mut();
+        // Upgrades use the existing UUID for the new version
         let app_uuid = match uuid {
-            Some(val) => {
-                for entry in entries.iter_mut() {
-                    // Find the existing active version of the app and make it inactive.
-                    // Use the existing UUID for our new app
-                    if entry.active_version && entry.app.uuid == val {
-                        entry.active_version = false;
-                        entry.save()?;
-                        break;
-                    }
-                }
-
-                val
-            }
+            Some(val) => val,
             None => Uuid::new_v4().hyphenated().to_string(),
         };
+        // The existing active version of the app, which the new version replaces
+        let previous = entries
+            .iter()
+            .position(|entry| entry.active_version && entry.app.uuid == app_uuid);
 
         let app_dir_str = format!(
             "{}/{}/{}",
@@ -269,30 +313,244 @@ impl AppRegistry {
             metadata.version.as_str()
         );
         let app_dir = Path::new(&app_dir_str);
-
-        if !app_dir.exists() {
-            fs::create_dir_all(app_dir)?;
-        }
-
-        fs::copy(app.path(), app_dir.join(app.file_name()))?;
-
-        self.set_active(&app_uuid, &app_dir_str)?;
+        fs::create_dir_all(format!("{}/{}", self.apps_dir, app_uuid))?;
+
+        // Re-registering an existing version replaces it. The old version is moved aside, rather
+        // than removed, until the new one is in place, so that it can be restored if anything
+        // fails. If we crash in between, it's cleaned up with the other staging directories
+        let replaced = if app_dir.exists() {
+            let aside = PathBuf::from(format!(
+                "{}/{}{}",
+                self.apps_dir,
+                STAGING_PREFIX,
+                Uuid::new_v4().hyphenated()
+            ));
+            fs::rename(app_dir, &aside)?;
+            Some(aside)
+        } else {
+            None
+        };
 
         let reg_entry = AppRegistryEntry {
             app: App {
-                uuid: app_uuid,
+                uuid: app_uuid.clone(),
                 metadata: metadata,
                 pid: 0,
-                path: format!("{}/{}", app_dir_str, app.file_name().to_string_lossy()),
+                path: format!("{}/{}", app_dir_str, executable),
             },
             active_version: true,
+            history: vec![Activation::now(
+                ActivationReason::Register,
+                previous.map(|index| entries[index].app.metadata.version.clone()),
+            )],
         };
+        // The previous version's app.toml only needs updating if it isn't being replaced
+        let deactivated = previous
+            .map(|index| entries[index].clone())
+            .filter(|entry| entry.app.metadata.version != reg_entry.app.metadata.version)
+            .map(|mut entry| {
+                entry.active_version = false;
+                entry
+            });
+
+        let result = fs::rename(&root, app_dir)
+            .map_err(AppError::from)
+            .and_then(|_| reg_entry.save())
+            .and_then(|_| match deactivated {
+                Some(ref entry) => entry.save(),
+                None => Ok(()),
+            }).and_then(|_| self.set_active(&app_uuid, &app_dir_str));
+
+        if let Err(error) = result {
+            // Put everything back the way it was. The new version is still in its staging
+            // directory if it wasn't moved into place
+            if app_dir.exists() {
+                let _ = fs::remove_dir_all(app_dir);
+            }
+            if let Some(ref aside) = replaced {
+                let _ = fs::rename(aside, app_dir);
+            }
+            if let Some(index) = previous {
+                let _ = entries[index].save();
+                let _ = self.set_active(&app_uuid, &entries[index].dir().to_string_lossy());
+            }
+            return Err(error);
+        }
+
+        if let Some(aside) = replaced {
+            let _ = fs::remove_dir_all(aside);
+        }
+        entries.retain(|ref e| {
+            e.app.uuid != reg_entry.app.uuid
+                || e.app.metadata.version != reg_entry.app.metadata.version
+        });
+        for entry in entries.iter_mut() {
+            if entry.app.uuid == reg_entry.app.uuid {
+                entry.active_version = false;
+            }
+        }
 
         // Add the new registry entry
-        entries.push(reg_entry);
-        // Create the app.toml file and save the metadata information
-        entries[entries.len() - 1].save()?;
-        Ok(entries[entries.len() - 1].clone())
+        entries.push(reg_entry.clone());
+        Ok(reg_entry)
+    }
+
+    /// Make a registered version of an application the active version. Any running instances
+    /// of the previously active version are left running.
+    ///
+    /// # Arguments
+    ///
+    /// * `app_uuid` - The UUID generated for the app when it was registered
+    /// * `version` - The version of the app to activate
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kubos_app::registry::AppRegistry;
+    /// let registry = AppRegistry::new();
+    /// registry.set_version("01234567-89ab-cdef0-1234-56789abcdef0", "1.0");
+    /// ```
+    ///
+    pub fn set_version(&self, app_uuid: &str, version: &str) -> Result<AppRegistryEntry, AppError> {
+        self.activate(app_uuid, version, ActivationReason::SetVersion)
+    }
+
+    // Make a version of an app the active version, recording the change in its history
+    fn activate(
+        &self,
+        app_uuid: &str,
+        version: &str,
+        reason: ActivationReason,
+    ) -> Result<AppRegistryEntry, AppError> {
+        let mut entries = self.entries.borrow_mut();
+
+        let index = match entries
+            .iter()
+            .position(|ref e| e.app.uuid == app_uuid && e.app.metadata.version == version)
+        {
+            Some(index) => index,
+            None => {
+                return Err(AppError::VersionError {
+                    err: format!("{} version {} not found in registry", app_uuid, version),
+                })
+            }
+        };
+
+        if entries[index].active_version {
+            return Ok(entries[index].clone());
+        }
+
+        // The installed files might have been changed, or the signing key might no longer be
+        // trusted, since the version was registered
+        self.signing
+            .verify(&entries[index].dir(), &entries[index].app.metadata)?;
+
+        self.set_active(
+            app_uuid,
+            &format!("{}/{}/{}", self.apps_dir, app_uuid, version),
+        )?;
+
+        let mut previous = None;
+        for entry in entries
+            .iter_mut()
+            .filter(|ref e| e.active_version && e.app.uuid == app_uuid)
+        {
+            entry.active_version = false;
+            entry.save()?;
+            previous = Some(entry.app.metadata.version.clone());
+        }
+
+        let entry = &mut entries[index];
+        entry.active_version = true;
+        entry.history.push(Activation::now(reason, previous));
+        entry.save()?;
+
+        Ok(entry.clone())
+    }
+
+    // The version an app would be rolled back to if it failed now, along with how much of the
+    // rollback window is left. Versions which were themselves activated by a rollback are never
+    // rolled back again, so that two broken versions can't keep replacing each other.
+    fn rollback_target(
+        &self,
+        entries: &[AppRegistryEntry],
+        entry: &AppRegistryEntry,
+    ) -> Option<(String, Duration)> {
+        let activation = entry.history.last()?;
+        if activation.reason == ActivationReason::Rollback {
+            return None;
+        }
+
+        let age = activation.age();
+        if age >= self.rollback_window {
+            return None;
+        }
+
+        let previous = activation.previous.as_ref()?;
+        if *previous == entry.app.metadata.version
+            || !entries
+                .iter()
+                .any(|ref e| e.app.uuid == entry.app.uuid && e.app.metadata.version == *previous)
+        {
+            return None;
+        }
+
+        Some((previous.clone(), self.rollback_window - age))
+    }
+
+    // Re-activate the previous version of an app which failed after being upgraded, and start it
+    fn rollback(
+        &self,
+        app_uuid: &str,
+        failed: &str,
+        previous: &str,
+        run_level: RunLevel,
+        args: Vec<String>,
+    ) -> Result<u32, AppError> {
+        eprintln!(
+            "{} version {} failed after being activated. Rolling back to version {}",
+            app_uuid, failed, previous
+        );
+
+        self.activate(app_uuid, previous, ActivationReason::Rollback)?;
+        self.start_app(app_uuid, run_level, Some(args))
+    }
+
+    /// Roll back any recently activated applications which have failed since the last call.
+    ///
+    /// The app service calls this periodically. An application is rolled back if it exits with
+    /// a failure within [`rollback_window`] of being activated, and it replaced another version.
+    /// The previous version is re-activated and started with the same run level and arguments.
+    ///
+    /// [`rollback_window`]: #structfield.rollback_window
+    pub fn check_rollbacks(&self) {
+        for spec in self.supervisor.take_rollbacks() {
+            // Only roll back if the failed version is still the active one
+            let previous = {
+                let entries = self.entries.borrow();
+                entries
+                    .iter()
+                    .find(|ref e| {
+                        e.active_version
+                            && e.app.uuid == spec.uuid
+                            && e.app.metadata.version == spec.version
+                    })
+                    .and_then(|entry| entry.history.last())
+                    .and_then(|activation| activation.previous.clone())
+            };
+
+            if let Some(previous) = previous {
+                if let Err(err) = self.rollback(
+                    &spec.uuid,
+                    &spec.version,
+                    &previous,
+                    spec.run_level,
+                    spec.args,
+                ) {
+                    eprintln!("Failed to roll back {}: {}", spec.uuid, err);
+                }
+            }
+        }
     }
 
     /// Uninstall an application from the AppRegistry
@@ -373,13 +631,17 @@ impl AppRegistry {
         args: Option<Vec<String>>,
     ) -> Result<u32, AppError> {
         // Look up the active version of the requested application
-        let app = {
+        let (app, sandbox, fallback) = {
             let entries = self.entries.borrow();
             match entries
                 .iter()
                 .find(|ref e| e.active_version && e.app.uuid == app_uuid)
             {
-                Some(entry) => entry.app.clone(),
+                Some(entry) => (
+                    entry.app.clone(),
+                    Sandbox::new(&entry.app.metadata, &entry.dir()),
+                    self.rollback_target(&entries, entry),
+                ),
                 None => {
                     return Err(AppError::StartError {
                         err: format!("No active version found for UUID {}", app_uuid),
@@ -388,6 +650,20 @@ impl AppRegistry {
             }
         };
 
+        if let RunLevel::Custom(ref level) = run_level {
+            if !app.metadata.run_levels.contains(level) {
+                let mut levels = vec!["OnBoot".to_owned(), "OnCommand".to_owned()];
+                levels.extend(app.metadata.run_levels.iter().cloned());
+                return Err(AppError::StartError {
+                    err: format!(
+                        "Unknown run level {}. Available run levels: {}",
+                        level,
+                        levels.join(", ")
+                    ),
+                });
+            }
+        }
+
         let app_path = PathBuf::from(&app.path);
         if !app_path.exists() {
             let msg = match self.uninstall(&app.uuid, &app.metadata.version) {
@@ -401,22 +677,246 @@ impl AppRegistry {
             return Err(AppError::StartError { err: msg });
         }
 
-        let mut cmd = Command::new(app_path);
+        let args = args.unwrap_or_default();
+        let result = self.supervisor.launch(LaunchSpec {
+            uuid: app.uuid.clone(),
+            name: app.metadata.name.clone(),
+            version: app.metadata.version.clone(),
+            path: app.path,
+            run_level: run_level.clone(),
+            args: args.clone(),
+            restart: app.metadata.restart,
+            rollback_until: fallback
+                .as_ref()
+                .map(|&(_, remaining)| Instant::now() + remaining),
+            log_dir: self.log_dir(app_uuid),
+            logs: self.logs.clone(),
+            state_dir: self.state_dir(app_uuid),
+            sandbox,
+        });
+
+        // A newly activated version which can't even be started is rolled back straight away
+        match (result, fallback) {
+            (Err(err), Some((previous, _))) => {
+                eprintln!("{}", err);
+                self.rollback(&app.uuid, &app.metadata.version, &previous, run_level, args)
+            }
+            (result, _) => result,
+        }
+    }
+
+    /// Stop all running instances of an application. Each instance is sent `SIGTERM`, and is
+    /// killed if it hasn't exited within `timeout`. Stopped applications are not restarted,
+    /// regardless of their restart policy.
+    ///
+    /// Returns the PIDs of the stopped processes.
+    ///
+    /// # Arguments
+    ///
+    /// * `app_uuid` - The UUID generated for the app when it was registered
+    /// * `timeout` - How long to wait before killing the application
+    pub fn stop_app(&self, app_uuid: &str, timeout: Duration) -> Result<Vec<u32>, AppError> {
+        self.supervisor.stop(app_uuid, timeout)
+    }
+
+    /// Send a signal to all running instances of an application. Killed applications are not
+    /// restarted, regardless of their restart policy.
+    ///
+    /// Returns the PIDs of the signalled processes.
+    ///
+    /// # Arguments
+    ///
+    /// * `app_uuid` - The UUID generated for the app when it was registered
+    /// * `signal` - The signal to send
+    pub fn kill_app(&self, app_uuid: &str, signal: i32) -> Result<Vec<u32>, AppError> {
+        self.supervisor.kill(app_uuid, signal)
+    }
+
+    /// Get the run state of an application, or `None` if it hasn't been started since the
+    /// app service started
+    ///
+    /// # Arguments
+    ///
+    /// * `app_uuid` - The UUID generated for the app when it was registered
+    pub fn app_status(&self, app_uuid: &str) -> Option<AppStatus> {
+        self.supervisor.reap();
+        self.supervisor.status(app_uuid)
+    }
+
+    /// Record that an application is still alive. Applications built with the `kubos_app`
+    /// framework report this with `AppInfo::heartbeat`.
+    ///
+    /// # Arguments
+    ///
+    /// * `app_uuid` - The UUID generated for the app when it was registered
+    pub fn heartbeat(&self, app_uuid: &str) -> Result<(), AppError> {
+        self.supervisor.reap();
+        self.supervisor.heartbeat(app_uuid)
+    }
 
-        cmd.env("KUBOS_APP_UUID", app.uuid.clone())
-            .arg("-r")
-            .arg(format!("{}", run_level));
+    // The directory an app's output is captured in
+    fn log_dir(&self, app_uuid: &str) -> PathBuf {
+        PathBuf::from(format!("{}/{}/{}", self.apps_dir, app_uuid, LOG_DIR))
+    }
 
-        if let Some(add_args) = args {
-            cmd.args(&add_args);
+    /// Get the logs of an application's most recent runs, oldest first. Each run's log holds
+    /// everything the app wrote to stdout and stderr.
+    ///
+    /// # Arguments
+    ///
+    /// * `app_uuid` - The UUID generated for the app when it was registered
+    pub fn app_logs(&self, app_uuid: &str) -> Result<Vec<RunLog>, AppError> {
+        if !self.entries.borrow().iter().any(|e| e.app.uuid == app_uuid) {
+            return Err(AppError::LogError {
+                err: format!("{} not found in registry", app_uuid),
+            });
         }
 
-        match cmd.spawn() {
-            Ok(child) => Ok(child.id()),
-            Err(err) => {
-                return Err(AppError::StartError {
-                    err: format!("Failed to spawn app: {:?}", err),
-                })
+        Ok(logs::runs(&self.log_dir(app_uuid)))
+    }
+
+    // The directory an app's persistent state is kept in
+    fn state_dir(&self, app_uuid: &str) -> PathBuf {
+        PathBuf::from(format!("{}/{}/{}", self.apps_dir, app_uuid, STATE_DIR))
+    }
+
+    // The persistent store of a registered app
+    fn store(&self, app_uuid: &str) -> Result<Store, AppError> {
+        if !self.entries.borrow().iter().any(|e| e.app.uuid == app_uuid) {
+            return Err(AppError::StateError {
+                err: format!("{} not found in registry", app_uuid),
+            });
+        }
+
+        Ok(Store::new(self.state_dir(app_uuid)))
+    }
+
+    /// Get the contents of an application's persistent key-value store, which apps built with
+    /// the `kubos_app` framework access with `AppInfo::store`
+    ///
+    /// # Arguments
+    ///
+    /// * `app_uuid` - The UUID generated for the app when it was registered
+    pub fn app_state(&self, app_uuid: &str) -> Result<BTreeMap<String, Value>, AppError> {
+        self.store(app_uuid)?
+            .load()
+            .map_err(|err| AppError::StateError {
+                err: err.to_string(),
+            })
+    }
+
+    /// Set a value in an application's persistent key-value store
+    ///
+    /// # Arguments
+    ///
+    /// * `app_uuid` - The UUID generated for the app when it was registered
+    /// * `key` - The key to set
+    /// * `value` - The new value, as JSON
+    pub fn set_app_state(&self, app_uuid: &str, key: &str, value: &str) -> Result<(), AppError> {
+        let store = self.store(app_uuid)?;
+        let value: Value = serde_json::from_str(value).map_err(|err| AppError::ParseError {
+            entity: format!("value of {}", key),
+            err: err.to_string(),
+        })?;
+
+        store.set(key, &value).map_err(|err| AppError::StateError {
+            err: err.to_string(),
+        })
+    }
+
+    /// Remove a value from an application's persistent key-value store
+    ///
+    /// # Arguments
+    ///
+    /// * `app_uuid` - The UUID generated for the app when it was registered
+    /// * `key` - The key to remove
+    pub fn remove_app_state(&self, app_uuid: &str, key: &str) -> Result<(), AppError> {
+        match self.store(app_uuid)?.remove(key) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(AppError::StateError {
+                err: format!("No value named {}", key),
+            }),
+            Err(err) => Err(AppError::StateError {
+                err: err.to_string(),
+            }),
+        }
+    }
+
+    /// Schedule an application to be started later, replacing any existing task with the same
+    /// name. The schedule is saved to disk, so it survives restarts of the app service.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Unique name for the task
+    /// * `app_uuid` - The UUID generated for the app when it was registered
+    /// * `args` - Additional arguments to start the app with
+    /// * `time` - When to start the app once, or when to first start a periodic app (seconds
+    ///   since the Unix epoch)
+    /// * `interval` - Start the app every `interval` seconds
+    /// * `cron` - Start the app whenever this cron expression matches
+    pub fn schedule(
+        &self,
+        name: &str,
+        app_uuid: &str,
+        args: Vec<String>,
+        time: Option<u64>,
+        interval: Option<u64>,
+        cron: Option<String>,
+    ) -> Result<ScheduledTask, AppError> {
+        if !self.entries.borrow().iter().any(|e| e.app.uuid == app_uuid) {
+            return Err(AppError::ScheduleError {
+                err: format!("{} not found in registry", app_uuid),
+            });
+        }
+
+        let task = ScheduledTask::new(
+            name,
+            app_uuid,
+            args,
+            time,
+            interval,
+            cron,
+            scheduler::now(),
+        )?;
+        self.scheduler.borrow_mut().add(task.clone())?;
+        Ok(task)
+    }
+
+    /// Remove a task from the schedule
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the task
+    pub fn unschedule(&self, name: &str) -> Result<(), AppError> {
+        self.scheduler.borrow_mut().remove(name)
+    }
+
+    /// Get all scheduled tasks
+    pub fn scheduled(&self) -> Vec<ScheduledTask> {
+        self.scheduler.borrow().tasks().to_vec()
+    }
+
+    /// Start any scheduled applications which are due.
+    ///
+    /// The app service calls this periodically. Apps are started with the `OnCommand` run
+    /// level. If a task was due while the app service wasn't running, it is run once as soon as
+    /// possible; periodic tasks then carry on from their next scheduled time.
+    pub fn run_schedule(&self) {
+        let now = scheduler::now();
+        let due = self.scheduler.borrow().due(now);
+
+        for task in due {
+            let result = match self.start_app(&task.uuid, RunLevel::OnCommand, Some(task.args)) {
+                Ok(pid) => format!("Started (pid {})", pid),
+                Err(err) => err.to_string(),
+            };
+
+            if let Err(err) = self
+                .scheduler
+                .borrow_mut()
+                .record_run(&task.name, now, result)
+            {
+                eprintln!("Failed to save schedule: {}", err);
             }
         }
     }
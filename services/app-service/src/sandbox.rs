@@ -0,0 +1,212 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use app_entry::{AppMetadata, ResourceLimits};
+use error::*;
+use libc;
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::ptr;
+
+/// A resource limit which an application was stopped for exceeding
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limit {
+    /// The application's resident set size went over its `memory` limit
+    Memory,
+    /// The application used more than its `cpu_time` limit
+    CpuTime,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Limit::Memory => write!(f, "memory"),
+            Limit::CpuTime => write!(f, "cpu-time"),
+        }
+    }
+}
+
+/// The restrictions an application is run with
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Sandbox {
+    /// Limits on the resources the application may use
+    pub limits: ResourceLimits,
+    /// The user to run the application as
+    pub user: Option<String>,
+    /// The directory to run the application in
+    pub working_dir: Option<PathBuf>,
+    /// Additional environment variables
+    pub env: BTreeMap<String, String>,
+}
+
+impl Sandbox {
+    /// The sandbox declared by an application's manifest. A relative working directory is taken
+    /// to be relative to `app_dir`, the root of the application's package
+    pub fn new(metadata: &AppMetadata, app_dir: &Path) -> Self {
+        Sandbox {
+            limits: metadata.limits.clone(),
+            user: metadata.user.clone(),
+            working_dir: metadata
+                .working_dir
+                .as_ref()
+                .map(|working_dir| app_dir.join(working_dir)),
+            env: metadata.env.clone(),
+        }
+    }
+
+    /// Creates a directory the application may write to, owned by the application's user
+    pub fn create_dir(&self, dir: &Path) -> Result<(), AppError> {
+        fs::create_dir_all(dir)?;
+
+        if let Some(ref user) = self.user {
+            let (uid, gid) = lookup_user(user)?;
+            let path =
+                CString::new(dir.as_os_str().as_bytes()).map_err(|_| AppError::StartError {
+                    err: format!("Invalid directory {:?}", dir),
+                })?;
+            if unsafe { libc::chown(path.as_ptr(), uid, gid) } != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets up a command to run inside the sandbox.
+    ///
+    /// The scheduling priority and resource limits are applied in the new process before the
+    /// application is executed, and then the process switches to the application's user. Errors
+    /// doing so are returned when the command is spawned.
+    pub fn apply(&self, cmd: &mut Command) -> Result<(), AppError> {
+        cmd.envs(&self.env);
+
+        if let Some(ref working_dir) = self.working_dir {
+            cmd.current_dir(working_dir);
+        }
+
+        let ids = match self.user {
+            Some(ref user) => Some(lookup_user(user)?),
+            None => None,
+        };
+
+        let nice = self.limits.nice;
+        let mut rlimits = vec![];
+        if let Some(limit) = self.limits.address_space {
+            rlimits.push((libc::RLIMIT_AS, limit, limit));
+        }
+        if let Some(limit) = self.limits.cpu_time {
+            // The hard limit kills the application with SIGKILL, so leave time for SIGXCPU,
+            // which identifies the reason it was stopped, to take effect first
+            rlimits.push((libc::RLIMIT_CPU, limit, limit.saturating_add(1)));
+        }
+        if let Some(limit) = self.limits.open_files {
+            rlimits.push((libc::RLIMIT_NOFILE, limit, limit));
+        }
+
+        if nice.is_none() && rlimits.is_empty() && ids.is_none() {
+            return Ok(());
+        }
+
+        // Only async-signal-safe calls may be made between fork and exec
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(nice) = nice {
+                    if libc::setpriority(libc::PRIO_PROCESS, 0, nice) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+
+                for &(resource, soft, hard) in rlimits.iter() {
+                    let limit = libc::rlimit {
+                        rlim_cur: soft,
+                        rlim_max: hard,
+                    };
+                    if libc::setrlimit(resource, &limit) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+
+                if let Some((uid, gid)) = ids {
+                    if libc::setgroups(1, &gid) != 0
+                        || libc::setgid(gid) != 0
+                        || libc::setuid(uid) != 0
+                    {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+
+                Ok(())
+            });
+        }
+
+        Ok(())
+    }
+}
+
+// Looks up the user and group IDs of a user
+fn lookup_user(user: &str) -> Result<(libc::uid_t, libc::gid_t), AppError> {
+    let name = CString::new(user).map_err(|_| AppError::StartError {
+        err: format!("Invalid user name {:?}", user),
+    })?;
+
+    let mut passwd: libc::passwd = unsafe { mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 16384];
+    let mut result = ptr::null_mut();
+
+    let err = unsafe {
+        libc::getpwnam_r(
+            name.as_ptr(),
+            &mut passwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+
+    if err != 0 {
+        return Err(AppError::StartError {
+            err: format!(
+                "Failed to look up user {}: {}",
+                user,
+                io::Error::from_raw_os_error(err)
+            ),
+        });
+    }
+
+    if result.is_null() {
+        return Err(AppError::StartError {
+            err: format!("User {} not found", user),
+        });
+    }
+
+    Ok((passwd.pw_uid, passwd.pw_gid))
+}
+
+/// The resident set size of a process, in bytes
+pub fn resident_size(pid: u32) -> Option<u64> {
+    let statm = fs::read_to_string(format!("/proc/{}/statm", pid)).ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * page_size as u64)
+}
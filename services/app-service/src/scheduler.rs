@@ -0,0 +1,415 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use chrono::{DateTime, Datelike, TimeZone, Timelike, Utc};
+use error::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use toml;
+
+/// The name of the file, in the registry directory, that the schedule is saved to
+pub const SCHEDULE_FILE: &str = "schedule.toml";
+
+// How far ahead to look for the next time a cron expression matches
+const CRON_SEARCH_YEARS: u64 = 5;
+
+/// Seconds since the Unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
+
+/// Parses an absolute UTC time in RFC 3339 format (e.g. `2018-10-19T14:30:00Z`)
+pub fn parse_time(time: &str) -> Result<u64, AppError> {
+    match DateTime::parse_from_rfc3339(time) {
+        Ok(time) if time.timestamp() >= 0 => Ok(time.timestamp() as u64),
+        _ => Err(AppError::ScheduleError {
+            err: format!("{} is not a valid RFC 3339 time", time),
+        }),
+    }
+}
+
+/// Formats seconds since the Unix epoch as an RFC 3339 UTC time
+pub fn format_time(time: u64) -> String {
+    Utc.timestamp(time as i64, 0)
+        .format("%Y-%m-%dT%H:%M:%SZ")
+        .to_string()
+}
+
+/// A parsed cron expression: `minute hour day-of-month month day-of-week`, evaluated in UTC.
+///
+/// Each field is `*`, a number, a range (`1-5`), a step (`*/15` or `0-30/10`), or a
+/// comma-separated list of those. Days of the week run from 0 (Sunday) to 6, and 7 is also
+/// accepted for Sunday. As with cron, if both the day of the month and the day of the week are
+/// restricted, a day matching either one is used.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+// Parses one cron field into a bitmask of the values it matches
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut mask = 0u64;
+
+    for part in field.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(index) => (
+                &part[0..index],
+                part[index + 1..]
+                    .parse::<u32>()
+                    .map_err(|_| format!("Invalid step in {}", part))?,
+            ),
+            None => (part, 1),
+        };
+
+        // Steps larger than the field's maximum are meaningless, and could overflow below
+        if step == 0 || step > max {
+            return Err(format!("Invalid step in {}", part));
+        }
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else {
+            let mut bounds = range.splitn(2, '-').map(|value| value.parse::<u32>());
+            match (bounds.next(), bounds.next()) {
+                (Some(Ok(start)), None) if part.contains('/') => (start, max),
+                (Some(Ok(start)), None) => (start, start),
+                (Some(Ok(start)), Some(Ok(end))) => (start, end),
+                _ => return Err(format!("Invalid value {}", part)),
+            }
+        };
+
+        if start < min || end > max || start > end {
+            return Err(format!("{} is outside of {}-{}", part, min, max));
+        }
+
+        let mut value = start;
+        while value <= end {
+            mask |= 1 << value;
+            value += step;
+        }
+    }
+
+    Ok(mask)
+}
+
+impl Cron {
+    /// Parses a cron expression
+    pub fn parse(expr: &str) -> Result<Cron, AppError> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(AppError::ScheduleError {
+                err: format!("Cron expression {:?} must have five fields", expr),
+            });
+        }
+
+        let parse = |index: usize, min: u32, max: u32| {
+            parse_field(fields[index], min, max).map_err(|err| AppError::ScheduleError {
+                err: format!("Invalid cron expression {:?}: {}", expr, err),
+            })
+        };
+
+        let mut weekdays = parse(4, 0, 7)?;
+        // Sunday may be given as either 0 or 7
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+
+        Ok(Cron {
+            minutes: parse(0, 0, 59)?,
+            hours: parse(1, 0, 23)?,
+            days: parse(2, 1, 31)?,
+            months: parse(3, 1, 12)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+
+    fn day_matches(&self, time: &DateTime<Utc>) -> bool {
+        let day = self.days & (1 << time.day()) != 0;
+        let weekday = self.weekdays & (1 << time.weekday().num_days_from_sunday()) != 0;
+
+        let day = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+
+        day && self.months & (1 << time.month()) != 0
+    }
+
+    /// Finds the first time the expression matches which is after `after` (in seconds since the
+    /// Unix epoch), or `None` if it doesn't match within the next few years
+    pub fn next_after(&self, after: u64) -> Option<u64> {
+        // Cron runs on whole minutes
+        let mut time = (after / 60 + 1) * 60;
+        let limit = after + CRON_SEARCH_YEARS * 366 * 24 * 60 * 60;
+
+        while time <= limit {
+            let date = Utc.timestamp(time as i64, 0);
+
+            if !self.day_matches(&date) {
+                // Skip to the start of the next day
+                time += 24 * 60 * 60 - u64::from(date.num_seconds_from_midnight());
+            } else if self.hours & (1 << date.hour()) == 0 {
+                // Skip to the start of the next hour
+                time += 60 * 60 - u64::from(date.minute()) * 60;
+            } else if self.minutes & (1 << date.minute()) == 0 {
+                time += 60;
+            } else {
+                return Some(time);
+            }
+        }
+
+        None
+    }
+}
+
+/// An application start which is scheduled for later.
+///
+/// A task runs either once at `time`, every `interval` seconds (starting at `time`, if given),
+/// or whenever its `cron` expression matches.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ScheduledTask {
+    /// Unique name of the task
+    pub name: String,
+    /// The UUID of the application to start
+    pub uuid: String,
+    /// Additional arguments to start the application with
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// When to run a one-shot task, or the first run of a periodic task (seconds since the Unix
+    /// epoch)
+    pub time: Option<u64>,
+    /// Number of seconds between runs of a periodic task
+    pub interval: Option<u64>,
+    /// Cron expression for a periodic task
+    pub cron: Option<String>,
+    /// When the task will next run, or `None` once a one-shot task has run
+    pub next_run: Option<u64>,
+    /// When the task last ran
+    pub last_run: Option<u64>,
+    /// The outcome of the task's last run
+    pub last_result: Option<String>,
+}
+
+impl ScheduledTask {
+    /// Creates a new task, working out when it should first run
+    pub fn new(
+        name: &str,
+        uuid: &str,
+        args: Vec<String>,
+        time: Option<u64>,
+        interval: Option<u64>,
+        cron: Option<String>,
+        now: u64,
+    ) -> Result<ScheduledTask, AppError> {
+        let error = |err: &str| {
+            Err(AppError::ScheduleError {
+                err: err.to_owned(),
+            })
+        };
+
+        if name.is_empty() {
+            return error("The task must have a name");
+        }
+
+        match (time, interval, cron.as_ref()) {
+            (None, None, None) => return error("One of time, interval or cron is required"),
+            (_, Some(_), Some(_)) => return error("Only one of interval or cron may be given"),
+            (Some(_), None, Some(_)) => {
+                return error("A start time can't be given for a cron task")
+            }
+            (_, Some(0), _) => return error("The interval must be greater than zero"),
+            (Some(time), None, None) if time <= now => return error("The time is in the past"),
+            _ => {}
+        }
+
+        let mut task = ScheduledTask {
+            name: name.to_owned(),
+            uuid: uuid.to_owned(),
+            args,
+            time,
+            interval,
+            cron,
+            next_run: None,
+            last_run: None,
+            last_result: None,
+        };
+
+        task.next_run = match task.cron {
+            Some(ref cron) => match Cron::parse(cron)?.next_after(now) {
+                Some(next) => Some(next),
+                None => return error("The cron expression never matches"),
+            },
+            None => Some(match (time, interval) {
+                // A periodic task which should have started already starts at its next slot
+                (Some(time), Some(interval)) if time <= now => {
+                    time + ((now - time) / interval + 1) * interval
+                }
+                (Some(time), _) => time,
+                (None, Some(interval)) => now + interval,
+                (None, None) => unreachable!(),
+            }),
+        };
+
+        Ok(task)
+    }
+
+    /// Whether the task should be run now
+    pub fn due(&self, now: u64) -> bool {
+        self.next_run.map(|next| next <= now).unwrap_or(false)
+    }
+
+    /// Records a run of the task and works out when it should next run. Periodic runs which were
+    /// missed (for example, while the system was off) are skipped, rather than run late.
+    pub fn record_run(&mut self, now: u64, result: String) {
+        self.last_run = Some(now);
+        self.last_result = Some(result);
+
+        self.next_run = match (self.interval, self.cron.as_ref()) {
+            (Some(interval), _) => self.next_run.map(|next| {
+                let next = next + interval;
+                if next > now {
+                    next
+                } else {
+                    next + ((now - next) / interval + 1) * interval
+                }
+            }),
+            (None, Some(cron)) => Cron::parse(cron)
+                .ok()
+                .and_then(|cron| cron.next_after(now)),
+            (None, None) => None,
+        };
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct ScheduleFile {
+    #[serde(default)]
+    task: Vec<ScheduledTask>,
+}
+
+/// The scheduled tasks, which are saved to disk whenever they change so that they survive
+/// restarts
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    path: PathBuf,
+    tasks: Vec<ScheduledTask>,
+}
+
+impl Scheduler {
+    /// Loads the saved schedule from the given file, if it exists.
+    ///
+    /// A schedule which can't be read is moved aside to `<path>.bad`, so that it isn't lost when
+    /// the new schedule is saved.
+    pub fn load(path: &Path) -> Scheduler {
+        let mut scheduler = Scheduler {
+            path: path.to_path_buf(),
+            tasks: vec![],
+        };
+
+        if !path.exists() {
+            return scheduler;
+        }
+
+        match fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|data| toml::from_str::<ScheduleFile>(&data).map_err(|err| err.to_string()))
+        {
+            Ok(file) => scheduler.tasks = file.task,
+            Err(err) => {
+                eprintln!("Failed to load schedule {}: {}", path.display(), err);
+                let mut bad = path.to_path_buf().into_os_string();
+                bad.push(".bad");
+                let _ = fs::rename(path, bad);
+            }
+        }
+
+        scheduler
+    }
+
+    fn save(&self) -> Result<(), AppError> {
+        let file = ScheduleFile {
+            task: self.tasks.clone(),
+        };
+
+        let data = toml::to_string(&file).map_err(|err| AppError::ParseError {
+            entity: "schedule".to_owned(),
+            err: err.to_string(),
+        })?;
+
+        // Write the new schedule alongside the old one, so a failure part way through doesn't
+        // lose it
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        fs::write(&temp, data)?;
+        fs::rename(&temp, &self.path)?;
+        Ok(())
+    }
+
+    /// All scheduled tasks
+    pub fn tasks(&self) -> &[ScheduledTask] {
+        &self.tasks
+    }
+
+    /// Adds a task to the schedule, replacing any existing task with the same name
+    pub fn add(&mut self, task: ScheduledTask) -> Result<(), AppError> {
+        match self.tasks.iter().position(|existing| existing.name == task.name) {
+            Some(index) => self.tasks[index] = task,
+            None => self.tasks.push(task),
+        }
+        self.save()
+    }
+
+    /// Removes a task from the schedule
+    pub fn remove(&mut self, name: &str) -> Result<(), AppError> {
+        let before = self.tasks.len();
+        self.tasks.retain(|task| task.name != name);
+        if self.tasks.len() == before {
+            return Err(AppError::ScheduleError {
+                err: format!("No task named {}", name),
+            });
+        }
+        self.save()
+    }
+
+    /// Returns the tasks which are due to run
+    pub fn due(&self, now: u64) -> Vec<ScheduledTask> {
+        self.tasks
+            .iter()
+            .filter(|task| task.due(now))
+            .cloned()
+            .collect()
+    }
+
+    /// Records the outcome of running a task
+    pub fn record_run(&mut self, name: &str, now: u64, result: String) -> Result<(), AppError> {
+        if let Some(task) = self.tasks.iter_mut().find(|task| task.name == name) {
+            task.record_run(now, result);
+        }
+        self.save()
+    }
+}
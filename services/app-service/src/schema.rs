@@ -17,8 +17,12 @@
 use juniper::FieldResult;
 use kubos_app::RunLevel;
 use kubos_service;
+use libc;
 use objects::*;
 use registry::AppRegistry;
+use scheduler;
+use std::time::Duration;
+use supervisor::DEFAULT_STOP_TIMEOUT;
 
 type Context = kubos_service::Context<AppRegistry>;
 
@@ -35,7 +39,8 @@ graphql_object!(QueryRoot : Context as "Query" |&self| {
         -> FieldResult<Vec<KAppRegistryEntry>> as "Kubos Apps Query"
     {
         let mut result: Vec<KAppRegistryEntry> = Vec::new();
-        let entries = executor.context().subsystem().entries.borrow();
+        let registry = executor.context().subsystem();
+        let entries = registry.entries.borrow();
         let mut final_iter = entries.iter().filter(|ref e| {
             if uuid.is_some() && &e.app.uuid != uuid.as_ref().unwrap() {
                 return false;
@@ -53,11 +58,48 @@ graphql_object!(QueryRoot : Context as "Query" |&self| {
         });
 
         for entry in final_iter {
-            result.push(KAppRegistryEntry(entry.clone()));
+            result.push(KAppRegistryEntry::new(entry.clone(), registry.app_status(&entry.app.uuid)));
         }
 
         Ok(result)
     }
+
+    field app_logs(&executor, uuid: String, run: Option<i32>) -> FieldResult<Vec<KRunLog>>
+        as "Captured output of an app's most recent runs"
+    {
+        let registry = executor.context().subsystem();
+        Ok(registry
+            .app_logs(&uuid)?
+            .into_iter()
+            .filter(|log| run.map(|run| log.run as i32 == run).unwrap_or(true))
+            .map(KRunLog)
+            .collect())
+    }
+
+    field app_state(&executor, uuid: String, key: Option<String>) -> FieldResult<Vec<AppStateValue>>
+        as "Contents of an app's persistent key-value store"
+    {
+        let registry = executor.context().subsystem();
+        Ok(registry
+            .app_state(&uuid)?
+            .into_iter()
+            .filter(|&(ref name, _)| key.as_ref().map(|key| name == key).unwrap_or(true))
+            .map(|(key, value)| AppStateValue { key, value: value.to_string() })
+            .collect())
+    }
+
+    field schedule(&executor, name: Option<String>, uuid: Option<String>) -> FieldResult<Vec<ScheduledTask>>
+        as "Scheduled app starts"
+    {
+        let registry = executor.context().subsystem();
+        Ok(registry
+            .scheduled()
+            .into_iter()
+            .filter(|task| name.as_ref().map(|name| &task.name == name).unwrap_or(true))
+            .filter(|task| uuid.as_ref().map(|uuid| &task.uuid == uuid).unwrap_or(true))
+            .map(ScheduledTask::from)
+            .collect())
+    }
 });
 
 ///
@@ -71,7 +113,7 @@ graphql_object!(MutationRoot : Context as "Mutation" |&self| {
     {
         let registry = executor.context().subsystem();
         Ok(match registry.register(&path, uuid) {
-            Ok(app) =>  RegisterResponse { success: true, errors: "".to_owned(), entry: Some(KAppRegistryEntry(app))},
+            Ok(app) =>  RegisterResponse { success: true, errors: "".to_owned(), entry: Some(KAppRegistryEntry::new(app, None))},
             Err(error) => RegisterResponse {
                 success: false,
                 errors: error.to_string(),
@@ -80,6 +122,24 @@ graphql_object!(MutationRoot : Context as "Mutation" |&self| {
         })
     }
 
+    field set_version(&executor, uuid: String, version: String) -> FieldResult<SetVersionResponse>
+        as "Activate a registered version of an app"
+    {
+        let registry = executor.context().subsystem();
+        Ok(match registry.set_version(&uuid, &version) {
+            Ok(entry) => SetVersionResponse {
+                success: true,
+                errors: "".to_owned(),
+                entry: Some(KAppRegistryEntry::new(entry, registry.app_status(&uuid))),
+            },
+            Err(error) => SetVersionResponse {
+                success: false,
+                errors: error.to_string(),
+                entry: None
+            }
+        })
+    }
+
     field uninstall(&executor, uuid: String, version: String) -> FieldResult<GenericResponse>
         as "Uninstall App"
     {
@@ -92,16 +152,107 @@ graphql_object!(MutationRoot : Context as "Mutation" |&self| {
     field start_app(&executor, uuid: String, run_level: String, args: Option<Vec<String>>) -> FieldResult<StartResponse>
         as "Start App"
     {
-        let run_level_o = {
-            match run_level.as_ref() {
-                "OnBoot" => RunLevel::OnBoot,
-                _ => RunLevel::OnCommand
+        Ok(match executor.context().subsystem().start_app(&uuid, RunLevel::from(run_level.as_ref()), args) {
+            Ok(num) => StartResponse { success: true, errors: "".to_owned(), pid: Some(num as i32)},
+            Err(error) => StartResponse { success: false, errors: error.to_string(), pid: None },
+        })
+    }
+
+    field heartbeat(&executor, uuid: String) -> FieldResult<GenericResponse>
+        as "Report that an app is still alive"
+    {
+        Ok(match executor.context().subsystem().heartbeat(&uuid) {
+            Ok(_) => GenericResponse { success: true, errors: "".to_owned() },
+            Err(error) => GenericResponse { success: false, errors: error.to_string() },
+        })
+    }
+
+    field schedule_app(
+        &executor,
+        name: String,
+        uuid: String,
+        args: Option<Vec<String>>,
+        time: Option<String>,
+        interval: Option<i32>,
+        cron: Option<String>
+    ) -> FieldResult<ScheduleResponse>
+        as "Schedule an app to be started at a time, periodically, or both"
+    {
+        let registry = executor.context().subsystem();
+        let result = time
+            .map(|time| scheduler::parse_time(&time))
+            .map_or(Ok(None), |time| time.map(Some))
+            .and_then(|time| {
+                registry.schedule(
+                    &name,
+                    &uuid,
+                    args.unwrap_or_default(),
+                    time,
+                    interval.map(|interval| interval.max(0) as u64),
+                    cron,
+                )
+            });
+
+        Ok(match result {
+            Ok(task) => ScheduleResponse { success: true, errors: "".to_owned(), task: Some(task.into()) },
+            Err(error) => ScheduleResponse { success: false, errors: error.to_string(), task: None },
+        })
+    }
+
+    field remove_schedule(&executor, name: String) -> FieldResult<GenericResponse>
+        as "Remove a scheduled app start"
+    {
+        Ok(match executor.context().subsystem().unschedule(&name) {
+            Ok(_) => GenericResponse { success: true, errors: "".to_owned() },
+            Err(error) => GenericResponse { success: false, errors: error.to_string() },
+        })
+    }
+
+    field set_app_state(&executor, uuid: String, key: String, value: String) -> FieldResult<GenericResponse>
+        as "Set a value, given as JSON, in an app's persistent key-value store"
+    {
+        Ok(match executor.context().subsystem().set_app_state(&uuid, &key, &value) {
+            Ok(_) => GenericResponse { success: true, errors: "".to_owned() },
+            Err(error) => GenericResponse { success: false, errors: error.to_string() },
+        })
+    }
+
+    field remove_app_state(&executor, uuid: String, key: String) -> FieldResult<GenericResponse>
+        as "Remove a value from an app's persistent key-value store"
+    {
+        Ok(match executor.context().subsystem().remove_app_state(&uuid, &key) {
+            Ok(_) => GenericResponse { success: true, errors: "".to_owned() },
+            Err(error) => GenericResponse { success: false, errors: error.to_string() },
+        })
+    }
+
+    field stop_app(&executor, uuid: String, timeout: Option<i32>) -> FieldResult<StopResponse>
+        as "Stop App"
+    {
+        let timeout = match timeout {
+            Some(secs) if secs < 0 => {
+                return Ok(StopResponse {
+                    success: false,
+                    errors: format!("Invalid timeout: {}", secs),
+                    pids: vec![],
+                })
             }
+            Some(secs) => secs as u64,
+            None => DEFAULT_STOP_TIMEOUT,
         };
 
-        Ok(match executor.context().subsystem().start_app(&uuid, run_level_o, args) {
-            Ok(num) => StartResponse { success: true, errors: "".to_owned(), pid: Some(num as i32)},
-            Err(error) => StartResponse { success: false, errors: error.to_string(), pid: None },
+        Ok(match executor.context().subsystem().stop_app(&uuid, Duration::from_secs(timeout)) {
+            Ok(pids) => StopResponse { success: true, errors: "".to_owned(), pids: pids.iter().map(|pid| *pid as i32).collect() },
+            Err(error) => StopResponse { success: false, errors: error.to_string(), pids: vec![] },
+        })
+    }
+
+    field kill_app(&executor, uuid: String, signal: Option<i32>) -> FieldResult<StopResponse>
+        as "Kill App"
+    {
+        Ok(match executor.context().subsystem().kill_app(&uuid, signal.unwrap_or(libc::SIGKILL)) {
+            Ok(pids) => StopResponse { success: true, errors: "".to_owned(), pids: pids.iter().map(|pid| *pid as i32).collect() },
+            Err(error) => StopResponse { success: false, errors: error.to_string(), pids: vec![] },
         })
     }
 });
@@ -0,0 +1,507 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use app_entry::{RestartMode, RestartPolicy};
+use error::*;
+use kubos_app::RunLevel;
+use libc;
+use logs::{self, LogSettings, RunLog};
+use sandbox::{self, Limit, Sandbox};
+use std::cmp;
+use std::collections::HashMap;
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How often the supervisor checks on running applications
+pub const SUPERVISOR_INTERVAL: Duration = Duration::from_millis(100);
+/// The default number of seconds an application has to exit after being asked to stop
+pub const DEFAULT_STOP_TIMEOUT: u64 = 5;
+
+/// Everything needed to (re)start an application
+#[derive(Clone, Debug)]
+pub struct LaunchSpec {
+    /// The generated UUID for the application
+    pub uuid: String,
+    /// The name of the application
+    pub name: String,
+    /// The version of the application being run
+    pub version: String,
+    /// The absolute path to the application binary
+    pub path: String,
+    /// The run level the application was started with
+    pub run_level: RunLevel,
+    /// Any additional arguments the application was started with
+    pub args: Vec<String>,
+    /// What to do when the application exits
+    pub restart: RestartPolicy,
+    /// Until when a failure should roll the application back to its previous version, rather
+    /// than restart it
+    pub rollback_until: Option<Instant>,
+    /// The directory the application's output is captured in
+    pub log_dir: PathBuf,
+    /// How much of the application's output to keep
+    pub logs: LogSettings,
+    /// The directory the application's persistent state is kept in
+    pub state_dir: PathBuf,
+    /// The restrictions the application is run with
+    pub sandbox: Sandbox,
+}
+
+impl LaunchSpec {
+    // Starts the application, with its stdout and stderr captured in a new run log
+    fn spawn(&self) -> Result<(Child, RunLog), AppError> {
+        let (log, file) = logs::create(&self.log_dir, &self.version, &self.logs)?;
+        let mut cmd = Command::new(&self.path);
+
+        self.sandbox.create_dir(&self.state_dir)?;
+        self.sandbox.apply(&mut cmd)?;
+        cmd.env("KUBOS_APP_UUID", self.uuid.clone())
+            .env("KUBOS_APP_NAME", self.name.clone())
+            .env("KUBOS_APP_VERSION", self.version.clone())
+            .env("KUBOS_APP_STATE_DIR", &self.state_dir)
+            .arg("-r")
+            .arg(format!("{}", self.run_level))
+            .args(&self.args)
+            .stdout(Stdio::from(file.try_clone()?))
+            .stderr(Stdio::from(file));
+
+        let child = cmd.spawn().map_err(|err| AppError::StartError {
+            err: format!("Failed to spawn app: {:?}", err),
+        })?;
+
+        Ok((child, log))
+    }
+}
+
+/// The current state of an application, as tracked by the supervisor
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AppStatus {
+    /// The version of the application which was most recently started
+    pub version: String,
+    /// The process IDs of all running instances of the application
+    pub pids: Vec<u32>,
+    /// The exit code of the most recent instance to exit, if it exited normally
+    pub last_exit_code: Option<i32>,
+    /// The signal which killed the most recent instance to exit, if it was killed
+    pub last_exit_signal: Option<i32>,
+    /// When the most recent instance exited
+    pub last_exit_time: Option<SystemTime>,
+    /// Number of consecutive automatic restarts
+    pub restarts: u32,
+    /// Whether the application is waiting to be restarted
+    pub restart_pending: bool,
+    /// The run number of the most recently started instance, which identifies its log
+    pub run: Option<u32>,
+    /// The resource limit the most recent instance to exit was stopped for exceeding, if any
+    pub limit_exceeded: Option<Limit>,
+    /// When the most recently started instance last reported that it was alive
+    pub last_heartbeat: Option<SystemTime>,
+}
+
+impl AppStatus {
+    /// Whether any instance of the application is running
+    pub fn running(&self) -> bool {
+        !self.pids.is_empty()
+    }
+
+    /// The most recent exit time, as seconds since the Unix epoch
+    pub fn last_exit_timestamp(&self) -> Option<f64> {
+        self.last_exit_time.and_then(timestamp)
+    }
+
+    /// The most recent heartbeat time, as seconds since the Unix epoch
+    pub fn last_heartbeat_timestamp(&self) -> Option<f64> {
+        self.last_heartbeat.and_then(timestamp)
+    }
+}
+
+fn timestamp(time: SystemTime) -> Option<f64> {
+    time.duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|time| time.as_secs() as f64 + f64::from(time.subsec_millis()) / 1000.0)
+}
+
+#[derive(Debug)]
+struct Instance {
+    child: Child,
+    log: RunLog,
+    spec: LaunchSpec,
+    started: Instant,
+    // Set once the instance has been asked to stop, so that it isn't restarted
+    stopping: bool,
+    kill_at: Option<Instant>,
+    // Set once the instance has been killed for exceeding a resource limit
+    exceeded: Option<Limit>,
+}
+
+#[derive(Debug)]
+struct PendingRestart {
+    spec: LaunchSpec,
+    at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    instances: Vec<Instance>,
+    pending: Vec<PendingRestart>,
+    status: HashMap<String, AppStatus>,
+    rollbacks: Vec<LaunchSpec>,
+}
+
+/// Owns the processes of running applications.
+///
+/// A background thread reaps applications as they exit, records how they exited, and restarts
+/// them according to their restart policy.
+#[derive(Debug)]
+pub struct Supervisor {
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Supervisor::new()
+    }
+}
+
+impl Supervisor {
+    /// Creates a new supervisor and starts its monitoring thread. The thread exits once the
+    /// supervisor is dropped.
+    pub fn new() -> Self {
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let weak: Weak<Mutex<State>> = Arc::downgrade(&state);
+        thread::spawn(move || loop {
+            thread::sleep(SUPERVISOR_INTERVAL);
+            match weak.upgrade() {
+                Some(state) => tick(&mut state.lock().unwrap()),
+                None => break,
+            }
+        });
+
+        Supervisor { state }
+    }
+
+    /// Starts a new instance of an application, returning its PID
+    pub fn launch(&self, spec: LaunchSpec) -> Result<u32, AppError> {
+        let (child, log) = spec.spawn()?;
+        let pid = child.id();
+
+        let mut state = self.state.lock().unwrap();
+
+        // A manual start replaces any automatic restart which is waiting to happen
+        state.pending.retain(|pending| pending.spec.uuid != spec.uuid);
+
+        {
+            let status = state
+                .status
+                .entry(spec.uuid.clone())
+                .or_insert_with(AppStatus::default);
+            status.version = spec.version.clone();
+            status.pids.push(pid);
+            status.restarts = 0;
+            status.restart_pending = false;
+            status.run = Some(log.run);
+            status.last_heartbeat = None;
+        }
+
+        state.instances.push(Instance {
+            child,
+            log,
+            spec,
+            started: Instant::now(),
+            stopping: false,
+            kill_at: None,
+            exceeded: None,
+        });
+
+        Ok(pid)
+    }
+
+    /// Asks all running instances of an application to exit by sending them `SIGTERM`. Any
+    /// instance still running after `timeout` is killed. Stopped applications aren't restarted.
+    ///
+    /// Returns the PIDs of the instances which were signalled
+    pub fn stop(&self, uuid: &str, timeout: Duration) -> Result<Vec<u32>, AppError> {
+        let kill_at = Instant::now()
+            .checked_add(timeout)
+            .ok_or_else(|| AppError::StopError {
+                err: format!("Invalid timeout: {}s", timeout.as_secs()),
+            })?;
+
+        let mut state = self.state.lock().unwrap();
+
+        let cancelled = cancel_restart(&mut state, uuid);
+        let pids = signal(&mut state, uuid, libc::SIGTERM, Some(kill_at))?;
+
+        if pids.is_empty() && !cancelled {
+            return Err(AppError::StopError {
+                err: format!("{} is not running", uuid),
+            });
+        }
+
+        Ok(pids)
+    }
+
+    /// Sends a signal to all running instances of an application. Killed applications aren't
+    /// restarted.
+    ///
+    /// Returns the PIDs of the instances which were signalled
+    pub fn kill(&self, uuid: &str, signum: i32) -> Result<Vec<u32>, AppError> {
+        let mut state = self.state.lock().unwrap();
+
+        let cancelled = cancel_restart(&mut state, uuid);
+        let pids = signal(&mut state, uuid, signum, None)?;
+
+        if pids.is_empty() && !cancelled {
+            return Err(AppError::StopError {
+                err: format!("{} is not running", uuid),
+            });
+        }
+
+        Ok(pids)
+    }
+
+    /// Records that an application is still alive
+    pub fn heartbeat(&self, uuid: &str) -> Result<(), AppError> {
+        let mut state = self.state.lock().unwrap();
+
+        match state.status.get_mut(uuid) {
+            Some(ref mut status) if status.running() => {
+                status.last_heartbeat = Some(SystemTime::now());
+                Ok(())
+            }
+            _ => Err(AppError::HeartbeatError {
+                err: format!("{} is not running", uuid),
+            }),
+        }
+    }
+
+    /// Returns the current state of an application, if it has ever been started
+    pub fn status(&self, uuid: &str) -> Option<AppStatus> {
+        self.state.lock().unwrap().status.get(uuid).cloned()
+    }
+
+    /// Returns (and forgets) the applications which have failed within their rollback window.
+    /// These aren't restarted; it's up to the caller to activate and start the previous version.
+    pub fn take_rollbacks(&self) -> Vec<LaunchSpec> {
+        let mut state = self.state.lock().unwrap();
+        state.rollbacks.drain(..).collect()
+    }
+
+    /// Reaps any applications which have exited and performs any restarts which are due.
+    ///
+    /// This is called periodically by the supervisor's thread, but may be called directly to
+    /// get an up-to-date view of the running applications.
+    pub fn reap(&self) {
+        tick(&mut self.state.lock().unwrap());
+    }
+}
+
+fn cancel_restart(state: &mut State, uuid: &str) -> bool {
+    let before = state.pending.len();
+    state.pending.retain(|pending| pending.spec.uuid != uuid);
+
+    if let Some(status) = state.status.get_mut(uuid) {
+        status.restart_pending = false;
+    }
+
+    state.pending.len() != before
+}
+
+fn signal(
+    state: &mut State,
+    uuid: &str,
+    signum: i32,
+    kill_at: Option<Instant>,
+) -> Result<Vec<u32>, AppError> {
+    let mut pids = vec![];
+
+    for instance in state
+        .instances
+        .iter_mut()
+        .filter(|instance| instance.spec.uuid == uuid)
+    {
+        let pid = instance.child.id();
+        if unsafe { libc::kill(pid as libc::pid_t, signum) } != 0 {
+            return Err(AppError::StopError {
+                err: format!(
+                    "Failed to signal {}: {}",
+                    pid,
+                    ::std::io::Error::last_os_error()
+                ),
+            });
+        }
+
+        instance.stopping = true;
+        instance.kill_at = kill_at;
+        pids.push(pid);
+    }
+
+    Ok(pids)
+}
+
+// Delay before the next restart: `backoff` seconds, doubled for each consecutive restart
+fn backoff(policy: &RestartPolicy, restarts: u32) -> Duration {
+    let delay = policy
+        .backoff
+        .checked_mul(1u64 << cmp::min(restarts, 32))
+        .unwrap_or(policy.max_backoff);
+    Duration::from_secs(cmp::min(delay, policy.max_backoff))
+}
+
+fn should_restart(policy: &RestartPolicy, status: &ExitStatus) -> bool {
+    match policy.policy {
+        RestartMode::Never => false,
+        RestartMode::OnFailure => !status.success(),
+        RestartMode::Always => true,
+    }
+}
+
+fn tick(state: &mut State) {
+    let now = Instant::now();
+    let mut exited = vec![];
+
+    // Reap anything which has exited, and kill anything which has ignored a stop request
+    let mut index = 0;
+    while index < state.instances.len() {
+        let result = state.instances[index].child.try_wait();
+        match result {
+            Ok(Some(status)) => {
+                exited.push((state.instances.remove(index), status));
+                continue;
+            }
+            Ok(None) => {
+                let instance = &mut state.instances[index];
+                if instance.kill_at.map(|at| at <= now).unwrap_or(false) {
+                    let _ = instance.child.kill();
+                    instance.kill_at = None;
+                }
+                if let Some(limit) = instance.spec.sandbox.limits.memory {
+                    let size = sandbox::resident_size(instance.child.id()).unwrap_or(0);
+                    if size > limit && instance.exceeded.is_none() {
+                        let _ = instance.child.kill();
+                        instance.exceeded = Some(Limit::Memory);
+                    }
+                }
+                if let Err(err) = logs::rotate(&instance.log, instance.spec.logs.max_size) {
+                    eprintln!("Failed to rotate log {:?}: {}", instance.log.path, err);
+                }
+            }
+            Err(err) => eprintln!(
+                "Failed to check app {}: {}",
+                state.instances[index].spec.uuid, err
+            ),
+        }
+        index += 1;
+    }
+
+    for (instance, exit) in exited {
+        let pid = instance.child.id();
+        let spec = instance.spec;
+        let policy = spec.restart.clone();
+
+        // A recently upgraded app which fails is rolled back instead of being restarted
+        let rollback = !instance.stopping
+            && !exit.success()
+            && spec.rollback_until.map(|until| now < until).unwrap_or(false);
+
+        let restart = {
+            let status = state
+                .status
+                .entry(spec.uuid.clone())
+                .or_insert_with(AppStatus::default);
+            status.pids.retain(|running| *running != pid);
+            status.last_exit_code = exit.code();
+            status.last_exit_signal = exit.signal();
+            status.last_exit_time = Some(SystemTime::now());
+            status.limit_exceeded = match exit.signal() {
+                Some(libc::SIGXCPU) => Some(Limit::CpuTime),
+                _ => instance.exceeded,
+            };
+
+            // An app which stayed up for a while is considered healthy again
+            if now.duration_since(instance.started) > Duration::from_secs(policy.max_backoff) {
+                status.restarts = 0;
+            }
+
+            let restart = !instance.stopping
+                && !rollback
+                && should_restart(&policy, &exit)
+                && (policy.max_retries == 0 || status.restarts < policy.max_retries);
+
+            if restart {
+                status.restart_pending = true;
+                Some(now + backoff(&policy, status.restarts))
+            } else {
+                None
+            }
+        };
+
+        if rollback {
+            state.rollbacks.push(spec);
+        } else if let Some(at) = restart {
+            state.pending.push(PendingRestart { spec, at });
+        }
+    }
+
+    // Perform any restarts which are due
+    let (due, waiting): (Vec<PendingRestart>, Vec<PendingRestart>) = state
+        .pending
+        .drain(..)
+        .partition(|pending| pending.at <= now);
+    state.pending = waiting;
+
+    for pending in due {
+        let spec = pending.spec;
+        let result = spec.spawn();
+
+        let status = state
+            .status
+            .entry(spec.uuid.clone())
+            .or_insert_with(AppStatus::default);
+        status.restarts += 1;
+        status.restart_pending = false;
+
+        match result {
+            Ok((child, log)) => {
+                status.pids.push(child.id());
+                status.run = Some(log.run);
+                status.last_heartbeat = None;
+                state.instances.push(Instance {
+                    child,
+                    log,
+                    spec,
+                    started: now,
+                    stopping: false,
+                    kill_at: None,
+                    exceeded: None,
+                });
+            }
+            Err(err) => {
+                eprintln!("Failed to restart app {}: {}", spec.uuid, err);
+                if spec.restart.max_retries == 0 || status.restarts < spec.restart.max_retries {
+                    status.restart_pending = true;
+                    let at = now + backoff(&spec.restart, status.restarts);
+                    state.pending.push(PendingRestart { spec, at });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use kubos_app::RunLevel;
+use kubos_service::{Config, Service};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+use registry::*;
+use schema;
+
+const UUID: &str = "a-b-c-d-e";
+
+fn install_app(registry_dir: &TempDir, body: &str) {
+    let app_dir = registry_dir.path().join(format!("{}/1.0", UUID));
+    fs::create_dir_all(app_dir.clone()).unwrap();
+
+    {
+        let mut bin = fs::File::create(app_dir.join("tiny-app")).unwrap();
+        bin.write_all(format!("#!/bin/sh\n{}\n", body).as_bytes())
+            .unwrap();
+        let mut perms = bin.metadata().unwrap().permissions();
+        perms.set_mode(0o755);
+        bin.set_permissions(perms).unwrap();
+    }
+
+    let toml = format!(
+        r#"
+            active_version = true
+
+            [app]
+            uuid = "{uuid}"
+            pid = 0
+            path = "{dir}/{uuid}/1.0/tiny-app"
+
+            [app.metadata]
+            name = "tiny-app"
+            version = "1.0"
+            author = "user"
+            run_levels = ["OnDeploy"]
+            "#,
+        uuid = UUID,
+        dir = registry_dir.path().to_string_lossy(),
+    );
+
+    fs::write(app_dir.join("app.toml"), toml).unwrap();
+}
+
+// Starts the app and waits for it to exit, returning its output
+fn run(registry: &AppRegistry, run_level: RunLevel) -> String {
+    registry.start_app(UUID, run_level, None).unwrap();
+
+    let start = Instant::now();
+    while registry.app_status(UUID).unwrap().running() && start.elapsed() < Duration::from_secs(5)
+    {
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    registry.app_logs(UUID).unwrap().pop().unwrap().tail(10).unwrap()
+}
+
+#[test]
+fn app_environment() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(
+        &registry_dir,
+        "echo $KUBOS_APP_UUID $KUBOS_APP_NAME $KUBOS_APP_VERSION",
+    );
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    assert_eq!(
+        run(&registry, RunLevel::OnCommand),
+        format!("{} tiny-app 1.0", UUID)
+    );
+}
+
+#[test]
+fn custom_run_level() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "echo \"$@\"");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    assert_eq!(
+        run(&registry, RunLevel::Custom("OnDeploy".to_owned())),
+        "-r OnDeploy"
+    );
+}
+
+#[test]
+fn custom_run_level_undeclared() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "exit 0");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    assert_eq!(
+        registry
+            .start_app(UUID, RunLevel::Custom("OnSafeMode".to_owned()), None)
+            .unwrap_err()
+            .to_string(),
+        "Failed to start app: Unknown run level OnSafeMode. \
+         Available run levels: OnBoot, OnCommand, OnDeploy"
+    );
+    assert_eq!(registry.app_status(UUID), None);
+}
+
+#[test]
+fn heartbeat() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "sleep 10");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry
+        .start_app(UUID, RunLevel::OnCommand, None)
+        .unwrap();
+    assert_eq!(registry.app_status(UUID).unwrap().last_heartbeat, None);
+
+    registry.heartbeat(UUID).unwrap();
+    assert!(registry.app_status(UUID).unwrap().last_heartbeat.is_some());
+
+    // A new instance hasn't reported in yet
+    registry.stop_app(UUID, Duration::from_secs(1)).unwrap();
+    registry
+        .start_app(UUID, RunLevel::OnCommand, None)
+        .unwrap();
+    assert_eq!(registry.app_status(UUID).unwrap().last_heartbeat, None);
+
+    registry.kill_app(UUID, 9).unwrap();
+}
+
+#[test]
+fn heartbeat_not_running() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "exit 0");
+    let service = mock_service!(registry_dir);
+
+    let mutation = r#"mutation {
+            heartbeat(uuid: "a-b-c-d-e") {
+                errors,
+                success
+            }
+        }"#;
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "heartbeat": {
+                "errors": "Failed to record heartbeat: a-b-c-d-e is not running",
+                "success": false
+            }
+        }
+    });
+
+    assert_eq!(service.process(mutation.to_owned()), expected.to_string());
+}
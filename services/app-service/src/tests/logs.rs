@@ -0,0 +1,288 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use kubos_app::RunLevel;
+use kubos_service::{Config, Service};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+use error::*;
+use logs::{self, LogSettings, RunLog};
+use registry::*;
+use schema;
+
+const UUID: &str = "a-b-c-d-e";
+
+fn install_app(registry_dir: &TempDir, body: &str) {
+    let app_dir = registry_dir.path().join(format!("{}/1.0", UUID));
+    fs::create_dir_all(app_dir.clone()).unwrap();
+
+    {
+        let mut bin = fs::File::create(app_dir.join("tiny-app")).unwrap();
+        bin.write_all(format!("#!/bin/sh\n{}\n", body).as_bytes())
+            .unwrap();
+        let mut perms = bin.metadata().unwrap().permissions();
+        perms.set_mode(0o755);
+        bin.set_permissions(perms).unwrap();
+    }
+
+    let toml = format!(
+        r#"
+            active_version = true
+
+            [app]
+            uuid = "{uuid}"
+            pid = 0
+            path = "{dir}/{uuid}/1.0/tiny-app"
+
+            [app.metadata]
+            name = "tiny-app"
+            version = "1.0"
+            author = "user"
+            "#,
+        uuid = UUID,
+        dir = registry_dir.path().to_string_lossy(),
+    );
+
+    fs::write(app_dir.join("app.toml"), toml).unwrap();
+}
+
+// Starts the app and waits for it to exit
+fn run(registry: &AppRegistry) -> u32 {
+    registry
+        .start_app(UUID, RunLevel::OnCommand, None)
+        .unwrap();
+
+    let start = Instant::now();
+    while registry.app_status(UUID).unwrap().running() && start.elapsed() < Duration::from_secs(5)
+    {
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    registry.app_status(UUID).unwrap().run.unwrap()
+}
+
+#[test]
+fn logs_capture_output() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "echo hello\necho oops >&2\nexit 1");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    assert_eq!(run(&registry), 1);
+
+    let runs = registry.app_logs(UUID).unwrap();
+    assert_eq!(runs.len(), 1);
+    assert_eq!(runs[0].run, 1);
+    assert_eq!(runs[0].version, "1.0");
+    assert_eq!(
+        runs[0].path,
+        registry_dir
+            .path()
+            .join(format!("{}/logs/1_1.0.log", UUID))
+    );
+    assert_eq!(runs[0].tail(10).unwrap(), "hello\noops");
+}
+
+#[test]
+fn logs_per_run() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "echo \"$@\"");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    assert_eq!(run(&registry), 1);
+    assert_eq!(run(&registry), 2);
+
+    let runs = registry.app_logs(UUID).unwrap();
+    assert_eq!(
+        runs.iter().map(|log| log.run).collect::<Vec<u32>>(),
+        vec![1, 2]
+    );
+    assert_eq!(runs[1].tail(1).unwrap(), "-r OnCommand");
+}
+
+#[test]
+fn logs_prune_old_runs() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "echo run");
+
+    let mut registry =
+        AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry.logs.max_runs = 2;
+
+    for _ in 0..3 {
+        run(&registry);
+    }
+
+    let runs = registry.app_logs(UUID).unwrap();
+    assert_eq!(
+        runs.iter().map(|log| log.run).collect::<Vec<u32>>(),
+        vec![2, 3]
+    );
+}
+
+#[test]
+fn logs_rotate() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(
+        &registry_dir,
+        "head -c 3000 /dev/zero | tr '\\0' 'a'\nsleep 10",
+    );
+
+    let mut registry =
+        AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry.logs.max_size = 1000;
+    registry
+        .start_app(UUID, RunLevel::OnCommand, None)
+        .unwrap();
+
+    let log = registry.app_logs(UUID).unwrap().pop().unwrap();
+    let start = Instant::now();
+    while !log.rotated().exists() && start.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    assert_eq!(fs::metadata(log.rotated()).unwrap().len(), 3000);
+    assert_eq!(log.size(), 0);
+
+    registry.kill_app(UUID, 9).unwrap();
+}
+
+#[test]
+fn logs_survive_restart() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "echo run");
+
+    {
+        let registry =
+            AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+        run(&registry);
+    }
+
+    // The log directory isn't mistaken for an app version
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    assert_eq!(registry.entries.borrow().len(), 1);
+    assert_eq!(run(&registry), 2);
+}
+
+#[test]
+fn logs_tail() {
+    let log_dir = TempDir::new().unwrap();
+    let (log, mut file) = logs::create(log_dir.path(), "1.0", &LogSettings::default()).unwrap();
+
+    for line in 0..10 {
+        writeln!(file, "line {}", line).unwrap();
+    }
+
+    assert_eq!(log.tail(3).unwrap(), "line 7\nline 8\nline 9");
+    assert_eq!(log.tail(0).unwrap(), "");
+    assert_eq!(log.tail(100).unwrap().lines().count(), 10);
+}
+
+#[test]
+fn logs_tail_large() {
+    let log_dir = TempDir::new().unwrap();
+    let (log, mut file) = logs::create(log_dir.path(), "1.0", &LogSettings::default()).unwrap();
+
+    for line in 0..10000 {
+        writeln!(file, "line {}", line).unwrap();
+    }
+
+    // Only the end of the file is read, and the partial first line is dropped
+    let tail = log.tail(100000).unwrap();
+    assert!(tail.len() as u64 <= logs::MAX_TAIL_SIZE);
+    assert!(tail.starts_with("line "));
+    assert!(tail.ends_with("line 9999"));
+}
+
+#[test]
+fn logs_ignore_other_files() {
+    let log_dir = TempDir::new().unwrap();
+    fs::write(log_dir.path().join("notes.txt"), "").unwrap();
+    fs::write(log_dir.path().join("x_1.0.log"), "").unwrap();
+    fs::write(log_dir.path().join("3_2.0.log.1"), "").unwrap();
+    fs::write(log_dir.path().join("3_2.0.log"), "").unwrap();
+
+    assert_eq!(
+        logs::runs(log_dir.path()),
+        vec![RunLog {
+            run: 3,
+            version: "2.0".to_owned(),
+            path: log_dir.path().join("3_2.0.log"),
+        }]
+    );
+}
+
+#[test]
+fn logs_query() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "echo one\necho two\necho three");
+
+    {
+        let registry =
+            AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+        run(&registry);
+        run(&registry);
+    }
+
+    let service = mock_service!(registry_dir);
+
+    let query = format!(
+        r#"{{
+            appLogs(uuid: "{}", run: 2) {{
+                run,
+                version,
+                size,
+                tail(lines: 2)
+            }}
+        }}"#,
+        UUID
+    );
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "appLogs": [{
+                "run": 2,
+                "version": "1.0",
+                "size": 14,
+                "tail": "two\nthree"
+            }]
+        }
+    });
+
+    assert_eq!(service.process(query.to_owned()), expected.to_string());
+}
+
+#[test]
+fn logs_unknown_app() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "exit 0");
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+
+    for uuid in &["f-g-h-i-j", "..", "../.."] {
+        assert_eq!(
+            registry.app_logs(uuid).unwrap_err(),
+            AppError::LogError {
+                err: format!("{} not found in registry", uuid),
+            }
+        );
+    }
+}
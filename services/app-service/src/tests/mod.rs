@@ -37,8 +37,17 @@ macro_rules! mock_service {
     }};
 }
 
+mod lifecycle;
+mod logs;
 mod register_app;
+mod register_package;
 mod registry_onboot;
 mod registry_start_app;
 mod registry_test;
+mod rollback;
+mod sandbox;
+mod scheduler;
+mod signing;
+mod state;
+mod supervisor;
 mod upgrade_app;
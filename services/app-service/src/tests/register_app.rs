@@ -118,7 +118,7 @@ fn register_no_manifest() {
             "msg": {
                "register": {
                    "entry": null,
-                   "errors": "Failed to register app: Exactly two files should be present in the app directory",
+                   "errors": "Failed to register app: Failed to find manifest file",
                    "success": false,
                }
             }
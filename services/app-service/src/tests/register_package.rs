@@ -0,0 +1,481 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tar;
+
+use tempfile::TempDir;
+
+use app_entry::AppRegistryEntry;
+use error::*;
+use package;
+use registry::*;
+
+// Builds a package with a binary in a subdirectory, plus some assets
+fn create_package(dir: &Path, manifest_extra: &str) {
+    fs::create_dir_all(dir.join("bin")).unwrap();
+    fs::create_dir_all(dir.join("data/tables")).unwrap();
+
+    fs::write(dir.join("bin/tiny-app"), "#!/bin/sh\nexit 0\n").unwrap();
+    let mut perms = fs::metadata(dir.join("bin/tiny-app")).unwrap().permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(dir.join("bin/tiny-app"), perms).unwrap();
+
+    fs::write(dir.join("config.toml"), "rate = 5\n").unwrap();
+    fs::write(dir.join("data/tables/lookup.csv"), "1,2,3\n").unwrap();
+
+    let manifest = format!(
+        r#"
+            name = "tiny-app"
+            version = "1.0"
+            author = "user"
+            {}
+            "#,
+        manifest_extra
+    );
+    fs::write(dir.join("manifest.toml"), manifest).unwrap();
+}
+
+// Packs a directory into a .tar.gz file, optionally nested under a top-level directory
+fn create_archive(source: &Path, archive: &Path, prefix: &str) {
+    let file = fs::File::create(archive).unwrap();
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    builder.append_dir_all(prefix, source).unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+}
+
+fn staging_dirs(registry_dir: &TempDir) -> usize {
+    fs::read_dir(registry_dir.path())
+        .unwrap()
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with(".staging")
+        })
+        .count()
+}
+
+#[test]
+fn register_tree() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "executable = \"bin/tiny-app\"");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    let entry = registry
+        .register(&package_dir.path().to_string_lossy(), None)
+        .unwrap();
+
+    let app_dir = registry_dir
+        .path()
+        .join(format!("{}/1.0", entry.app.uuid));
+    assert_eq!(
+        entry.app.path,
+        format!("{}/bin/tiny-app", app_dir.to_string_lossy())
+    );
+    assert_eq!(
+        fs::read_to_string(app_dir.join("data/tables/lookup.csv")).unwrap(),
+        "1,2,3\n"
+    );
+    assert!(app_dir.join("config.toml").is_file());
+    assert!(app_dir.join("manifest.toml").is_file());
+    assert!(app_dir.join("app.toml").is_file());
+
+    // Permissions are preserved, so the app can still be run
+    let mode = fs::metadata(app_dir.join("bin/tiny-app"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o111, 0o111);
+
+    assert_eq!(staging_dirs(&registry_dir), 0);
+
+    // The entry survives a restart of the service
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    let entries = registry.entries.borrow();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].app.path, entry.app.path);
+    assert_eq!(
+        entries[0].app.metadata.executable,
+        Some("bin/tiny-app".to_owned())
+    );
+}
+
+#[test]
+fn register_tree_no_executable() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+
+    assert_eq!(
+        registry
+            .register(&package_dir.path().to_string_lossy(), None)
+            .unwrap_err(),
+        AppError::RegisterError {
+            err: "Exactly two files should be present in the app directory".to_owned(),
+        }
+    );
+    assert_eq!(registry.entries.borrow().len(), 0);
+    assert_eq!(staging_dirs(&registry_dir), 0);
+}
+
+#[test]
+fn register_executable_missing() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "executable = \"bin/other-app\"");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+
+    assert_eq!(
+        registry
+            .register(&package_dir.path().to_string_lossy(), None)
+            .unwrap_err(),
+        AppError::RegisterError {
+            err: "Executable bin/other-app not found in package".to_owned(),
+        }
+    );
+}
+
+#[test]
+fn register_executable_outside_package() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "executable = \"../bin/tiny-app\"");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+
+    assert_eq!(
+        registry
+            .register(&package_dir.path().to_string_lossy(), None)
+            .unwrap_err(),
+        AppError::RegisterError {
+            err: "../bin/tiny-app is not a relative path within the package".to_owned(),
+        }
+    );
+}
+
+#[test]
+fn register_invalid_version() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    fs::write(package_dir.path().join("tiny-app"), "#!/bin/sh\nexit 0\n").unwrap();
+    fs::write(
+        package_dir.path().join("manifest.toml"),
+        "name = \"tiny-app\"\nversion = \"../..\"\nauthor = \"user\"\n",
+    ).unwrap();
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+
+    assert_eq!(
+        registry
+            .register(&package_dir.path().to_string_lossy(), None)
+            .unwrap_err(),
+        AppError::RegisterError {
+            err: "Invalid version: \"../..\"".to_owned(),
+        }
+    );
+}
+
+#[test]
+fn register_reserved_version() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "executable = \"bin/tiny-app\"");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    let entry = registry
+        .register(&package_dir.path().to_string_lossy(), None)
+        .unwrap();
+    let app_dir = registry_dir.path().join(&entry.app.uuid);
+
+    for version in &["logs", "state"] {
+        fs::create_dir_all(app_dir.join(version)).unwrap();
+        fs::write(app_dir.join(version).join("keep"), "").unwrap();
+        fs::write(
+            package_dir.path().join("manifest.toml"),
+            format!(
+                "name = \"tiny-app\"\nversion = {:?}\nauthor = \"user\"\n\
+                 executable = \"bin/tiny-app\"\n",
+                version
+            ),
+        ).unwrap();
+
+        assert_eq!(
+            registry
+                .register(
+                    &package_dir.path().to_string_lossy(),
+                    Some(entry.app.uuid.clone())
+                ).unwrap_err(),
+            AppError::RegisterError {
+                err: format!("Invalid version: {:?} is reserved", version),
+            }
+        );
+        assert!(app_dir.join(version).join("keep").exists());
+    }
+    assert_eq!(registry.entries.borrow().len(), 1);
+}
+
+#[test]
+fn register_invalid_uuid() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "executable = \"bin/tiny-app\"");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    let entry = registry
+        .register(&package_dir.path().to_string_lossy(), None)
+        .unwrap();
+
+    for uuid in &["..", "a/b", "/tmp", ""] {
+        assert_eq!(
+            registry
+                .register(
+                    &package_dir.path().to_string_lossy(),
+                    Some(uuid.to_string())
+                ).unwrap_err(),
+            AppError::RegisterError {
+                err: format!("Invalid UUID: {:?}", uuid),
+            }
+        );
+    }
+
+    // Nothing was changed by the rejected requests
+    let entries = registry.entries.borrow();
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0].active_version);
+    assert!(Path::new(&entry.app.path).is_file());
+}
+
+#[test]
+fn register_archive() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    let archive_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "executable = \"bin/tiny-app\"");
+
+    let archive = archive_dir.path().join("tiny-app.tar.gz");
+    create_archive(package_dir.path(), &archive, ".");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    let entry = registry
+        .register(&archive.to_string_lossy(), None)
+        .unwrap();
+
+    let app_dir = registry_dir
+        .path()
+        .join(format!("{}/1.0", entry.app.uuid));
+    assert!(app_dir.join("bin/tiny-app").is_file());
+    assert!(app_dir.join("data/tables/lookup.csv").is_file());
+    assert_eq!(staging_dirs(&registry_dir), 0);
+}
+
+#[test]
+fn register_archive_nested() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    let archive_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "executable = \"bin/tiny-app\"");
+
+    let archive = archive_dir.path().join("tiny-app.tgz");
+    create_archive(package_dir.path(), &archive, "tiny-app");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    let entry = registry
+        .register(&archive.to_string_lossy(), None)
+        .unwrap();
+
+    let app_dir = registry_dir
+        .path()
+        .join(format!("{}/1.0", entry.app.uuid));
+    assert!(app_dir.join("manifest.toml").is_file());
+    assert!(app_dir.join("bin/tiny-app").is_file());
+}
+
+#[test]
+fn register_not_a_package() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    let file = package_dir.path().join("tiny-app.zip");
+    fs::write(&file, "not a package").unwrap();
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+
+    assert_eq!(
+        registry.register(&file.to_string_lossy(), None).unwrap_err(),
+        AppError::RegisterError {
+            err: format!("{} is not a directory or a .tar.gz package", file.display()),
+        }
+    );
+}
+
+#[test]
+fn register_checksums_good() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "");
+
+    let binary = package::checksum(&package_dir.path().join("bin/tiny-app")).unwrap();
+    let table = package::checksum(&package_dir.path().join("data/tables/lookup.csv")).unwrap();
+    assert_eq!(binary.len(), package::CHECKSUM_SIZE * 2);
+
+    create_package(
+        package_dir.path(),
+        &format!(
+            "executable = \"bin/tiny-app\"\n[checksums]\n\"bin/tiny-app\" = \"{}\"\n\"data/tables/lookup.csv\" = \"{}\"",
+            binary,
+            table.to_uppercase()
+        ),
+    );
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    let entry = registry
+        .register(&package_dir.path().to_string_lossy(), None)
+        .unwrap();
+
+    assert_eq!(entry.app.metadata.checksums.len(), 2);
+}
+
+#[test]
+fn register_checksums_mismatch() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(
+        package_dir.path(),
+        "executable = \"bin/tiny-app\"\n[checksums]\n\"config.toml\" = \"0123\"",
+    );
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+
+    assert_eq!(
+        registry
+            .register(&package_dir.path().to_string_lossy(), None)
+            .unwrap_err(),
+        AppError::ChecksumError {
+            err: "config.toml does not match its checksum".to_owned(),
+        }
+    );
+    assert_eq!(registry.entries.borrow().len(), 0);
+    assert_eq!(staging_dirs(&registry_dir), 0);
+}
+
+#[test]
+fn register_checksums_missing_file() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(
+        package_dir.path(),
+        "executable = \"bin/tiny-app\"\n[checksums]\n\"data/missing.csv\" = \"0123\"",
+    );
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+
+    assert_eq!(
+        registry
+            .register(&package_dir.path().to_string_lossy(), None)
+            .unwrap_err(),
+        AppError::ChecksumError {
+            err: "data/missing.csv not found in package".to_owned(),
+        }
+    );
+}
+
+#[test]
+fn register_same_version_replaces() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "executable = \"bin/tiny-app\"");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    let entry = registry
+        .register(&package_dir.path().to_string_lossy(), None)
+        .unwrap();
+
+    fs::remove_file(package_dir.path().join("config.toml")).unwrap();
+    registry
+        .register(
+            &package_dir.path().to_string_lossy(),
+            Some(entry.app.uuid.clone()),
+        )
+        .unwrap();
+
+    let app_dir = registry_dir
+        .path()
+        .join(format!("{}/1.0", entry.app.uuid));
+    assert!(!app_dir.join("config.toml").exists());
+
+    let entries = registry.entries.borrow();
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0].active_version);
+    assert_eq!(staging_dirs(&registry_dir), 0);
+}
+
+#[test]
+fn register_same_version_restores_on_error() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "executable = \"bin/tiny-app\"");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    let entry = registry
+        .register(&package_dir.path().to_string_lossy(), None)
+        .unwrap();
+
+    // Activating the new version fails when the active symlink can't be replaced
+    let active = registry_dir.path().join("active").join(&entry.app.uuid);
+    fs::remove_file(&active).unwrap();
+    fs::create_dir_all(active.join("blocked")).unwrap();
+
+    fs::remove_file(package_dir.path().join("config.toml")).unwrap();
+    assert!(
+        registry
+            .register(
+                &package_dir.path().to_string_lossy(),
+                Some(entry.app.uuid.clone())
+            ).is_err()
+    );
+
+    // The old version's files are back in place, and it's still registered
+    let app_dir = registry_dir
+        .path()
+        .join(format!("{}/1.0", entry.app.uuid));
+    assert!(app_dir.join("config.toml").exists());
+    assert!(Path::new(&entry.app.path).is_file());
+    assert!(AppRegistryEntry::from_dir(&app_dir).unwrap().active_version);
+    let entries = registry.entries.borrow();
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0].active_version);
+    assert_eq!(staging_dirs(&registry_dir), 0);
+}
+
+#[test]
+fn discover_removes_staging() {
+    let registry_dir = TempDir::new().unwrap();
+    fs::create_dir_all(registry_dir.path().join(".staging-1234/bin")).unwrap();
+
+    AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+
+    assert_eq!(staging_dirs(&registry_dir), 0);
+}
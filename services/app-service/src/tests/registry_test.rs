@@ -61,11 +61,47 @@ fn serialize_entry() {
                 name: String::from("dummy"),
                 version: String::from("0.0.1"),
                 author: String::from("noone"),
+                executable: Some(String::from("bin/dummy")),
+                run_levels: vec![String::from("OnDeploy")],
+                user: Some(String::from("payload")),
+                working_dir: Some(String::from("data")),
+                restart: RestartPolicy {
+                    policy: RestartMode::OnFailure,
+                    max_retries: 3,
+                    ..Default::default()
+                },
+                limits: ResourceLimits {
+                    memory: Some(1 << 20),
+                    nice: Some(5),
+                    ..Default::default()
+                },
+                env: vec![(String::from("MODE"), String::from("safe"))]
+                    .into_iter()
+                    .collect(),
+                checksums: vec![(String::from("bin/dummy"), String::from("abcd"))]
+                    .into_iter()
+                    .collect(),
+                signature: Some(PackageSignature {
+                    key: String::from("ground"),
+                    value: String::from("0123"),
+                }),
             },
             pid: 101,
             path: String::from("/fake/path"),
         },
         active_version: true,
+        history: vec![
+            Activation {
+                time: 1000,
+                reason: ActivationReason::Register,
+                previous: None,
+            },
+            Activation {
+                time: 2000,
+                reason: ActivationReason::Rollback,
+                previous: Some(String::from("0.0.2")),
+            },
+        ],
     };
 
     let str = toml::to_string(&dummy).unwrap();
@@ -78,4 +114,14 @@ fn serialize_entry() {
     assert_eq!(parsed.app.metadata.name, dummy.app.metadata.name);
     assert_eq!(parsed.app.metadata.version, dummy.app.metadata.version);
     assert_eq!(parsed.app.metadata.author, dummy.app.metadata.author);
+    assert_eq!(parsed.app.metadata.executable, dummy.app.metadata.executable);
+    assert_eq!(parsed.app.metadata.run_levels, dummy.app.metadata.run_levels);
+    assert_eq!(parsed.app.metadata.user, dummy.app.metadata.user);
+    assert_eq!(parsed.app.metadata.working_dir, dummy.app.metadata.working_dir);
+    assert_eq!(parsed.app.metadata.restart, dummy.app.metadata.restart);
+    assert_eq!(parsed.app.metadata.limits, dummy.app.metadata.limits);
+    assert_eq!(parsed.app.metadata.env, dummy.app.metadata.env);
+    assert_eq!(parsed.app.metadata.checksums, dummy.app.metadata.checksums);
+    assert_eq!(parsed.app.metadata.signature, dummy.app.metadata.signature);
+    assert_eq!(parsed.history, dummy.history);
 }
@@ -0,0 +1,307 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use kubos_app::RunLevel;
+use kubos_service::{Config, Service};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use tempfile::TempDir;
+
+use app_entry::*;
+use error::*;
+use registry::*;
+use schema;
+
+const UUID: &str = "a-b-c-d-e";
+
+// Installs a version of an app directly into the registry directory. An active version is
+// recorded as having been upgraded from `previous` `age` seconds ago.
+fn install_version(registry_dir: &TempDir, version: &str, body: &str, active: Option<(&str, u64)>) {
+    let app_dir = registry_dir.path().join(format!("{}/{}", UUID, version));
+    fs::create_dir_all(app_dir.clone()).unwrap();
+
+    {
+        let mut bin = fs::File::create(app_dir.join("tiny-app")).unwrap();
+        bin.write_all(format!("#!/bin/sh\n{}\n", body).as_bytes())
+            .unwrap();
+        let mut perms = bin.metadata().unwrap().permissions();
+        perms.set_mode(0o755);
+        bin.set_permissions(perms).unwrap();
+    }
+
+    let history = match active {
+        Some((previous, age)) => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            format!(
+                "[[history]]\ntime = {}\nreason = \"register\"\nprevious = \"{}\"",
+                now - age,
+                previous
+            )
+        }
+        None => String::new(),
+    };
+
+    let toml = format!(
+        r#"
+            active_version = {active}
+
+            [app]
+            uuid = "{uuid}"
+            pid = 0
+            path = "{dir}/{uuid}/{version}/tiny-app"
+
+            [app.metadata]
+            name = "tiny-app"
+            version = "{version}"
+            author = "user"
+
+            {history}
+            "#,
+        active = active.is_some(),
+        uuid = UUID,
+        dir = registry_dir.path().to_string_lossy(),
+        version = version,
+        history = history,
+    );
+
+    fs::write(app_dir.join("app.toml"), toml).unwrap();
+}
+
+fn active_entry(registry: &AppRegistry) -> AppRegistryEntry {
+    registry
+        .entries
+        .borrow()
+        .iter()
+        .find(|entry| entry.active_version)
+        .unwrap()
+        .clone()
+}
+
+// Lets the registry check for failed apps until the condition is met (or a few seconds pass)
+fn wait_for<F>(registry: &AppRegistry, condition: F)
+where
+    F: Fn(&AppRegistry) -> bool,
+{
+    let start = Instant::now();
+    while !condition(registry) && start.elapsed() < Duration::from_secs(5) {
+        registry.check_rollbacks();
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn set_version_good() {
+    let registry_dir = TempDir::new().unwrap();
+    install_version(&registry_dir, "1.0", "exit 0", None);
+    install_version(&registry_dir, "2.0", "exit 0", Some(("1.0", 1000)));
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    let entry = registry.set_version(UUID, "1.0").unwrap();
+
+    assert!(entry.active_version);
+    assert_eq!(entry.app.metadata.version, "1.0");
+    let activation = entry.history.last().unwrap();
+    assert_eq!(activation.reason, ActivationReason::SetVersion);
+    assert_eq!(activation.previous, Some("2.0".to_owned()));
+
+    assert_eq!(
+        fs::read_link(registry_dir.path().join(format!("active/{}", UUID))).unwrap(),
+        registry_dir.path().join(format!("{}/1.0", UUID))
+    );
+
+    // The change is saved to each version's app.toml
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    let entries = registry.entries.borrow();
+    assert_eq!(entries.iter().filter(|e| e.active_version).count(), 1);
+    let entry = entries.iter().find(|e| e.active_version).unwrap();
+    assert_eq!(entry.app.metadata.version, "1.0");
+    assert_eq!(entry.history.len(), 1);
+    assert_eq!(entry.history[0].reason, ActivationReason::SetVersion);
+}
+
+#[test]
+fn set_version_not_found() {
+    let registry_dir = TempDir::new().unwrap();
+    install_version(&registry_dir, "1.0", "exit 0", Some(("0.9", 1000)));
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+
+    assert_eq!(
+        registry.set_version(UUID, "2.0").unwrap_err(),
+        AppError::VersionError {
+            err: format!("{} version 2.0 not found in registry", UUID),
+        }
+    );
+    assert_eq!(active_entry(&registry).app.metadata.version, "1.0");
+}
+
+#[test]
+fn rollback_on_crash() {
+    let registry_dir = TempDir::new().unwrap();
+    install_version(&registry_dir, "1.0", "sleep 10", None);
+    install_version(&registry_dir, "2.0", "exit 1", Some(("1.0", 0)));
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry
+        .start_app(UUID, RunLevel::OnCommand, Some(vec!["-x".to_owned()]))
+        .unwrap();
+
+    wait_for(&registry, |registry| {
+        active_entry(registry).app.metadata.version == "1.0"
+    });
+
+    let entry = active_entry(&registry);
+    assert_eq!(entry.app.metadata.version, "1.0");
+    let activation = entry.history.last().unwrap();
+    assert_eq!(activation.reason, ActivationReason::Rollback);
+    assert_eq!(activation.previous, Some("2.0".to_owned()));
+
+    // The previous version is started in place of the failed one
+    let status = registry.app_status(UUID).unwrap();
+    assert_eq!(status.version, "1.0");
+    assert!(status.running());
+
+    registry.kill_app(UUID, 9).unwrap();
+}
+
+#[test]
+fn rollback_on_start_failure() {
+    let registry_dir = TempDir::new().unwrap();
+    install_version(&registry_dir, "1.0", "sleep 10", None);
+    install_version(&registry_dir, "2.0", "exit 0", Some(("1.0", 0)));
+
+    // Make the new version impossible to run
+    fs::remove_file(registry_dir.path().join(format!("{}/2.0/tiny-app", UUID))).unwrap();
+    fs::create_dir(registry_dir.path().join(format!("{}/2.0/tiny-app", UUID))).unwrap();
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    let pid = registry.start_app(UUID, RunLevel::OnCommand, None).unwrap();
+
+    assert_eq!(active_entry(&registry).app.metadata.version, "1.0");
+    let status = registry.app_status(UUID).unwrap();
+    assert_eq!(status.version, "1.0");
+    assert_eq!(status.pids, vec![pid]);
+
+    registry.kill_app(UUID, 9).unwrap();
+}
+
+#[test]
+fn no_rollback_outside_window() {
+    let registry_dir = TempDir::new().unwrap();
+    install_version(&registry_dir, "1.0", "sleep 10", None);
+    install_version(&registry_dir, "2.0", "exit 1", Some(("1.0", 1000)));
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry.start_app(UUID, RunLevel::OnCommand, None).unwrap();
+
+    wait_for(&registry, |registry| {
+        !registry.app_status(UUID).unwrap().running()
+    });
+    registry.check_rollbacks();
+
+    assert_eq!(active_entry(&registry).app.metadata.version, "2.0");
+    assert_eq!(registry.app_status(UUID).unwrap().last_exit_code, Some(1));
+}
+
+#[test]
+fn no_rollback_when_disabled() {
+    let registry_dir = TempDir::new().unwrap();
+    install_version(&registry_dir, "1.0", "sleep 10", None);
+    install_version(&registry_dir, "2.0", "exit 1", Some(("1.0", 0)));
+
+    let mut registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry.rollback_window = Duration::from_secs(0);
+    registry.start_app(UUID, RunLevel::OnCommand, None).unwrap();
+
+    wait_for(&registry, |registry| {
+        !registry.app_status(UUID).unwrap().running()
+    });
+    registry.check_rollbacks();
+
+    assert_eq!(active_entry(&registry).app.metadata.version, "2.0");
+}
+
+#[test]
+fn no_rollback_after_rollback() {
+    let registry_dir = TempDir::new().unwrap();
+    install_version(&registry_dir, "1.0", "exit 1", None);
+    install_version(&registry_dir, "2.0", "exit 1", Some(("1.0", 0)));
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry.start_app(UUID, RunLevel::OnCommand, None).unwrap();
+
+    // Both versions are broken. Once rolled back to 1.0, the app stays on 1.0
+    wait_for(&registry, |registry| {
+        let status = registry.app_status(UUID).unwrap();
+        status.version == "1.0" && !status.running()
+    });
+    registry.check_rollbacks();
+
+    let entry = active_entry(&registry);
+    assert_eq!(entry.app.metadata.version, "1.0");
+    assert_eq!(
+        entry.history.last().unwrap().reason,
+        ActivationReason::Rollback
+    );
+}
+
+#[test]
+fn set_version_mutation() {
+    let registry_dir = TempDir::new().unwrap();
+    install_version(&registry_dir, "1.0", "exit 0", None);
+    install_version(&registry_dir, "2.0", "exit 0", Some(("1.0", 1000)));
+
+    let service = mock_service!(registry_dir);
+
+    let query = format!(
+        r#"mutation {{
+            setVersion(uuid: "{}", version: "1.0") {{
+                success,
+                errors,
+                entry {{
+                    active,
+                    app {{ version }},
+                    history {{ reason, previous }}
+                }}
+            }}
+        }}"#,
+        UUID
+    );
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "setVersion": {
+                "entry": {
+                    "active": true,
+                    "app": { "version": "1.0" },
+                    "history": [{ "reason": "set-version", "previous": "2.0" }]
+                },
+                "errors": "",
+                "success": true
+            }
+        }
+    });
+
+    assert_eq!(service.process(query.to_owned()), expected.to_string());
+}
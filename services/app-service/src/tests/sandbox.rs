@@ -0,0 +1,235 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use kubos_app::RunLevel;
+use libc;
+use std::ffi::CString;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+use registry::*;
+use sandbox::{Limit, Sandbox};
+use supervisor::AppStatus;
+
+const UUID: &str = "a-b-c-d-e";
+
+// Installs an app with extra `[app.metadata]` settings
+fn install_app(registry_dir: &TempDir, body: &str, metadata: &str) {
+    let app_dir = registry_dir.path().join(format!("{}/1.0", UUID));
+    fs::create_dir_all(app_dir.join("data")).unwrap();
+
+    {
+        let mut bin = fs::File::create(app_dir.join("tiny-app")).unwrap();
+        bin.write_all(format!("#!/bin/sh\n{}\n", body).as_bytes())
+            .unwrap();
+        let mut perms = bin.metadata().unwrap().permissions();
+        perms.set_mode(0o755);
+        bin.set_permissions(perms).unwrap();
+    }
+
+    let toml = format!(
+        r#"
+            active_version = true
+
+            [app]
+            uuid = "{uuid}"
+            pid = 0
+            path = "{dir}/{uuid}/1.0/tiny-app"
+
+            [app.metadata]
+            name = "tiny-app"
+            version = "1.0"
+            author = "user"
+            {metadata}
+            "#,
+        uuid = UUID,
+        dir = registry_dir.path().to_string_lossy(),
+        metadata = metadata,
+    );
+
+    fs::write(app_dir.join("app.toml"), toml).unwrap();
+}
+
+// Starts the app and waits for it to exit
+fn run(registry: &AppRegistry) -> AppStatus {
+    registry
+        .start_app(UUID, RunLevel::OnCommand, None)
+        .unwrap();
+
+    let start = Instant::now();
+    while registry.app_status(UUID).unwrap().running() && start.elapsed() < Duration::from_secs(10)
+    {
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    registry.app_status(UUID).unwrap()
+}
+
+fn output(registry: &AppRegistry) -> String {
+    registry.app_logs(UUID).unwrap().pop().unwrap().tail(10).unwrap()
+}
+
+#[test]
+fn sandbox_env_and_working_dir() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(
+        &registry_dir,
+        "pwd\necho $MODE $KUBOS_APP_UUID",
+        r#"
+            working_dir = "data"
+
+            [app.metadata.env]
+            MODE = "safe"
+            KUBOS_APP_UUID = "spoofed"
+        "#,
+    );
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    assert_eq!(run(&registry).last_exit_code, Some(0));
+    assert_eq!(
+        output(&registry),
+        format!(
+            "{}/{}/1.0/data\nsafe {}",
+            registry_dir.path().canonicalize().unwrap().display(),
+            UUID,
+            UUID
+        )
+    );
+}
+
+#[test]
+fn sandbox_nice_and_open_files() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(
+        &registry_dir,
+        "nice\nulimit -n",
+        r#"
+            [app.metadata.limits]
+            nice = 5
+            open_files = 32
+        "#,
+    );
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    assert_eq!(run(&registry).last_exit_code, Some(0));
+    assert_eq!(output(&registry), "5\n32");
+}
+
+#[test]
+fn sandbox_cpu_time() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(
+        &registry_dir,
+        "while :; do :; done",
+        r#"
+            [app.metadata.limits]
+            cpu_time = 1
+        "#,
+    );
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    let status = run(&registry);
+    assert_eq!(status.last_exit_signal, Some(libc::SIGXCPU));
+    assert_eq!(status.limit_exceeded, Some(Limit::CpuTime));
+}
+
+#[test]
+fn sandbox_cpu_time_max() {
+    let mut sandbox = Sandbox::default();
+    sandbox.limits.cpu_time = Some(::std::u64::MAX);
+
+    let mut cmd = Command::new("true");
+    sandbox.apply(&mut cmd).unwrap();
+    assert!(cmd.status().unwrap().success());
+}
+
+#[test]
+fn sandbox_memory() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(
+        &registry_dir,
+        "exec dd if=/dev/zero of=/dev/null bs=64M count=1000",
+        r#"
+            [app.metadata.limits]
+            memory = 8388608
+        "#,
+    );
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    let status = run(&registry);
+    assert_eq!(status.last_exit_signal, Some(libc::SIGKILL));
+    assert_eq!(status.limit_exceeded, Some(Limit::Memory));
+}
+
+#[test]
+fn sandbox_address_space() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(
+        &registry_dir,
+        "exec dd if=/dev/zero of=/dev/null bs=64M count=1",
+        r#"
+            [app.metadata.limits]
+            address_space = 16777216
+        "#,
+    );
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    let status = run(&registry);
+    assert_eq!(status.last_exit_code, Some(1));
+    assert_eq!(status.limit_exceeded, None);
+}
+
+#[test]
+fn sandbox_user() {
+    // Only root can switch to another user
+    if unsafe { libc::getuid() } != 0 {
+        return;
+    }
+
+    let registry_dir = TempDir::new().unwrap();
+    fs::set_permissions(registry_dir.path(), fs::Permissions::from_mode(0o755)).unwrap();
+    install_app(&registry_dir, "id -u\nid -G", r#"user = "nobody""#);
+
+    let nobody = unsafe { &*libc::getpwnam(CString::new("nobody").unwrap().as_ptr()) };
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    assert_eq!(run(&registry).last_exit_code, Some(0));
+    assert_eq!(
+        output(&registry),
+        format!("{}\n{}", nobody.pw_uid, nobody.pw_gid)
+    );
+}
+
+#[test]
+fn sandbox_unknown_user() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "exit 0", r#"user = "no-such-user""#);
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    assert_eq!(
+        registry
+            .start_app(UUID, RunLevel::OnCommand, None)
+            .unwrap_err()
+            .to_string(),
+        "Failed to start app: User no-such-user not found"
+    );
+}
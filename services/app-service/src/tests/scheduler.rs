@@ -0,0 +1,483 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use kubos_service::{Config, Service};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+
+use tempfile::TempDir;
+
+use registry::*;
+use scheduler::*;
+use schema;
+
+// 2018-10-19T14:30:00Z, a Friday
+const NOW: u64 = 1_539_959_400;
+
+const UUID: &str = "a-b-c-d-e";
+
+fn install_app(registry_dir: &TempDir) {
+    let app_dir = registry_dir.path().join(format!("{}/1.0", UUID));
+    fs::create_dir_all(app_dir.clone()).unwrap();
+
+    {
+        let mut bin = fs::File::create(app_dir.join("tiny-app")).unwrap();
+        bin.write_all(b"#!/bin/sh\necho $@\n").unwrap();
+        let mut perms = bin.metadata().unwrap().permissions();
+        perms.set_mode(0o755);
+        bin.set_permissions(perms).unwrap();
+    }
+
+    let toml = format!(
+        r#"
+            active_version = true
+
+            [app]
+            uuid = "{uuid}"
+            pid = 0
+            path = "{dir}/{uuid}/1.0/tiny-app"
+
+            [app.metadata]
+            name = "tiny-app"
+            version = "1.0"
+            author = "user"
+            "#,
+        uuid = UUID,
+        dir = registry_dir.path().to_string_lossy(),
+    );
+
+    fs::write(app_dir.join("app.toml"), toml).unwrap();
+}
+
+fn registry(registry_dir: &TempDir) -> AppRegistry {
+    AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap()
+}
+
+// Writes a schedule with a single task which is overdue
+fn write_overdue(registry_dir: &TempDir, periodic: &str) {
+    fs::write(
+        registry_dir.path().join(SCHEDULE_FILE),
+        format!(
+            r#"
+                [[task]]
+                name = "overdue"
+                uuid = "{}"
+                args = ["-v"]
+                time = {}
+                {}
+                next_run = {}
+                "#,
+            UUID,
+            now() - 3600,
+            periodic,
+            now() - 3600
+        ),
+    ).unwrap();
+}
+
+fn next(expr: &str, after: u64) -> Option<String> {
+    Cron::parse(expr)
+        .unwrap()
+        .next_after(after)
+        .map(format_time)
+}
+
+#[test]
+fn time_round_trip() {
+    assert_eq!(format_time(NOW), "2018-10-19T14:30:00Z");
+    assert_eq!(parse_time("2018-10-19T14:30:00Z").unwrap(), NOW);
+    assert_eq!(parse_time("2018-10-19T16:30:00+02:00").unwrap(), NOW);
+    assert!(parse_time("tomorrow").is_err());
+}
+
+#[test]
+fn cron_every_minute() {
+    assert_eq!(next("* * * * *", NOW), Some("2018-10-19T14:31:00Z".to_owned()));
+    assert_eq!(
+        next("* * * * *", NOW + 59),
+        Some("2018-10-19T14:31:00Z".to_owned())
+    );
+}
+
+#[test]
+fn cron_steps_and_lists() {
+    assert_eq!(next("*/15 * * * *", NOW), Some("2018-10-19T14:45:00Z".to_owned()));
+    assert_eq!(next("5,10 * * * *", NOW), Some("2018-10-19T15:05:00Z".to_owned()));
+    assert_eq!(
+        next("0 9-17/4 * * *", NOW),
+        Some("2018-10-19T17:00:00Z".to_owned())
+    );
+}
+
+#[test]
+fn cron_days() {
+    // Next Monday
+    assert_eq!(next("0 0 * * 1", NOW), Some("2018-10-22T00:00:00Z".to_owned()));
+    // Sunday, given as 7
+    assert_eq!(next("0 0 * * 7", NOW), Some("2018-10-21T00:00:00Z".to_owned()));
+    // First of the month
+    assert_eq!(next("30 6 1 * *", NOW), Some("2018-11-01T06:30:00Z".to_owned()));
+    // Either the 25th or a Sunday, whichever comes first
+    assert_eq!(next("0 0 25 * 0", NOW), Some("2018-10-21T00:00:00Z".to_owned()));
+    // Leap day
+    assert_eq!(next("0 0 29 2 *", NOW), Some("2020-02-29T00:00:00Z".to_owned()));
+}
+
+#[test]
+fn cron_never() {
+    assert_eq!(next("0 0 30 2 *", NOW), None);
+}
+
+#[test]
+fn cron_invalid() {
+    assert!(Cron::parse("* * * *").is_err());
+    assert!(Cron::parse("60 * * * *").is_err());
+    assert!(Cron::parse("* * 0 * *").is_err());
+    assert!(Cron::parse("*/0 * * * *").is_err());
+    assert!(Cron::parse("1-59/4294967295 * * * *").is_err());
+    assert!(Cron::parse("* * * * */8").is_err());
+    assert!(Cron::parse("5-1 * * * *").is_err());
+    assert!(Cron::parse("a * * * *").is_err());
+}
+
+#[test]
+fn task_one_shot() {
+    let mut task =
+        ScheduledTask::new("once", "a-b-c", vec![], Some(NOW + 100), None, None, NOW).unwrap();
+    assert_eq!(task.next_run, Some(NOW + 100));
+    assert!(!task.due(NOW + 99));
+    assert!(task.due(NOW + 100));
+
+    task.record_run(NOW + 100, "ok".to_owned());
+    assert_eq!(task.next_run, None);
+    assert_eq!(task.last_run, Some(NOW + 100));
+    assert!(!task.due(NOW + 1000));
+}
+
+#[test]
+fn task_interval() {
+    let mut task =
+        ScheduledTask::new("poll", "a-b-c", vec![], None, Some(60), None, NOW).unwrap();
+    assert_eq!(task.next_run, Some(NOW + 60));
+
+    task.record_run(NOW + 61, "ok".to_owned());
+    assert_eq!(task.next_run, Some(NOW + 120));
+
+    // Missed runs are skipped
+    task.record_run(NOW + 1000, "ok".to_owned());
+    assert_eq!(task.next_run, Some(NOW + 1020));
+}
+
+#[test]
+fn task_interval_started_in_past() {
+    let task =
+        ScheduledTask::new("poll", "a-b-c", vec![], Some(NOW - 90), Some(60), None, NOW)
+            .unwrap();
+    assert_eq!(task.next_run, Some(NOW + 30));
+}
+
+#[test]
+fn task_cron() {
+    let mut task = ScheduledTask::new(
+        "hourly",
+        "a-b-c",
+        vec![],
+        None,
+        None,
+        Some("0 * * * *".to_owned()),
+        NOW,
+    ).unwrap();
+    assert_eq!(task.next_run, Some(NOW + 30 * 60));
+
+    task.record_run(NOW + 30 * 60, "ok".to_owned());
+    assert_eq!(task.next_run, Some(NOW + 90 * 60));
+}
+
+#[test]
+fn task_invalid() {
+    let new = |time, interval, cron: Option<&str>| {
+        ScheduledTask::new(
+            "task",
+            "a-b-c",
+            vec![],
+            time,
+            interval,
+            cron.map(|cron| cron.to_owned()),
+            NOW,
+        )
+    };
+
+    assert!(new(None, None, None).is_err());
+    assert!(new(Some(NOW - 1), None, None).is_err());
+    assert!(new(None, Some(0), None).is_err());
+    assert!(new(None, Some(60), Some("* * * * *")).is_err());
+    assert!(new(Some(NOW + 1), None, Some("* * * * *")).is_err());
+    assert!(new(None, None, Some("0 0 30 2 *")).is_err());
+}
+
+#[test]
+fn schedule_persists() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir);
+
+    let task = registry(&registry_dir)
+        .schedule(
+            "nightly",
+            UUID,
+            vec!["--full".to_owned()],
+            None,
+            None,
+            Some("0 2 * * *".to_owned()),
+        )
+        .unwrap();
+
+    assert_eq!(registry(&registry_dir).scheduled(), vec![task]);
+}
+
+#[test]
+fn schedule_replaces_by_name() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir);
+    let registry = registry(&registry_dir);
+
+    registry
+        .schedule("poll", UUID, vec![], None, Some(60), None)
+        .unwrap();
+    let task = registry
+        .schedule("poll", UUID, vec![], None, Some(120), None)
+        .unwrap();
+
+    assert_eq!(registry.scheduled(), vec![task]);
+}
+
+#[test]
+fn schedule_unknown_app() {
+    let registry_dir = TempDir::new().unwrap();
+    let registry = registry(&registry_dir);
+
+    assert_eq!(
+        registry
+            .schedule("poll", UUID, vec![], None, Some(60), None)
+            .unwrap_err()
+            .to_string(),
+        "Failed to schedule app: a-b-c-d-e not found in registry"
+    );
+    assert!(!registry_dir.path().join(SCHEDULE_FILE).exists());
+}
+
+#[test]
+fn unschedule() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir);
+    let registry = registry(&registry_dir);
+
+    registry
+        .schedule("poll", UUID, vec![], None, Some(60), None)
+        .unwrap();
+    registry.unschedule("poll").unwrap();
+
+    assert_eq!(registry.scheduled(), vec![]);
+    assert_eq!(
+        registry.unschedule("poll").unwrap_err().to_string(),
+        "Failed to schedule app: No task named poll"
+    );
+}
+
+#[test]
+fn run_overdue_one_shot() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir);
+    write_overdue(&registry_dir, "");
+
+    let registry = registry(&registry_dir);
+    registry.run_schedule();
+
+    let task = registry.scheduled().pop().unwrap();
+    assert!(task.last_result.unwrap().starts_with("Started (pid "));
+    assert!(task.last_run.unwrap() >= now() - 5);
+    assert_eq!(task.next_run, None);
+
+    // The run is saved, so the task doesn't run again after a restart
+    let task = self::registry(&registry_dir).scheduled().pop().unwrap();
+    assert!(task.last_run.is_some());
+    assert_eq!(task.next_run, None);
+}
+
+#[test]
+fn run_overdue_interval() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir);
+    write_overdue(&registry_dir, "interval = 600");
+
+    let registry = registry(&registry_dir);
+    registry.run_schedule();
+
+    // Missed runs aren't replayed
+    let task = registry.scheduled().pop().unwrap();
+    assert!(task.last_run.is_some());
+    let next = task.next_run.unwrap();
+    assert!(next > now() && next <= now() + 600);
+    assert_eq!((next - task.time.unwrap()) % 600, 0);
+}
+
+#[test]
+fn run_failure_recorded() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir);
+    write_overdue(&registry_dir, "");
+    fs::remove_file(
+        registry_dir
+            .path()
+            .join(format!("{}/1.0/tiny-app", UUID)),
+    ).unwrap();
+
+    let registry = registry(&registry_dir);
+    registry.run_schedule();
+
+    let task = registry.scheduled().pop().unwrap();
+    assert!(
+        task.last_result
+            .unwrap()
+            .starts_with("Failed to start app: ")
+    );
+}
+
+#[test]
+fn corrupt_schedule_moved_aside() {
+    let registry_dir = TempDir::new().unwrap();
+    let path = registry_dir.path().join(SCHEDULE_FILE);
+    fs::write(&path, "[[task]]\nname = 1\n").unwrap();
+
+    assert_eq!(registry(&registry_dir).scheduled(), vec![]);
+    assert!(!path.exists());
+    assert!(
+        registry_dir
+            .path()
+            .join(format!("{}.bad", SCHEDULE_FILE))
+            .exists()
+    );
+}
+
+#[test]
+fn schedule_mutation() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir);
+    let service = mock_service!(registry_dir);
+    let time = format_time(now() + 3600);
+
+    let mutation = format!(
+        r#"mutation {{
+            scheduleApp(name: "pass", uuid: "{}", args: ["-v"], time: "{}") {{
+                errors,
+                success,
+                task {{ name, args, time, interval, nextRun, lastRun }}
+            }}
+        }}"#,
+        UUID, time
+    );
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "scheduleApp": {
+                "errors": "",
+                "success": true,
+                "task": {
+                    "name": "pass",
+                    "args": ["-v"],
+                    "time": time,
+                    "interval": null,
+                    "nextRun": time,
+                    "lastRun": null
+                }
+            }
+        }
+    });
+
+    assert_eq!(service.process(mutation.to_owned()), expected.to_string());
+
+    let query = r#"{
+            schedule(uuid: "a-b-c-d-e") { name, uuid, cron }
+        }"#;
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "schedule": [{
+                "name": "pass",
+                "uuid": UUID,
+                "cron": null
+            }]
+        }
+    });
+
+    assert_eq!(service.process(query.to_owned()), expected.to_string());
+}
+
+#[test]
+fn schedule_mutation_bad_time() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir);
+    let service = mock_service!(registry_dir);
+
+    let mutation = r#"mutation {
+            scheduleApp(name: "pass", uuid: "a-b-c-d-e", time: "noon") {
+                errors,
+                success
+            }
+        }"#;
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "scheduleApp": {
+                "errors": "Failed to schedule app: noon is not a valid RFC 3339 time",
+                "success": false
+            }
+        }
+    });
+
+    assert_eq!(service.process(mutation.to_owned()), expected.to_string());
+}
+
+#[test]
+fn remove_schedule_mutation() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir);
+    let service = mock_service!(registry_dir);
+
+    let mutation = r#"mutation {
+            scheduleApp(name: "poll", uuid: "a-b-c-d-e", interval: 60) { success }
+        }"#;
+    service.process(mutation.to_owned());
+
+    let mutation = r#"mutation {
+            removeSchedule(name: "poll") { errors, success }
+        }"#;
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "removeSchedule": {
+                "errors": "",
+                "success": true
+            }
+        }
+    });
+
+    assert_eq!(service.process(mutation.to_owned()), expected.to_string());
+}
@@ -0,0 +1,389 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer};
+use kubos_service::Config;
+use std::fs;
+use std::path::Path;
+use toml;
+
+use tempfile::TempDir;
+
+use app_entry::*;
+use error::*;
+use package::{self, SigningPolicy};
+use registry::*;
+
+fn keypair(seed: u8) -> Keypair {
+    let secret = SecretKey::from_bytes(&[seed; 32]).unwrap();
+    let public = PublicKey::from(&secret);
+    Keypair { secret, public }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn trusting(seed: u8, require_signatures: bool) -> SigningPolicy {
+    let mut policy = SigningPolicy::default();
+    policy
+        .trusted_keys
+        .insert("ground".to_owned(), keypair(seed).public);
+    policy.require_signatures = require_signatures;
+    policy
+}
+
+fn create_package(dir: &Path, version: &str) {
+    fs::create_dir_all(dir.join("bin")).unwrap();
+    fs::write(dir.join("bin/tiny-app"), "#!/bin/sh\nexit 0\n").unwrap();
+    fs::write(dir.join("tables.csv"), "1,2,3\n").unwrap();
+
+    let manifest = format!(
+        "name = \"tiny-app\"\nversion = \"{}\"\nauthor = \"user\"\nexecutable = \"bin/tiny-app\"\n",
+        version
+    );
+    fs::write(dir.join("manifest.toml"), manifest).unwrap();
+}
+
+// Signs a package with the given key, adding the signature to its manifest
+fn sign(dir: &Path, key: &str, keypair: &Keypair) {
+    let manifest = fs::read_to_string(dir.join("manifest.toml")).unwrap();
+    let metadata: AppMetadata = toml::from_str(&manifest).unwrap();
+
+    let payload = package::signing_payload(dir, &metadata).unwrap();
+    let signature = keypair.sign(&payload);
+
+    let manifest = format!(
+        "{}\n[signature]\nkey = \"{}\"\nvalue = \"{}\"\n",
+        manifest,
+        key,
+        hex(&signature.to_bytes())
+    );
+    fs::write(dir.join("manifest.toml"), manifest).unwrap();
+}
+
+fn register_err(registry: &AppRegistry, dir: &TempDir) -> AppError {
+    registry
+        .register(&dir.path().to_string_lossy(), None)
+        .unwrap_err()
+}
+
+#[test]
+fn signed_good() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "1.0");
+    sign(package_dir.path(), "ground", &keypair(1));
+
+    let mut registry =
+        AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry.signing = trusting(1, true);
+
+    let entry = registry
+        .register(&package_dir.path().to_string_lossy(), None)
+        .unwrap();
+    assert_eq!(entry.app.metadata.signature.unwrap().key, "ground");
+}
+
+#[test]
+fn signed_file_changed() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "1.0");
+    sign(package_dir.path(), "ground", &keypair(1));
+    fs::write(package_dir.path().join("tables.csv"), "4,5,6\n").unwrap();
+
+    let mut registry =
+        AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry.signing = trusting(1, false);
+
+    assert_eq!(
+        register_err(&registry, &package_dir),
+        AppError::SignatureError {
+            err: "Signature does not match package contents".to_owned(),
+        }
+    );
+    assert_eq!(registry.entries.borrow().len(), 0);
+}
+
+#[test]
+fn signed_manifest_changed() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "1.0");
+    sign(package_dir.path(), "ground", &keypair(1));
+
+    // Settings which don't name any files are covered by the signature too
+    let manifest = fs::read_to_string(package_dir.path().join("manifest.toml")).unwrap();
+    fs::write(
+        package_dir.path().join("manifest.toml"),
+        manifest.replace(
+            "executable = \"bin/tiny-app\"\n",
+            "executable = \"bin/tiny-app\"\nrun_levels = [\"debug\"]\n",
+        ),
+    ).unwrap();
+
+    let mut registry =
+        AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry.signing = trusting(1, false);
+
+    assert_eq!(
+        register_err(&registry, &package_dir),
+        AppError::SignatureError {
+            err: "Signature does not match package contents".to_owned(),
+        }
+    );
+}
+
+#[test]
+fn signed_user_changed() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "1.0");
+
+    let manifest_path = package_dir.path().join("manifest.toml");
+    let manifest = fs::read_to_string(&manifest_path).unwrap();
+    fs::write(&manifest_path, format!("{}user = \"nobody\"\n", manifest)).unwrap();
+    sign(package_dir.path(), "ground", &keypair(1));
+
+    // Running a signed app as a different user needs a new signature
+    let manifest = fs::read_to_string(&manifest_path).unwrap();
+    fs::write(
+        &manifest_path,
+        manifest.replace("user = \"nobody\"", "user = \"root\""),
+    ).unwrap();
+
+    let mut registry =
+        AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry.signing = trusting(1, false);
+
+    assert_eq!(
+        register_err(&registry, &package_dir),
+        AppError::SignatureError {
+            err: "Signature does not match package contents".to_owned(),
+        }
+    );
+}
+
+#[test]
+fn signed_file_added() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "1.0");
+    sign(package_dir.path(), "ground", &keypair(1));
+    fs::write(package_dir.path().join("bin/helper"), "#!/bin/sh\n").unwrap();
+
+    let mut registry =
+        AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry.signing = trusting(1, false);
+
+    assert_eq!(
+        register_err(&registry, &package_dir),
+        AppError::SignatureError {
+            err: "Signature does not match package contents".to_owned(),
+        }
+    );
+}
+
+#[test]
+fn signed_version_changed() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "1.0");
+    sign(package_dir.path(), "ground", &keypair(1));
+
+    let manifest = fs::read_to_string(package_dir.path().join("manifest.toml")).unwrap();
+    fs::write(
+        package_dir.path().join("manifest.toml"),
+        manifest.replace("version = \"1.0\"", "version = \"9.0\""),
+    ).unwrap();
+
+    let mut registry =
+        AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry.signing = trusting(1, false);
+
+    assert_eq!(
+        register_err(&registry, &package_dir),
+        AppError::SignatureError {
+            err: "Signature does not match package contents".to_owned(),
+        }
+    );
+}
+
+#[test]
+fn signed_untrusted_key() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "1.0");
+    sign(package_dir.path(), "someone", &keypair(2));
+
+    let mut registry =
+        AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry.signing = trusting(1, false);
+
+    assert_eq!(
+        register_err(&registry, &package_dir),
+        AppError::SignatureError {
+            err: "someone is not a trusted key".to_owned(),
+        }
+    );
+}
+
+#[test]
+fn signed_wrong_key() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "1.0");
+    sign(package_dir.path(), "ground", &keypair(2));
+
+    let mut registry =
+        AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry.signing = trusting(1, false);
+
+    assert_eq!(
+        register_err(&registry, &package_dir),
+        AppError::SignatureError {
+            err: "Signature does not match package contents".to_owned(),
+        }
+    );
+}
+
+#[test]
+fn unsigned_allowed() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "1.0");
+
+    let mut registry =
+        AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry.signing = trusting(1, false);
+
+    assert!(
+        registry
+            .register(&package_dir.path().to_string_lossy(), None)
+            .is_ok()
+    );
+}
+
+#[test]
+fn unsigned_refused() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+    create_package(package_dir.path(), "1.0");
+
+    let mut registry =
+        AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry.signing = trusting(1, true);
+
+    assert_eq!(
+        register_err(&registry, &package_dir),
+        AppError::SignatureError {
+            err: "Package is not signed".to_owned(),
+        }
+    );
+    assert_eq!(registry.entries.borrow().len(), 0);
+}
+
+#[test]
+fn set_version_verifies_signature() {
+    let registry_dir = TempDir::new().unwrap();
+    let package_dir = TempDir::new().unwrap();
+
+    let mut registry =
+        AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry.signing = trusting(1, true);
+
+    create_package(package_dir.path(), "1.0");
+    sign(package_dir.path(), "ground", &keypair(1));
+    let uuid = registry
+        .register(&package_dir.path().to_string_lossy(), None)
+        .unwrap()
+        .app
+        .uuid;
+
+    create_package(package_dir.path(), "2.0");
+    sign(package_dir.path(), "ground", &keypair(1));
+    registry
+        .register(&package_dir.path().to_string_lossy(), Some(uuid.clone()))
+        .unwrap();
+
+    // Tamper with the installed copy of the old version
+    fs::write(
+        registry_dir
+            .path()
+            .join(format!("{}/1.0/bin/tiny-app", uuid)),
+        "#!/bin/sh\nexit 1\n",
+    ).unwrap();
+
+    assert_eq!(
+        registry.set_version(&uuid, "1.0").unwrap_err(),
+        AppError::SignatureError {
+            err: "Signature does not match package contents".to_owned(),
+        }
+    );
+
+    let entries = registry.entries.borrow();
+    let active = entries.iter().find(|entry| entry.active_version).unwrap();
+    assert_eq!(active.app.metadata.version, "2.0");
+}
+
+#[test]
+fn policy_from_config() {
+    let config = Config::new_from_str(
+        "app-service",
+        &format!(
+            r#"
+            [app-service]
+            require-signatures = true
+
+            [app-service.trusted-keys]
+            ground = "{}"
+            "#,
+            hex(keypair(1).public.as_bytes())
+        ),
+    );
+
+    let policy = SigningPolicy::from_config(&config).unwrap();
+    assert!(policy.require_signatures);
+    assert_eq!(policy.trusted_keys.len(), 1);
+    assert_eq!(policy.trusted_keys["ground"], keypair(1).public);
+}
+
+#[test]
+fn policy_from_config_defaults() {
+    let policy = SigningPolicy::from_config(&Config::new_from_str("app-service", "")).unwrap();
+    assert!(!policy.require_signatures);
+    assert!(policy.trusted_keys.is_empty());
+}
+
+#[test]
+fn policy_from_config_bad_key() {
+    let config = Config::new_from_str(
+        "app-service",
+        r#"
+        [app-service.trusted-keys]
+        ground = "not-a-key"
+        "#,
+    );
+
+    assert_eq!(
+        SigningPolicy::from_config(&config).unwrap_err(),
+        AppError::ParseError {
+            entity: "trusted key ground".to_owned(),
+            err: "Expected a hex-encoded Ed25519 public key".to_owned(),
+        }
+    );
+}
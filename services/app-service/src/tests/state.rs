@@ -0,0 +1,253 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use kubos_app::{RunLevel, Store};
+use kubos_service::{Config, Service};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+use registry::*;
+use schema;
+
+const UUID: &str = "a-b-c-d-e";
+
+fn install_app(registry_dir: &TempDir, version: &str, body: &str) {
+    let app_dir = registry_dir.path().join(format!("{}/{}", UUID, version));
+    fs::create_dir_all(app_dir.clone()).unwrap();
+
+    {
+        let mut bin = fs::File::create(app_dir.join("tiny-app")).unwrap();
+        bin.write_all(format!("#!/bin/sh\n{}\n", body).as_bytes())
+            .unwrap();
+        let mut perms = bin.metadata().unwrap().permissions();
+        perms.set_mode(0o755);
+        bin.set_permissions(perms).unwrap();
+    }
+
+    let toml = format!(
+        r#"
+            active_version = true
+
+            [app]
+            uuid = "{uuid}"
+            pid = 0
+            path = "{dir}/{uuid}/{version}/tiny-app"
+
+            [app.metadata]
+            name = "tiny-app"
+            version = "{version}"
+            author = "user"
+            "#,
+        uuid = UUID,
+        version = version,
+        dir = registry_dir.path().to_string_lossy(),
+    );
+
+    fs::write(app_dir.join("app.toml"), toml).unwrap();
+}
+
+fn state_dir(registry_dir: &TempDir) -> ::std::path::PathBuf {
+    registry_dir.path().join(format!("{}/{}", UUID, STATE_DIR))
+}
+
+#[test]
+fn state_dir_passed_to_app() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "1.0", "echo $KUBOS_APP_STATE_DIR");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry.start_app(UUID, RunLevel::OnCommand, None).unwrap();
+
+    let start = Instant::now();
+    while registry.app_status(UUID).unwrap().running() && start.elapsed() < Duration::from_secs(5)
+    {
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    assert_eq!(
+        registry.app_logs(UUID).unwrap().pop().unwrap().tail(10).unwrap(),
+        state_dir(&registry_dir).to_string_lossy()
+    );
+    assert!(state_dir(&registry_dir).is_dir());
+}
+
+#[test]
+fn state_survives_registry_reload() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "1.0", "exit 0");
+    Store::new(state_dir(&registry_dir)).set("count", &7).unwrap();
+
+    // The state directory isn't mistaken for a version of the app
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    assert_eq!(registry.entries.borrow().len(), 1);
+    assert_eq!(registry.app_state(UUID).unwrap()["count"].as_u64(), Some(7));
+}
+
+#[test]
+fn set_app_state() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "1.0", "exit 0");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry
+        .set_app_state(UUID, "mode", r#"{"name": "safe", "level": 2}"#)
+        .unwrap();
+
+    let store = Store::new(state_dir(&registry_dir));
+    assert_eq!(
+        store.load().unwrap()["mode"].to_string(),
+        r#"{"level":2,"name":"safe"}"#
+    );
+}
+
+#[test]
+fn set_app_state_bad_json() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "1.0", "exit 0");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    assert_eq!(
+        registry
+            .set_app_state(UUID, "mode", "safe")
+            .unwrap_err()
+            .to_string(),
+        "Failed to parse value of mode: expected value at line 1 column 1"
+    );
+}
+
+#[test]
+fn app_state_unknown_app() {
+    let registry_dir = TempDir::new().unwrap();
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    assert_eq!(
+        registry
+            .set_app_state(UUID, "count", "1")
+            .unwrap_err()
+            .to_string(),
+        "Failed to access app state: a-b-c-d-e not found in registry"
+    );
+    assert!(!state_dir(&registry_dir).exists());
+}
+
+#[test]
+fn app_state_query() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "1.0", "exit 0");
+    let store = Store::new(state_dir(&registry_dir));
+    store.set("count", &7).unwrap();
+    store.set("image", &"img-0042.png").unwrap();
+    let service = mock_service!(registry_dir);
+
+    let query = r#"{
+            appState(uuid: "a-b-c-d-e") { key, value }
+        }"#;
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "appState": [
+                { "key": "count", "value": "7" },
+                { "key": "image", "value": "\"img-0042.png\"" }
+            ]
+        }
+    });
+
+    assert_eq!(service.process(query.to_owned()), expected.to_string());
+
+    let query = r#"{
+            appState(uuid: "a-b-c-d-e", key: "count") { value }
+        }"#;
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "appState": [{ "value": "7" }]
+        }
+    });
+
+    assert_eq!(service.process(query.to_owned()), expected.to_string());
+}
+
+#[test]
+fn set_app_state_mutation() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "1.0", "exit 0");
+    let service = mock_service!(registry_dir);
+
+    let mutation = r#"mutation {
+            setAppState(uuid: "a-b-c-d-e", key: "count", value: "12") { errors, success }
+        }"#;
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "setAppState": {
+                "errors": "",
+                "success": true
+            }
+        }
+    });
+
+    assert_eq!(service.process(mutation.to_owned()), expected.to_string());
+    assert_eq!(
+        Store::new(state_dir(&registry_dir))
+            .get::<u32>("count")
+            .unwrap(),
+        Some(12)
+    );
+}
+
+#[test]
+fn remove_app_state_mutation() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "1.0", "exit 0");
+    Store::new(state_dir(&registry_dir)).set("count", &7).unwrap();
+    let service = mock_service!(registry_dir);
+
+    let mutation = r#"mutation {
+            removeAppState(uuid: "a-b-c-d-e", key: "count") { errors, success }
+        }"#;
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "removeAppState": {
+                "errors": "",
+                "success": true
+            }
+        }
+    });
+
+    assert_eq!(service.process(mutation.to_owned()), expected.to_string());
+
+    let expected = json!({
+        "errs": "",
+        "msg": {
+            "removeAppState": {
+                "errors": "Failed to access app state: No value named count",
+                "success": false
+            }
+        }
+    });
+
+    assert_eq!(service.process(mutation.to_owned()), expected.to_string());
+}
@@ -0,0 +1,264 @@
+/*
+ * Copyright (C) 2018 Kubos Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use kubos_app::RunLevel;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+use registry::*;
+use supervisor::*;
+
+// Installs an app directly into the registry directory, with the given script body and
+// manifest `[restart]` section
+fn install_app(registry_dir: &TempDir, uuid: &str, body: &str, restart: &str) {
+    let app_dir = registry_dir.path().join(format!("{}/1.0", uuid));
+    fs::create_dir_all(app_dir.clone()).unwrap();
+
+    // Keep the file's lifetime short, so that it's closed before we try to execute it
+    {
+        let mut bin = fs::File::create(app_dir.join("tiny-app")).unwrap();
+        bin.write_all(format!("#!/bin/sh\n{}\n", body).as_bytes())
+            .unwrap();
+        let mut perms = bin.metadata().unwrap().permissions();
+        perms.set_mode(0o755);
+        bin.set_permissions(perms).unwrap();
+    }
+
+    let toml = format!(
+        r#"
+            active_version = true
+
+            [app]
+            uuid = "{uuid}"
+            pid = 0
+            path = "{dir}/{uuid}/1.0/tiny-app"
+
+            [app.metadata]
+            name = "tiny-app"
+            version = "1.0"
+            author = "user"
+
+            [app.metadata.restart]
+            {restart}
+            "#,
+        uuid = uuid,
+        dir = registry_dir.path().to_string_lossy(),
+        restart = restart,
+    );
+
+    fs::write(app_dir.join("app.toml"), toml).unwrap();
+}
+
+// Polls the app's status until the condition is met (or a few seconds have passed)
+fn wait_for<F>(registry: &AppRegistry, uuid: &str, condition: F) -> AppStatus
+where
+    F: Fn(&AppStatus) -> bool,
+{
+    let start = Instant::now();
+    loop {
+        let status = registry.app_status(uuid).unwrap();
+        if condition(&status) || start.elapsed() > Duration::from_secs(5) {
+            return status;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn supervisor_exit_code() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "a-b-c-d-e", "exit 3", "");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    let pid = registry
+        .start_app("a-b-c-d-e", RunLevel::OnCommand, None)
+        .unwrap();
+
+    let status = registry.app_status("a-b-c-d-e").unwrap();
+    assert_eq!(status.version, "1.0");
+    assert!(status.pids == vec![pid] || status.last_exit_code.is_some());
+
+    let status = wait_for(&registry, "a-b-c-d-e", |status| !status.running());
+    assert!(!status.running());
+    assert_eq!(status.last_exit_code, Some(3));
+    assert_eq!(status.last_exit_signal, None);
+    assert!(status.last_exit_time.is_some());
+    assert_eq!(status.restarts, 0);
+}
+
+#[test]
+fn supervisor_restart_on_failure() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(
+        &registry_dir,
+        "a-b-c-d-e",
+        "exit 1",
+        "policy = \"on-failure\"\nmax_retries = 2\nbackoff = 0",
+    );
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry
+        .start_app("a-b-c-d-e", RunLevel::OnCommand, None)
+        .unwrap();
+
+    // The app is restarted twice, and then left stopped
+    let status = wait_for(&registry, "a-b-c-d-e", |status| {
+        status.restarts == 2 && !status.running() && !status.restart_pending
+    });
+    assert_eq!(status.restarts, 2);
+    assert!(!status.running());
+    assert!(!status.restart_pending);
+    assert_eq!(status.last_exit_code, Some(1));
+}
+
+#[test]
+fn supervisor_no_restart_on_success() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(
+        &registry_dir,
+        "a-b-c-d-e",
+        "exit 0",
+        "policy = \"on-failure\"\nbackoff = 0",
+    );
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry
+        .start_app("a-b-c-d-e", RunLevel::OnCommand, None)
+        .unwrap();
+
+    let status = wait_for(&registry, "a-b-c-d-e", |status| {
+        status.last_exit_code.is_some()
+    });
+    thread::sleep(SUPERVISOR_INTERVAL * 3);
+
+    let status = registry.app_status("a-b-c-d-e").unwrap_or(status);
+    assert_eq!(status.last_exit_code, Some(0));
+    assert_eq!(status.restarts, 0);
+    assert!(!status.running());
+}
+
+#[test]
+fn supervisor_stop() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(
+        &registry_dir,
+        "a-b-c-d-e",
+        "exec sleep 30",
+        "policy = \"always\"\nbackoff = 0",
+    );
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    let pid = registry
+        .start_app("a-b-c-d-e", RunLevel::OnCommand, None)
+        .unwrap();
+    assert!(registry.app_status("a-b-c-d-e").unwrap().running());
+
+    assert_eq!(
+        registry.stop_app("a-b-c-d-e", Duration::from_secs(5)),
+        Ok(vec![pid])
+    );
+
+    // Stopped apps aren't restarted, even with the "always" policy
+    let status = wait_for(&registry, "a-b-c-d-e", |status| !status.running());
+    thread::sleep(SUPERVISOR_INTERVAL * 3);
+    let status = registry.app_status("a-b-c-d-e").unwrap_or(status);
+    assert!(!status.running());
+    assert_eq!(status.last_exit_signal, Some(15));
+    assert_eq!(status.restarts, 0);
+
+    assert!(registry.stop_app("a-b-c-d-e", Duration::from_secs(5)).is_err());
+}
+
+#[test]
+fn supervisor_stop_timeout() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(
+        &registry_dir,
+        "a-b-c-d-e",
+        "trap '' TERM\nwhile true; do sleep 0.1; done",
+        "",
+    );
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry
+        .start_app("a-b-c-d-e", RunLevel::OnCommand, None)
+        .unwrap();
+
+    // Give the shell a chance to install its trap
+    thread::sleep(Duration::from_millis(200));
+    registry
+        .stop_app("a-b-c-d-e", Duration::from_millis(200))
+        .unwrap();
+
+    // The app ignores SIGTERM, so is killed once the timeout expires
+    let status = wait_for(&registry, "a-b-c-d-e", |status| !status.running());
+    assert!(!status.running());
+    assert_eq!(status.last_exit_signal, Some(9));
+}
+
+#[test]
+fn supervisor_stop_timeout_overflow() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "a-b-c-d-e", "exec sleep 30", "");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry
+        .start_app("a-b-c-d-e", RunLevel::OnCommand, None)
+        .unwrap();
+
+    // An unrepresentable deadline is rejected without signalling the app
+    assert!(
+        registry
+            .stop_app("a-b-c-d-e", Duration::from_secs(u64::max_value()))
+            .is_err()
+    );
+    assert!(registry.app_status("a-b-c-d-e").unwrap().running());
+
+    registry.kill_app("a-b-c-d-e", 9).unwrap();
+}
+
+#[test]
+fn supervisor_kill() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "a-b-c-d-e", "exec sleep 30", "");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+    registry
+        .start_app("a-b-c-d-e", RunLevel::OnCommand, None)
+        .unwrap();
+
+    assert!(registry.kill_app("a-b-c-d-e", 2).is_ok());
+
+    let status = wait_for(&registry, "a-b-c-d-e", |status| !status.running());
+    assert_eq!(status.last_exit_signal, Some(2));
+    assert_eq!(status.last_exit_code, None);
+}
+
+#[test]
+fn supervisor_never_started() {
+    let registry_dir = TempDir::new().unwrap();
+    install_app(&registry_dir, "a-b-c-d-e", "exit 0", "");
+
+    let registry = AppRegistry::new_from_dir(&registry_dir.path().to_string_lossy()).unwrap();
+
+    assert_eq!(registry.app_status("a-b-c-d-e"), None);
+    assert!(registry.kill_app("a-b-c-d-e", 9).is_err());
+}
@@ -20,17 +20,66 @@ extern crate kubos_system;
 #[macro_use]
 extern crate log;
 extern crate failure;
+extern crate serde;
 extern crate serde_cbor;
+#[macro_use]
+extern crate serde_derive;
 extern crate simplelog;
 
 use file_protocol::{FileProtocol, FileProtocolConfig, ProtocolError, State};
-use kubos_system::Config as ServiceConfig;
+use kubos_system::{Config as ServiceConfig, ConfigSection, ConfigWatcher};
 use std::collections::HashMap;
 use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
+// How often the listener checks whether the config should be reloaded while idle
+const RELOAD_POLL: Duration = Duration::from_secs(1);
+
+/// Settings read from the `[file-transfer-service]` config section
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct FileServiceConfig {
+    /// Directory used for temporary/intermediate transfer storage
+    pub storage_dir: Option<String>,
+    /// Size, in bytes, of each transferred chunk
+    pub chunk_size: usize,
+    /// Number of consecutive timeouts allowed before a transfer is abandoned
+    pub hold_count: u16,
+    /// Seconds to wait for each message in a transfer
+    pub timeout: u64,
+}
+
+impl Default for FileServiceConfig {
+    fn default() -> Self {
+        FileServiceConfig {
+            storage_dir: None,
+            chunk_size: 4096,
+            hold_count: 5,
+            timeout: 2,
+        }
+    }
+}
+
+impl ConfigSection for FileServiceConfig {
+    fn validate(&self) -> Result<(), String> {
+        if self.chunk_size == 0 {
+            return Err("chunk_size must be greater than zero".to_owned());
+        }
+        if self.timeout == 0 {
+            return Err("timeout must be greater than zero".to_owned());
+        }
+        Ok(())
+    }
+}
+
+impl FileServiceConfig {
+    fn protocol_config(&self) -> FileProtocolConfig {
+        FileProtocolConfig::new(self.storage_dir.clone(), self.chunk_size, self.hold_count)
+    }
+}
+
 // We need this in this lib.rs file so we can build integration tests
 pub fn recv_loop(config: ServiceConfig) -> Result<(), failure::Error> {
     // Get and bind our UDP listening socket
@@ -40,34 +89,15 @@ pub fn recv_loop(config: ServiceConfig) -> Result<(), failure::Error> {
     let mut host_parts = host.split(':').map(|val| val.to_owned());
     let host_ip = host_parts.next().unwrap();
 
-    // Get the storage directory prefix that we'll be using for our
-    // temporary/intermediate storage location
-    let prefix = match config.get("storage_dir") {
-        Some(val) => val.as_str().and_then(|str| Some(str.to_owned())),
-        None => None,
-    };
-
-    // Get the chunk size to be used for transfers
-    let chunk_size = match config.get("chunk_size") {
-        Some(val) => val.as_integer().unwrap_or(4096),
-        None => 4096,
-    } as usize;
-
-    let hold_count = match config.get("hold_count") {
-        Some(val) => val.as_integer().unwrap_or(5),
-        None => 5,
-    } as u16;
+    let mut settings: FileServiceConfig = config.section()?;
+    let mut f_config = settings.protocol_config();
+    let mut timeout = Duration::from_secs(settings.timeout);
 
-    let f_config = FileProtocolConfig::new(prefix, chunk_size, hold_count);
+    // The listening socket's buffer is sized to fit a single chunk
+    let c_protocol = cbor_protocol::Protocol::new(host.clone(), settings.chunk_size);
 
-    let c_protocol = cbor_protocol::Protocol::new(host.clone(), chunk_size);
-
-    let timeout = config
-        .get("timeout")
-        .and_then(|val| {
-            val.as_integer()
-                .and_then(|num| Some(Duration::from_secs(num as u64)))
-        }).unwrap_or(Duration::from_secs(2));
+    let mut config = config;
+    let mut watcher = ConfigWatcher::new(&config);
 
     // Setup map of channel IDs to thread channels
     let raw_threads: HashMap<u32, Sender<serde_cbor::Value>> = HashMap::new();
@@ -75,9 +105,32 @@ pub fn recv_loop(config: ServiceConfig) -> Result<(), failure::Error> {
     let threads = Arc::new(Mutex::new(raw_threads));
 
     loop {
+        // Pick up any config changes. These only affect transfers started after the reload
+        if watcher.changed(&config) {
+            match config
+                .reload()
+                .and_then(|new| new.section::<FileServiceConfig>().map(|section| (new, section)))
+            {
+                Ok((new, mut section)) => {
+                    if section.chunk_size != settings.chunk_size {
+                        warn!("chunk_size changes require a service restart");
+                        section.chunk_size = settings.chunk_size;
+                    }
+                    info!("Reloaded config: {:?}", section);
+                    f_config = section.protocol_config();
+                    timeout = Duration::from_secs(section.timeout);
+                    settings = section;
+                    config = new;
+                }
+                Err(e) => warn!("Failed to reload config: {}", e),
+            }
+            watcher.update(&config);
+        }
+
         // Listen on UDP port
-        let (source, first_message) = match c_protocol.recv_message_peer() {
+        let (source, first_message) = match c_protocol.recv_message_peer_timeout(RELOAD_POLL) {
             Ok((source, first_message)) => (source, first_message),
+            Err(cbor_protocol::ProtocolError::Timeout) => continue,
             Err(e) => {
                 warn!("Error receiving message: {:?}", e);
                 continue;
@@ -0,0 +1,367 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use blake2_rfc::blake2b::blake2b;
+use kubos_system::{encode_hex, Config};
+use operation::mutation_fields;
+use serde_json::{self, Value};
+use std::cell::RefCell;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The directory audit logs are written to when no path is configured
+pub const DEFAULT_AUDIT_DIR: &str = "/home/system/log";
+/// The default size, in bytes, an audit log may reach before it is rotated
+pub const DEFAULT_AUDIT_MAX_SIZE: u64 = 1024 * 1024;
+/// The default number of rotated audit logs to keep
+pub const DEFAULT_AUDIT_MAX_FILES: u32 = 4;
+
+/// Records the requests a service executes, along with who made them and what happened.
+///
+/// Enabled by adding an `audit` section to the service's configuration:
+///
+/// ```toml
+/// [example-service.audit]
+/// # Defaults to /home/system/log/<service-name>-audit.log
+/// path = "/home/system/log/example-service-audit.log"
+/// # Rotate the log once it reaches this many bytes
+/// max_size = 1048576
+/// # Number of rotated logs to keep (<path>.1 is the most recent)
+/// max_files = 4
+/// # Record queries as well as mutations (defaults to false)
+/// all_requests = false
+/// # Store the full request text, rather than just its hash (defaults to true)
+/// include_text = true
+/// # Also send each record to the telemetry database via its direct UDP port
+/// telemetry = "127.0.0.1:8006"
+/// ```
+///
+/// Each record is written as a single line of JSON.
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    max_size: u64,
+    max_files: u32,
+    all_requests: bool,
+    include_text: bool,
+    subsystem: String,
+    telemetry: Option<(UdpSocket, SocketAddr)>,
+    file: RefCell<Option<File>>,
+}
+
+impl AuditLog {
+    /// Loads the audit settings from a service's configuration
+    pub fn new(config: &Config) -> Self {
+        let audit = config.get("audit");
+        let audit = audit.as_ref();
+        let get = |key: &str| audit.and_then(|audit| audit.get(key));
+
+        let path = audit.map(|_| match get("path").and_then(|path| path.as_str()) {
+            Some(path) => PathBuf::from(path),
+            None => PathBuf::from(format!(
+                "{}/{}-audit.log",
+                DEFAULT_AUDIT_DIR,
+                config.name()
+            )),
+        });
+
+        let telemetry = get("telemetry")
+            .and_then(|addr| addr.as_str())
+            .and_then(|addr| match addr.parse::<SocketAddr>() {
+                Ok(addr) => Some(addr),
+                Err(err) => {
+                    eprintln!("Invalid audit telemetry address {}: {}", addr, err);
+                    None
+                }
+            }).and_then(|addr| match UdpSocket::bind("0.0.0.0:0") {
+                Ok(socket) => Some((socket, addr)),
+                Err(err) => {
+                    eprintln!("Unable to create audit telemetry socket: {}", err);
+                    None
+                }
+            });
+
+        AuditLog {
+            path,
+            max_size: get("max_size")
+                .and_then(|size| size.as_integer())
+                .map(|size| size as u64)
+                .unwrap_or(DEFAULT_AUDIT_MAX_SIZE),
+            max_files: get("max_files")
+                .and_then(|count| count.as_integer())
+                .map(|count| count as u32)
+                .unwrap_or(DEFAULT_AUDIT_MAX_FILES),
+            all_requests: get("all_requests")
+                .and_then(|all| all.as_bool())
+                .unwrap_or(false),
+            include_text: get("include_text")
+                .and_then(|text| text.as_bool())
+                .unwrap_or(true),
+            subsystem: config.name().to_owned(),
+            telemetry,
+            file: RefCell::new(None),
+        }
+    }
+
+    /// Records a request and the response which was sent for it
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - The address the request came from
+    /// * `key` - The name of the key the request was verified as signed with, if any
+    /// * `claimed_key` - The key name given by a signed request which failed verification, if any
+    /// * `query` - The GraphQL query which was requested
+    /// * `response` - The response returned to the requester
+    pub fn record(
+        &self,
+        peer: &SocketAddr,
+        key: Option<&str>,
+        claimed_key: Option<&str>,
+        query: &str,
+        response: &Value,
+    ) {
+        if self.path.is_none() && self.telemetry.is_none() {
+            return;
+        }
+
+        let mutations = mutation_fields(query);
+        if mutations.is_empty() && !self.all_requests {
+            return;
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        // Successful requests report an empty "errs" string. Anything else (including requests
+        // which failed to parse and only returned a list of errors) is a failure
+        let errors = match response.get("errs") {
+            Some(Value::String(errs)) => errs.clone(),
+            _ => response.to_string(),
+        };
+
+        let mut entry = json!({
+            "timestamp": now.as_secs() as f64 + f64::from(now.subsec_millis()) / 1000.0,
+            "peer": peer.to_string(),
+            "key": key,
+            "claimed_key": claimed_key,
+            "mutations": mutations,
+            "hash": encode_hex(blake2b(32, &[], query.as_bytes()).as_bytes()),
+            "success": errors.is_empty(),
+            "errors": errors,
+        });
+
+        if self.include_text {
+            entry["query"] = json!(query);
+            entry["response"] = response.get("msg").cloned().unwrap_or(Value::Null);
+        }
+
+        if let Err(err) = self.write(&entry) {
+            eprintln!("Failed to write audit record: {}", err);
+        }
+
+        if let Some((ref socket, ref addr)) = self.telemetry {
+            // The telemetry database only stores strings, so keep these records short
+            let summary = json!({
+                "subsystem": self.subsystem,
+                "parameter": "audit",
+                "value": json!({
+                    "peer": entry["peer"],
+                    "key": entry["key"],
+                    "claimed_key": entry["claimed_key"],
+                    "mutations": entry["mutations"],
+                    "hash": entry["hash"],
+                    "success": entry["success"],
+                }).to_string(),
+            });
+            let _ = socket.send_to(summary.to_string().as_bytes(), addr);
+        }
+    }
+
+    fn write(&self, entry: &Value) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let mut file = self.file.borrow_mut();
+
+        let size = match *file {
+            Some(ref file) => file.metadata()?.len(),
+            None => fs::metadata(path).map(|meta| meta.len()).unwrap_or(0),
+        };
+
+        if size > 0 && size + line.len() as u64 > self.max_size {
+            *file = None;
+            self.rotate(path)?;
+        }
+
+        if file.is_none() {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            *file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        }
+
+        match *file {
+            Some(ref mut file) => file.write_all(line.as_bytes()),
+            None => Ok(()),
+        }
+    }
+
+    // Shift each existing log up by one (dropping the oldest), freeing up the main log path
+    fn rotate(&self, path: &PathBuf) -> io::Result<()> {
+        let rotated = |index: u32| PathBuf::from(format!("{}.{}", path.display(), index));
+
+        if self.max_files == 0 {
+            return fs::remove_file(path);
+        }
+
+        let _ = fs::remove_file(rotated(self.max_files));
+        for index in (1..self.max_files).rev() {
+            let _ = fs::rename(rotated(index), rotated(index + 1));
+        }
+
+        fs::rename(path, rotated(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn audit_log(dir: &TempDir, extra: &str) -> AuditLog {
+        let config = format!(
+            r#"
+            [test-service.audit]
+            path = "{}/audit.log"
+            {}
+            "#,
+            dir.path().display(),
+            extra
+        );
+        AuditLog::new(&Config::new_from_str("test-service", &config))
+    }
+
+    fn peer() -> SocketAddr {
+        "10.0.0.1:4000".parse().unwrap()
+    }
+
+    fn read_entries(path: PathBuf) -> Vec<Value> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn audit_disabled() {
+        let log = AuditLog::new(&Config::new_from_str("test-service", ""));
+        assert!(log.path.is_none());
+        log.record(&peer(), None, None, "mutation { reset }", &json!({}));
+    }
+
+    #[test]
+    fn audit_mutation() {
+        let dir = TempDir::new().unwrap();
+        let log = audit_log(&dir, "");
+
+        log.record(
+            &peer(),
+            Some("ground"),
+            None,
+            "mutation { reset { success } }",
+            &json!({"msg": {"reset": {"success": true}}, "errs": ""}),
+        );
+
+        let entries = read_entries(dir.path().join("audit.log"));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["peer"], json!("10.0.0.1:4000"));
+        assert_eq!(entries[0]["key"], json!("ground"));
+        assert_eq!(entries[0]["mutations"], json!(["reset"]));
+        assert_eq!(entries[0]["success"], json!(true));
+        assert_eq!(entries[0]["errors"], json!(""));
+        assert_eq!(entries[0]["query"], json!("mutation { reset { success } }"));
+        assert_eq!(entries[0]["response"], json!({"reset": {"success": true}}));
+    }
+
+    #[test]
+    fn audit_skip_query() {
+        let dir = TempDir::new().unwrap();
+        let log = audit_log(&dir, "");
+
+        log.record(&peer(), None, None, "{ ping }", &json!({"msg": {"ping": "pong"}, "errs": ""}));
+
+        assert!(!dir.path().join("audit.log").exists());
+    }
+
+    #[test]
+    fn audit_all_requests_hash_only() {
+        let dir = TempDir::new().unwrap();
+        let log = audit_log(&dir, "all_requests = true\ninclude_text = false");
+
+        log.record(&peer(), None, None, "{ ping }", &json!({"msg": {"ping": "pong"}, "errs": ""}));
+
+        let entries = read_entries(dir.path().join("audit.log"));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["mutations"], json!([]));
+        assert_eq!(entries[0]["query"], Value::Null);
+        assert_eq!(entries[0]["hash"].as_str().unwrap().len(), 64);
+    }
+
+    #[test]
+    fn audit_failure() {
+        let dir = TempDir::new().unwrap();
+        let log = audit_log(&dir, "");
+
+        log.record(
+            &peer(),
+            None,
+            Some("ground"),
+            "mutation { reset }",
+            &json!({"msg": null, "errs": "Request is not authenticated"}),
+        );
+
+        let entries = read_entries(dir.path().join("audit.log"));
+        assert_eq!(entries[0]["key"], Value::Null);
+        assert_eq!(entries[0]["claimed_key"], json!("ground"));
+        assert_eq!(entries[0]["success"], json!(false));
+        assert_eq!(entries[0]["errors"], json!("Request is not authenticated"));
+    }
+
+    #[test]
+    fn audit_rotate() {
+        let dir = TempDir::new().unwrap();
+        let log = audit_log(&dir, "max_size = 100\nmax_files = 2");
+
+        for _ in 0..5 {
+            log.record(&peer(), None, None, "mutation { reset }", &json!({"errs": ""}));
+        }
+
+        assert!(dir.path().join("audit.log").exists());
+        assert!(dir.path().join("audit.log.1").exists());
+        assert!(dir.path().join("audit.log.2").exists());
+        assert!(!dir.path().join("audit.log.3").exists());
+        assert_eq!(read_entries(dir.path().join("audit.log")).len(), 1);
+    }
+}
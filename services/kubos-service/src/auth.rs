@@ -0,0 +1,317 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use kubos_system::{decode_hex, Config, SignedRequest};
+use operation::{mutation_fields, ANY_MUTATION};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use toml::Value;
+
+/// The default number of seconds a signed request's timestamp may differ from the local clock
+pub const DEFAULT_AUTH_WINDOW: u64 = 60;
+
+/// Errors which cause a request to be rejected before it is executed
+#[derive(Debug, Fail, PartialEq)]
+pub enum AuthError {
+    /// The service requires authentication, but the request was not signed
+    #[fail(display = "Request is not authenticated")]
+    Unauthenticated,
+    /// The request was signed with a key the service doesn't know about
+    #[fail(display = "Unknown key: {}", key)]
+    UnknownKey {
+        /// Name of the key
+        key: String,
+    },
+    /// The request's MAC doesn't match its contents
+    #[fail(display = "Invalid MAC for key {}", key)]
+    InvalidMac {
+        /// Name of the key
+        key: String,
+    },
+    /// The request's counter has already been used
+    #[fail(display = "Replayed request for key {}: counter {}", key, counter)]
+    Replay {
+        /// Name of the key
+        key: String,
+        /// The stale counter value
+        counter: u64,
+    },
+    /// The request's timestamp is outside of the configured window
+    #[fail(display = "Request timestamp {} is outside of the allowed window", timestamp)]
+    Expired {
+        /// The request's timestamp
+        timestamp: u64,
+    },
+    /// The requester's roles don't allow the requested mutation
+    #[fail(display = "Not authorized to execute mutation {}", mutation)]
+    Forbidden {
+        /// The forbidden mutation
+        mutation: String,
+    },
+    /// The requester's roles don't allow subscriptions
+    #[fail(display = "Not authorized to subscribe")]
+    ForbiddenSubscription,
+}
+
+/// A request which has passed authentication and authorization
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    /// The raw GraphQL query
+    pub query: String,
+    /// The name of the key used to sign the request, if it was signed
+    pub key: Option<String>,
+}
+
+struct Key {
+    secret: Vec<u8>,
+    roles: Vec<String>,
+}
+
+/// Authenticates incoming requests and enforces per-mutation role allow lists.
+///
+/// Configured via the service's `auth` section:
+///
+/// ```toml
+/// [example-service.auth]
+/// # Reject any request which isn't signed (defaults to false)
+/// required = true
+/// # Allowed clock difference, in seconds, between the client and the service.
+/// # Zero disables the timestamp check (defaults to 60)
+/// window = 60
+/// # Roles allowed to subscribe to queries. Subscription requests must always be signed
+/// # once an `auth` section is present; when this is omitted, any known key may subscribe
+/// subscribe = ["operator"]
+///
+/// [example-service.auth.keys.ground]
+/// secret = "00112233445566778899aabbccddeeff"
+/// roles = ["operator"]
+///
+/// # Mutations not listed here may be run by any requester allowed by `required`
+/// [example-service.auth.mutations]
+/// powercycle = ["operator"]
+/// ```
+///
+/// When no `auth` section is present, all requests are accepted.
+pub struct Authenticator {
+    configured: bool,
+    required: bool,
+    subscribe: Option<Vec<String>>,
+    window: u64,
+    keys: HashMap<String, Key>,
+    mutations: HashMap<String, Vec<String>>,
+    counters: RefCell<HashMap<String, u64>>,
+}
+
+fn string_list(value: Option<&Value>) -> Vec<String> {
+    value
+        .and_then(|val| val.as_array())
+        .map(|list| {
+            list.iter()
+                .filter_map(|item| item.as_str())
+                .map(|item| item.to_owned())
+                .collect()
+        }).unwrap_or_default()
+}
+
+impl Authenticator {
+    /// Loads the authentication settings from a service's configuration
+    pub fn new(config: &Config) -> Self {
+        let auth = config.get("auth");
+        let auth = auth.as_ref();
+
+        let mut keys = HashMap::new();
+        if let Some(table) = auth
+            .and_then(|auth| auth.get("keys"))
+            .and_then(|keys| keys.as_table())
+        {
+            for (name, key) in table.iter() {
+                match key.get("secret").and_then(|secret| secret.as_str()).map(decode_hex) {
+                    Some(Ok(secret)) => {
+                        keys.insert(
+                            name.to_owned(),
+                            Key {
+                                secret,
+                                roles: string_list(key.get("roles")),
+                            },
+                        );
+                    }
+                    Some(Err(err)) => eprintln!("Ignoring auth key {}: {}", name, err),
+                    None => eprintln!("Ignoring auth key {}: No secret given", name),
+                }
+            }
+        }
+
+        let mut mutations = HashMap::new();
+        if let Some(table) = auth
+            .and_then(|auth| auth.get("mutations"))
+            .and_then(|mutations| mutations.as_table())
+        {
+            for (name, roles) in table.iter() {
+                mutations.insert(name.to_owned(), string_list(Some(roles)));
+            }
+        }
+
+        Authenticator {
+            configured: auth.is_some(),
+            required: auth
+                .and_then(|auth| auth.get("required"))
+                .and_then(|required| required.as_bool())
+                .unwrap_or(false),
+            window: auth
+                .and_then(|auth| auth.get("window"))
+                .and_then(|window| window.as_integer())
+                .map(|window| window as u64)
+                .unwrap_or(DEFAULT_AUTH_WINDOW),
+            subscribe: auth
+                .and_then(|auth| auth.get("subscribe"))
+                .map(|roles| string_list(Some(roles))),
+            keys,
+            mutations,
+            counters: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Replaces the authentication settings with those from an updated configuration.
+    ///
+    /// The last counter seen for each key is kept, so that requests captured before the
+    /// reload can't be replayed afterwards.
+    pub fn reconfigure(&mut self, config: &Config) {
+        let counters = self.counters.replace(HashMap::new());
+        *self = Authenticator::new(config);
+        self.counters = RefCell::new(counters);
+    }
+
+    /// Authenticates a raw request and checks that the requester may run every mutation it
+    /// contains. On success, returns the GraphQL query which should be executed.
+    pub fn authorize(&self, raw: &str) -> Result<Request, AuthError> {
+        let request = match SignedRequest::from_slice(raw.as_bytes()) {
+            Some(signed) => {
+                self.verify(&signed)?;
+                Request {
+                    query: signed.query,
+                    key: Some(signed.key),
+                }
+            }
+            None if self.required => return Err(AuthError::Unauthenticated),
+            None => Request {
+                query: raw.to_owned(),
+                key: None,
+            },
+        };
+
+        if self.mutations.is_empty() {
+            return Ok(request);
+        }
+
+        let roles = request
+            .key
+            .as_ref()
+            .and_then(|key| self.keys.get(key))
+            .map(|key| key.roles.as_slice())
+            .unwrap_or(&[]);
+
+        for field in mutation_fields(&request.query) {
+            if field == ANY_MUTATION {
+                // We can't tell which mutations a fragment will invoke, so the requester
+                // must be allowed to run all of the restricted ones
+                for (mutation, allowed) in self.mutations.iter() {
+                    if !allowed.iter().any(|role| roles.contains(role)) {
+                        return Err(AuthError::Forbidden {
+                            mutation: mutation.to_owned(),
+                        });
+                    }
+                }
+            } else if let Some(allowed) = self.mutations.get(&field) {
+                if !allowed.iter().any(|role| roles.contains(role)) {
+                    return Err(AuthError::Forbidden { mutation: field });
+                }
+            }
+        }
+
+        Ok(request)
+    }
+
+    /// Checks that an authorized request may start or manage subscriptions.
+    ///
+    /// Subscription results are sent to the request's source address, which can't be trusted
+    /// unless the request was signed, so once authentication is configured subscription
+    /// requests must be signed even if `required` is false.
+    pub fn authorize_subscription(&self, request: &Request) -> Result<(), AuthError> {
+        if !self.configured {
+            return Ok(());
+        }
+
+        let roles = match request.key.as_ref().and_then(|key| self.keys.get(key)) {
+            Some(key) => &key.roles,
+            None => return Err(AuthError::Unauthenticated),
+        };
+
+        match self.subscribe {
+            Some(ref allowed) if !allowed.iter().any(|role| roles.contains(role)) => {
+                Err(AuthError::ForbiddenSubscription)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn verify(&self, request: &SignedRequest) -> Result<(), AuthError> {
+        let key = self
+            .keys
+            .get(&request.key)
+            .ok_or_else(|| AuthError::UnknownKey {
+                key: request.key.clone(),
+            })?;
+
+        if !request.verify(&key.secret) {
+            return Err(AuthError::InvalidMac {
+                key: request.key.clone(),
+            });
+        }
+
+        if self.window > 0 {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs())
+                .unwrap_or(0);
+            let skew = if now > request.timestamp {
+                now - request.timestamp
+            } else {
+                request.timestamp - now
+            };
+
+            if skew > self.window {
+                return Err(AuthError::Expired {
+                    timestamp: request.timestamp,
+                });
+            }
+        }
+
+        // Only record the counter once the request is known to be genuine, so that
+        // forged requests can't be used to lock out a key
+        let mut counters = self.counters.borrow_mut();
+        let last = counters.entry(request.key.clone()).or_insert(0);
+        if request.counter <= *last {
+            return Err(AuthError::Replay {
+                key: request.key.clone(),
+                counter: request.counter,
+            });
+        }
+        *last = request.counter;
+
+        Ok(())
+    }
+}
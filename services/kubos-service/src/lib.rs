@@ -59,6 +59,97 @@
 //! Note - the `service-name` used in the sections must match the name used when creating
 //! the `Config` instance inside your service.
 //!
+//! ## Authentication
+//!
+//! Services can require that requests be signed with a shared secret, and can restrict which
+//! keys may run individual mutations, by adding an `auth` section to their configuration:
+//!
+//! ```toml,ignore
+//! [service-name.auth]
+//! required = true
+//! window = 60
+//!
+//! [service-name.auth.keys.ground]
+//! secret = "00112233445566778899aabbccddeeff"
+//! roles = ["operator"]
+//!
+//! [service-name.auth.mutations]
+//! powercycle = ["operator"]
+//! ```
+//!
+//! Signed requests are generated with `kubos_system::Credentials`. When `required` is false
+//! (the default), unsigned requests are still accepted for any mutation which isn't listed in
+//! the `mutations` section.
+//!
+//! ## Auditing
+//!
+//! Services can keep a record of every mutation they execute (when it happened, who requested
+//! it, and what the result was) by adding an `audit` section to their configuration:
+//!
+//! ```toml,ignore
+//! [service-name.audit]
+//! path = "/home/system/log/service-name-audit.log"
+//! max_size = 1048576
+//! max_files = 4
+//! telemetry = "127.0.0.1:8006"
+//! ```
+//!
+//! Records are written as JSON lines, and the log is rotated once it reaches `max_size` bytes.
+//! When `telemetry` is set, a summary of each record is also sent to the telemetry service's
+//! direct UDP port.
+//!
+//! ## Subscriptions
+//!
+//! Instead of polling, clients can subscribe to a query. The service then re-runs the query at
+//! the requested interval and pushes each result back to the client's address until the lease
+//! runs out:
+//!
+//! ```json
+//! {"subscribe": {"query": "{ attitude { roll pitch yaw } }", "lease": 60, "interval": 0.5}}
+//! ```
+//!
+//! The response contains the subscription's `id`, which is included in each pushed result
+//! (`{"subscription": 1, "msg": {...}, "errs": ""}`). Setting `"on_change": true` only sends
+//! results which differ from the previous one. Subscriptions are renewed by subscribing again
+//! with the same `id`, and cancelled with `{"unsubscribe": {"id": 1}}`. Subscription requests
+//! may be signed like any other request, and must be signed once the service has an `auth`
+//! section, since results are sent to the request's source address. The `auth.subscribe` list
+//! restricts subscriptions to keys with the given roles. Limits are set in the service's
+//! `subscriptions` section:
+//!
+//! ```toml,ignore
+//! [service-name.subscriptions]
+//! max_lease = 300
+//! max_count = 16
+//! max_per_peer = 4
+//! min_interval = 0.1
+//! ```
+//!
+//! ## Request IDs
+//!
+//! Clients which retry requests can tag each one with an ID, so that a late reply to an earlier
+//! attempt isn't mistaken for the reply to the current one. The raw request (which may itself be
+//! a signed request) is wrapped in an envelope, and the service adds the same `id` to its
+//! response:
+//!
+//! ```json
+//! {"id": 42, "request": "{ ping }"}
+//! ```
+//!
+//! ## Reloading
+//!
+//! Sending a service `SIGHUP` makes it re-read its config file without restarting. Setting
+//! `watch_config = true` under `[service-name]` also reloads the config whenever the file is
+//! modified. The `auth` and `audit` settings are applied automatically; services register a hook
+//! with `Service::on_reload` to apply their own settings. The listening address can't be changed
+//! without a restart.
+//!
+//! Services which need to do periodic housekeeping can register a hook with `Service::on_tick`.
+//! It's called from the same thread as requests, after each request and at least once a second.
+//!
+//! Any config value can also be overridden with an environment variable, such as
+//! `KUBOS_SERVICE_NAME__ADDR__PORT=8282` (see `kubos_system::Config`).
+//!
 //! ### Examples
 //!
 //! # Creating and starting a simple service.
@@ -93,6 +184,18 @@
 //! ).start();
 //! ```
 //!
+//! # Applying config changes without restarting.
+//!
+//! ```rust,ignore
+//! Service::new(config, subsystem, QueryRoot, MutationRoot)
+//!     .on_reload(|subsystem, config| {
+//!         if let Ok(settings) = config.section::<Settings>() {
+//!             subsystem.set_timeout(settings.timeout);
+//!         }
+//!     })
+//!     .start();
+//! ```
+//!
 //! # Running a service with the default config file (`/home/system/etc/config.toml`).
 //!
 //! ```bash
@@ -105,18 +208,27 @@
 //! $ ./example-service -c config.toml
 //! ```
 
-#[cfg(test)]
+extern crate blake2_rfc;
 #[macro_use]
 extern crate failure;
 extern crate juniper;
 extern crate serde;
 #[macro_use]
+extern crate serde_derive;
+#[macro_use]
 extern crate serde_json;
+#[cfg(test)]
+extern crate tempfile;
+extern crate toml;
 
 extern crate kubos_system;
 
+mod audit;
+mod auth;
 mod macros;
+mod operation;
 mod service;
+mod subscription;
 
 pub use kubos_system::Config;
 pub use service::{Context, Service};
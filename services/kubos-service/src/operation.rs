@@ -0,0 +1,82 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use juniper::parser::{Lexer, Token};
+
+/// Placeholder returned in place of a field name when a mutation's selection set
+/// contains a fragment, meaning the mutations it invokes can't be determined up front
+pub const ANY_MUTATION: &str = "*";
+
+/// Finds the names of the top-level mutation fields requested by a GraphQL document.
+///
+/// Aliases are resolved to the underlying field name. Fragments used directly in a mutation's
+/// selection set are reported as [`ANY_MUTATION`]. Documents which fail to tokenize return an
+/// empty list, since they will also be rejected when executed.
+pub fn mutation_fields(query: &str) -> Vec<String> {
+    let tokens: Vec<Token> = match Lexer::new(query).collect::<Result<Vec<_>, _>>() {
+        Ok(tokens) => tokens.into_iter().map(|token| token.item).collect(),
+        Err(_) => return vec![],
+    };
+
+    let mut fields = vec![];
+    let mut depth = 0;
+    let mut parens = 0;
+    let mut in_mutation = false;
+    let mut index = 0;
+
+    while index < tokens.len() {
+        match tokens[index] {
+            Token::ParenOpen => parens += 1,
+            Token::ParenClose => parens -= 1,
+            // Anything inside of arguments or variable definitions can't be a field
+            _ if parens > 0 => {}
+            Token::CurlyOpen => depth += 1,
+            Token::CurlyClose => {
+                depth -= 1;
+                if depth == 0 {
+                    in_mutation = false;
+                }
+            }
+            Token::Name("mutation") if depth == 0 => in_mutation = true,
+            Token::Ellipsis if depth == 1 && in_mutation => {
+                fields.push(ANY_MUTATION.to_owned());
+                // Skip over the fragment name or type condition
+                match tokens.get(index + 1) {
+                    Some(Token::Name("on")) => index += 2,
+                    Some(Token::Name(_)) => index += 1,
+                    _ => {}
+                }
+            }
+            // Skip over directive names
+            Token::At => index += 1,
+            Token::Name(name) if depth == 1 && in_mutation => {
+                if let (Some(Token::Colon), Some(Token::Name(field))) =
+                    (tokens.get(index + 1), tokens.get(index + 2))
+                {
+                    fields.push(field.to_string());
+                    index += 2;
+                } else {
+                    fields.push(name.to_owned());
+                }
+            }
+            _ => {}
+        }
+
+        index += 1;
+    }
+
+    fields
+}
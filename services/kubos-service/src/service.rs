@@ -14,12 +14,20 @@
 // limitations under the License.
 //
 
+use audit::AuditLog;
+use auth::Authenticator;
 use juniper::{execute, Context as JuniperContext, GraphQLType, RootNode, Variables};
-use kubos_system::Config;
-use serde_json;
+use kubos_system::{Config, ConfigWatcher, SignedRequest, TaggedRequest};
+use serde_json::{self, Value};
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::io::ErrorKind;
 use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+use subscription::{SubscriptionRequest, Subscriptions};
+
+// How often the service checks whether its configuration should be reloaded while idle
+const RELOAD_POLL: Duration = Duration::from_secs(1);
 
 /// Context struct used by a service to provide Juniper context,
 /// subsystem access and persistent storage.
@@ -102,9 +110,14 @@ where
     Query: GraphQLType<Context = Context<S>> + Send + Sync + 'static,
     Mutation: GraphQLType<Context = Context<S>> + Send + Sync + 'static,
 {
-    config: Config,
+    config: RefCell<Config>,
     root_node: RootNode<'a, Query, Mutation>,
     context: Context<S>,
+    auth: RefCell<Authenticator>,
+    audit: RefCell<AuditLog>,
+    subscriptions: RefCell<Subscriptions>,
+    reload_hook: Option<Box<Fn(&S, &Config)>>,
+    tick_hook: Option<Box<Fn(&S)>>,
 }
 
 impl<'a, Query, Mutation, S> Service<'a, Query, Mutation, S>
@@ -122,13 +135,82 @@ where
     /// `mutation` - The root mutation struct holding all other GraphQL mutations.
     pub fn new(config: Config, subsystem: S, query: Query, mutation: Mutation) -> Self {
         Service {
-            config: config,
+            auth: RefCell::new(Authenticator::new(&config)),
+            audit: RefCell::new(AuditLog::new(&config)),
+            subscriptions: RefCell::new(Subscriptions::new(&config)),
+            config: RefCell::new(config),
             root_node: RootNode::new(query, mutation),
             context: Context {
                 subsystem: subsystem,
                 storage: RefCell::new(HashMap::new()),
             },
+            reload_hook: None,
+            tick_hook: None,
+        }
+    }
+
+    /// Registers a function to be called whenever the service's configuration is reloaded
+    /// (after receiving `SIGHUP`, or after the config file changes if `watch_config` is set).
+    ///
+    /// The service's own `auth` and `audit` settings are always reloaded. The hook gives the
+    /// subsystem a chance to pick up any of its own settings which have changed.
+    ///
+    /// # Arguments
+    ///
+    /// `hook` - Function called with the subsystem and the newly loaded config
+    pub fn on_reload<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&S, &Config) + 'static,
+    {
+        self.reload_hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Registers a function to be called from the service's main loop after every request, and
+    /// at least once a second while the service is idle.
+    ///
+    /// This lets a subsystem do periodic housekeeping on the same thread which handles requests,
+    /// so it doesn't need to make its state thread-safe.
+    ///
+    /// # Arguments
+    ///
+    /// `hook` - Function called with the subsystem
+    pub fn on_tick<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&S) + 'static,
+    {
+        self.tick_hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Reloads the service's configuration from its config file, forwarding the new
+    /// configuration to the reload hook.
+    ///
+    /// If the file can no longer be read or parsed, the current configuration is kept.
+    /// Changes to the service's address only take effect once the service is restarted.
+    pub fn reload(&self) {
+        let config = match self.config.borrow().reload() {
+            Ok(config) => config,
+            Err(err) => {
+                eprintln!("Failed to reload config: {}", err);
+                return;
+            }
+        };
+
+        if config.hosturl() != self.config.borrow().hosturl() {
+            eprintln!("Service address changed to {}. Restart to apply", config.hosturl());
+        }
+
+        self.auth.borrow_mut().reconfigure(&config);
+        *self.audit.borrow_mut() = AuditLog::new(&config);
+        self.subscriptions.borrow_mut().reconfigure(&config);
+
+        if let Some(ref hook) = self.reload_hook {
+            hook(&self.context.subsystem, &config);
         }
+
+        *self.config.borrow_mut() = config;
+        println!("Reloaded config");
     }
 
     /// Starts the service's GraphQL/UDP server. This function runs
@@ -140,39 +222,148 @@ where
     /// cannot be bound (like if they are already in use), or if for some reason the socket fails
     /// to receive a message.
     pub fn start(&self) {
-        let addr = self.config.hosturl().parse::<SocketAddr>().unwrap();
+        let addr = self.config.borrow().hosturl().parse::<SocketAddr>().unwrap();
 
         let socket = UdpSocket::bind(&addr).unwrap();
         println!("Listening on: {}", socket.local_addr().unwrap());
 
+        let mut watcher = ConfigWatcher::new(&self.config.borrow());
+
         let mut buf = [0; 4096];
         loop {
+            if watcher.changed(&self.config.borrow()) {
+                self.reload();
+                watcher.update(&self.config.borrow());
+            }
+
+            // Wake up periodically, so that reload requests are handled even when the service is
+            // idle, and so that subscription results are sent on time
+            let now = Instant::now();
+            let wait = match self.subscriptions.borrow().next_due() {
+                Some(due) if due <= now => Duration::from_millis(1),
+                Some(due) => (due - now).min(RELOAD_POLL),
+                None => RELOAD_POLL,
+            };
+            socket.set_read_timeout(Some(wait)).unwrap();
+
             // Wait for an incoming message
-            let (size, peer) = socket
-                .recv_from(&mut buf)
-                .expect("Failed to receive a message");
-            if let Ok(query_string) = String::from_utf8(buf[0..(size)].to_vec()) {
-                //println!(
-                //  "[{}] <- [{}] {}",
-                //  peer,
-                //  socket.local_addr().unwrap(),
-                //  &query_string
-                //);
-
-                // Go process the request
-                let res = self.process(query_string);
-
-                // And then send the response back
-                let _amt = socket.send_to(&res.as_bytes(), &peer);
-                //println!("[{}] -> [{}] {}", socket.local_addr().unwrap(), peer, &res);
+            match socket.recv_from(&mut buf) {
+                Ok((size, peer)) => self.respond(&socket, &buf[0..size], peer),
+                Err(ref err)
+                    if err.kind() == ErrorKind::WouldBlock
+                        || err.kind() == ErrorKind::TimedOut
+                        || err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => panic!("Failed to receive a message: {}", err),
             }
+
+            // Push out any subscription results which are due
+            let messages = self
+                .subscriptions
+                .borrow_mut()
+                .poll(Instant::now(), |query| self.execute(query));
+            for (peer, message) in messages {
+                let _amt = socket.send_to(message.to_string().as_bytes(), &peer);
+            }
+
+            if let Some(ref hook) = self.tick_hook {
+                hook(&self.context.subsystem);
+            }
+        }
+    }
+
+    // Handles a single incoming request
+    fn respond(&self, socket: &UdpSocket, data: &[u8], peer: SocketAddr) {
+        // Requests tagged with an ID get the same ID back, so the client can match them up
+        let (id, data) = match TaggedRequest::from_slice(data) {
+            Some(tagged) => (Some(tagged.id), tagged.request.into_bytes()),
+            None => (None, data.to_vec()),
+        };
+
+        if let Ok(query_string) = String::from_utf8(data) {
+            //println!(
+            //  "[{}] <- [{}] {}",
+            //  peer,
+            //  socket.local_addr().unwrap(),
+            //  &query_string
+            //);
+
+            // Make sure the requester is allowed to run this request,
+            // then go process it
+            // Only keys which verified the request are trusted. The key named by a request which
+            // failed verification is recorded separately, since anyone could have claimed it
+            let (key, claimed_key, query, res) = match self.auth.borrow().authorize(&query_string) {
+                Ok(request) => {
+                    let res = match SubscriptionRequest::from_str(&request.query) {
+                        Some(sub) => match self.auth.borrow().authorize_subscription(&request) {
+                            Ok(()) => self.subscribe(peer, sub),
+                            Err(err) => json!({
+                                "msg": Value::Null,
+                                "errs": err.to_string()}),
+                        },
+                        None => self.execute(&request.query),
+                    };
+                    (request.key, None, request.query, res)
+                }
+                Err(err) => {
+                    let res = json!({
+                        "msg": Value::Null,
+                        "errs": err.to_string()});
+                    match SignedRequest::from_slice(query_string.as_bytes()) {
+                        Some(signed) => (None, Some(signed.key), signed.query, res),
+                        None => (None, None, query_string, res),
+                    }
+                }
+            };
+
+            self.audit
+                .borrow()
+                .record(
+                    &peer,
+                    key.as_ref().map(|key| key.as_str()),
+                    claimed_key.as_ref().map(|key| key.as_str()),
+                    &query,
+                    &res,
+                );
+
+            let res = match (id, res) {
+                (Some(id), Value::Object(mut fields)) => {
+                    fields.insert("id".to_owned(), json!(id));
+                    Value::Object(fields)
+                }
+                (Some(id), errs) => json!({"msg": Value::Null, "errs": errs, "id": id}),
+                (None, res) => res,
+            };
+
+            // And then send the response back
+            let res = res.to_string();
+            let _amt = socket.send_to(&res.as_bytes(), &peer);
+            //println!("[{}] -> [{}] {}", socket.local_addr().unwrap(), peer, &res);
+        }
+    }
+
+    // Registers, renews or cancels a subscription
+    fn subscribe(&self, peer: SocketAddr, request: SubscriptionRequest) -> Value {
+        match self
+            .subscriptions
+            .borrow_mut()
+            .handle(peer, request, Instant::now())
+        {
+            Ok(msg) => json!({"msg": msg, "errs": ""}),
+            Err(err) => json!({
+                "msg": Value::Null,
+                "errs": err.to_string()}),
         }
     }
 
     /// Processes a GraphQL query
     pub fn process(&self, query: String) -> String {
+        self.execute(&query).to_string()
+    }
+
+    // Executes a GraphQL query, returning the JSON response which should be sent to the requester
+    fn execute(&self, query: &str) -> Value {
         match execute(
-            &query,
+            query,
             None,
             &self.root_node,
             &Variables::new(),
@@ -186,9 +377,8 @@ where
                 json!({
                     "msg": val,
                     "errs": errs_msg})
-                    .to_string()
             }
-            Err(e) => serde_json::to_string(&e).unwrap(),
+            Err(e) => serde_json::to_value(&e).unwrap(),
         }
     }
 }
@@ -0,0 +1,601 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use kubos_system::Config;
+use operation::mutation_fields;
+use serde_json::{self, Value};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// The default longest lease, in seconds, a subscription may request
+pub const DEFAULT_MAX_LEASE: u64 = 300;
+/// The default number of subscriptions a service will serve at once
+pub const DEFAULT_MAX_SUBSCRIPTIONS: usize = 16;
+/// The default number of subscriptions a service will serve at once for a single host
+pub const DEFAULT_MAX_PER_PEER: usize = 4;
+/// The default shortest interval, in seconds, at which a subscription may be evaluated
+pub const DEFAULT_MIN_INTERVAL: f64 = 0.1;
+/// The interval, in seconds, used when a subscription doesn't request one
+pub const DEFAULT_INTERVAL: f64 = 1.0;
+
+/// Errors which cause a subscription request to be rejected
+#[derive(Debug, Fail, PartialEq)]
+pub enum SubscriptionError {
+    /// The service is already serving as many subscriptions as it allows
+    #[fail(display = "Too many active subscriptions (max {})", max)]
+    TooMany {
+        /// The maximum number of subscriptions
+        max: usize,
+    },
+    /// The requesting host already has as many subscriptions as the service allows
+    #[fail(display = "Too many active subscriptions for this host (max {})", max)]
+    TooManyForPeer {
+        /// The maximum number of subscriptions per host
+        max: usize,
+    },
+    /// The requested subscription doesn't exist (or belongs to someone else)
+    #[fail(display = "Unknown subscription: {}", id)]
+    Unknown {
+        /// The requested subscription ID
+        id: u64,
+    },
+    /// Subscriptions are re-evaluated repeatedly, so may only contain queries
+    #[fail(display = "Subscriptions may not contain mutations")]
+    Mutation,
+    /// The requested lease was zero
+    #[fail(display = "Subscription lease must be greater than zero")]
+    NoLease,
+    /// The requested lease ends too far in the future to be represented
+    #[fail(display = "Subscription lease is too long")]
+    LeaseTooLong,
+    /// The requested interval wasn't a finite number of seconds
+    #[fail(display = "Subscription interval must be a finite number of seconds")]
+    BadInterval,
+}
+
+/// A request to start, renew, or cancel a subscription.
+///
+/// Sent to the service in place of a GraphQL query (and may be signed like one):
+///
+/// ```json
+/// {"subscribe": {"query": "{ position { x y z } }", "lease": 60, "interval": 0.5, "on_change": true}}
+/// {"subscribe": {"id": 3, "query": "{ position { x y z } }", "lease": 60}}
+/// {"unsubscribe": {"id": 3}}
+/// ```
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SubscriptionRequest {
+    /// Start a new subscription, or renew an existing one if `id` is given
+    Subscribe {
+        /// ID of the subscription to renew
+        #[serde(default)]
+        id: Option<u64>,
+        /// The GraphQL query to evaluate
+        query: String,
+        /// Number of seconds until the subscription expires, unless renewed
+        lease: u64,
+        /// Number of seconds between evaluations of the query
+        #[serde(default)]
+        interval: Option<f64>,
+        /// Only send results which differ from the previous one
+        #[serde(default)]
+        on_change: bool,
+    },
+    /// Cancel a subscription
+    Unsubscribe {
+        /// ID of the subscription to cancel
+        id: u64,
+    },
+}
+
+impl SubscriptionRequest {
+    /// Attempts to interpret a raw service request as a subscription request.
+    ///
+    /// Returns `None` if the data is not a subscription request (for example, a plain GraphQL
+    /// query)
+    pub fn from_str(data: &str) -> Option<SubscriptionRequest> {
+        serde_json::from_str(data).ok()
+    }
+}
+
+struct Subscription {
+    peer: SocketAddr,
+    query: String,
+    interval: Duration,
+    on_change: bool,
+    expires: Instant,
+    next: Instant,
+    last: Option<Value>,
+}
+
+/// Tracks the subscriptions registered with a service, and generates the results which should
+/// be pushed to each subscriber.
+///
+/// Limits are configured via the service's `subscriptions` section:
+///
+/// ```toml
+/// [example-service.subscriptions]
+/// # Longest lease (in seconds) a subscriber may request. Longer leases are shortened
+/// max_lease = 300
+/// # Maximum number of subscriptions served at once
+/// max_count = 16
+/// # Maximum number of subscriptions served at once for a single host (IP address)
+/// max_per_peer = 4
+/// # Shortest evaluation interval (in seconds). Shorter intervals are lengthened
+/// min_interval = 0.1
+/// ```
+///
+/// Intervals longer than the subscription's lease are shortened to the lease.
+///
+/// Each result is sent to the subscriber's address as
+/// `{"subscription": <id>, "msg": <result>, "errs": <errors>}`. When a lease runs out, a final
+/// message with a `null` result and an `errs` value of `"Subscription expired"` is sent.
+pub struct Subscriptions {
+    max_lease: u64,
+    max_count: usize,
+    max_per_peer: usize,
+    min_interval: f64,
+    next_id: Cell<u64>,
+    active: BTreeMap<u64, Subscription>,
+}
+
+impl Subscriptions {
+    /// Loads the subscription limits from a service's configuration
+    pub fn new(config: &Config) -> Self {
+        let mut subscriptions = Subscriptions {
+            max_lease: DEFAULT_MAX_LEASE,
+            max_count: DEFAULT_MAX_SUBSCRIPTIONS,
+            max_per_peer: DEFAULT_MAX_PER_PEER,
+            min_interval: DEFAULT_MIN_INTERVAL,
+            next_id: Cell::new(1),
+            active: BTreeMap::new(),
+        };
+        subscriptions.reconfigure(config);
+        subscriptions
+    }
+
+    /// Updates the subscription limits from an updated configuration. Existing subscriptions
+    /// are kept.
+    pub fn reconfigure(&mut self, config: &Config) {
+        let section = config.get("subscriptions");
+        let get = |key: &str| section.as_ref().and_then(|section| section.get(key).cloned());
+
+        self.max_lease = get("max_lease")
+            .and_then(|lease| lease.as_integer())
+            .map(|lease| lease as u64)
+            .unwrap_or(DEFAULT_MAX_LEASE);
+        self.max_count = get("max_count")
+            .and_then(|count| count.as_integer())
+            .map(|count| count as usize)
+            .unwrap_or(DEFAULT_MAX_SUBSCRIPTIONS);
+        self.max_per_peer = get("max_per_peer")
+            .and_then(|count| count.as_integer())
+            .map(|count| count as usize)
+            .unwrap_or(DEFAULT_MAX_PER_PEER);
+        self.min_interval = get("min_interval")
+            .and_then(|interval| {
+                interval
+                    .as_float()
+                    .or_else(|| interval.as_integer().map(|val| val as f64))
+            }).unwrap_or(DEFAULT_MIN_INTERVAL);
+    }
+
+    /// Handles a subscription request, returning the response which should be sent back
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - The address the request came from, which results will be sent to
+    /// * `request` - The subscription request
+    /// * `now` - The current time
+    pub fn handle(
+        &mut self,
+        peer: SocketAddr,
+        request: SubscriptionRequest,
+        now: Instant,
+    ) -> Result<Value, SubscriptionError> {
+        match request {
+            SubscriptionRequest::Subscribe {
+                id,
+                query,
+                lease,
+                interval,
+                on_change,
+            } => {
+                if !mutation_fields(&query).is_empty() {
+                    return Err(SubscriptionError::Mutation);
+                }
+                if lease == 0 {
+                    return Err(SubscriptionError::NoLease);
+                }
+
+                let lease = lease.min(self.max_lease);
+                let interval = interval.unwrap_or(DEFAULT_INTERVAL);
+                if !interval.is_finite() {
+                    return Err(SubscriptionError::BadInterval);
+                }
+                let interval = interval.max(self.min_interval).min(lease as f64);
+                let expires = now
+                    .checked_add(Duration::from_secs(lease))
+                    .ok_or(SubscriptionError::LeaseTooLong)?;
+
+                let id = match id {
+                    Some(id) => {
+                        self.owned(id, &peer)?;
+                        id
+                    }
+                    None => {
+                        if self.active.len() >= self.max_count {
+                            return Err(SubscriptionError::TooMany {
+                                max: self.max_count,
+                            });
+                        }
+                        // Count by host rather than address, so that a client can't get around
+                        // the limit by sending from a different port
+                        let count = self
+                            .active
+                            .values()
+                            .filter(|sub| sub.peer.ip() == peer.ip())
+                            .count();
+                        if count >= self.max_per_peer {
+                            return Err(SubscriptionError::TooManyForPeer {
+                                max: self.max_per_peer,
+                            });
+                        }
+                        let id = self.next_id.get();
+                        self.next_id.set(id + 1);
+                        id
+                    }
+                };
+
+                // The first result is sent straight away
+                self.active.insert(
+                    id,
+                    Subscription {
+                        peer,
+                        query,
+                        interval: Duration::from_millis((interval * 1000.0) as u64),
+                        on_change,
+                        expires,
+                        next: now,
+                        last: None,
+                    },
+                );
+
+                Ok(json!({"subscribe": {"id": id, "lease": lease, "interval": interval}}))
+            }
+            SubscriptionRequest::Unsubscribe { id } => {
+                self.owned(id, &peer)?;
+                self.active.remove(&id);
+                Ok(json!({"unsubscribe": {"id": id}}))
+            }
+        }
+    }
+
+    fn owned(&self, id: u64, peer: &SocketAddr) -> Result<(), SubscriptionError> {
+        match self.active.get(&id) {
+            Some(sub) if sub.peer == *peer => Ok(()),
+            _ => Err(SubscriptionError::Unknown { id }),
+        }
+    }
+
+    /// Returns the time at which the next subscription needs attention, if there are any
+    pub fn next_due(&self) -> Option<Instant> {
+        self.active
+            .values()
+            .map(|sub| sub.next.min(sub.expires))
+            .min()
+    }
+
+    /// Evaluates every subscription which is due, returning the messages which should be sent
+    /// to each subscriber. Expired subscriptions are removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time
+    /// * `execute` - Executes a query, returning the service's response
+    pub fn poll<F>(&mut self, now: Instant, execute: F) -> Vec<(SocketAddr, Value)>
+    where
+        F: Fn(&str) -> Value,
+    {
+        let mut messages = vec![];
+
+        let expired: Vec<u64> = self
+            .active
+            .iter()
+            .filter(|(_, sub)| sub.expires <= now)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            if let Some(sub) = self.active.remove(&id) {
+                messages.push((
+                    sub.peer,
+                    json!({"subscription": id, "msg": Value::Null, "errs": "Subscription expired"}),
+                ));
+            }
+        }
+
+        for (id, sub) in self.active.iter_mut() {
+            if sub.next > now {
+                continue;
+            }
+
+            // Schedule from the previous due time, so that results don't drift, unless we've
+            // fallen more than an interval behind
+            sub.next = match sub.next.checked_add(sub.interval) {
+                Some(next) if next > now => next,
+                _ => now.checked_add(sub.interval).unwrap_or(sub.expires),
+            };
+
+            let response = execute(&sub.query);
+            if sub.on_change && sub.last.as_ref() == Some(&response) {
+                continue;
+            }
+
+            let mut message = json!({"subscription": id});
+            message["msg"] = response.get("msg").cloned().unwrap_or(Value::Null);
+            message["errs"] = match response.get("errs") {
+                Some(errs) => errs.clone(),
+                None => response.clone(),
+            };
+
+            messages.push((sub.peer, message));
+            sub.last = Some(response);
+        }
+
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn subscriptions(extra: &str) -> Subscriptions {
+        let config = format!("[test-service.subscriptions]\n{}", extra);
+        Subscriptions::new(&Config::new_from_str("test-service", &config))
+    }
+
+    fn peer() -> SocketAddr {
+        "10.0.0.1:4000".parse().unwrap()
+    }
+
+    fn subscribe(query: &str, lease: u64, interval: f64, on_change: bool) -> SubscriptionRequest {
+        SubscriptionRequest::Subscribe {
+            id: None,
+            query: query.to_owned(),
+            lease,
+            interval: Some(interval),
+            on_change,
+        }
+    }
+
+    #[test]
+    fn parse_request() {
+        assert_eq!(
+            SubscriptionRequest::from_str(
+                r#"{"subscribe": {"query": "{ ping }", "lease": 10, "on_change": true}}"#
+            ),
+            Some(SubscriptionRequest::Subscribe {
+                id: None,
+                query: "{ ping }".to_owned(),
+                lease: 10,
+                interval: None,
+                on_change: true,
+            })
+        );
+        assert_eq!(
+            SubscriptionRequest::from_str(r#"{"unsubscribe": {"id": 4}}"#),
+            Some(SubscriptionRequest::Unsubscribe { id: 4 })
+        );
+        assert_eq!(SubscriptionRequest::from_str("{ ping }"), None);
+    }
+
+    #[test]
+    fn subscribe_and_poll() {
+        let mut subs = subscriptions("");
+        let start = Instant::now();
+
+        let res = subs
+            .handle(peer(), subscribe("{ ping }", 10, 1.0, false), start)
+            .unwrap();
+        assert_eq!(res, json!({"subscribe": {"id": 1, "lease": 10, "interval": 1.0}}));
+
+        let execute = |_: &str| json!({"msg": {"ping": "pong"}, "errs": ""});
+
+        let messages = subs.poll(start, execute);
+        assert_eq!(
+            messages,
+            vec![(
+                peer(),
+                json!({"subscription": 1, "msg": {"ping": "pong"}, "errs": ""})
+            )]
+        );
+
+        // Nothing is due until the interval has passed
+        assert!(subs.poll(start + Duration::from_millis(500), execute).is_empty());
+        assert_eq!(subs.next_due(), Some(start + Duration::from_secs(1)));
+        assert_eq!(subs.poll(start + Duration::from_secs(1), execute).len(), 1);
+    }
+
+    #[test]
+    fn subscribe_on_change() {
+        let mut subs = subscriptions("");
+        let start = Instant::now();
+        subs.handle(peer(), subscribe("{ ping }", 10, 1.0, true), start)
+            .unwrap();
+
+        let count = Cell::new(0);
+        let execute = |_: &str| {
+            count.set(count.get() + 1);
+            json!({"msg": {"count": count.get() / 2}, "errs": ""})
+        };
+
+        // Results: 0, 1, 1, 2
+        assert_eq!(subs.poll(start, &execute).len(), 1);
+        assert_eq!(subs.poll(start + Duration::from_secs(1), &execute).len(), 1);
+        assert_eq!(subs.poll(start + Duration::from_secs(2), &execute).len(), 0);
+        assert_eq!(subs.poll(start + Duration::from_secs(3), &execute).len(), 1);
+    }
+
+    #[test]
+    fn subscribe_expire() {
+        let mut subs = subscriptions("max_lease = 5");
+        let start = Instant::now();
+
+        let res = subs
+            .handle(peer(), subscribe("{ ping }", 60, 1.0, false), start)
+            .unwrap();
+        assert_eq!(res["subscribe"]["lease"], json!(5));
+
+        let messages = subs.poll(start + Duration::from_secs(5), |_| json!({}));
+        assert_eq!(
+            messages,
+            vec![(
+                peer(),
+                json!({"subscription": 1, "msg": null, "errs": "Subscription expired"})
+            )]
+        );
+        assert_eq!(subs.next_due(), None);
+    }
+
+    #[test]
+    fn subscribe_renew() {
+        let mut subs = subscriptions("");
+        let start = Instant::now();
+        subs.handle(peer(), subscribe("{ ping }", 10, 1.0, false), start)
+            .unwrap();
+
+        let renew = SubscriptionRequest::Subscribe {
+            id: Some(1),
+            query: "{ ping }".to_owned(),
+            lease: 10,
+            interval: None,
+            on_change: false,
+        };
+        subs.handle(peer(), renew, start + Duration::from_secs(8))
+            .unwrap();
+
+        let messages = subs.poll(start + Duration::from_secs(12), |_| json!({"errs": ""}));
+        assert_eq!(messages[0].1["subscription"], json!(1));
+        assert_eq!(messages[0].1["errs"], json!(""));
+    }
+
+    #[test]
+    fn subscribe_errors() {
+        let mut subs = subscriptions("max_count = 1");
+        let start = Instant::now();
+
+        assert_eq!(
+            subs.handle(peer(), subscribe("mutation { reset }", 10, 1.0, false), start),
+            Err(SubscriptionError::Mutation)
+        );
+        assert_eq!(
+            subs.handle(peer(), subscribe("{ ping }", 0, 1.0, false), start),
+            Err(SubscriptionError::NoLease)
+        );
+
+        subs.handle(peer(), subscribe("{ ping }", 10, 1.0, false), start)
+            .unwrap();
+        assert_eq!(
+            subs.handle(peer(), subscribe("{ ping }", 10, 1.0, false), start),
+            Err(SubscriptionError::TooMany { max: 1 })
+        );
+
+        // Only the subscriber may cancel its subscription
+        let other: SocketAddr = "10.0.0.2:4000".parse().unwrap();
+        assert_eq!(
+            subs.handle(other, SubscriptionRequest::Unsubscribe { id: 1 }, start),
+            Err(SubscriptionError::Unknown { id: 1 })
+        );
+        assert!(
+            subs.handle(peer(), SubscriptionRequest::Unsubscribe { id: 1 }, start)
+                .is_ok()
+        );
+        assert_eq!(subs.next_due(), None);
+    }
+
+    #[test]
+    fn subscribe_max_per_peer() {
+        let mut subs = subscriptions("max_per_peer = 2");
+        let start = Instant::now();
+
+        subs.handle(peer(), subscribe("{ ping }", 10, 1.0, false), start)
+            .unwrap();
+        let port: SocketAddr = "10.0.0.1:4001".parse().unwrap();
+        subs.handle(port, subscribe("{ ping }", 10, 1.0, false), start)
+            .unwrap();
+
+        // Other ports on the same host share its limit
+        let another: SocketAddr = "10.0.0.1:4002".parse().unwrap();
+        assert_eq!(
+            subs.handle(another, subscribe("{ ping }", 10, 1.0, false), start),
+            Err(SubscriptionError::TooManyForPeer { max: 2 })
+        );
+
+        let other: SocketAddr = "10.0.0.2:4000".parse().unwrap();
+        assert!(
+            subs.handle(other, subscribe("{ ping }", 10, 1.0, false), start)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn subscribe_min_interval() {
+        let mut subs = subscriptions("min_interval = 0.5");
+        let res = subs
+            .handle(peer(), subscribe("{ ping }", 10, 0.01, false), Instant::now())
+            .unwrap();
+        assert_eq!(res["subscribe"]["interval"], json!(0.5));
+    }
+
+    #[test]
+    fn subscribe_max_interval() {
+        let mut subs = subscriptions("");
+        let start = Instant::now();
+
+        // Intervals are capped at the lease, so scheduling the next evaluation can't overflow
+        let request = SubscriptionRequest::from_str(
+            r#"{"subscribe":{"query":"{ping}","lease":10,"interval":1e20}}"#,
+        ).unwrap();
+        let res = subs.handle(peer(), request, start).unwrap();
+        assert_eq!(res["subscribe"]["interval"], json!(10.0));
+
+        let execute = |_: &str| json!({"msg": {"ping": "pong"}, "errs": ""});
+        assert_eq!(subs.poll(start, execute).len(), 1);
+        assert_eq!(subs.next_due(), Some(start + Duration::from_secs(10)));
+
+        assert_eq!(
+            subs.handle(peer(), subscribe("{ ping }", 10, ::std::f64::NAN, false), start),
+            Err(SubscriptionError::BadInterval)
+        );
+        assert_eq!(
+            subs.handle(peer(), subscribe("{ ping }", 10, ::std::f64::INFINITY, false), start),
+            Err(SubscriptionError::BadInterval)
+        );
+    }
+
+    #[test]
+    fn subscribe_lease_too_long() {
+        let mut subs = subscriptions(&format!("max_lease = {}", ::std::i64::MAX));
+        assert_eq!(
+            subs.handle(peer(), subscribe("{ ping }", ::std::u64::MAX, 1.0, false), Instant::now()),
+            Err(SubscriptionError::LeaseTooLong)
+        );
+    }
+}
@@ -0,0 +1,315 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use failure;
+use kubos_system::ServiceRegistry;
+use libc;
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use process;
+
+const DROP_CACHES: &str = "/proc/sys/vm/drop_caches";
+
+/// How long, in seconds, a sync is waited for if the request doesn't say
+pub const DEFAULT_SYNC_TIMEOUT: i32 = 30;
+/// The longest time, in seconds, a sync may be waited for, since nothing else can be served
+/// while waiting
+pub const MAX_SYNC_TIMEOUT: i32 = 300;
+
+// Set while a sync is running, so that timed out syncs don't pile up
+static SYNCING: AtomicBool = AtomicBool::new(false);
+
+/// The names of the services in the system config file, with the ports they listen on
+pub fn kubos_services() -> Result<Vec<(String, u16)>, failure::Error> {
+    Ok(service_ports(&ServiceRegistry::new()?))
+}
+
+fn service_ports(registry: &ServiceRegistry) -> Vec<(String, u16)> {
+    let mut services: Vec<(String, u16)> = registry
+        .services()
+        .iter()
+        .filter_map(|service| {
+            service
+                .addr
+                .rsplit(':')
+                .next()
+                .and_then(|port| port.parse().ok())
+                .map(|port| (service.name.clone(), port))
+        }).collect();
+    services.sort();
+    services.dedup();
+    services
+}
+
+/// Why a process shouldn't be disturbed without being forced, if it shouldn't be: it is init,
+/// the monitor service itself, or one of the given services.
+///
+/// Services are recognized by the UDP ports they listen on, since their names in the config
+/// file don't always match the names of their programs
+pub fn protection(pid: i32, services: &[(String, u16)]) -> Result<Option<String>, failure::Error> {
+    if pid == 1 {
+        return Ok(Some("PID 1 is init".to_owned()));
+    }
+    if pid as u32 == ::std::process::id() {
+        return Ok(Some(format!("PID {} is the monitor service", pid)));
+    }
+    if services.is_empty() {
+        return Ok(None);
+    }
+
+    let ports = process::udp_ports()?;
+    let bound: HashSet<u16> = process::socket_inodes(pid)?
+        .iter()
+        .filter_map(|inode| ports.get(inode).cloned())
+        .collect();
+
+    Ok(services
+        .iter()
+        .find(|&&(_, port)| bound.contains(&port))
+        .map(|&(ref name, _)| format!("PID {} is {}, a Kubos service", pid, name)))
+}
+
+// Refuses PIDs which would select more than one process, and protected processes unless forced
+fn check_target(pid: i32, force: bool) -> Result<(), failure::Error> {
+    if pid <= 0 {
+        bail!("{} is not a valid PID", pid);
+    }
+    if !force {
+        // Init and the monitor service are protected even if the services can't be listed
+        if let Some(reason) = protection(pid, &[])? {
+            bail!("{}. Use force to override", reason);
+        }
+        check_services(pid, kubos_services())?;
+    }
+    Ok(())
+}
+
+// Refuses a process running one of the given services. If they (or the process' sockets)
+// couldn't be listed, every process is refused, since any of them could be a service
+fn check_services(
+    pid: i32,
+    services: Result<Vec<(String, u16)>, failure::Error>,
+) -> Result<(), failure::Error> {
+    let reason = services
+        .and_then(|services| protection(pid, &services))
+        .map_err(|err| {
+            format_err!(
+                "Can't tell whether PID {} is a Kubos service: {}. Use force to override",
+                pid,
+                err
+            )
+        })?;
+    match reason {
+        Some(reason) => bail!("{}. Use force to override", reason),
+        None => Ok(()),
+    }
+}
+
+/// Send a signal to a process
+pub fn kill(pid: i32, signal: i32, force: bool) -> Result<(), failure::Error> {
+    check_target(pid, force)?;
+    if unsafe { libc::kill(pid, signal) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Change the scheduling priority of a process, from -20 (highest) to 19 (lowest). Like
+/// `renice`, only the process' main thread is changed
+pub fn renice(pid: i32, nice: i32, force: bool) -> Result<(), failure::Error> {
+    if nice < -20 || nice > 19 {
+        bail!("Nice values must be between -20 and 19");
+    }
+    check_target(pid, force)?;
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, pid as libc::id_t, nice) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Checks the number of seconds a sync request asked to wait for, which must be between 1 and
+/// `MAX_SYNC_TIMEOUT`
+pub fn sync_timeout(timeout: Option<i32>) -> Result<Duration, failure::Error> {
+    let timeout = timeout.unwrap_or(DEFAULT_SYNC_TIMEOUT);
+    if timeout < 1 || timeout > MAX_SYNC_TIMEOUT {
+        bail!(
+            "Sync timeout must be between 1 and {} seconds, not {}",
+            MAX_SYNC_TIMEOUT,
+            timeout
+        );
+    }
+    Ok(Duration::from_secs(timeout as u64))
+}
+
+/// Flush all filesystem buffers to storage, then drop the clean page, dentry and inode caches
+/// if requested. Gives up waiting after `timeout`, returning an error, but the flush carries on
+/// in the background. Returns how long it took
+pub fn sync(drop_caches: bool, timeout: Duration) -> Result<Duration, failure::Error> {
+    if SYNCING.swap(true, Ordering::SeqCst) {
+        bail!("A sync is already running");
+    }
+
+    let start = Instant::now();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        unsafe { libc::sync() };
+        let result = if drop_caches {
+            fs::write(DROP_CACHES, "3\n").map_err(|err| format!("Failed to drop caches: {}", err))
+        } else {
+            Ok(())
+        };
+        SYNCING.store(false, Ordering::SeqCst);
+        // Nobody is listening if the sync timed out
+        let _ = sender.send(result);
+    });
+
+    match receiver.recv_timeout(timeout) {
+        Ok(Ok(())) => Ok(start.elapsed()),
+        Ok(Err(err)) => Err(format_err!("{}", err)),
+        Err(_) => bail!("Sync still running after {} seconds", timeout.as_secs()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Command;
+
+    // The services' real names, none of which match their program names. The test root's PID
+    // 1492 listens on the app service's port
+    fn services() -> Vec<(String, u16)> {
+        let registry = ServiceRegistry::new_from_str(
+            r#"
+            [app-service.addr]
+            ip = "0.0.0.0"
+            port = 8000
+
+            [file-transfer-service.addr]
+            ip = "0.0.0.0"
+            port = 8008
+
+            [shell-service.addr]
+            ip = "0.0.0.0"
+            port = 8010
+            "#,
+        ).unwrap();
+        service_ports(&registry)
+    }
+
+    #[test]
+    fn service_ports_list() {
+        assert_eq!(
+            services(),
+            vec![
+                ("app-service".to_owned(), 8000),
+                ("file-transfer-service".to_owned(), 8008),
+                ("shell-service".to_owned(), 8010),
+            ]
+        );
+    }
+
+    #[test]
+    fn protected_processes() {
+        let services = services();
+        assert_eq!(protection(1, &services).unwrap(), Some("PID 1 is init".to_owned()));
+        assert!(
+            protection(::std::process::id() as i32, &services)
+                .unwrap()
+                .is_some()
+        );
+        assert_eq!(
+            protection(1492, &services).unwrap(),
+            Some("PID 1492 is app-service, a Kubos service".to_owned())
+        );
+        assert_eq!(protection(1492, &services[1..]).unwrap(), None);
+        assert_eq!(protection(1492, &[]).unwrap(), None);
+        // PID 761's open files can't be listed
+        assert!(protection(761, &services).is_err());
+    }
+
+    #[test]
+    fn kill_refused() {
+        assert!(kill(0, libc::SIGTERM, true).is_err());
+        assert!(kill(-1, libc::SIGTERM, true).is_err());
+        let err = kill(1, libc::SIGTERM, false).unwrap_err();
+        assert_eq!(err.to_string(), "PID 1 is init. Use force to override");
+    }
+
+    #[test]
+    fn services_unknown() {
+        assert!(check_services(1492, Ok(vec![])).is_ok());
+        assert_eq!(
+            check_services(1492, Ok(services()))
+                .unwrap_err()
+                .to_string(),
+            "PID 1492 is app-service, a Kubos service. Use force to override"
+        );
+        // The process' sockets can't be listed
+        assert!(check_services(232, Ok(services())).is_err());
+        assert_eq!(
+            check_services(1492, Err(format_err!("No config file")))
+                .unwrap_err()
+                .to_string(),
+            "Can't tell whether PID 1492 is a Kubos service: No config file. Use force to override"
+        );
+    }
+
+    #[test]
+    fn kill_child() {
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        kill(child.id() as i32, libc::SIGUSR1, true).unwrap();
+        assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGUSR1));
+    }
+
+    #[test]
+    fn renice_child() {
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let pid = child.id() as i32;
+        renice(pid, 10, true).unwrap();
+        let nice = unsafe { libc::getpriority(libc::PRIO_PROCESS, pid as libc::id_t) };
+        assert_eq!(nice, 10);
+
+        assert!(renice(pid, 20, true).is_err());
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn sync_timeouts() {
+        assert_eq!(sync_timeout(None).unwrap(), Duration::from_secs(30));
+        assert_eq!(sync_timeout(Some(1)).unwrap(), Duration::from_secs(1));
+        assert_eq!(sync_timeout(Some(300)).unwrap(), Duration::from_secs(300));
+        for timeout in &[0, -1, 301, ::std::i32::MAX] {
+            assert_eq!(
+                sync_timeout(Some(*timeout)).unwrap_err().to_string(),
+                format!("Sync timeout must be between 1 and 300 seconds, not {}", timeout)
+            );
+        }
+    }
+
+    #[test]
+    fn sync_flush() {
+        assert!(sync(false, Duration::from_secs(10)).is_ok());
+    }
+}
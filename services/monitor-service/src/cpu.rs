@@ -0,0 +1,270 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use failure;
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::time::{Duration, Instant};
+
+use process::root_dir;
+
+/// How often the CPU times are sampled
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time spent by a CPU in each state since boot, in clock ticks, from /proc/stat
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CpuTimes {
+    name: String,
+    user: u64,
+    nice: u64,
+    system: u64,
+    idle: u64,
+    iowait: u64,
+    irq: u64,
+    softirq: u64,
+    steal: u64,
+}
+
+impl CpuTimes {
+    /// Parse the CPU lines of a /proc/stat file. The first entry, `cpu`, is the total of all
+    /// CPUs, followed by an entry for each CPU (`cpu0`, `cpu1`, ...)
+    pub fn parse<R>(stat: R) -> Result<Vec<CpuTimes>, failure::Error>
+        where R: BufRead
+    {
+        let mut cpus = vec![];
+
+        for line in stat.lines() {
+            let line = line?;
+            let mut iter = line.split_whitespace();
+            let name = match iter.next() {
+                Some(name) if name.starts_with("cpu") => name,
+                _ => continue,
+            };
+
+            let mut times = iter.map(|val| val.parse::<u64>().unwrap_or_default());
+            let mut next = || times.next().unwrap_or_default();
+            cpus.push(CpuTimes {
+                name: name.to_owned(),
+                user: next(),
+                nice: next(),
+                system: next(),
+                idle: next(),
+                iowait: next(),
+                irq: next(),
+                softirq: next(),
+                steal: next(),
+            });
+        }
+        Ok(cpus)
+    }
+
+    pub fn from_proc() -> Result<Vec<CpuTimes>, failure::Error> {
+        let file = File::open(root_path!("proc", "stat"))?;
+        Self::parse(BufReader::new(file))
+    }
+
+    fn total(&self) -> u64 {
+        self.user + self.nice + self.system + self.idle + self.iowait + self.irq + self.softirq
+            + self.steal
+    }
+}
+
+/// How a CPU's time was spent between two samples, as percentages
+#[derive(Clone, Debug, PartialEq)]
+pub struct CpuUsage {
+    name: String,
+    user: f64,
+    system: f64,
+    iowait: f64,
+    idle: f64,
+}
+
+impl CpuUsage {
+    /// Calculate the usage of a CPU from two samples of its times
+    pub fn between(prev: &CpuTimes, current: &CpuTimes) -> CpuUsage {
+        let total = current.total().saturating_sub(prev.total());
+        let percent = |current: u64, prev: u64| match total {
+            0 => 0.0,
+            total => current.saturating_sub(prev) as f64 * 100.0 / total as f64,
+        };
+
+        CpuUsage {
+            name: current.name.clone(),
+            user: percent(current.user + current.nice, prev.user + prev.nice),
+            system: percent(
+                current.system + current.irq + current.softirq,
+                prev.system + prev.irq + prev.softirq,
+            ),
+            iowait: percent(current.iowait, prev.iowait),
+            idle: percent(current.idle, prev.idle),
+        }
+    }
+
+    /// The name of the CPU (`cpu` for all CPUs combined)
+    pub fn name(&self) -> &str { &self.name }
+    /// Percentage of time spent running processes or handling interrupts
+    pub fn usage(&self) -> f64 { 100.0 - self.idle - self.iowait }
+    /// Percentage of time spent running user processes
+    pub fn user(&self) -> f64 { self.user }
+    /// Percentage of time spent in the kernel, including handling interrupts
+    pub fn system(&self) -> f64 { self.system }
+    /// Percentage of time spent idle while waiting for I/O to complete
+    pub fn iowait(&self) -> f64 { self.iowait }
+}
+
+/// Keeps the two most recent samples of the CPU times, so that CPU usage can be calculated
+pub struct CpuSampler {
+    previous: Option<Vec<CpuTimes>>,
+    current: Option<(Instant, Vec<CpuTimes>)>,
+}
+
+impl CpuSampler {
+    pub fn new() -> Self {
+        Self {
+            previous: None,
+            current: None,
+        }
+    }
+
+    /// Take a new sample, unless the last one was taken less than `SAMPLE_INTERVAL` ago
+    pub fn sample(&mut self) -> Result<(), failure::Error> {
+        match self.current {
+            Some((taken, _)) if taken.elapsed() < SAMPLE_INTERVAL => Ok(()),
+            _ => {
+                let times = CpuTimes::from_proc()?;
+                self.add(times);
+                Ok(())
+            }
+        }
+    }
+
+    fn add(&mut self, times: Vec<CpuTimes>) {
+        self.previous = self.current.take().map(|(_, times)| times);
+        self.current = Some((Instant::now(), times));
+    }
+
+    /// The usage of each CPU between the last two samples. This is empty until two samples
+    /// have been taken
+    pub fn usage(&self) -> Vec<CpuUsage> {
+        match (&self.previous, &self.current) {
+            (Some(previous), Some((_, current))) => current
+                .iter()
+                .filter_map(|cpu| {
+                    previous
+                        .iter()
+                        .find(|prev| prev.name == cpu.name)
+                        .map(|prev| CpuUsage::between(prev, cpu))
+                }).collect(),
+            _ => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAW: &[u8] = b"cpu  4705 356 584 3699 23 23 0 0 0 0\n\
+                         cpu0 1393 280 134 1840 12 1 0 0 0 0\n\
+                         cpu1 3312 76 450 1859 11 22 0 0 0 0\n\
+                         intr 114930548 113199788 3 0 5 263 0 4 [... lots more numbers ...]\n\
+                         ctxt 1990473\n\
+                         btime 1062191376\n";
+
+    const RAW_LATER: &[u8] = b"cpu  4905 356 684 3799 123 23 0 0 0 0\n\
+                               cpu0 1493 280 134 1840 112 1 0 0 0 0\n\
+                               cpu1 3412 76 550 1959 11 22 0 0 0 0\n";
+
+    #[test]
+    fn cpu_parse() {
+        let cpus = CpuTimes::parse(RAW).unwrap();
+        assert_eq!(cpus.len(), 3);
+        assert_eq!(cpus[1], CpuTimes {
+            name: "cpu0".into(),
+            user: 1393,
+            nice: 280,
+            system: 134,
+            idle: 1840,
+            iowait: 12,
+            irq: 1,
+            softirq: 0,
+            steal: 0,
+        });
+        assert_eq!(cpus[2].name, "cpu1");
+    }
+
+    #[test]
+    fn cpu_parse_partial() {
+        let cpus = CpuTimes::parse(&b"cpu 10 20 30 40\n"[..]).unwrap();
+        assert_eq!(cpus, vec![CpuTimes {
+            name: "cpu".into(),
+            user: 10,
+            nice: 20,
+            system: 30,
+            idle: 40,
+            ..Default::default()
+        }]);
+    }
+
+    #[test]
+    fn cpu_usage_between() {
+        let prev = CpuTimes::parse(RAW).unwrap();
+        let current = CpuTimes::parse(RAW_LATER).unwrap();
+
+        // cpu0 spent 100 ticks running user processes and 100 waiting for I/O
+        let usage = CpuUsage::between(&prev[1], &current[1]);
+        assert_eq!(usage.name(), "cpu0");
+        assert_eq!(usage.user(), 50.0);
+        assert_eq!(usage.system(), 0.0);
+        assert_eq!(usage.iowait(), 50.0);
+        assert_eq!(usage.usage(), 50.0);
+
+        // cpu1 was busy for 200 of 300 ticks
+        let usage = CpuUsage::between(&prev[2], &current[2]);
+        assert!((usage.usage() - 200.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn cpu_usage_no_time() {
+        let prev = CpuTimes::parse(RAW).unwrap();
+        let usage = CpuUsage::between(&prev[0], &prev[0]);
+        assert_eq!(usage.user(), 0.0);
+        assert_eq!(usage.iowait(), 0.0);
+    }
+
+    #[test]
+    fn cpu_sampler() {
+        let mut sampler = CpuSampler::new();
+        assert_eq!(sampler.usage(), vec![]);
+
+        sampler.add(CpuTimes::parse(RAW).unwrap());
+        assert_eq!(sampler.usage(), vec![]);
+
+        sampler.add(CpuTimes::parse(RAW_LATER).unwrap());
+        let usage = sampler.usage();
+        assert_eq!(usage.len(), 3);
+        assert_eq!(usage[0].name(), "cpu");
+        assert_eq!(usage[1].user(), 50.0);
+    }
+
+    #[test]
+    fn cpu_from_proc() {
+        let cpus = CpuTimes::from_proc().unwrap();
+        assert_eq!(cpus.len(), 2);
+        assert_eq!(cpus[1].name, "cpu0");
+    }
+}
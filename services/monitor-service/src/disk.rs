@@ -0,0 +1,225 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use failure;
+use libc;
+
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::mem;
+use std::path::Path;
+
+use process::root_dir;
+
+/// A mounted filesystem, from /proc/mounts
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mount {
+    device: String,
+    path: String,
+    fs_type: String,
+}
+
+impl Mount {
+    // Spaces, tabs and backslashes in mount fields are written as octal escapes, like `\040`
+    fn unescape(field: &str) -> String {
+        let mut result = Vec::with_capacity(field.len());
+        let bytes = field.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            let escaped = bytes.get(i + 1..i + 4).and_then(|digits| {
+                ::std::str::from_utf8(digits)
+                    .ok()
+                    .and_then(|digits| u8::from_str_radix(digits, 8).ok())
+            });
+            match (bytes[i], escaped) {
+                (b'\\', Some(byte)) => {
+                    result.push(byte);
+                    i += 4;
+                }
+                (byte, _) => {
+                    result.push(byte);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8_lossy(&result).into_owned()
+    }
+
+    pub fn parse<R>(mounts: R) -> Result<Vec<Mount>, failure::Error>
+        where R: BufRead
+    {
+        let mut result = vec![];
+
+        for line in mounts.lines() {
+            let line = line?;
+            let mut iter = line.split_whitespace();
+            match (iter.next(), iter.next(), iter.next()) {
+                (Some(device), Some(path), Some(fs_type)) => result.push(Mount {
+                    device: Self::unescape(device),
+                    path: Self::unescape(path),
+                    fs_type: fs_type.to_owned(),
+                }),
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn from_proc() -> Result<Vec<Mount>, failure::Error> {
+        let file = File::open(root_path!("proc", "mounts"))?;
+        Self::parse(BufReader::new(file))
+    }
+
+    /// The device (or pseudo filesystem name) which is mounted
+    pub fn device(&self) -> &str { &self.device }
+    /// The directory the filesystem is mounted on
+    pub fn path(&self) -> &str { &self.path }
+    /// The type of the filesystem, like `ext4`
+    pub fn fs_type(&self) -> &str { &self.fs_type }
+}
+
+/// The space used by a mounted filesystem
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiskUsage {
+    mount: Mount,
+    total: u64,
+    free: u64,
+    available: u64,
+}
+
+impl DiskUsage {
+    /// Look up the space used by a mounted filesystem with `statvfs`
+    pub fn from_mount(mount: Mount) -> Result<DiskUsage, failure::Error> {
+        let path = CString::new(mount.path.clone())?;
+        let mut stat: libc::statvfs = unsafe { mem::zeroed() };
+        if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        let kb = |blocks| blocks as u64 * stat.f_frsize as u64 / 1024;
+        Ok(DiskUsage {
+            mount,
+            total: kb(stat.f_blocks),
+            free: kb(stat.f_bfree),
+            available: kb(stat.f_bavail),
+        })
+    }
+
+    /// The space used by each mounted filesystem which has any storage. Pseudo filesystems,
+    /// like `/proc`, and filesystems which can't be read are skipped
+    pub fn all() -> Result<Vec<DiskUsage>, failure::Error> {
+        Ok(Mount::from_proc()?
+            .into_iter()
+            .filter_map(|mount| Self::from_mount(mount).ok())
+            .filter(|usage| usage.total > 0)
+            .collect())
+    }
+
+    /// The space used by the filesystem which `path` is stored on
+    pub fn for_path(path: &str) -> Result<DiskUsage, failure::Error> {
+        Mount::from_proc()?
+            .into_iter()
+            .filter(|mount| Path::new(path).starts_with(&mount.path))
+            .max_by_key(|mount| mount.path.len())
+            .ok_or_else(|| format_err!("No filesystem is mounted at {}", path))
+            .and_then(Self::from_mount)
+    }
+
+    pub fn mount(&self) -> &Mount { &self.mount }
+    /// Size of the filesystem in kB
+    pub fn total(&self) -> u64 { self.total }
+    /// Free space in kB, including space reserved for root
+    pub fn free(&self) -> u64 { self.free }
+    /// Free space in kB which is available to unprivileged users
+    pub fn available(&self) -> u64 { self.available }
+    /// Percentage of the filesystem which is in use
+    pub fn used_percent(&self) -> f64 {
+        match self.total {
+            0 => 0.0,
+            total => (total - self.free) as f64 * 100.0 / total as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAW: &[u8] = b"/dev/root / ext4 rw,relatime,data=ordered 0 0\n\
+                         proc /proc proc rw,relatime 0 0\n\
+                         /dev/mmcblk0p7 /home ext4 rw,relatime,data=ordered 0 0\n\
+                         /dev/sda1 /media/My\\040Disk vfat rw 0 0\n";
+
+    #[test]
+    fn mounts_parse() {
+        let mounts = Mount::parse(RAW).unwrap();
+        assert_eq!(mounts.len(), 4);
+        assert_eq!(mounts[2], Mount {
+            device: "/dev/mmcblk0p7".into(),
+            path: "/home".into(),
+            fs_type: "ext4".into(),
+        });
+        assert_eq!(mounts[3].path(), "/media/My Disk");
+        assert_eq!(mounts[3].fs_type(), "vfat");
+    }
+
+    #[test]
+    fn mounts_unescape() {
+        assert_eq!(Mount::unescape("a\\134b\\011c"), "a\\b\tc");
+        assert_eq!(Mount::unescape("trailing\\04"), "trailing\\04");
+    }
+
+    #[test]
+    fn disk_usage_root() {
+        let usage = DiskUsage::from_mount(Mount {
+            device: "/dev/root".into(),
+            path: "/".into(),
+            fs_type: "ext4".into(),
+        }).unwrap();
+        assert!(usage.total() > 0);
+        assert!(usage.free() <= usage.total());
+        assert!(usage.available() <= usage.free());
+        assert!(usage.used_percent() >= 0.0 && usage.used_percent() <= 100.0);
+    }
+
+    #[test]
+    fn disk_usage_missing() {
+        assert!(DiskUsage::from_mount(Mount {
+            device: "none".into(),
+            path: "/not/a/mount".into(),
+            fs_type: "ext4".into(),
+        }).is_err());
+    }
+
+    #[test]
+    fn disk_usage_all() {
+        // The test mounts include /proc, which has no storage
+        let disks = DiskUsage::all().unwrap();
+        assert_eq!(disks.len(), 1);
+        assert_eq!(disks[0].mount().path(), "/");
+        assert_eq!(disks[0].mount().device(), "/dev/root");
+    }
+
+    #[test]
+    fn disk_usage_for_path() {
+        let usage = DiskUsage::for_path("/home/kubos").unwrap();
+        assert_eq!(usage.mount().path(), "/");
+
+        // The most specific mount is used, even though it can't be read
+        assert!(DiskUsage::for_path("/not/mounted/here/file").is_err());
+    }
+}
@@ -0,0 +1,112 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use failure;
+
+use std::fs::File;
+use std::io::Read;
+
+use process::root_dir;
+
+/// System load averages, from /proc/loadavg
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LoadAvg {
+    one: f64,
+    five: f64,
+    fifteen: f64,
+    running: u32,
+    total: u32,
+}
+
+impl LoadAvg {
+    pub fn parse<R>(mut loadavg: R) -> Result<LoadAvg, failure::Error>
+        where R: Read
+    {
+        let mut contents = String::new();
+        loadavg.read_to_string(&mut contents)?;
+
+        let mut iter = contents.split_whitespace();
+        let mut next_avg = || -> Result<f64, failure::Error> {
+            let avg = iter.next().ok_or(format_err!("Invalid loadavg format"))?;
+            Ok(avg.parse()?)
+        };
+        let one = next_avg()?;
+        let five = next_avg()?;
+        let fifteen = next_avg()?;
+
+        // The scheduling entities currently runnable, and that exist, as "running/total"
+        let mut entities = iter
+            .next()
+            .unwrap_or("")
+            .split('/')
+            .map(|num| num.parse().unwrap_or_default());
+
+        Ok(LoadAvg {
+            one,
+            five,
+            fifteen,
+            running: entities.next().unwrap_or_default(),
+            total: entities.next().unwrap_or_default(),
+        })
+    }
+
+    pub fn from_proc() -> Result<LoadAvg, failure::Error> {
+        let file = File::open(root_path!("proc", "loadavg"))?;
+        Self::parse(file)
+    }
+
+    /// Average number of jobs in the run queue or waiting for disk I/O over the last minute
+    pub fn one(&self) -> f64 { self.one }
+    /// Average number of jobs in the run queue or waiting for disk I/O over the last 5 minutes
+    pub fn five(&self) -> f64 { self.five }
+    /// Average number of jobs in the run queue or waiting for disk I/O over the last 15 minutes
+    pub fn fifteen(&self) -> f64 { self.fifteen }
+    /// Number of currently runnable processes and threads
+    pub fn running(&self) -> u32 { self.running }
+    /// Number of processes and threads which currently exist
+    pub fn total(&self) -> u32 { self.total }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loadavg_parse() {
+        let loadavg = LoadAvg::parse(&b"0.18 0.38 0.26 2/72 19379\n"[..]);
+        assert_eq!(loadavg.ok(), Some(LoadAvg {
+            one: 0.18,
+            five: 0.38,
+            fifteen: 0.26,
+            running: 2,
+            total: 72,
+        }));
+    }
+
+    #[test]
+    fn loadavg_invalid() {
+        assert!(LoadAvg::parse(&b"0.18 high"[..]).is_err());
+    }
+
+    #[test]
+    fn loadavg_from_proc() {
+        let loadavg = LoadAvg::from_proc().unwrap();
+        assert_eq!(loadavg.one(), 1.5);
+        assert_eq!(loadavg.fifteen(), 0.25);
+        assert_eq!(loadavg.running(), 1);
+        assert_eq!(loadavg.total(), 105);
+    }
+}
@@ -0,0 +1,482 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+use failure;
+use kubos_system::ConfigSection;
+use libc;
+use regex::Regex;
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Component, Path};
+
+use process::root_dir;
+use telemetry;
+use uptime::Uptime;
+
+/// The syslog severity names, from 0 (the most severe) to 7
+pub const LEVELS: [&str; 8] = [
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+
+// Only the start of a log line is searched for its level, so messages which mention one
+// aren't mistaken for it
+const LEVEL_WORDS: usize = 6;
+
+/// Settings read from the `[monitor-service]` config section
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct LogConfig {
+    /// The log files which can be read, by name
+    pub log_files: HashMap<String, String>,
+    /// The directory which log queries write their `output` files to
+    pub log_output_dir: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        let mut log_files = HashMap::new();
+        log_files.insert("messages".to_owned(), "/var/log/messages".to_owned());
+        LogConfig {
+            log_files,
+            log_output_dir: "/home/system/log/monitor".to_owned(),
+        }
+    }
+}
+
+impl ConfigSection for LogConfig {}
+
+/// Converts a severity name, or a common abbreviation of one, to its syslog level
+pub fn parse_level(name: &str) -> Option<u8> {
+    match name.to_lowercase().as_str() {
+        "emerg" | "emergency" | "panic" => Some(0),
+        "alert" => Some(1),
+        "crit" | "critical" => Some(2),
+        "err" | "error" => Some(3),
+        "warn" | "warning" => Some(4),
+        "notice" => Some(5),
+        "info" => Some(6),
+        "debug" | "trace" => Some(7),
+        _ => None,
+    }
+}
+
+/// A message from a log
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogEntry {
+    /// When the message was logged, in seconds since the UNIX epoch, if known
+    pub timestamp: Option<f64>,
+    /// The message's syslog severity, from 0 (emergency) to 7 (debug), if known
+    pub level: Option<u8>,
+    /// The message as it appears in the log
+    pub message: String,
+}
+
+impl LogEntry {
+    /// Parse a record read from /dev/kmsg: `priority,sequence,microseconds,flags;message`.
+    /// The message is formatted like `dmesg` output, and `boot_time` (in seconds since the
+    /// UNIX epoch) is used to find when it was logged. Continuation lines, which start with a
+    /// space, and malformed records return `None`
+    pub fn parse_kmsg(record: &str, boot_time: f64) -> Option<LogEntry> {
+        let semicolon = record.find(';')?;
+        let mut fields = record[0..semicolon].split(',');
+        let priority: u32 = fields.next()?.parse().ok()?;
+        let micros: u64 = fields.nth(1)?.parse().ok()?;
+
+        let seconds = micros as f64 / 1e6;
+        Some(LogEntry {
+            timestamp: Some(boot_time + seconds),
+            level: Some((priority & 7) as u8),
+            message: format!("[{:12.6}] {}", seconds, &record[semicolon + 1..]),
+        })
+    }
+
+    /// Parse a line of a log file. The time is found if the line starts with an RFC 3339 time,
+    /// or a syslog time like `Oct 19 12:34:56` in the given year, which is treated as UTC. The
+    /// level is found from a word like `user.err`, `[ERROR]` or `<err>` near the start
+    pub fn parse_line(line: &str, year: i32) -> LogEntry {
+        let first = line.split_whitespace().next().unwrap_or_default();
+        let timestamp = DateTime::parse_from_rfc3339(first)
+            .ok()
+            .map(|time| time.timestamp() as f64 + f64::from(time.timestamp_subsec_micros()) / 1e6)
+            .or_else(|| {
+                line.get(0..15).and_then(|time| {
+                    NaiveDateTime::parse_from_str(&format!("{} {}", year, time), "%Y %b %e %H:%M:%S")
+                        .ok()
+                        .map(|time| time.timestamp() as f64)
+                })
+            });
+
+        let level = line
+            .split_whitespace()
+            .take(LEVEL_WORDS)
+            .filter_map(|word| {
+                if word.len() > 2 && (word.starts_with('[') && word.ends_with(']')
+                    || word.starts_with('<') && word.ends_with('>'))
+                {
+                    parse_level(&word[1..word.len() - 1])
+                } else {
+                    word.rfind('.').and_then(|dot| parse_level(&word[dot + 1..]))
+                }
+            })
+            .next();
+
+        LogEntry {
+            timestamp,
+            level,
+            message: line.to_owned(),
+        }
+    }
+}
+
+/// Selects the entries of a log to return
+#[derive(Clone, Debug, Default)]
+pub struct LogFilter {
+    /// The most entries to return. The last matching entries are kept
+    pub lines: usize,
+    /// The least severe level to return. Entries without a level are skipped
+    pub level: Option<u8>,
+    /// The earliest time to return, in seconds since the UNIX epoch. Entries without a time
+    /// are skipped
+    pub since: Option<f64>,
+    /// A pattern the entries' messages must match
+    pub pattern: Option<Regex>,
+}
+
+impl LogFilter {
+    /// Create a filter from a request's arguments. By default the last 100 entries are kept
+    pub fn new(
+        lines: Option<i32>,
+        level: Option<&str>,
+        since: Option<f64>,
+        grep: Option<&str>,
+    ) -> Result<LogFilter, failure::Error> {
+        let lines = match lines {
+            Some(lines) if lines <= 0 => bail!("lines must be greater than zero"),
+            Some(lines) => lines as usize,
+            None => 100,
+        };
+        let level = match level {
+            Some(name) => Some(parse_level(name).ok_or_else(|| {
+                format_err!("Unknown level {}. Must be one of {}", name, LEVELS.join(", "))
+            })?),
+            None => None,
+        };
+        let pattern = match grep {
+            Some(grep) => Some(Regex::new(grep)?),
+            None => None,
+        };
+
+        Ok(LogFilter {
+            lines,
+            level,
+            since,
+            pattern,
+        })
+    }
+
+    fn matches(&self, entry: &LogEntry) -> bool {
+        self.level.map_or(true, |max| entry.level.map_or(false, |level| level <= max))
+            && self.since.map_or(true, |since| {
+                entry.timestamp.map_or(false, |timestamp| timestamp >= since)
+            })
+            && self.pattern
+                .as_ref()
+                .map_or(true, |pattern| pattern.is_match(&entry.message))
+    }
+
+    /// The last `lines` entries which match the filter, oldest first
+    pub fn apply<I>(&self, entries: I) -> Vec<LogEntry>
+    where
+        I: Iterator<Item = LogEntry>,
+    {
+        // `lines` comes from the request, so only grows as entries are found
+        let mut kept = VecDeque::new();
+        for entry in entries.filter(|entry| self.matches(entry)) {
+            if kept.len() == self.lines {
+                kept.pop_front();
+            }
+            kept.push_back(entry);
+        }
+        kept.into_iter().collect()
+    }
+}
+
+/// Read the kernel's ring buffer from /dev/kmsg
+pub fn kernel_log(filter: &LogFilter) -> Result<Vec<LogEntry>, failure::Error> {
+    let boot_time = telemetry::timestamp() - Uptime::from_proc()?.seconds();
+    let mut kmsg = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(root_path!("dev", "kmsg"))?;
+
+    // Each read returns one record, until there are no more
+    let mut records = String::new();
+    let mut buf = [0; 8192];
+    loop {
+        match kmsg.read(&mut buf) {
+            Ok(0) => break,
+            Ok(size) => records.push_str(&String::from_utf8_lossy(&buf[0..size])),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+            // The oldest records were overwritten while they were being read
+            Err(ref err) if err.raw_os_error() == Some(libc::EPIPE) => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok(filter.apply(
+        records
+            .lines()
+            .filter_map(|record| LogEntry::parse_kmsg(record, boot_time)),
+    ))
+}
+
+/// Read a log file. Lines without a time of their own, such as the rest of a multi-line
+/// message, are given the time of the line before them
+pub fn read_log(path: &str, filter: &LogFilter) -> Result<Vec<LogEntry>, failure::Error> {
+    let file = File::open(path)?;
+    let now = Utc::now();
+    let mut last = None;
+
+    // Stop at the first read error, which may keep happening (if the path is a directory, say)
+    let entries = BufReader::new(file)
+        .split(b'\n')
+        .map_while(Result::ok)
+        .map(|line| String::from_utf8_lossy(&line).into_owned())
+        .map(|line| {
+            let mut entry = LogEntry::parse_line(&line, now.year());
+            // Syslog times don't have a year, so a time after now must be from last year
+            if entry.timestamp.map_or(false, |time| time > now.timestamp() as f64 + 86400.0) {
+                entry = LogEntry::parse_line(&line, now.year() - 1);
+            }
+            match entry.timestamp {
+                Some(time) => last = Some(time),
+                None => entry.timestamp = last,
+            }
+            entry
+        });
+
+    Ok(filter.apply(entries))
+}
+
+/// Write log entries to a new file in `dir`, one per line, so that it can be downloaded.
+/// `name` must be a plain file name, and the file must not already exist. Returns the file's path
+pub fn write_log(dir: &str, name: &str, entries: &[LogEntry]) -> Result<String, failure::Error> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(part)), None) if part == name => {}
+        _ => bail!("Output must be a file name, not a path: {}", name),
+    }
+
+    fs::create_dir_all(dir)?;
+    let path = Path::new(dir).join(name);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(&path)
+        .map_err(|err| format_err!("Failed to create {}: {}", path.display(), err))?;
+    for entry in entries {
+        writeln!(file, "{}", entry.message)?;
+    }
+    Ok(path.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::env;
+    use std::fs;
+
+    fn filter(lines: usize) -> LogFilter {
+        LogFilter {
+            lines,
+            ..Default::default()
+        }
+    }
+
+    fn messages() -> String {
+        root_path!("var", "log", "messages").to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn level_names() {
+        assert_eq!(parse_level("err"), Some(3));
+        assert_eq!(parse_level("ERROR"), Some(3));
+        assert_eq!(parse_level("Warn"), Some(4));
+        assert_eq!(parse_level("debug"), Some(7));
+        assert_eq!(parse_level("loud"), None);
+        assert_eq!(parse_level(LEVELS[5]), Some(5));
+    }
+
+    #[test]
+    fn kmsg_parse() {
+        let entry = LogEntry::parse_kmsg("30,1234,5678901,-;usb 1-1: new device", 1000.0);
+        assert_eq!(
+            entry,
+            Some(LogEntry {
+                timestamp: Some(1005.678901),
+                level: Some(6),
+                message: "[    5.678901] usb 1-1: new device".to_owned(),
+            })
+        );
+
+        assert_eq!(LogEntry::parse_kmsg(" SUBSYSTEM=usb", 1000.0), None);
+        assert_eq!(LogEntry::parse_kmsg("not a record", 1000.0), None);
+    }
+
+    #[test]
+    fn line_parse_syslog() {
+        let entry = LogEntry::parse_line("Oct  9 12:34:56 kubos user.err app: Failed", 2018);
+        let time = Utc.ymd(2018, 10, 9).and_hms(12, 34, 56).timestamp() as f64;
+        assert_eq!(entry.timestamp, Some(time));
+        assert_eq!(entry.level, Some(3));
+        assert_eq!(entry.message, "Oct  9 12:34:56 kubos user.err app: Failed");
+    }
+
+    #[test]
+    fn line_parse_formats() {
+        let entry = LogEntry::parse_line("2018-10-19T12:34:56.5+00:00 kubos app[12]: <info> up", 0);
+        let time = Utc.ymd(2018, 10, 19).and_hms(12, 34, 56).timestamp() as f64;
+        assert_eq!(entry.timestamp, Some(time + 0.5));
+        assert_eq!(entry.level, Some(6));
+
+        let entry = LogEntry::parse_line("12:34:56 [WARN] Low on space", 2018);
+        assert_eq!(entry.timestamp, None);
+        assert_eq!(entry.level, Some(4));
+
+        // Levels mentioned later in the message are ignored
+        let entry = LogEntry::parse_line("Oct 19 12:34:56 kubos app: one two three [ERROR]", 2018);
+        assert_eq!(entry.level, None);
+    }
+
+    #[test]
+    fn filter_new() {
+        let filter = LogFilter::new(None, Some("warn"), Some(5.0), Some("^a")).unwrap();
+        assert_eq!(filter.lines, 100);
+        assert_eq!(filter.level, Some(4));
+        assert_eq!(filter.since, Some(5.0));
+        assert!(filter.pattern.unwrap().is_match("abc"));
+
+        assert!(LogFilter::new(Some(0), None, None, None).is_err());
+        let err = LogFilter::new(None, Some("loud"), None, None).unwrap_err();
+        assert!(err.to_string().starts_with("Unknown level loud"));
+        assert!(LogFilter::new(None, None, None, Some("(")).is_err());
+    }
+
+    #[test]
+    fn filter_apply() {
+        let entries = || {
+            (0..10).map(|index| LogEntry {
+                timestamp: Some(index as f64),
+                level: Some(index % 8),
+                message: format!("message {}", index),
+            })
+        };
+
+        let kept = filter(3).apply(entries());
+        assert_eq!(kept.len(), 3);
+        assert_eq!(kept[0].message, "message 7");
+
+        let kept = LogFilter {
+            level: Some(3),
+            since: Some(2.0),
+            ..filter(10)
+        }.apply(entries());
+        let messages: Vec<&str> = kept.iter().map(|entry| entry.message.as_str()).collect();
+        assert_eq!(messages, ["message 2", "message 3", "message 8", "message 9"]);
+
+        let kept = LogFilter {
+            pattern: Some(Regex::new("e [45]$").unwrap()),
+            ..filter(10)
+        }.apply(entries());
+        assert_eq!(kept.len(), 2);
+    }
+
+    #[test]
+    fn kernel_log_read() {
+        let entries = kernel_log(&filter(100)).unwrap();
+        assert_eq!(entries.len(), 5);
+        assert_eq!(
+            entries[4].message,
+            "[    5.100042] usb 1-1: new high-speed USB device number 2"
+        );
+
+        let entries = kernel_log(&LogFilter {
+            level: Some(4),
+            ..filter(100)
+        }).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].level, Some(3));
+    }
+
+    #[test]
+    fn read_log_file() {
+        let entries = read_log(&messages(), &filter(100)).unwrap();
+        assert_eq!(entries.len(), 6);
+        // The rest of a multi-line message takes the time of its first line
+        assert_eq!(entries[3].level, None);
+        assert_eq!(entries[3].timestamp, entries[2].timestamp);
+
+        let since = entries[4].timestamp;
+        let entries = read_log(&messages(), &LogFilter { since, ..filter(100) }).unwrap();
+        assert_eq!(entries.len(), 2);
+
+        let entries = read_log(&messages(), &LogFilter {
+            level: Some(4),
+            pattern: Some(Regex::new("service").unwrap()),
+            ..filter(1)
+        }).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].message.contains("Rule storage is broken"));
+
+        assert!(read_log("/not/a/log", &filter(100)).is_err());
+    }
+
+    #[test]
+    fn write_log_file() {
+        let dir = env::temp_dir().join(format!("monitor-logs-{}", ::std::process::id()));
+        let dir = dir.to_string_lossy().into_owned();
+        let entries = read_log(&messages(), &filter(2)).unwrap();
+
+        let path = write_log(&dir, "storage.log", &entries).unwrap();
+        assert_eq!(path, format!("{}/storage.log", dir));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "Oct 19 08:04:02 kubos user.warn monitor-service: Rule storage is broken\n\
+             Oct 19 08:04:10 kubos user.info monitor-service: Rule storage is met again\n"
+        );
+
+        // Existing files are never overwritten
+        assert!(write_log(&dir, "storage.log", &entries).is_err());
+        for name in &["../storage.log", "/tmp/storage.log", "a/storage.log", ".", ""] {
+            let err = write_log(&dir, name, &entries).unwrap_err();
+            assert!(err.to_string().starts_with("Output must be a file name"), "{}", name);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_log_directory() {
+        // Reading a directory fails on every line, which mustn't keep the read going forever
+        let entries = read_log(&root_path!("var").to_string_lossy(), &filter(100)).unwrap();
+        assert!(entries.is_empty());
+    }
+}
@@ -14,19 +14,51 @@
 // limitations under the License.
 //
 
-//! Service for monitoring KubOS Linux processes, memory, and CPU usage
+//! Service for monitoring KubOS Linux processes, memory, CPU, disk and network usage
+//!
+//! It also checks the watchdog rules in its config section, taking each broken rule's action,
+//! can signal or renice processes and flush the filesystems, and can read the kernel and system
+//! logs
 //!
 //! # GraphQL Schema
 //!
 //! ```graphql
 //! schema {
 //!     query: Query
+//!     mutation: Mutation
 //! }
 //!
 //! type Query {
 //!     ping: String!
 //!     memInfo: MemInfo!
 //!     ps(pids: [Int!] = null): [ProcInfo!]!
+//!     cpu: [CpuUsage!]!
+//!     loadAvg: LoadAvg!
+//!     uptime: Uptime!
+//!     disks: [DiskUsage!]!
+//!     network: [NetDev!]!
+//!     thermal: [ThermalZone!]!
+//!     rules: [RuleStatus!]!
+//!     kernelLog(lines: Int = 100, level: String, since: Float, grep: String, output: String): LogResponse!
+//!     log(name: String!, lines: Int = 100, level: String, since: Float, grep: String, output: String): LogResponse!
+//!     logFiles: [String!]!
+//! }
+//!
+//! type Mutation {
+//!     kill(pid: Int!, signal: Int = 15, force: Boolean = false): GenericResponse!
+//!     renice(pid: Int!, nice: Int!, force: Boolean = false): GenericResponse!
+//!     sync(dropCaches: Boolean = false, timeout: Int = 30): SyncResponse!
+//! }
+//!
+//! type GenericResponse {
+//!     errors: String!
+//!     success: Boolean!
+//! }
+//!
+//! type SyncResponse {
+//!     errors: String!
+//!     success: Boolean!
+//!     duration: Float
 //! }
 //!
 //! type MemInfo {
@@ -48,37 +80,158 @@
 //!     rss: Int
 //!     threads: Int
 //!     cmd: String
+//!     cpu: Float
+//!     age: Float
+//!     fds: Int
+//!     history: [ProcSample!]!
+//! }
+//!
+//! type ProcSample {
+//!     timestamp: Float!
+//!     cpu: Float!
+//!     rss: Int!
+//!     fds: Int
+//! }
+//!
+//! type CpuUsage {
+//!     name: String!
+//!     usage: Float!
+//!     user: Float!
+//!     system: Float!
+//!     iowait: Float!
+//! }
+//!
+//! type LoadAvg {
+//!     one: Float!
+//!     five: Float!
+//!     fifteen: Float!
+//!     running: Int!
+//!     total: Int!
+//! }
+//!
+//! type Uptime {
+//!     seconds: Float!
+//!     idle: Float!
+//! }
+//!
+//! type DiskUsage {
+//!     device: String!
+//!     path: String!
+//!     fsType: String!
+//!     total: Int!
+//!     free: Int!
+//!     available: Int!
+//!     usedPercent: Float!
+//! }
+//!
+//! type NetDev {
+//!     name: String!
+//!     rxBytes: Float!
+//!     rxPackets: Float!
+//!     rxErrors: Float!
+//!     rxDropped: Float!
+//!     txBytes: Float!
+//!     txPackets: Float!
+//!     txErrors: Float!
+//!     txDropped: Float!
+//! }
+//!
+//! type ThermalZone {
+//!     name: String!
+//!     type: String
+//!     temp: Float
+//! }
+//!
+//! type RuleStatus {
+//!     name: String!
+//!     ok: Boolean
+//!     message: String
+//!     checked: Float
+//!     failingSince: Float
+//!     failures: Int!
+//!     actions: Int!
+//!     lastAction: Float
+//! }
+//!
+//! type LogResponse {
+//!     count: Int!
+//!     entries: [LogEntry!]!
+//!     file: String
+//! }
+//!
+//! type LogEntry {
+//!     timestamp: Float
+//!     level: String
+//!     message: String!
 //! }
 //! ```
 
+extern crate chrono;
 #[macro_use]
 extern crate failure;
 #[macro_use]
 extern crate juniper;
 extern crate kubos_service;
+extern crate kubos_system;
+extern crate libc;
 extern crate regex;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
 
 #[cfg(test)]
 #[macro_use]
 extern crate lazy_static;
 
 use kubos_service::{Config, Service};
+use model::Subsystem;
 use schema::{MutationRoot, QueryRoot};
+use std::net::SocketAddr;
 
-mod meminfo;
-mod objects;
+// Defines the root_path! macro used by the other modules, so must come first
 #[macro_use]
 mod process;
+
+mod control;
+mod cpu;
+mod disk;
+mod loadavg;
+mod logs;
+mod meminfo;
+mod model;
+mod net;
+mod objects;
+mod sampler;
 mod schema;
+mod telemetry;
+mod thermal;
+mod uptime;
 mod userinfo;
+mod watchdog;
+
+// Process samples and alarms are sent to the telemetry service's direct UDP port, if it has one
+fn telemetry_addr() -> Option<SocketAddr> {
+    let config = Config::new("telemetry-service");
+    config
+        .get("direct_port")
+        .and_then(|port| port.as_integer())
+        .and_then(|port| format!("{}:{}", config.addr().ip(), port).parse().ok())
+}
 
 fn main() {
     let config = Config::new("monitor-service");
+    let subsystem = match Subsystem::new(&config, telemetry_addr()) {
+        Ok(subsystem) => subsystem,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
 
-    Service::new(
-        config,
-        (),
-        QueryRoot,
-        MutationRoot,
-    ).start();
+    Service::new(config, subsystem, QueryRoot, MutationRoot)
+        .on_tick(|subsystem| subsystem.tick())
+        .on_reload(|subsystem, config| subsystem.reconfigure(config))
+        .start();
 }
@@ -20,6 +20,8 @@ use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::str::FromStr;
 
+use process::root_dir;
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct MemInfo {
     total: Option<u32>,
@@ -76,7 +78,7 @@ impl MemInfo {
     }
 
     pub fn from_proc() -> Result<MemInfo, failure::Error> {
-        let file = File::open("/proc/meminfo")?;
+        let file = File::open(root_path!("proc", "meminfo"))?;
         let reader = BufReader::new(file);
         Self::parse(reader)
     }
@@ -140,6 +142,13 @@ mod tests {
         assert_eq!(info.low_free(), Some(317980));
     }
 
+    #[test]
+    fn meminfo_from_proc() {
+        let info = MemInfo::from_proc().unwrap();
+        assert_eq!(info.total(), Some(515352));
+        assert_eq!(info.available(), Some(498232));
+    }
+
     #[test]
     fn meminfo_partial() {
         let info = MemInfo::parse(RAW_PARTIAL);
@@ -0,0 +1,111 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use kubos_system::{Config, ConfigError};
+
+use std::cell::RefCell;
+use std::net::SocketAddr;
+
+use cpu::{CpuSampler, CpuUsage};
+use logs::LogConfig;
+use sampler::{ProcessSample, ProcessSampler, SamplerConfig};
+use watchdog::{RuleStatus, Watchdog, WatchdogConfig};
+
+/// Monitor service state, which is kept between requests
+pub struct Subsystem {
+    cpu: RefCell<CpuSampler>,
+    processes: RefCell<ProcessSampler>,
+    watchdog: RefCell<Watchdog>,
+    logs: RefCell<LogConfig>,
+}
+
+impl Subsystem {
+    /// Create the subsystem from the `[monitor-service]` config section. Samples and alarms
+    /// are sent to the telemetry service's direct UDP port, if its address is given
+    pub fn new(config: &Config, telemetry: Option<SocketAddr>) -> Result<Self, ConfigError> {
+        let sampler: SamplerConfig = config.section()?;
+        let watchdog: WatchdogConfig = config.section()?;
+        let logs: LogConfig = config.section()?;
+
+        let subsystem = Subsystem {
+            cpu: RefCell::new(CpuSampler::new()),
+            processes: RefCell::new(ProcessSampler::new(sampler, telemetry)),
+            watchdog: RefCell::new(Watchdog::new(watchdog, telemetry)),
+            logs: RefCell::new(logs),
+        };
+        subsystem.tick();
+        Ok(subsystem)
+    }
+
+    /// Take any samples which are due. Called from the service's main loop
+    pub fn tick(&self) {
+        if let Err(err) = self.cpu.borrow_mut().sample() {
+            eprintln!("Failed to sample CPU times: {}", err);
+        }
+        if let Err(err) = self.processes.borrow_mut().sample() {
+            eprintln!("Failed to sample processes: {}", err);
+        }
+        self.watchdog.borrow_mut().check();
+    }
+
+    /// Apply new settings. Any which are invalid are logged, and the previous ones are kept
+    pub fn reconfigure(&self, config: &Config) {
+        match config.section() {
+            Ok(sampler) => self.processes.borrow_mut().reconfigure(sampler),
+            Err(err) => eprintln!("Keeping previous sampler settings: {}", err),
+        }
+        match config.section() {
+            Ok(watchdog) => self.watchdog.borrow_mut().reconfigure(watchdog),
+            Err(err) => eprintln!("Keeping previous watchdog rules: {}", err),
+        }
+        match config.section() {
+            Ok(logs) => *self.logs.borrow_mut() = logs,
+            Err(err) => eprintln!("Keeping previous log files: {}", err),
+        }
+    }
+
+    /// The usage of each CPU over the last sample interval
+    pub fn cpu_usage(&self) -> Vec<CpuUsage> {
+        self.cpu.borrow().usage()
+    }
+
+    /// The recent samples of a process, oldest first
+    pub fn process_history(&self, pid: i32, start_time: u64) -> Vec<ProcessSample> {
+        self.processes.borrow().history(pid, start_time)
+    }
+
+    /// The latest status of each watchdog rule
+    pub fn rule_status(&self) -> Vec<RuleStatus> {
+        self.watchdog.borrow().status()
+    }
+
+    /// The names of the log files which can be read, in alphabetical order
+    pub fn log_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.logs.borrow().log_files.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// The directory which log queries write their output files to
+    pub fn log_output_dir(&self) -> String {
+        self.logs.borrow().log_output_dir.clone()
+    }
+
+    /// The path of a log file, by name
+    pub fn log_path(&self, name: &str) -> Option<String> {
+        self.logs.borrow().log_files.get(name).cloned()
+    }
+}
@@ -0,0 +1,146 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use failure;
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use process::root_dir;
+
+/// Traffic counters for a network interface, from /proc/net/dev
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NetDev {
+    name: String,
+    rx_bytes: u64,
+    rx_packets: u64,
+    rx_errors: u64,
+    rx_dropped: u64,
+    tx_bytes: u64,
+    tx_packets: u64,
+    tx_errors: u64,
+    tx_dropped: u64,
+}
+
+impl NetDev {
+    pub fn parse<R>(dev: R) -> Result<Vec<NetDev>, failure::Error>
+        where R: BufRead
+    {
+        let mut result = vec![];
+
+        // The first two lines are column headers
+        for line in dev.lines().skip(2) {
+            let line = line?;
+            let mut parts = line.splitn(2, ':');
+            let (name, counters) = match (parts.next(), parts.next()) {
+                (Some(name), Some(counters)) => (name.trim(), counters),
+                _ => continue,
+            };
+
+            let counters: Vec<u64> = counters
+                .split_whitespace()
+                .map(|val| val.parse().unwrap_or_default())
+                .collect();
+            let counter = |index: usize| counters.get(index).cloned().unwrap_or_default();
+
+            // Receive: bytes packets errs drop fifo frame compressed multicast
+            // Transmit: bytes packets errs drop fifo colls carrier compressed
+            result.push(NetDev {
+                name: name.to_owned(),
+                rx_bytes: counter(0),
+                rx_packets: counter(1),
+                rx_errors: counter(2),
+                rx_dropped: counter(3),
+                tx_bytes: counter(8),
+                tx_packets: counter(9),
+                tx_errors: counter(10),
+                tx_dropped: counter(11),
+            });
+        }
+        Ok(result)
+    }
+
+    pub fn from_proc() -> Result<Vec<NetDev>, failure::Error> {
+        let file = File::open(root_path!("proc", "net", "dev"))?;
+        Self::parse(BufReader::new(file))
+    }
+
+    /// The name of the interface, like `eth0`
+    pub fn name(&self) -> &str { &self.name }
+    /// Bytes received
+    pub fn rx_bytes(&self) -> u64 { self.rx_bytes }
+    /// Packets received
+    pub fn rx_packets(&self) -> u64 { self.rx_packets }
+    /// Receive errors detected by the device driver
+    pub fn rx_errors(&self) -> u64 { self.rx_errors }
+    /// Received packets which were dropped
+    pub fn rx_dropped(&self) -> u64 { self.rx_dropped }
+    /// Bytes transmitted
+    pub fn tx_bytes(&self) -> u64 { self.tx_bytes }
+    /// Packets transmitted
+    pub fn tx_packets(&self) -> u64 { self.tx_packets }
+    /// Transmit errors detected by the device driver
+    pub fn tx_errors(&self) -> u64 { self.tx_errors }
+    /// Packets which were dropped instead of being transmitted
+    pub fn tx_dropped(&self) -> u64 { self.tx_dropped }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAW: &[u8] = b"Inter-|   Receive                                                |  Transmit\n\
+                         \x20face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed\n\
+                         \x20   lo:  104052     1234    0    0    0     0          0         0   104052     1234    0    0    0     0       0          0\n\
+                         \x20 eth0:5899612478 4256081    3   17    0     0          0      1020 61349220  390441    1    2    0     0       0          0\n";
+
+    #[test]
+    fn netdev_parse() {
+        let devs = NetDev::parse(RAW).unwrap();
+        assert_eq!(devs.len(), 2);
+        assert_eq!(devs[1], NetDev {
+            name: "eth0".into(),
+            rx_bytes: 5899612478,
+            rx_packets: 4256081,
+            rx_errors: 3,
+            rx_dropped: 17,
+            tx_bytes: 61349220,
+            tx_packets: 390441,
+            tx_errors: 1,
+            tx_dropped: 2,
+        });
+    }
+
+    #[test]
+    fn netdev_getters() {
+        let devs = NetDev::parse(RAW).unwrap();
+        let lo = &devs[0];
+        assert_eq!(lo.name(), "lo");
+        assert_eq!(lo.rx_bytes(), 104052);
+        assert_eq!(lo.rx_packets(), 1234);
+        assert_eq!(lo.tx_bytes(), 104052);
+        assert_eq!(lo.tx_packets(), 1234);
+        assert_eq!(lo.tx_dropped(), 0);
+    }
+
+    #[test]
+    fn netdev_from_proc() {
+        let devs = NetDev::from_proc().unwrap();
+        let names: Vec<&str> = devs.iter().map(|dev| dev.name()).collect();
+        assert_eq!(names, ["lo", "eth0", "can0"]);
+        assert_eq!(devs[2].rx_packets(), 500);
+    }
+}
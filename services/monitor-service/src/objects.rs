@@ -13,9 +13,52 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 //
+use cpu::CpuUsage;
+use disk::DiskUsage;
+use loadavg::LoadAvg;
+use logs::{LogEntry, LEVELS};
 use meminfo::MemInfo;
-use process::ProcStat;
+use net::NetDev;
+use kubos_service::Context;
+use model::Subsystem;
+use process::{self, ProcStat};
+use sampler::ProcessSample;
+use thermal::ThermalZone;
+use uptime::Uptime;
 use userinfo::UserInfo;
+use watchdog::RuleStatus;
+
+/// Common response fields structure for requests
+/// which don't return any specific data
+#[derive(GraphQLObject)]
+pub struct GenericResponse {
+    /// Any errors encountered by the request
+    pub errors: String,
+    /// Request completion success or failure
+    pub success: bool,
+}
+
+/// Response fields for the `sync` mutation
+#[derive(GraphQLObject)]
+pub struct SyncResponse {
+    /// Any errors encountered by the request
+    pub errors: String,
+    /// Request completion success or failure
+    pub success: bool,
+    /// How long the sync took, in seconds
+    pub duration: Option<f64>,
+}
+
+/// Response fields for the `kernelLog` and `log` queries
+#[derive(GraphQLObject)]
+pub struct LogResponse {
+    /// The number of matching entries
+    pub count: i32,
+    /// The matching entries, oldest first. Empty if they were written to a file
+    pub entries: Vec<LogEntry>,
+    /// The file the entries were written to, if requested
+    pub file: Option<String>,
+}
 
 pub struct MemInfoResponse {
     pub info: MemInfo,
@@ -55,7 +98,7 @@ impl PSResponse {
     }
 }
 
-graphql_object!(PSResponse: () |&self| {
+graphql_object!(PSResponse: Context<Subsystem> |&self| {
     field pid(&executor) -> i32 {
         self.pid
     }
@@ -101,4 +144,233 @@ graphql_object!(PSResponse: () |&self| {
             stat.cmd().ok().map(|argv| argv.join(" "))
         })
     }
+
+    field cpu(&executor) -> Option<f64> {
+        self.history(executor.context()).last().map(|sample| sample.cpu)
+    }
+
+    field age(&executor) -> Option<f64> {
+        let uptime = Uptime::from_proc().ok()?;
+        self.stat.as_ref().map(|stat| stat.age(uptime.seconds()))
+    }
+
+    field fds(&executor) -> Option<i32> {
+        process::open_fds(self.pid).ok().map(|fds| fds as i32)
+    }
+
+    field history(&executor) -> Vec<ProcessSample> {
+        self.history(executor.context())
+    }
+});
+
+impl PSResponse {
+    fn history(&self, context: &Context<Subsystem>) -> Vec<ProcessSample> {
+        match self.stat {
+            Some(ref stat) => context.subsystem().process_history(self.pid, stat.start_time()),
+            None => vec![],
+        }
+    }
+}
+
+graphql_object!(ProcessSample: () as "ProcSample" |&self| {
+    field timestamp() -> f64 {
+        self.timestamp
+    }
+
+    field cpu() -> f64 {
+        self.cpu
+    }
+
+    field rss() -> i32 {
+        self.rss as i32
+    }
+
+    field fds() -> Option<i32> {
+        self.fds.map(|fds| fds as i32)
+    }
+});
+
+graphql_object!(CpuUsage: () |&self| {
+    field name() -> &str {
+        self.name()
+    }
+
+    field usage() -> f64 {
+        self.usage()
+    }
+
+    field user() -> f64 {
+        self.user()
+    }
+
+    field system() -> f64 {
+        self.system()
+    }
+
+    field iowait() -> f64 {
+        self.iowait()
+    }
+});
+
+graphql_object!(LoadAvg: () |&self| {
+    field one() -> f64 {
+        self.one()
+    }
+
+    field five() -> f64 {
+        self.five()
+    }
+
+    field fifteen() -> f64 {
+        self.fifteen()
+    }
+
+    field running() -> i32 {
+        self.running() as i32
+    }
+
+    field total() -> i32 {
+        self.total() as i32
+    }
+});
+
+graphql_object!(Uptime: () |&self| {
+    field seconds() -> f64 {
+        self.seconds()
+    }
+
+    field idle() -> f64 {
+        self.idle()
+    }
+});
+
+graphql_object!(DiskUsage: () |&self| {
+    field device() -> &str {
+        self.mount().device()
+    }
+
+    field path() -> &str {
+        self.mount().path()
+    }
+
+    field fs_type() -> &str {
+        self.mount().fs_type()
+    }
+
+    field total() -> i32 {
+        self.total() as i32
+    }
+
+    field free() -> i32 {
+        self.free() as i32
+    }
+
+    field available() -> i32 {
+        self.available() as i32
+    }
+
+    field used_percent() -> f64 {
+        self.used_percent()
+    }
+});
+
+// GraphQL integers are only 32 bits, so counters which can grow larger are given as floats
+graphql_object!(NetDev: () |&self| {
+    field name() -> &str {
+        self.name()
+    }
+
+    field rx_bytes() -> f64 {
+        self.rx_bytes() as f64
+    }
+
+    field rx_packets() -> f64 {
+        self.rx_packets() as f64
+    }
+
+    field rx_errors() -> f64 {
+        self.rx_errors() as f64
+    }
+
+    field rx_dropped() -> f64 {
+        self.rx_dropped() as f64
+    }
+
+    field tx_bytes() -> f64 {
+        self.tx_bytes() as f64
+    }
+
+    field tx_packets() -> f64 {
+        self.tx_packets() as f64
+    }
+
+    field tx_errors() -> f64 {
+        self.tx_errors() as f64
+    }
+
+    field tx_dropped() -> f64 {
+        self.tx_dropped() as f64
+    }
+});
+
+graphql_object!(ThermalZone: () |&self| {
+    field name() -> &str {
+        self.name()
+    }
+
+    field type() -> Option<&str> {
+        self.kind()
+    }
+
+    field temp() -> Option<f64> {
+        self.temp()
+    }
+});
+
+graphql_object!(RuleStatus: () |&self| {
+    field name() -> &str {
+        &self.name
+    }
+
+    field ok() -> Option<bool> {
+        self.ok
+    }
+
+    field message() -> Option<&str> {
+        self.message.as_ref().map(|message| message.as_str())
+    }
+
+    field checked() -> Option<f64> {
+        self.checked
+    }
+
+    field failing_since() -> Option<f64> {
+        self.failing_since
+    }
+
+    field failures() -> i32 {
+        self.failures as i32
+    }
+
+    field actions() -> i32 {
+        self.actions as i32
+    }
+
+    field last_action() -> Option<f64> {
+        self.last_action
+    }
+});
+
+graphql_object!(LogEntry: () |&self| {
+    field timestamp() -> Option<f64> {
+        self.timestamp
+    }
+
+    field level() -> Option<&str> {
+        self.level.map(|level| LEVELS[level as usize])
+    }
+
+    field message() -> &str {
+        &self.message
+    }
 });
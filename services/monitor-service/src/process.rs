@@ -13,12 +13,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 use failure;
+use libc;
 use regex::Regex;
 
+use std::collections::HashMap;
 use std::i32;
 use std::fs::{self, File};
 use std::io::{Read, BufReader};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 /// Stats provided by the Linux /proc/<pid>/stat file format
@@ -204,6 +206,11 @@ impl ProcStat {
         self.state
     }
 
+    /// The process ID
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
     /// The PID of the parent of this process
     pub fn parent_pid(&self) -> i32 {
         self.ppid
@@ -226,6 +233,26 @@ impl ProcStat {
         self.num_threads as i32
     }
 
+    /// Resident Set Size in kB
+    pub fn rss_kb(&self) -> u64 {
+        self.rss.max(0) as u64 * page_size() / 1024
+    }
+
+    /// Time this process has been scheduled in user and kernel mode, in clock ticks
+    pub fn cpu_ticks(&self) -> u64 {
+        self.utime + self.stime
+    }
+
+    /// The time the process started after system boot, in clock ticks
+    pub fn start_time(&self) -> u64 {
+        self.starttime
+    }
+
+    /// Seconds since the process started, given the system's uptime in seconds
+    pub fn age(&self, uptime: f64) -> f64 {
+        (uptime - self.starttime as f64 / clock_ticks() as f64).max(0.0)
+    }
+
     /// Attempts to read the command line arguments used to execute this process, and falls
     /// back to the raw process name if /proc/[pid]/cmdline does not exist or is empty
     pub fn cmd(&self) -> Result<Vec<String>, failure::Error> {
@@ -242,6 +269,87 @@ impl ProcStat {
             Ok(argv)
         }
     }
+
+    /// The name of the program the process is running, without its directory. This is the
+    /// (possibly truncated) process name if the command line can't be read
+    pub fn program(&self) -> Option<String> {
+        self.cmd().ok().and_then(|argv| {
+            argv.first().and_then(|program| {
+                Path::new(program)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+        })
+    }
+}
+
+/// The number of clock ticks per second, which process times are measured in
+pub fn clock_ticks() -> u64 {
+    match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+        ticks if ticks > 0 => ticks as u64,
+        _ => 100,
+    }
+}
+
+/// The size of a memory page in bytes
+pub fn page_size() -> u64 {
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as u64,
+        _ => 4096,
+    }
+}
+
+/// Counts the file descriptors a process has open, from the entries in /proc/[pid]/fd
+pub fn open_fds(pid: i32) -> Result<u32, failure::Error> {
+    Ok(fs::read_dir(root_path!("proc", pid, "fd"))?.count() as u32)
+}
+
+/// The inodes of the sockets a process has open, from the links in /proc/[pid]/fd
+pub fn socket_inodes(pid: i32) -> Result<Vec<u64>, failure::Error> {
+    let mut inodes = vec![];
+    for entry in fs::read_dir(root_path!("proc", pid, "fd"))?.filter_map(|e| e.ok()) {
+        // Links look like "socket:[12345]"
+        if let Ok(target) = fs::read_link(entry.path()) {
+            let target = target.to_string_lossy();
+            if target.starts_with("socket:[") && target.ends_with(']') {
+                if let Ok(inode) = u64::from_str(&target[8..target.len() - 1]) {
+                    inodes.push(inode);
+                }
+            }
+        }
+    }
+    Ok(inodes)
+}
+
+/// The local port of every UDP socket, by the socket's inode, from /proc/net/udp and
+/// /proc/net/udp6
+pub fn udp_ports() -> Result<HashMap<u64, u16>, failure::Error> {
+    let mut ports = HashMap::new();
+    for table in &["udp", "udp6"] {
+        let contents = match fs::read_to_string(root_path!("proc", "net", table)) {
+            Ok(contents) => contents,
+            // IPv6 may be disabled
+            Err(_) if *table == "udp6" => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        // Columns: sl local_address rem_address st tx_queue:rx_queue tr:tm->when retrnsmt uid
+        // timeout inode ...
+        for line in contents.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 {
+                continue;
+            }
+            let port = fields[1]
+                .rsplit(':')
+                .next()
+                .and_then(|port| u16::from_str_radix(port, 16).ok());
+            if let (Some(port), Ok(inode)) = (port, u64::from_str(fields[9])) {
+                ports.insert(inode, port);
+            }
+        }
+    }
+    Ok(ports)
 }
 
 /// Finds the running process IDs by finding the valid numerical directory names in /proc
@@ -376,6 +484,52 @@ mod tests {
         assert_eq!(stat.cmd().unwrap(), ["/usr/sbin/cron", "-f"]);
     }
 
+    #[test]
+    fn procstat_cpu_and_age() {
+        let stat = ProcStat::from_pid(1492).unwrap();
+        assert_eq!(stat.cpu_ticks(), 526 + 810);
+        assert_eq!(stat.start_time(), 864);
+
+        let started = 864.0 / clock_ticks() as f64;
+        assert!((stat.age(started + 60.0) - 60.0).abs() < 1e-9);
+        // The uptime can't be earlier than the process' start
+        assert_eq!(stat.age(0.0), 0.0);
+    }
+
+    #[test]
+    fn procstat_rss_kb() {
+        let stat = ProcStat::parse(STAT).unwrap();
+        assert_eq!(stat.rss_kb(), 458 * page_size() / 1024);
+    }
+
+    #[test]
+    fn procstat_program() {
+        assert_eq!(ProcStat::from_pid(1492).unwrap().program(), Some("cron".into()));
+        // Kernel threads have an empty command line
+        assert_eq!(ProcStat::from_pid(232).unwrap().program(), Some("edac-poller".into()));
+    }
+
+    #[test]
+    fn open_fds_count() {
+        assert_eq!(open_fds(1492).unwrap(), 4);
+        assert!(open_fds(232).is_err());
+    }
+
+    #[test]
+    fn socket_inodes_list() {
+        assert_eq!(socket_inodes(1492).unwrap(), vec![28391]);
+        assert!(socket_inodes(232).is_err());
+    }
+
+    #[test]
+    fn udp_ports_list() {
+        let ports = udp_ports().unwrap();
+        assert_eq!(ports.len(), 3);
+        assert_eq!(ports[&28391], 8000);
+        assert_eq!(ports[&28400], 8008);
+        assert_eq!(ports[&28410], 8010);
+    }
+
     #[test]
     fn running_pids() {
         let pids = super::running_pids();
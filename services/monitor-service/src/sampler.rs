@@ -0,0 +1,370 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use failure;
+use kubos_system::ConfigSection;
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use process::{self, ProcStat};
+use telemetry::{self, Telemetry};
+use uptime::Uptime;
+
+/// The metrics which can be sent to the telemetry database
+pub const METRICS: [&str; 3] = ["cpu", "rss", "fds"];
+
+/// Settings read from the `[monitor-service]` config section
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct SamplerConfig {
+    /// Seconds between samples of each process
+    pub sample_interval: u64,
+    /// Number of samples kept for each process
+    pub history_length: usize,
+    /// Names of the processes whose samples are sent to the telemetry database
+    pub telemetry_processes: Vec<String>,
+    /// Which metrics of those processes are sent to the telemetry database
+    pub telemetry_metrics: Vec<String>,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        SamplerConfig {
+            sample_interval: 10,
+            history_length: 60,
+            telemetry_processes: vec![],
+            telemetry_metrics: METRICS.iter().map(|metric| metric.to_string()).collect(),
+        }
+    }
+}
+
+impl ConfigSection for SamplerConfig {
+    fn validate(&self) -> Result<(), String> {
+        if self.sample_interval == 0 {
+            return Err("sample_interval must be greater than zero".to_owned());
+        }
+        if self.history_length == 0 {
+            return Err("history_length must be greater than zero".to_owned());
+        }
+        if let Some(metric) = self
+            .telemetry_metrics
+            .iter()
+            .find(|metric| !METRICS.contains(&metric.as_str()))
+        {
+            return Err(format!(
+                "Unknown telemetry metric {}. Must be one of {}",
+                metric,
+                METRICS.join(", ")
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A measurement of a process' resource usage
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProcessSample {
+    /// When the sample was taken, in seconds since the UNIX epoch
+    pub timestamp: f64,
+    /// Percentage of a single CPU used by the process since the previous sample
+    pub cpu: f64,
+    /// Resident set size, in kB
+    pub rss: u64,
+    /// Number of open file descriptors, if they could be counted
+    pub fds: Option<u32>,
+}
+
+impl ProcessSample {
+    fn metric(&self, name: &str) -> Option<String> {
+        match name {
+            "cpu" => Some(format!("{:.2}", self.cpu)),
+            "rss" => Some(self.rss.to_string()),
+            "fds" => self.fds.map(|fds| fds.to_string()),
+            _ => None,
+        }
+    }
+}
+
+// The samples of a single process
+struct ProcessHistory {
+    // Distinguishes the process from a later one which reuses its PID
+    start_time: u64,
+    cpu_ticks: u64,
+    sampled: Instant,
+    samples: VecDeque<ProcessSample>,
+}
+
+/// Periodically samples the resource usage of every process, keeping a short history of each
+pub struct ProcessSampler {
+    config: SamplerConfig,
+    last_sample: Option<Instant>,
+    processes: HashMap<i32, ProcessHistory>,
+    telemetry: Option<Telemetry>,
+}
+
+impl ProcessSampler {
+    /// Create a sampler. If a telemetry service address is given, the samples of the
+    /// configured processes are sent to its direct UDP port
+    pub fn new(config: SamplerConfig, telemetry: Option<SocketAddr>) -> Self {
+        ProcessSampler {
+            config,
+            last_sample: None,
+            processes: HashMap::new(),
+            telemetry: Telemetry::new(telemetry),
+        }
+    }
+
+    /// Apply new settings. Histories longer than the new length are trimmed
+    pub fn reconfigure(&mut self, config: SamplerConfig) {
+        for history in self.processes.values_mut() {
+            while history.samples.len() > config.history_length {
+                history.samples.pop_front();
+            }
+        }
+        self.config = config;
+    }
+
+    /// Sample every process, unless the last samples were taken less than
+    /// `sample_interval` seconds ago
+    pub fn sample(&mut self) -> Result<(), failure::Error> {
+        let interval = Duration::from_secs(self.config.sample_interval);
+        match self.last_sample {
+            Some(taken) if taken.elapsed() < interval => Ok(()),
+            _ => self.sample_now(),
+        }
+    }
+
+    fn sample_now(&mut self) -> Result<(), failure::Error> {
+        let now = Instant::now();
+        let timestamp = telemetry::timestamp();
+        let uptime = Uptime::from_proc()?.seconds();
+        let ticks = process::clock_ticks() as f64;
+
+        let mut processes = HashMap::new();
+        for pid in process::running_pids()? {
+            // The process may have exited since the PIDs were read
+            let stat = match ProcStat::from_pid(pid) {
+                Ok(stat) => stat,
+                Err(_) => continue,
+            };
+
+            let mut history = match self.processes.remove(&pid) {
+                Some(ref history) if history.start_time != stat.start_time() => None,
+                history => history,
+            };
+
+            // A new process' usage is averaged over its whole life
+            let (busy, elapsed) = match history {
+                Some(ref history) => (
+                    stat.cpu_ticks().saturating_sub(history.cpu_ticks) as f64 / ticks,
+                    duration_secs(now.duration_since(history.sampled)),
+                ),
+                None => (stat.cpu_ticks() as f64 / ticks, stat.age(uptime)),
+            };
+            let sample = ProcessSample {
+                timestamp,
+                cpu: match elapsed {
+                    elapsed if elapsed > 0.0 => busy * 100.0 / elapsed,
+                    _ => 0.0,
+                },
+                rss: stat.rss_kb(),
+                fds: process::open_fds(pid).ok(),
+            };
+
+            self.send_telemetry(&stat, &sample);
+
+            let mut history = history.take().unwrap_or_else(|| ProcessHistory {
+                start_time: stat.start_time(),
+                cpu_ticks: 0,
+                sampled: now,
+                samples: VecDeque::new(),
+            });
+            history.cpu_ticks = stat.cpu_ticks();
+            history.sampled = now;
+            history.samples.push_back(sample);
+            while history.samples.len() > self.config.history_length {
+                history.samples.pop_front();
+            }
+            processes.insert(pid, history);
+        }
+
+        // Processes which have exited are forgotten
+        self.processes = processes;
+        self.last_sample = Some(now);
+        Ok(())
+    }
+
+    fn send_telemetry(&self, stat: &ProcStat, sample: &ProcessSample) {
+        let telemetry = match self.telemetry {
+            Some(ref telemetry) if !self.config.telemetry_processes.is_empty() => telemetry,
+            _ => return,
+        };
+        let name = match stat.program() {
+            Some(ref name) if self.config.telemetry_processes.contains(name) => name.clone(),
+            _ => return,
+        };
+
+        for metric in self.config.telemetry_metrics.iter() {
+            if let Some(value) = sample.metric(metric) {
+                telemetry.send(&name, metric, &value);
+            }
+        }
+    }
+
+    /// The samples of a process, oldest first. `start_time` is the process' start time, in
+    /// clock ticks, so that an earlier process with the same PID isn't reported
+    pub fn history(&self, pid: i32, start_time: u64) -> Vec<ProcessSample> {
+        match self.processes.get(&pid) {
+            Some(history) if history.start_time == start_time => {
+                history.samples.iter().cloned().collect()
+            }
+            _ => vec![],
+        }
+    }
+}
+
+/// A duration in seconds, including the fraction
+pub fn duration_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kubos_system::Config;
+    use serde_json;
+    use std::net::UdpSocket;
+    use std::thread;
+
+    fn config(history_length: usize) -> SamplerConfig {
+        SamplerConfig {
+            history_length,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn config_defaults() {
+        let config: SamplerConfig = Config::new_from_str("monitor-service", "")
+            .section()
+            .unwrap();
+        assert_eq!(config, SamplerConfig::default());
+        assert_eq!(config.sample_interval, 10);
+        assert_eq!(config.telemetry_metrics, ["cpu", "rss", "fds"]);
+    }
+
+    #[test]
+    fn config_bad_metric() {
+        let config = Config::new_from_str(
+            "monitor-service",
+            "[monitor-service]\ntelemetry_metrics = [\"cpu\", \"disk\"]",
+        );
+        let err = config.section::<SamplerConfig>().unwrap_err();
+        assert!(format!("{}", err).contains("Unknown telemetry metric disk"));
+    }
+
+    #[test]
+    fn sample_processes() {
+        let mut sampler = ProcessSampler::new(config(60), None);
+        sampler.sample().unwrap();
+
+        let history = sampler.history(1492, 864);
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].fds, Some(4));
+        assert_eq!(history[0].rss, 522 * process::page_size() / 1024);
+
+        // cron has used 13.36 seconds of CPU time since it started
+        let ticks = process::clock_ticks() as f64;
+        let age = 350735.47 - 864.0 / ticks;
+        assert!((history[0].cpu - 1336.0 / ticks * 100.0 / age).abs() < 1e-9);
+
+        // Processes without a stat file can't be sampled
+        assert_eq!(sampler.history(720, 0), vec![]);
+    }
+
+    #[test]
+    fn sample_interval() {
+        let mut sampler = ProcessSampler::new(config(60), None);
+        sampler.sample().unwrap();
+        sampler.sample().unwrap();
+        assert_eq!(sampler.history(1492, 864).len(), 1);
+
+        sampler.sample_now().unwrap();
+        let history = sampler.history(1492, 864);
+        assert_eq!(history.len(), 2);
+        // The test process hasn't used any more CPU time
+        assert_eq!(history[1].cpu, 0.0);
+    }
+
+    #[test]
+    fn sample_history_length() {
+        let mut sampler = ProcessSampler::new(config(3), None);
+        for _ in 0..5 {
+            sampler.sample_now().unwrap();
+        }
+        assert_eq!(sampler.history(1492, 864).len(), 3);
+
+        sampler.reconfigure(config(2));
+        assert_eq!(sampler.history(1492, 864).len(), 2);
+    }
+
+    #[test]
+    fn sample_reused_pid() {
+        let mut sampler = ProcessSampler::new(config(60), None);
+        sampler.sample_now().unwrap();
+        assert_eq!(sampler.history(1492, 1000), vec![]);
+    }
+
+    #[test]
+    fn sample_telemetry() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let config = SamplerConfig {
+            telemetry_processes: vec!["cron".to_owned()],
+            telemetry_metrics: vec!["rss".to_owned(), "fds".to_owned()],
+            ..Default::default()
+        };
+
+        let mut sampler = ProcessSampler::new(config, Some(receiver.local_addr().unwrap()));
+        sampler.sample_now().unwrap();
+        // Wait for both messages to arrive
+        thread::sleep(Duration::from_millis(10));
+
+        let mut buf = [0; 1024];
+        let mut messages = vec![];
+        for _ in 0..2 {
+            let size = receiver.recv(&mut buf).unwrap();
+            let message: serde_json::Value = serde_json::from_slice(&buf[0..size]).unwrap();
+            messages.push(message);
+        }
+
+        assert_eq!(
+            messages,
+            vec![
+                json!({
+                    "subsystem": "cron",
+                    "parameter": "rss",
+                    "value": (522 * process::page_size() / 1024).to_string(),
+                }),
+                json!({"subsystem": "cron", "parameter": "fds", "value": "4"}),
+            ]
+        );
+    }
+}
@@ -16,12 +16,43 @@
 
 use juniper::{self, FieldResult, FieldError};
 use kubos_service;
+use libc;
 
+use control;
+
+use cpu::CpuUsage;
+use disk::DiskUsage;
+use loadavg::LoadAvg;
+use logs::{self, LogEntry, LogFilter};
 use meminfo;
+use model::Subsystem;
+use net::NetDev;
 use objects::*;
 use process;
+use sampler::duration_secs;
+use thermal::ThermalZone;
+use uptime::Uptime;
+use watchdog::RuleStatus;
+
+type Context = kubos_service::Context<Subsystem>;
 
-type Context = kubos_service::Context<()>;
+// Returns the entries, or writes them to a new file called `output` in the configured output
+// directory and returns only how many there were
+fn log_response(
+    context: &Context,
+    entries: Vec<LogEntry>,
+    output: Option<String>,
+) -> FieldResult<LogResponse> {
+    let count = entries.len() as i32;
+    match output {
+        Some(name) => {
+            let dir = context.subsystem().log_output_dir();
+            let path = logs::write_log(&dir, &name, &entries)?;
+            Ok(LogResponse { count, entries: vec![], file: Some(path) })
+        }
+        None => Ok(LogResponse { count, entries, file: None }),
+    }
+}
 
 pub struct QueryRoot;
 
@@ -47,10 +78,106 @@ graphql_object!(QueryRoot: Context as "Query" |&self| {
 
         Ok(pids_vec.into_iter().map(|pid| PSResponse::new(pid)).collect())
     }
+
+    field cpu(&executor) -> Vec<CpuUsage> {
+        executor.context().subsystem().cpu_usage()
+    }
+
+    field load_avg(&executor) -> FieldResult<LoadAvg> {
+        LoadAvg::from_proc().map_err(|err| FieldError::new(err, juniper::Value::null()))
+    }
+
+    field uptime(&executor) -> FieldResult<Uptime> {
+        Uptime::from_proc().map_err(|err| FieldError::new(err, juniper::Value::null()))
+    }
+
+    field disks(&executor) -> FieldResult<Vec<DiskUsage>> {
+        DiskUsage::all().map_err(|err| FieldError::new(err, juniper::Value::null()))
+    }
+
+    field network(&executor) -> FieldResult<Vec<NetDev>> {
+        NetDev::from_proc().map_err(|err| FieldError::new(err, juniper::Value::null()))
+    }
+
+    field thermal(&executor) -> FieldResult<Vec<ThermalZone>> {
+        ThermalZone::all().map_err(|err| FieldError::new(err, juniper::Value::null()))
+    }
+
+    field rules(&executor) -> Vec<RuleStatus> {
+        executor.context().subsystem().rule_status()
+    }
+
+    field kernel_log(
+        &executor,
+        lines: Option<i32>,
+        level: Option<String>,
+        since: Option<f64>,
+        grep: Option<String>,
+        output: Option<String>
+    ) -> FieldResult<LogResponse>
+        as "Read the kernel's ring buffer"
+    {
+        let filter = LogFilter::new(lines, level.as_ref().map(|level| level.as_str()), since,
+                                    grep.as_ref().map(|grep| grep.as_str()))?;
+        log_response(executor.context(), logs::kernel_log(&filter)?, output)
+    }
+
+    field log(
+        &executor,
+        name: String,
+        lines: Option<i32>,
+        level: Option<String>,
+        since: Option<f64>,
+        grep: Option<String>,
+        output: Option<String>
+    ) -> FieldResult<LogResponse>
+        as "Read one of the configured log files"
+    {
+        let path = executor.context().subsystem().log_path(&name)
+            .ok_or_else(|| format!("Unknown log file {}", name))?;
+        let filter = LogFilter::new(lines, level.as_ref().map(|level| level.as_str()), since,
+                                    grep.as_ref().map(|grep| grep.as_str()))?;
+        log_response(executor.context(), logs::read_log(&path, &filter)?, output)
+    }
+
+    field log_files(&executor) -> Vec<String>
+        as "The names of the log files which can be read"
+    {
+        executor.context().subsystem().log_names()
+    }
 });
 
 pub struct MutationRoot;
 
 /// Base GraphQL mutation model
 graphql_object!(MutationRoot: Context as "Mutation" |&self| {
+    field kill(&executor, pid: i32, signal: Option<i32>, force: Option<bool>) -> FieldResult<GenericResponse>
+        as "Send a signal to a process"
+    {
+        let signal = signal.unwrap_or(libc::SIGTERM);
+        Ok(match control::kill(pid, signal, force.unwrap_or(false)) {
+            Ok(_) => GenericResponse { success: true, errors: "".to_owned() },
+            Err(error) => GenericResponse { success: false, errors: error.to_string() },
+        })
+    }
+
+    field renice(&executor, pid: i32, nice: i32, force: Option<bool>) -> FieldResult<GenericResponse>
+        as "Change the scheduling priority of a process"
+    {
+        Ok(match control::renice(pid, nice, force.unwrap_or(false)) {
+            Ok(_) => GenericResponse { success: true, errors: "".to_owned() },
+            Err(error) => GenericResponse { success: false, errors: error.to_string() },
+        })
+    }
+
+    field sync(&executor, drop_caches: Option<bool>, timeout: Option<i32>) -> FieldResult<SyncResponse>
+        as "Flush filesystem buffers to storage, and optionally drop the memory caches"
+    {
+        let result = control::sync_timeout(timeout)
+            .and_then(|timeout| control::sync(drop_caches.unwrap_or(false), timeout));
+        Ok(match result {
+            Ok(duration) => SyncResponse { success: true, errors: "".to_owned(), duration: Some(duration_secs(duration)) },
+            Err(error) => SyncResponse { success: false, errors: error.to_string(), duration: None },
+        })
+    }
 });
@@ -0,0 +1,59 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Sends measurements to the telemetry service's direct UDP port
+pub struct Telemetry {
+    socket: UdpSocket,
+    addr: SocketAddr,
+}
+
+impl Telemetry {
+    /// Create a sender for the telemetry service at `addr`. Returns `None`, after logging why,
+    /// if the address isn't known or a socket can't be created
+    pub fn new(addr: Option<SocketAddr>) -> Option<Self> {
+        addr.and_then(|addr| match UdpSocket::bind("0.0.0.0:0") {
+            Ok(socket) => Some(Telemetry { socket, addr }),
+            Err(err) => {
+                eprintln!("Failed to create telemetry socket: {}", err);
+                None
+            }
+        })
+    }
+
+    /// Insert a value into the telemetry database. Delivery isn't confirmed, so failures are
+    /// only logged
+    pub fn send(&self, subsystem: &str, parameter: &str, value: &str) {
+        let message = json!({
+            "subsystem": subsystem,
+            "parameter": parameter,
+            "value": value,
+        });
+        if let Err(err) = self.socket.send_to(message.to_string().as_bytes(), self.addr) {
+            eprintln!("Failed to send {} telemetry for {}: {}", parameter, subsystem, err);
+        }
+    }
+}
+
+/// The current time, in seconds since the UNIX epoch
+pub fn timestamp() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs() as f64 + f64::from(time.subsec_nanos()) / 1e9)
+        .unwrap_or_default()
+}
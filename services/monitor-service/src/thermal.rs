@@ -0,0 +1,114 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use failure;
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+
+use process::root_dir;
+
+/// A temperature sensor, from /sys/class/thermal
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ThermalZone {
+    name: String,
+    kind: Option<String>,
+    temp: Option<i64>,
+}
+
+// Reads a sysfs attribute, without its trailing newline
+fn read_attr(path: &Path) -> Result<String, io::Error> {
+    let mut contents = String::new();
+    File::open(path)?.read_to_string(&mut contents)?;
+    Ok(contents.trim().to_owned())
+}
+
+impl ThermalZone {
+    /// Read a thermal zone's attributes from its sysfs directory
+    pub fn from_dir(dir: &Path) -> ThermalZone {
+        ThermalZone {
+            name: dir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            kind: read_attr(&dir.join("type")).ok(),
+            temp: read_attr(&dir.join("temp"))
+                .ok()
+                .and_then(|temp| temp.parse().ok()),
+        }
+    }
+
+    /// All of the system's thermal zones, in order. Systems without any thermal zones
+    /// have no /sys/class/thermal directory, so have an empty list
+    pub fn all() -> Result<Vec<ThermalZone>, failure::Error> {
+        let entries = match fs::read_dir(root_path!("sys", "class", "thermal")) {
+            Ok(entries) => entries,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut zones: Vec<ThermalZone> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("thermal_zone"))
+            .map(|entry| Self::from_dir(&entry.path()))
+            .collect();
+        zones.sort_by_key(|zone| {
+            zone.name["thermal_zone".len()..]
+                .parse::<u32>()
+                .unwrap_or_default()
+        });
+        Ok(zones)
+    }
+
+    /// The name of the zone's directory, like `thermal_zone0`
+    pub fn name(&self) -> &str { &self.name }
+    /// What the zone measures, like `cpu-thermal`
+    pub fn kind(&self) -> Option<&str> { self.kind.as_ref().map(|kind| kind.as_str()) }
+    /// The zone's temperature in degrees Celsius, if it could be read
+    pub fn temp(&self) -> Option<f64> { self.temp.map(|temp| temp as f64 / 1000.0) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thermal_all() {
+        let zones = ThermalZone::all().unwrap();
+        assert_eq!(zones, vec![
+            ThermalZone {
+                name: "thermal_zone0".into(),
+                kind: Some("cpu-thermal".into()),
+                temp: Some(47850),
+            },
+            ThermalZone {
+                name: "thermal_zone1".into(),
+                kind: Some("battery".into()),
+                temp: None,
+            },
+        ]);
+    }
+
+    #[test]
+    fn thermal_getters() {
+        let zones = ThermalZone::all().unwrap();
+        assert_eq!(zones[0].name(), "thermal_zone0");
+        assert_eq!(zones[0].kind(), Some("cpu-thermal"));
+        assert_eq!(zones[0].temp(), Some(47.85));
+        assert_eq!(zones[1].temp(), None);
+    }
+}
@@ -0,0 +1,82 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use failure;
+
+use std::fs::File;
+use std::io::Read;
+
+use process::root_dir;
+
+/// How long the system has been running, from /proc/uptime
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Uptime {
+    seconds: f64,
+    idle: f64,
+}
+
+impl Uptime {
+    pub fn parse<R>(mut uptime: R) -> Result<Uptime, failure::Error>
+        where R: Read
+    {
+        let mut contents = String::new();
+        uptime.read_to_string(&mut contents)?;
+
+        let mut iter = contents.split_whitespace();
+        let seconds = iter.next().ok_or(format_err!("Invalid uptime format"))?;
+
+        Ok(Uptime {
+            seconds: seconds.parse()?,
+            idle: iter.next().and_then(|idle| idle.parse().ok()).unwrap_or_default(),
+        })
+    }
+
+    pub fn from_proc() -> Result<Uptime, failure::Error> {
+        let file = File::open(root_path!("proc", "uptime"))?;
+        Self::parse(file)
+    }
+
+    /// Seconds since the system booted
+    pub fn seconds(&self) -> f64 { self.seconds }
+    /// Seconds spent idle since the system booted, summed across all CPUs
+    pub fn idle(&self) -> f64 { self.idle }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uptime_parse() {
+        let uptime = Uptime::parse(&b"7748.55 6680.21\n"[..]);
+        assert_eq!(uptime.ok(), Some(Uptime {
+            seconds: 7748.55,
+            idle: 6680.21,
+        }));
+    }
+
+    #[test]
+    fn uptime_invalid() {
+        assert!(Uptime::parse(&b""[..]).is_err());
+    }
+
+    #[test]
+    fn uptime_from_proc() {
+        let uptime = Uptime::from_proc().unwrap();
+        assert_eq!(uptime.seconds(), 350735.47);
+        assert_eq!(uptime.idle(), 234388.90);
+    }
+}
@@ -0,0 +1,742 @@
+//
+// Copyright (C) 2018 Kubos Corporation
+//
+// Licensed under the Apache License, Version 2.0 (the "License")
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+
+use failure;
+use kubos_system::{self, ConfigSection};
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use disk::DiskUsage;
+use meminfo::MemInfo;
+use process::{self, ProcStat};
+use telemetry::{self, Telemetry};
+use uptime::Uptime;
+
+/// The telemetry subsystem which alarms are stored under, with the rule's name as the parameter
+pub const ALARM_SUBSYSTEM: &str = "monitor-service";
+
+/// What to do when a rule is broken
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Only log the problem
+    Log,
+    /// Insert an alarm into the telemetry database
+    Alarm,
+    /// Run the rule's restart command
+    Restart,
+    /// Reboot the OBC
+    Reboot,
+}
+
+impl Default for Action {
+    fn default() -> Self {
+        Action::Log
+    }
+}
+
+/// A condition the system should meet, from a `[[monitor-service.rules]]` config entry.
+/// Each rule checks one of a process, the free memory or the free space on a disk
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Rule {
+    /// Identifies the rule in logs, alarms and queries
+    pub name: String,
+    /// The name of a program which must be running
+    pub process: Option<String>,
+    /// A file holding the PID of a process which must be running
+    pub pidfile: Option<String>,
+    /// The most resident memory the process may use, in kB
+    pub max_rss: Option<u64>,
+    /// The least memory which must be available, in kB
+    pub min_free_memory: Option<u64>,
+    /// A path on the filesystem whose free space is checked
+    pub disk: Option<String>,
+    /// The least space which must be available on the filesystem, in kB
+    pub min_free_disk: Option<u64>,
+    /// What to do when the rule is broken
+    pub action: Action,
+    /// The shell command run by the `restart` action
+    pub command: Option<String>,
+    /// Seconds before the action is repeated, if the rule is still broken
+    pub action_interval: u64,
+    /// The number of checks in a row which must find the rule broken before the action is taken
+    pub failures: u32,
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule {
+            name: String::new(),
+            process: None,
+            pidfile: None,
+            max_rss: None,
+            min_free_memory: None,
+            disk: None,
+            min_free_disk: None,
+            action: Action::default(),
+            command: None,
+            action_interval: 60,
+            failures: 1,
+        }
+    }
+}
+
+impl Rule {
+    fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("Every rule needs a name".to_owned());
+        }
+
+        let has_process = self.process.is_some() || self.pidfile.is_some();
+        let has_disk = self.disk.is_some() || self.min_free_disk.is_some();
+        let checks = [has_process, self.min_free_memory.is_some(), has_disk];
+        match checks.iter().filter(|check| **check).count() {
+            0 => Err(format!("Rule {} has nothing to check", self.name)),
+            1 => Ok(()),
+            _ => Err(format!(
+                "Rule {} must check only one of a process, memory or a disk",
+                self.name
+            )),
+        }?;
+
+        if self.process.is_some() && self.pidfile.is_some() {
+            Err(format!("Rule {} can't have both a process and a pidfile", self.name))
+        } else if self.max_rss.is_some() && !has_process {
+            Err(format!("Rule {} needs a process or pidfile to use max_rss", self.name))
+        } else if has_disk && (self.disk.is_none() || self.min_free_disk.is_none()) {
+            Err(format!("Rule {} needs both disk and min_free_disk", self.name))
+        } else if self.action == Action::Restart && self.command.is_none() {
+            Err(format!("Rule {} needs a command to restart", self.name))
+        } else if self.failures == 0 {
+            Err(format!("Rule {} must allow at least one failure", self.name))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Returns a description of the problem if the rule is broken
+    fn check(&self) -> Result<Option<String>, failure::Error> {
+        if let Some(min) = self.min_free_memory {
+            let info = MemInfo::from_proc()?;
+            let free = info
+                .available()
+                .or(info.free())
+                .ok_or_else(|| format_err!("The amount of free memory is unknown"))?;
+            return Ok(match u64::from(free) {
+                free if free < min => Some(format!(
+                    "Only {} kB of memory is available (min {} kB)",
+                    free, min
+                )),
+                _ => None,
+            });
+        }
+
+        if let (Some(path), Some(min)) = (self.disk.as_ref(), self.min_free_disk) {
+            let usage = DiskUsage::for_path(path)?;
+            return Ok(match usage.available() {
+                free if free < min => Some(format!(
+                    "Only {} kB is available on {} (min {} kB)",
+                    free,
+                    usage.mount().path(),
+                    min
+                )),
+                _ => None,
+            });
+        }
+
+        let processes = self.processes()?;
+        if processes.is_empty() {
+            return Ok(Some(match self.pidfile {
+                Some(ref pidfile) => format!("The process in {} is not running", pidfile),
+                None => format!("{} is not running", self.process.as_ref().unwrap()),
+            }));
+        }
+
+        if let Some(max) = self.max_rss {
+            for stat in processes {
+                if stat.rss_kb() > max {
+                    return Ok(Some(format!(
+                        "{} ({}) is using {} kB of memory (max {} kB)",
+                        stat.program().unwrap_or_default(),
+                        stat.pid(),
+                        stat.rss_kb(),
+                        max
+                    )));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    // The running processes which the rule applies to
+    fn processes(&self) -> Result<Vec<ProcStat>, failure::Error> {
+        let stats: Vec<ProcStat> = match (self.pidfile.as_ref(), self.process.as_ref()) {
+            (Some(pidfile), _) => {
+                let contents = match fs::read_to_string(pidfile) {
+                    Ok(contents) => contents,
+                    // The pidfile is removed when its process exits
+                    Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+                    Err(err) => return Err(err.into()),
+                };
+                let pid = contents
+                    .trim()
+                    .parse()
+                    .map_err(|_| format_err!("{} doesn't contain a PID", pidfile))?;
+                ProcStat::from_pid(pid).into_iter().collect()
+            }
+            (None, Some(name)) => process::running_pids()?
+                .into_iter()
+                .filter_map(|pid| ProcStat::from_pid(pid).ok())
+                .filter(|stat| stat.program().as_ref() == Some(name))
+                .collect(),
+            (None, None) => vec![],
+        };
+
+        // Zombies have exited, and are only waiting for their parent to notice
+        Ok(stats.into_iter().filter(|stat| stat.state() != 'Z').collect())
+    }
+}
+
+/// Settings read from the `[monitor-service]` config section
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct WatchdogConfig {
+    /// Seconds between checks of the rules
+    pub check_interval: u64,
+    /// Seconds after the system boots before any rule may reboot it, so that a condition which
+    /// a reboot doesn't fix can't keep the system in a reboot loop
+    pub reboot_delay: u64,
+    /// The conditions the system should meet
+    pub rules: Vec<Rule>,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        WatchdogConfig {
+            check_interval: 10,
+            reboot_delay: 600,
+            rules: vec![],
+        }
+    }
+}
+
+impl ConfigSection for WatchdogConfig {
+    fn validate(&self) -> Result<(), String> {
+        if self.check_interval == 0 {
+            return Err("check_interval must be greater than zero".to_owned());
+        }
+
+        let mut names = HashSet::new();
+        for rule in self.rules.iter() {
+            rule.validate()?;
+            if !names.insert(&rule.name) {
+                return Err(format!("There is more than one rule named {}", rule.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The latest result of checking a rule
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RuleStatus {
+    /// The rule's name
+    pub name: String,
+    /// Whether the rule is met. `None` until it has been checked successfully
+    pub ok: Option<bool>,
+    /// What is wrong, if the rule is broken or couldn't be checked
+    pub message: Option<String>,
+    /// When the rule was last checked, in seconds since the UNIX epoch
+    pub checked: Option<f64>,
+    /// When the rule was found to be broken, if it still is
+    pub failing_since: Option<f64>,
+    /// The number of checks in a row which have found the rule broken
+    pub failures: u32,
+    /// The number of times the rule's action has been taken
+    pub actions: u32,
+    /// When the rule's action was last taken
+    pub last_action: Option<f64>,
+    // When the action was last taken, for measuring the action interval
+    acted: Option<Instant>,
+}
+
+/// Periodically checks the configured rules, taking each broken rule's action
+pub struct Watchdog {
+    config: WatchdogConfig,
+    last_check: Option<Instant>,
+    status: Vec<RuleStatus>,
+    telemetry: Option<Telemetry>,
+}
+
+impl Watchdog {
+    /// Create a watchdog. If a telemetry service address is given, alarms are sent to its
+    /// direct UDP port
+    pub fn new(config: WatchdogConfig, telemetry: Option<SocketAddr>) -> Self {
+        let mut watchdog = Watchdog {
+            config: WatchdogConfig::default(),
+            last_check: None,
+            status: vec![],
+            telemetry: Telemetry::new(telemetry),
+        };
+        watchdog.reconfigure(config);
+        watchdog
+    }
+
+    /// Apply new settings. Rules which are kept, by name, keep their status
+    pub fn reconfigure(&mut self, config: WatchdogConfig) {
+        let mut old: Vec<RuleStatus> = self.status.drain(..).collect();
+        self.status = config
+            .rules
+            .iter()
+            .map(|rule| match old.iter().position(|status| status.name == rule.name) {
+                Some(index) => old.swap_remove(index),
+                None => RuleStatus {
+                    name: rule.name.clone(),
+                    ..Default::default()
+                },
+            })
+            .collect();
+        self.config = config;
+    }
+
+    /// Check every rule, unless they were checked less than `check_interval` seconds ago
+    pub fn check(&mut self) {
+        let interval = Duration::from_secs(self.config.check_interval);
+        match self.last_check {
+            Some(checked) if checked.elapsed() < interval => {}
+            _ => self.check_now(),
+        }
+    }
+
+    fn check_now(&mut self) {
+        let now = Instant::now();
+        let timestamp = telemetry::timestamp();
+
+        for (rule, status) in self.config.rules.iter().zip(self.status.iter_mut()) {
+            status.checked = Some(timestamp);
+            match rule.check() {
+                Ok(None) => {
+                    if status.failing_since.is_some() {
+                        eprintln!("Rule {} is met again", rule.name);
+                    }
+                    status.ok = Some(true);
+                    status.message = None;
+                    status.failing_since = None;
+                    status.failures = 0;
+                    status.acted = None;
+                }
+                Ok(Some(message)) => {
+                    status.ok = Some(false);
+                    status.failing_since = status.failing_since.or(Some(timestamp));
+                    status.failures = status.failures.saturating_add(1);
+
+                    let interval = Duration::from_secs(rule.action_interval);
+                    let due = status.failures >= rule.failures
+                        && status.acted.map_or(true, |acted| now.duration_since(acted) >= interval);
+                    if due && rule.action == Action::Reboot && !reboot_allowed(&self.config) {
+                        eprintln!(
+                            "Rule {} is broken: {}. Not rebooting within {} seconds of boot",
+                            rule.name, message, self.config.reboot_delay
+                        );
+                    } else if due {
+                        act(rule, &message, self.telemetry.as_ref());
+                        status.actions += 1;
+                        status.last_action = Some(timestamp);
+                        status.acted = Some(now);
+                    }
+                    status.message = Some(message);
+                }
+                Err(err) => {
+                    eprintln!("Failed to check rule {}: {}", rule.name, err);
+                    status.ok = None;
+                    status.message = Some(err.to_string());
+                }
+            }
+        }
+
+        self.last_check = Some(now);
+    }
+
+    /// The status of each rule, in the order they were configured
+    pub fn status(&self) -> Vec<RuleStatus> {
+        self.status.clone()
+    }
+}
+
+// Whether the system has been up for long enough to be rebooted by a rule
+fn reboot_allowed(config: &WatchdogConfig) -> bool {
+    match Uptime::from_proc() {
+        Ok(uptime) => uptime.seconds() >= config.reboot_delay as f64,
+        Err(err) => {
+            eprintln!("Failed to read uptime: {}", err);
+            false
+        }
+    }
+}
+
+// Takes a broken rule's action
+fn act(rule: &Rule, message: &str, telemetry: Option<&Telemetry>) {
+    eprintln!("Rule {} is broken: {}", rule.name, message);
+
+    match rule.action {
+        Action::Log => {}
+        Action::Alarm => match telemetry {
+            Some(telemetry) => telemetry.send(ALARM_SUBSYSTEM, &rule.name, message),
+            None => eprintln!("Can't raise an alarm: the telemetry service has no direct_port"),
+        },
+        Action::Restart => {
+            if let Some(ref command) = rule.command {
+                restart(&rule.name, command);
+            }
+        }
+        Action::Reboot => {
+            eprintln!("Rebooting");
+            if let Err(err) = kubos_system::reboot() {
+                eprintln!("Failed to reboot: {}", err);
+            }
+        }
+    }
+}
+
+// Runs a restart command in the background, so requests are still handled while it runs
+fn restart(name: &str, command: &str) {
+    let name = name.to_owned();
+    let command = command.to_owned();
+    thread::spawn(move || match Command::new("sh").arg("-c").arg(&command).status() {
+        Ok(ref status) if status.success() => {}
+        Ok(status) => eprintln!("Restart command for rule {} failed: {}", name, status),
+        Err(err) => eprintln!("Failed to run restart command for rule {}: {}", name, err),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kubos_system::Config;
+    use serde_json;
+    use std::env;
+    use std::net::UdpSocket;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("monitor-watchdog-{}-{}", ::std::process::id(), name))
+    }
+
+    fn process_rule(name: &str) -> Rule {
+        Rule {
+            name: "test".to_owned(),
+            process: Some(name.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    fn pidfile_rule(pidfile: &PathBuf) -> Rule {
+        Rule {
+            name: "test".to_owned(),
+            pidfile: Some(pidfile.to_string_lossy().into_owned()),
+            ..Default::default()
+        }
+    }
+
+    fn watchdog(rules: Vec<Rule>, telemetry: Option<SocketAddr>) -> Watchdog {
+        Watchdog::new(
+            WatchdogConfig {
+                rules,
+                ..Default::default()
+            },
+            telemetry,
+        )
+    }
+
+    #[test]
+    fn config_rules() {
+        let config: WatchdogConfig = Config::new_from_str(
+            "monitor-service",
+            r#"
+            [monitor-service]
+            check_interval = 5
+
+            [[monitor-service.rules]]
+            name = "telemetry"
+            process = "telemetry-service"
+            max_rss = 20000
+            action = "restart"
+            command = "/etc/init.d/S90kubos-telemetry-service restart"
+
+            [[monitor-service.rules]]
+            name = "storage"
+            disk = "/home"
+            min_free_disk = 1024
+            "#,
+        ).section()
+            .unwrap();
+
+        assert_eq!(config.check_interval, 5);
+        assert_eq!(config.rules.len(), 2);
+        assert_eq!(config.rules[0].action, Action::Restart);
+        assert_eq!(config.rules[0].max_rss, Some(20000));
+        assert_eq!(config.rules[1].action, Action::Log);
+        assert_eq!(config.rules[1].action_interval, 60);
+        assert_eq!(config.rules[1].failures, 1);
+        assert_eq!(config.reboot_delay, 600);
+    }
+
+    #[test]
+    fn config_invalid() {
+        let invalid = [
+            ("process = \"cron\"", "Every rule needs a name"),
+            ("name = \"a\"", "Rule a has nothing to check"),
+            (
+                "name = \"a\"\nprocess = \"cron\"\nmin_free_memory = 10",
+                "must check only one of",
+            ),
+            (
+                "name = \"a\"\nprocess = \"cron\"\npidfile = \"/var/run/cron.pid\"",
+                "can't have both a process and a pidfile",
+            ),
+            (
+                "name = \"a\"\nmin_free_memory = 10\nmax_rss = 10",
+                "needs a process or pidfile to use max_rss",
+            ),
+            ("name = \"a\"\ndisk = \"/home\"", "needs both disk and min_free_disk"),
+            (
+                "name = \"a\"\nprocess = \"cron\"\naction = \"restart\"",
+                "needs a command to restart",
+            ),
+            ("name = \"a\"\nprocess = \"cron\"\naction = \"explode\"", "unknown variant"),
+            (
+                "name = \"a\"\nprocess = \"cron\"\nfailures = 0",
+                "must allow at least one failure",
+            ),
+        ];
+
+        for &(rule, expected) in invalid.iter() {
+            let config = Config::new_from_str(
+                "monitor-service",
+                &format!("[[monitor-service.rules]]\n{}", rule),
+            );
+            let err = config.section::<WatchdogConfig>().unwrap_err().to_string();
+            assert!(err.contains(expected), "{}: {}", rule, err);
+        }
+
+        let config = Config::new_from_str(
+            "monitor-service",
+            "[[monitor-service.rules]]\nname = \"a\"\nprocess = \"cron\"\n\
+             [[monitor-service.rules]]\nname = \"a\"\nprocess = \"top\"",
+        );
+        let err = config.section::<WatchdogConfig>().unwrap_err().to_string();
+        assert!(err.contains("more than one rule named a"));
+    }
+
+    #[test]
+    fn rule_process() {
+        assert_eq!(process_rule("cron").check().unwrap(), None);
+        assert_eq!(
+            process_rule("anacron").check().unwrap(),
+            Some("anacron is not running".to_owned())
+        );
+
+        let rule = Rule {
+            max_rss: Some(1),
+            ..process_rule("cron")
+        };
+        let message = rule.check().unwrap().unwrap();
+        assert!(message.starts_with("cron (1492) is using"), message);
+    }
+
+    #[test]
+    fn rule_pidfile() {
+        let pidfile = temp_path("rule.pid");
+        let rule = pidfile_rule(&pidfile);
+
+        fs::write(&pidfile, "1492\n").unwrap();
+        assert_eq!(rule.check().unwrap(), None);
+
+        fs::write(&pidfile, "99999\n").unwrap();
+        assert!(rule.check().unwrap().unwrap().ends_with("is not running"));
+
+        fs::write(&pidfile, "cron\n").unwrap();
+        assert!(rule.check().is_err());
+
+        fs::remove_file(&pidfile).unwrap();
+        assert!(rule.check().unwrap().unwrap().ends_with("is not running"));
+    }
+
+    #[test]
+    fn rule_memory() {
+        let rule = |min| Rule {
+            name: "memory".to_owned(),
+            min_free_memory: Some(min),
+            ..Default::default()
+        };
+        assert_eq!(rule(498232).check().unwrap(), None);
+        assert_eq!(
+            rule(498233).check().unwrap(),
+            Some("Only 498232 kB of memory is available (min 498233 kB)".to_owned())
+        );
+    }
+
+    #[test]
+    fn rule_disk() {
+        let rule = |min| Rule {
+            name: "storage".to_owned(),
+            disk: Some("/home".to_owned()),
+            min_free_disk: Some(min),
+            ..Default::default()
+        };
+        assert_eq!(rule(0).check().unwrap(), None);
+        assert!(rule(u64::max_value()).check().unwrap().unwrap().contains("available on /"));
+    }
+
+    #[test]
+    fn watchdog_alarm() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let rule = Rule {
+            action: Action::Alarm,
+            ..process_rule("anacron")
+        };
+
+        let mut watchdog = watchdog(vec![rule], Some(receiver.local_addr().unwrap()));
+        watchdog.check_now();
+        watchdog.check_now();
+
+        let status = &watchdog.status()[0];
+        assert_eq!(status.ok, Some(false));
+        assert_eq!(status.message, Some("anacron is not running".to_owned()));
+        assert!(status.failing_since.is_some());
+        // The action isn't repeated until the action interval has passed
+        assert_eq!(status.actions, 1);
+
+        let mut buf = [0; 1024];
+        let size = receiver.recv(&mut buf).unwrap();
+        let message: serde_json::Value = serde_json::from_slice(&buf[0..size]).unwrap();
+        assert_eq!(
+            message,
+            json!({
+                "subsystem": "monitor-service",
+                "parameter": "test",
+                "value": "anacron is not running",
+            })
+        );
+        assert!(receiver.recv(&mut buf).is_err());
+    }
+
+    #[test]
+    fn watchdog_restart() {
+        let pidfile = temp_path("restart.pid");
+        let rule = Rule {
+            action: Action::Restart,
+            command: Some(format!("echo 1492 > {}", pidfile.display())),
+            action_interval: 0,
+            ..pidfile_rule(&pidfile)
+        };
+
+        let mut watchdog = watchdog(vec![rule], None);
+        watchdog.check_now();
+        assert_eq!(watchdog.status()[0].ok, Some(false));
+        assert_eq!(watchdog.status()[0].actions, 1);
+
+        // Wait for the restart command to finish
+        for _ in 0..100 {
+            if pidfile.exists() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        thread::sleep(Duration::from_millis(10));
+
+        watchdog.check_now();
+        let status = &watchdog.status()[0];
+        assert_eq!(status.ok, Some(true));
+        assert_eq!(status.message, None);
+        assert_eq!(status.failing_since, None);
+        assert_eq!(status.actions, 1);
+        fs::remove_file(&pidfile).unwrap();
+    }
+
+    #[test]
+    fn watchdog_failures() {
+        let rule = Rule {
+            failures: 3,
+            ..process_rule("anacron")
+        };
+
+        let mut watchdog = watchdog(vec![rule], None);
+        watchdog.check_now();
+        watchdog.check_now();
+        assert_eq!(watchdog.status()[0].failures, 2);
+        assert_eq!(watchdog.status()[0].actions, 0);
+
+        watchdog.check_now();
+        assert_eq!(watchdog.status()[0].failures, 3);
+        assert_eq!(watchdog.status()[0].actions, 1);
+    }
+
+    #[test]
+    fn watchdog_reboot_delay() {
+        // The test system has been up for 350735 seconds
+        let rule = Rule {
+            action: Action::Reboot,
+            ..process_rule("anacron")
+        };
+        let mut watchdog = Watchdog::new(
+            WatchdogConfig {
+                reboot_delay: 400000,
+                rules: vec![rule],
+                ..Default::default()
+            },
+            None,
+        );
+
+        watchdog.check_now();
+        let status = &watchdog.status()[0];
+        assert_eq!(status.ok, Some(false));
+        assert_eq!(status.failures, 1);
+        assert_eq!(status.actions, 0);
+        assert_eq!(status.last_action, None);
+    }
+
+    #[test]
+    fn watchdog_reconfigure() {
+        let mut watchdog = watchdog(vec![process_rule("anacron")], None);
+        watchdog.check_now();
+        assert_eq!(watchdog.status()[0].actions, 1);
+
+        let mut other = process_rule("cron");
+        other.name = "other".to_owned();
+        watchdog.reconfigure(WatchdogConfig {
+            rules: vec![other, process_rule("anacron")],
+            ..Default::default()
+        });
+
+        let status = watchdog.status();
+        assert_eq!(status[0].name, "other");
+        assert_eq!(status[0].checked, None);
+        assert_eq!(status[1].name, "test");
+        assert_eq!(status[1].actions, 1);
+    }
+}
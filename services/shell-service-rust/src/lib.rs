@@ -24,15 +24,63 @@ extern crate serde_cbor;
 extern crate shell_protocol;
 extern crate simplelog;
 
-use channel_protocol::ChannelMessage;
+use channel_protocol::{ChannelMessage, ChannelProtocol};
 use kubos_system::Config as ServiceConfig;
-use shell_protocol::{ProtocolError, ShellProtocol};
+use serde_cbor::Value;
+use shell_protocol::messages::{self, MESSAGE_SIZE};
+use shell_protocol::{ProtocolError, SessionList, ShellProtocol};
 use std::collections::HashMap;
 use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
+/// How long, in seconds, a session may go without any activity before it is ended
+pub const DEFAULT_IDLE_TIMEOUT: u64 = 1800;
+/// How many sessions may be running at once
+pub const DEFAULT_MAX_SESSIONS: usize = 16;
+
+// Reads a timeout, in seconds, from the config. A timeout of zero disables it.
+fn config_timeout(config: &ServiceConfig, key: &str, default: Option<u64>) -> Option<Duration> {
+    config
+        .get(key)
+        .and_then(|val| val.as_integer().map(|num| num as u64))
+        .or(default)
+        .and_then(|secs| match secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        })
+}
+
+// Tells a client why its request failed
+fn send_error(
+    host_ip: &str,
+    remote_addr: &str,
+    channel_id: u32,
+    message: &str,
+) -> Result<(), ProtocolError> {
+    let c_protocol = ChannelProtocol::new(host_ip, remote_addr, MESSAGE_SIZE as u32);
+    c_protocol.send(messages::error::to_cbor(channel_id, message)?)?;
+    Ok(())
+}
+
+// Frees a session's slot in the thread list once its thread finishes, even if it panics
+struct SessionSlot {
+    threads: Arc<Mutex<HashMap<u32, Sender<ChannelMessage>>>>,
+    channel_id: u32,
+}
+
+impl Drop for SessionSlot {
+    fn drop(&mut self) {
+        // Still free the slot if another session thread poisoned the lock
+        let mut threads = match self.threads.lock() {
+            Ok(threads) => threads,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        threads.remove(&self.channel_id);
+    }
+}
+
 // We need this in this lib.rs file so we can build integration tests
 pub fn recv_loop(config: ServiceConfig) -> Result<(), failure::Error> {
     // Get and bind our UDP listening socket
@@ -50,11 +98,19 @@ pub fn recv_loop(config: ServiceConfig) -> Result<(), failure::Error> {
             val.as_integer()
                 .and_then(|num| Some(Duration::from_secs(num as u64)))
         }).unwrap_or(Duration::from_millis(2));
+    let idle_timeout = config_timeout(&config, "idle-timeout", Some(DEFAULT_IDLE_TIMEOUT));
+    let session_timeout = config_timeout(&config, "session-timeout", None);
+    let max_sessions = config
+        .get("max-sessions")
+        .and_then(|val| val.as_integer().map(|num| num as usize))
+        .unwrap_or(DEFAULT_MAX_SESSIONS);
 
     // Setup map of channel IDs to thread channels
     let raw_threads: HashMap<u32, Sender<ChannelMessage>> = HashMap::new();
     // Create thread sharable wrapper
     let threads = Arc::new(Mutex::new(raw_threads));
+    // The processes currently running, for answering list requests
+    let sessions: SessionList = Arc::new(Mutex::new(HashMap::new()));
 
     loop {
         // Listen on UDP port
@@ -69,7 +125,7 @@ pub fn recv_loop(config: ServiceConfig) -> Result<(), failure::Error> {
         let host_ref = host_ip.clone();
         let timeout_ref = timeout.clone();
 
-        let parsed_message = match channel_protocol::parse_message(first_message) {
+        let mut parsed_message = match channel_protocol::parse_message(first_message) {
             Ok(parsed_message) => parsed_message,
             Err(e) => {
                 warn!("Error parsing channel message: {:?}", e);
@@ -78,16 +134,63 @@ pub fn recv_loop(config: ServiceConfig) -> Result<(), failure::Error> {
         };
         let channel_id = parsed_message.channel_id;
 
+        match parsed_message.name.as_ref() {
+            // List requests aren't part of a session, so are answered immediately
+            "list" => {
+                let mut s_protocol =
+                    ShellProtocol::new(&host_ref, &format!("{}", source), channel_id)
+                        .with_sessions(sessions.clone());
+                if let Err(e) = s_protocol.process_message(parsed_message) {
+                    warn!("Failed to list processes: {}", e);
+                }
+                continue;
+            }
+            // The session needs to know where its new client is
+            "attach" => parsed_message.payload = vec![Value::String(format!("{}", source))],
+            _ => {}
+        }
+
         if !threads.lock().unwrap().contains_key(&channel_id) {
+            // Only spawn requests start new sessions
+            if parsed_message.name != "spawn" {
+                warn!(
+                    "No session found for {} message on channel {}",
+                    parsed_message.name, channel_id
+                );
+                continue;
+            }
+
+            if threads.lock().unwrap().len() >= max_sessions {
+                warn!(
+                    "Refusing session on channel {}: {} sessions already running",
+                    channel_id, max_sessions
+                );
+                let message = format!("Too many sessions (max {})", max_sessions);
+                if let Err(e) = send_error(&host_ref, &format!("{}", source), channel_id, &message)
+                {
+                    warn!("Failed to send error: {}", e);
+                }
+                continue;
+            }
+
             let (sender, receiver): (Sender<ChannelMessage>, Receiver<ChannelMessage>) =
                 mpsc::channel();
             threads.lock().unwrap().insert(channel_id, sender.clone());
             // Break the processing work off into its own thread so we can
             // listen for requests from other clients
             let shared_threads = threads.clone();
+            let shared_sessions = sessions.clone();
             thread::spawn(move || {
+                let _slot = SessionSlot {
+                    threads: shared_threads,
+                    channel_id,
+                };
+
                 let mut s_protocol =
-                    ShellProtocol::new(&host_ref, &format!("{}", source), channel_id);
+                    ShellProtocol::new(&host_ref, &format!("{}", source), channel_id)
+                        .with_sessions(shared_sessions)
+                        .idle_timeout(idle_timeout)
+                        .session_timeout(session_timeout);
 
                 // Listen, process, and react to the remaining messages in the
                 // requested operation
@@ -104,9 +207,6 @@ pub fn recv_loop(config: ServiceConfig) -> Result<(), failure::Error> {
                     Err(e) => warn!("Encountered errors while processing transaction: {}", e),
                     _ => {}
                 }
-
-                // Remove ourselves from threads list if we are finished
-                shared_threads.lock().unwrap().remove(&channel_id);
             });
         }
 